@@ -78,6 +78,8 @@ impl<'source, T, S> Lexer<T, S>
         let mut last_matches: Vec<T> = Vec::new();
 
         let mut token_match_map: HashMap<T, Range<usize>> = HashMap::new();
+        // Position where each token started matching, a literal keyword starts later than text
+        let mut first_match_pos: HashMap<T, usize> = HashMap::new();
 
         while self.current_pos < self.source_end {
             last_slice = self.get_slice();
@@ -126,6 +128,10 @@ impl<'source, T, S> Lexer<T, S>
                     if !last_matches.contains(token) {
                         let range = begin_pos..self.current_pos + 1;
                         token_match_map.insert(token.clone(), range);
+                        first_match_pos.insert(token.clone(), self.current_pos);
+                    } else if let Some(range) = token_match_map.get_mut(token) {
+                        // Still matching, e.g. text continuing past a keyword
+                        *range = range.start..self.current_pos + 1;
                     }
                 }
 
@@ -135,10 +141,12 @@ impl<'source, T, S> Lexer<T, S>
             self.current_pos += 1;
         }
 
-        if self.current_pos == self.source_end {
-            if !matched_in_past {
-                self.token = T::get_end_variant();
-            }
+        // Only whitespace was left, which is the end rather than an error
+        if !matched_in_past && current_slice.trim().is_empty() {
+            self.token_begin = self.current_pos;
+            self.token_end = self.current_pos;
+            self.token = T::get_end_variant();
+            return;
         }
 
         let mut match_results: Vec<(T, Range<usize>)> = token_match_map.into_iter().collect();
@@ -148,7 +156,10 @@ impl<'source, T, S> Lexer<T, S>
             let len2 = range2.len();
             let prio1 = t1.prio();
             let prio2 = t2.prio();
-            if len1 == len2 {
+            if len1 == len2 && prio1 == prio2 {
+                // The more specific token, which matched fewer prefixes, wins
+                return first_match_pos.get(t2).cmp(&first_match_pos.get(t1));
+            } else if len1 == len2 {
                 return prio2.cmp(&prio1);
             } else {
                 return len2.cmp(&len1);
@@ -75,6 +75,17 @@ fn test_lexer_basic() {
     assert_eq!(lexer.slice(), "bool");
 }
 
+#[test]
+fn test_lexer_trailing_whitespace() {
+    let code = "int \n\t ";
+    let mut lexer = Token::lexer(code);
+
+    assert_eq!(lexer.token, Token::Int);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::End);
+    assert_eq!(lexer.range(), code.len()..code.len());
+}
+
 #[test]
 fn test_lexer_int_literal() {
     let code = "1231232 123331";
@@ -171,6 +182,23 @@ fn test_lexer_keyword_text() {
     assert_eq!(lexer.slice(), "intisntoneeither");
 }
 
+#[test]
+fn test_lexer_keyword_prefix_text() {
+    let code = "ints floats fnx";
+    let mut lexer = Token::lexer(code);
+
+    assert_eq!(lexer.token, Token::Text);
+    assert_eq!(lexer.slice(), "ints");
+
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Text);
+    assert_eq!(lexer.slice(), "floats");
+
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Text);
+    assert_eq!(lexer.slice(), "fnx");
+}
+
 #[test]
 fn test_lexer_import_string() {
     let code = "
@@ -11,8 +11,10 @@ use crate::{
     },
     vm::{
         core::{
-            Core
+            Core,
+            CoreResult
         },
+        strings::StringRepr,
         register::{
             Register as RegisterUnion,
             RegisterAccess
@@ -31,6 +33,15 @@ use serde::{
     de::DeserializeOwned
 };
 
+/// Whether the return value of a foreign function is available when it returns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForeignResult {
+    /// The return value is set
+    Ready,
+    /// The core suspends until the value is delivered with the token, see Core::resume_with
+    Pending(u64)
+}
+
 pub struct Adapter<'c> {
     pub function: Function,
     pub core: &'c mut Core
@@ -69,21 +80,58 @@ impl<'c> Adapter<'c> {
     pub fn remove_foreign_ptr<T>(&mut self, ptr: u64) -> Arc<Mutex<T>> {
         self.core.remove_foreign_ptr(ptr).unwrap()
     }
+
+    /// Gets the address and the size of a mapped buffer by name, see Core::map_buffer
+    pub fn get_buffer(&self, name: &str) -> Option<(u64, usize)> {
+        self.core.get_buffer(name)
+    }
+
+    /// Calls a script function, its arguments are pushed onto the stack before, see Core::call_fn
+    pub fn call_fn(&mut self, uid: u64) -> CoreResult<()> {
+        self.core.call_fn(uid)
+    }
+
+    /// Interns a string, returns the handle scripts compiled with StringRepr::Handle refer to it by
+    pub fn intern_string(&mut self, string: &str) -> u64 {
+        self.core.intern_string(string)
+    }
+
+    /// Halts the script with the given error code once this function returns
+    pub fn trap(&mut self, err_code: u8) {
+        self.core.set_trap(err_code);
+    }
+
+    /// Sets whether the return value is ready, a pending result suspends the core
+    /// once this function returns
+    pub fn set_result(&mut self, result: ForeignResult) {
+        self.core.set_foreign_result(result);
+    }
 }
 
 pub trait FromArg: DeserializeOwned {
     fn get(adapter: &mut Adapter, arg_index: usize) -> Self;
 }
 
+/// An invalid string argument is read as an empty string, the core fails with the error
+/// once the foreign function returns
 impl FromArg for String {
     fn get(adapter: &mut Adapter, arg_index: usize) -> String {
-        let arg_offset = adapter.function.get_arg_offset(arg_index).abs() as u64;
-        //println!("Arg offset of Arg #{}: -{}B", arg_index, arg_offset);
-        let mut stack_addr = adapter.core.reg(16).unwrap().get::<u64>();
-        stack_addr -= arg_offset;
-        let string_res = adapter.core.mem_get_string(stack_addr);
+        let string_res = if adapter.core.get_string_repr() == StringRepr::Handle {
+            let handle: u64 = u64::get(adapter, arg_index);
+            adapter.core.get_string(handle)
+                .map(String::from)
+        } else {
+            let arg_offset = adapter.function.get_arg_offset(arg_index).unsigned_abs();
+            //println!("Arg offset of Arg #{}: -{}B", arg_index, arg_offset);
+            let mut stack_addr = adapter.core.reg(16).unwrap().get::<u64>();
+            stack_addr -= arg_offset;
+            adapter.core.mem_get_string(stack_addr)
+        };
         //println!("{:?}", string_res);
-        string_res.unwrap()
+        string_res.unwrap_or_else(|err| {
+            adapter.core.set_foreign_error(err);
+            String::new()
+        })
     }
 }
 
@@ -103,6 +151,14 @@ impl FromArg for f32 {
     }
 }
 
+impl FromArg for f64 {
+    fn get(adapter: &mut Adapter, arg_index: usize) -> f64 {
+        let arg_offset = adapter.function.get_arg_offset(arg_index) as i16;
+        let addr = adapter.core.reg(16).unwrap().get::<u64>();
+        adapter.core.mem_get((addr, arg_offset)).unwrap()
+    }
+}
+
 impl FromArg for u64 {
     fn get(adapter: &mut Adapter, arg_index: usize) -> u64 {
         let arg_offset = adapter.function.get_arg_offset(arg_index) as i16;
@@ -0,0 +1,44 @@
+use crate::{
+    api::{
+        module::{
+            Module
+        },
+        function::{
+            Function
+        },
+        adapter::{
+            Adapter
+        }
+    },
+    parser::{
+        ast::{
+            Type
+        }
+    }
+};
+
+/// Name of the module the built-in modules are registered under in the root.
+/// Host and script modules keep all other names, including the ones of the built-in modules.
+pub const BUILTIN_MODULE: &str = "__builtin";
+
+/// Creates the module holding all built-in modules
+pub fn builtin_module() -> Module {
+    Module::new(BUILTIN_MODULE)
+        .with_module(string_module())
+}
+
+/// Creates the built-in string module
+pub fn string_module() -> Module {
+    let equals_function = Function::new("equals")
+        .with_arg(Type::String)
+        .with_arg(Type::String)
+        .with_ret_type(Type::Bool)
+        .with_closure(Box::new(|adapter: &mut Adapter| {
+            let lhs: String = adapter.get_arg(0);
+            let rhs: String = adapter.get_arg(1);
+            adapter.return_value(lhs == rhs);
+        }));
+
+    Module::new("string")
+        .with_function(equals_function)
+}
@@ -11,6 +11,11 @@ use crate::{
         ast::{
             Type
         }
+    },
+    vm::{
+        core::{
+            Value
+        }
     }
 };
 
@@ -19,8 +24,7 @@ use std::{
         HashMap
     },
     ops::{
-        FnMut,
-        DerefMut
+        Fn
     },
     cmp::{
         PartialEq
@@ -34,8 +38,7 @@ use std::{
         Clone
     },
     sync::{
-        Arc,
-        Mutex
+        Arc
     }
 };
 
@@ -50,7 +53,9 @@ pub struct Function {
     arg_sizes: HashMap<usize, usize>,
     /// Return type
     pub return_type: Type,
-    closure: Option<Arc<Mutex<FunctionClosureType>>>
+    ret_size: usize,
+    closure: Option<Arc<FunctionClosureType>>,
+    is_callback: bool
 }
 
 impl Debug for Function {
@@ -78,7 +83,13 @@ impl Clone for Function {
     }
 }*/
 
-pub type FunctionClosureType = dyn FnMut(&mut Adapter) -> ();
+/// Closures are Send and Sync, so programs binding them can be shared between threads
+/// and the cores running them call the closures at the same time
+pub type FunctionClosureType = dyn Fn(&mut Adapter) + Send + Sync;
+
+/// A host function registered with a core for a callback function, see Function::as_callback.
+/// It takes the arguments and returns the value of the call, the value is ignored for void callbacks.
+pub type ScriptCallback = Box<dyn Fn(Vec<Value>) -> Value + Send>;
 
 impl Function {
     /// Creates a new function
@@ -91,7 +102,9 @@ impl Function {
             arg_offsets: HashMap::new(),
             arg_sizes: HashMap::new(),
             return_type: Type::Void,
-            closure: None
+            ret_size: 0,
+            closure: None,
+            is_callback: false
         }
     }
 
@@ -121,23 +134,76 @@ impl Function {
         }
     }
 
+    /// INTERNAL: Sets the size of the return value
+    pub fn set_ret_size(&mut self, ret_size: usize) {
+        self.ret_size = ret_size;
+    }
+
     /// Gets the byte offset of an argument
     pub fn get_arg_offset(&self, arg_index: usize) -> i64 {
         *self.arg_offsets.get(&arg_index).unwrap()
     }
 
+    /// Gets the byte size of an argument
+    pub fn get_arg_size(&self, arg_index: usize) -> usize {
+        *self.arg_sizes.get(&arg_index).unwrap()
+    }
+
+    /// Gets the byte size of the return value, only set for callback functions
+    pub fn get_ret_size(&self) -> usize {
+        self.ret_size
+    }
+
     /// Runs the internal closure
     pub fn run(&self, adapter: &mut Adapter) {
-        let closure_arc = self.closure.as_ref().unwrap();
-        let mut closure_lock = closure_arc.lock().unwrap();
-        let closure = closure_lock.deref_mut();
+        let closure = self.closure.as_ref().unwrap();
         closure(adapter);
     }
     
+    /// Whether a closure is bound to this function
+    pub fn is_bound(&self) -> bool {
+        self.closure.is_some()
+    }
+
+    /// Makes this a callback function, builder style. Scripts call it with CALL_CALLBACK,
+    /// which runs the ScriptCallback registered with the core instead of a closure, see Core::register_callback.
+    /// Callbacks only take and return ints, floats and bools.
+    pub fn as_callback(mut self) -> Function {
+        self.is_callback = true;
+        self
+    }
+
+    /// Whether this is a callback function
+    pub fn is_callback(&self) -> bool {
+        self.is_callback
+    }
+
+    /// Checks whether both functions take and return the same types
+    pub fn signature_eq(&self, rhs: &Function) -> bool {
+        self.arg_types == rhs.arg_types && self.return_type == rhs.return_type
+    }
+
+    /// Formats the signature like "name(int, float) -> bool"
+    pub fn signature_string(&self) -> String {
+        let arg_types: Vec<String> = self.arg_types.iter()
+            .map(|arg_type| arg_type.to_string())
+            .collect();
+        format!("{}({}) -> {}", self.name, arg_types.join(", "), self.return_type)
+    }
+
+    /// Binds the closure of another function, keeping the argument layout of this one
+    pub fn rebind(&mut self, function: Function) {
+        self.closure = function.closure;
+    }
+
+    /// Removes the closure, calling the function fails at runtime
+    pub fn unbind(&mut self) {
+        self.closure = None;
+    }
+
     /// Sets the closure to be executes
     pub fn with_closure(mut self, closure: Box<FunctionClosureType>) -> Function {
-        let closure_arc = Arc::new(Mutex::new(closure));
-        self.closure = Some(closure_arc);
+        self.closure = Some(Arc::from(closure));
         self
     }
 }
\ No newline at end of file
@@ -8,4 +8,6 @@ pub mod module;
 pub mod adapter;
 
 /// Contains the container API
-pub mod container;
\ No newline at end of file
+pub mod container;
+/// Contains the built-in modules
+pub mod builtin;
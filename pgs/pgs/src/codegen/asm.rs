@@ -0,0 +1,548 @@
+use crate::{
+    vm::{
+        is::{
+            Opcode,
+            decode::{
+                opcode_operand_layout,
+                decode_instructions,
+                decode_operands,
+                DecodeError,
+                Operand,
+                OperandKind
+            }
+        }
+    },
+    codegen::{
+        builder::{
+            Builder,
+            Label,
+            Relocation
+        },
+        compiler::CompilerError,
+        instruction::Instruction,
+        program::{
+            Program,
+            DebugInfo
+        },
+        register::Register,
+        uid_generator::UIDGenerator
+    }
+};
+
+use std::{
+    collections::{
+        BTreeSet,
+        HashMap
+    },
+    convert::TryFrom,
+    error::Error,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult
+    }
+};
+
+use num_traits::FromPrimitive;
+
+/// Number of data bytes per line of a listing
+const DATA_LINE_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+pub enum AsmError {
+    /// Line number and content of a line which is not valid assembly
+    InvalidLine(usize, String),
+    UnknownMnemonic(usize, String),
+    /// Line number and the expected operand count
+    OperandCount(usize, usize),
+    InvalidOperand(usize, String),
+    UnknownLabel(usize, String),
+    UnknownFunction(usize, String),
+    InvalidData(usize),
+    Compiler(CompilerError)
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Names used when printing operands
+struct Names {
+    labels: HashMap<u64, String>,
+    functions: HashMap<u64, String>
+}
+
+/// Produces a listing of a program, which can be read back by assemble
+pub fn emit_asm(program: &Program) -> String {
+    emit_listing(program, None)
+}
+
+/// Produces a listing of a program like emit_asm, with the source line of each statement
+/// as a comment in front of its instructions. A line is shown at most once per basic block.
+pub fn disassemble_with_source(program: &Program, source: &str, debug_info: &DebugInfo) -> String {
+    let source_lines: Vec<&str> = source.lines().collect();
+    emit_listing(program, Some((&source_lines, debug_info)))
+}
+
+fn emit_listing(program: &Program, source: Option<(&[&str], &DebugInfo)>) -> String {
+    let mut asm = String::new();
+    let code_start = program.code_start.min(program.code.len());
+    emit_data(&mut asm, &program.code[..code_start]);
+
+    let mut fn_names = program.debug_info.function_names.clone();
+    for uid in program.functions.keys() {
+        fn_names.entry(*uid).or_insert_with(|| format!("fn_{:X}", uid));
+    }
+
+    asm += ".code\n";
+
+    // Functions without code in this program, e.g. foreign ones
+    let mut externs: Vec<(&String, &u64)> = fn_names.iter()
+        .filter(|(uid, _)| !program.functions.contains_key(uid))
+        .map(|(uid, name)| (name, uid))
+        .collect();
+    externs.sort();
+    for (name, uid) in externs {
+        asm += &format!(".extern {} 0x{:X}\n", name, uid);
+    }
+
+    let instructions = decode_code(&program.code, code_start);
+
+    // Only jump targets at instruction boundaries get a label
+    let mut boundaries: BTreeSet<u64> = instructions.iter()
+        .map(|(addr, _)| *addr as u64)
+        .collect();
+    boundaries.insert(program.code.len() as u64);
+    let targets: BTreeSet<u64> = instructions.iter()
+        .filter_map(|(_, instr)| instr.as_ref().ok())
+        .flat_map(|(_, operands)| operands.iter())
+        .filter_map(|(_, operand)| match operand {
+            Operand::CodeAddress(addr) if boundaries.contains(addr) => Some(*addr),
+            _ => None
+        })
+        .collect();
+    let names = Names {
+        labels: targets.iter()
+            .enumerate()
+            .map(|(i, addr)| (*addr, format!("L{}", i)))
+            .collect(),
+        functions: fn_names
+    };
+
+    let mut fn_starts: Vec<(usize, &String, u64)> = program.functions.iter()
+        .map(|(uid, offset)| (*offset, names.functions.get(uid).unwrap(), *uid))
+        .collect();
+    fn_starts.sort();
+
+    // Source lines already shown in the current basic block
+    let mut block_lines = BTreeSet::new();
+    for (addr, instr) in instructions.iter() {
+        for (_, name, uid) in fn_starts.iter().filter(|(offset, _, _)| offset == addr) {
+            asm += &format!(".fn {} 0x{:X}\n", name, uid);
+            block_lines.clear();
+        }
+        if let Some(label) = names.labels.get(&(*addr as u64)) {
+            asm += &format!("{}:\n", label);
+            block_lines.clear();
+        }
+        if let Some((source_lines, debug_info)) = source {
+            let line = debug_info.line_table.get(addr)
+                .filter(|line| block_lines.insert(**line));
+            if let Some(source_line) = line.and_then(|line| source_lines.get(line - 1)) {
+                asm += &format!("    ; {}: {}\n", line.unwrap(), source_line.trim());
+            }
+        }
+        match instr {
+            Ok((opcode, operands)) => {
+                let operands: Vec<String> = operands.iter()
+                    .map(|(_, operand)| format_operand(operand, &names))
+                    .collect();
+                emit_instruction(&mut asm, opcode, &operands);
+            },
+            Err(byte) => {
+                asm += &format!("    ; invalid opcode 0x{:X} at 0x{:X}\n", byte, addr);
+            }
+        };
+        // Control flow ends the basic block
+        if let Ok((opcode, _)) = instr {
+            if ends_block(opcode) {
+                block_lines.clear();
+            }
+        }
+    }
+    if let Some(label) = names.labels.get(&(program.code.len() as u64)) {
+        asm += &format!("{}:\n", label);
+    }
+
+    asm
+}
+
+/// Produces a listing of the instructions of a builder, relocations are
+/// printed as jump labels and offsets into the data
+pub fn emit_builder_asm(builder: &Builder) -> String {
+    emit_builder_ir(builder, HashMap::new())
+}
+
+/// Produces a listing of the instructions of a builder like emit_builder_asm,
+/// printing the uids of called functions as their names
+pub fn emit_builder_ir(builder: &Builder, functions: HashMap<u64, String>) -> String {
+    let mut asm = String::from(".code\n");
+
+    let mut fn_labels: Vec<(&usize, &String)> = builder.labels.iter()
+        .map(|(name, pos)| (pos, name))
+        .collect();
+    fn_labels.sort();
+    let jump_labels = builder.get_placed_labels();
+    let names = Names {
+        labels: HashMap::new(),
+        functions
+    };
+
+    let emit_labels = |asm: &mut String, pos: usize| {
+        for (_, name) in fn_labels.iter().filter(|(fn_pos, _)| **fn_pos == pos) {
+            *asm += &format!(".fn {}\n", name);
+        }
+        for (label, _) in jump_labels.iter().filter(|(_, label_pos)| *label_pos == pos) {
+            *asm += &format!("L{}:\n", label.index());
+        }
+    };
+
+    for (i, instruction) in builder.instructions.iter().enumerate() {
+        emit_labels(&mut asm, i);
+        let operands = match decode_operands(&instruction.opcode, &instruction.operands) {
+            Some(operands) => operands,
+            None => {
+                asm += &format!("    ; malformed {:?}\n", instruction.opcode);
+                continue;
+            }
+        };
+        let operands: Vec<String> = operands.iter()
+            .map(|(offset, operand)| match instruction.relocation {
+                Some((reloc_offset, Relocation::Code(label))) if reloc_offset == *offset => format!("L{}", label.index()),
+                Some((reloc_offset, Relocation::Data(data_offset))) if reloc_offset == *offset => format!("data+{}", data_offset),
+                _ => format_operand(operand, &names)
+            })
+            .collect();
+        emit_instruction(&mut asm, &instruction.opcode, &operands);
+    }
+    emit_labels(&mut asm, builder.instructions.len());
+
+    asm
+}
+
+/// Assembles a listing in the format of emit_asm into a program.
+/// Foreign functions are only declared by name, they have to be added to the program afterwards.
+pub fn assemble(asm: &str) -> Result<Program, AsmError> {
+    let lines: Vec<(usize, &str)> = asm.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, strip_comment(line).trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+
+    let mut builder = Builder::new();
+    let mut labels: HashMap<&str, Label> = HashMap::new();
+    let mut functions: HashMap<String, u64> = HashMap::new();
+    let mut uid_generator = UIDGenerator::new();
+
+    // First pass: declare labels and functions, so they can be used before their definition
+    for (line_nr, line) in lines.iter() {
+        if line.starts_with(".fn ") || line.starts_with(".extern ") {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let name = String::from(parts[1]);
+            let uid = match parts.get(2) {
+                Some(uid_raw) => parse_number(uid_raw)
+                    .and_then(|uid| u64::try_from(uid).ok())
+                    .ok_or(AsmError::InvalidOperand(*line_nr, String::from(*uid_raw)))?,
+                None => uid_generator.get_function_uid(&name)
+            };
+            if parts.len() > 3 {
+                return Err(AsmError::InvalidLine(*line_nr, String::from(*line)));
+            }
+            functions.insert(name, uid);
+        } else if let (false, Some(name)) = (line.starts_with('"'), line.strip_suffix(':')) {
+            if !labels.contains_key(name) {
+                labels.insert(name, builder.new_label());
+            }
+        }
+    }
+
+    // Second pass: emit data and instructions
+    let mut data = Vec::new();
+    let mut in_data = false;
+    let mut defined_functions = Vec::new();
+    for (line_nr, line) in lines.iter() {
+        let line_nr = *line_nr;
+        if *line == ".data" {
+            in_data = true;
+        } else if *line == ".code" {
+            in_data = false;
+        } else if line.starts_with('"') {
+            if !in_data {
+                return Err(AsmError::InvalidData(line_nr));
+            }
+            let mut bytes = parse_data_string(line)
+                .ok_or(AsmError::InvalidData(line_nr))?;
+            data.append(&mut bytes);
+        } else if line.starts_with(".fn ") {
+            let name = String::from(line.split_whitespace().nth(1).unwrap());
+            builder.push_label(name.clone());
+            defined_functions.push(name);
+        } else if line.starts_with(".extern ") {
+            // Declared in the first pass
+        } else if let Some(name) = line.strip_suffix(':') {
+            builder.place_label(labels[name]);
+        } else if in_data || line.starts_with('.') {
+            return Err(AsmError::InvalidLine(line_nr, String::from(*line)));
+        } else {
+            let instruction = parse_instruction(line_nr, line, &labels, &functions)?;
+            builder.push_instr(instruction);
+        }
+    }
+
+    let data_len = data.len();
+    let mut program_builder = Program::builder();
+    for name in defined_functions.iter() {
+        let fn_offset = builder.get_label_offset(name)
+            .ok_or(AsmError::Compiler(CompilerError::Unknown))?;
+        program_builder = program_builder.add_function(functions[name], fn_offset + data_len);
+    }
+
+    let mut debug_info = DebugInfo::new();
+    for (name, uid) in functions {
+        debug_info = debug_info.with_function_name(uid, name);
+    }
+
+    let mut code = data;
+    code.append(&mut builder.build_at(data_len, 0).map_err(AsmError::Compiler)?);
+
+    program_builder
+        .code(code)
+        .code_start(data_len)
+        .debug_info(debug_info)
+        .build()
+        .map_err(AsmError::Compiler)
+}
+
+fn ends_block(opcode: &Opcode) -> bool {
+    match opcode {
+        Opcode::JMP |
+        Opcode::JMPT |
+        Opcode::JMPF |
+        Opcode::DJMP |
+        Opcode::DJMPT |
+        Opcode::DJMPF |
+        Opcode::JMP_TBL |
+        Opcode::RET |
+        Opcode::HALT |
+        Opcode::TRAP => true,
+        _ => false
+    }
+}
+
+fn emit_data(asm: &mut String, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    *asm += ".data\n";
+    for chunk in data.chunks(DATA_LINE_LEN) {
+        *asm += "    \"";
+        for byte in chunk {
+            match byte {
+                b'"' => *asm += "\\\"",
+                b'\\' => *asm += "\\\\",
+                b'\n' => *asm += "\\n",
+                b'\t' => *asm += "\\t",
+                0x20..=0x7E => asm.push(*byte as char),
+                _ => *asm += &format!("\\x{:02X}", byte)
+            };
+        }
+        *asm += "\"\n";
+    }
+}
+
+fn emit_instruction(asm: &mut String, opcode: &Opcode, operands: &[String]) {
+    if operands.is_empty() {
+        *asm += &format!("    {:?}\n", opcode);
+    } else {
+        *asm += &format!("    {:?} {}\n", opcode, operands.join(", "));
+    }
+}
+
+/// An opcode with its operands and their byte offsets, or the invalid opcode byte
+type DecodedInstruction = Result<(Opcode, Vec<(usize, Operand)>), u8>;
+
+/// Decodes the instructions of the code starting at code_start.
+/// Decoding stops at the first invalid opcode, which is returned as error.
+fn decode_code(code: &[u8], code_start: usize) -> Vec<(usize, DecodedInstruction)> {
+    decode_instructions(code, code_start)
+        .map(|instruction| match instruction {
+            Ok(instruction) => (instruction.addr, Ok((instruction.opcode, instruction.operands))),
+            Err(DecodeError::InvalidOpcode(addr, byte)) => (addr, Err(byte)),
+            Err(DecodeError::Truncated(addr, opcode)) => (addr, Err(opcode.into()))
+        })
+        .collect()
+}
+
+fn format_operand(operand: &Operand, names: &Names) -> String {
+    match operand {
+        Operand::Register(reg) => Register::from_u8(*reg)
+            .map(|reg| format!("{:?}", reg).to_lowercase())
+            .unwrap_or_else(|| format!("{}", reg)),
+        Operand::Byte(byte) => format!("{}", byte),
+        Operand::Offset(offset) => format!("{}", offset),
+        Operand::Count(count) => format!("{}", count),
+        Operand::Int(int) => format!("{}", int),
+        Operand::Uint(uint) => format!("{}", uint),
+        Operand::Float(float) => format!("{:?}", float),
+        Operand::Double(double) => format!("{:?}", double),
+        Operand::Bool(boolean) => format!("{}", boolean),
+        Operand::CodeAddress(addr) => names.labels.get(addr)
+            .cloned()
+            .unwrap_or_else(|| format!("0x{:X}", addr)),
+        Operand::FunctionUid(uid) => names.functions.get(uid)
+            .cloned()
+            .unwrap_or_else(|| format!("0x{:X}", uid))
+    }
+}
+
+fn parse_instruction(line_nr: usize, line: &str, labels: &HashMap<&str, Label>,
+    functions: &HashMap<String, u64>) -> Result<Instruction, AsmError> {
+    let (mnemonic, rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, "")
+    };
+    let opcode = parse_mnemonic(mnemonic)
+        .ok_or(AsmError::UnknownMnemonic(line_nr, String::from(mnemonic)))?;
+    let layout = opcode_operand_layout(&opcode);
+    let operands: Vec<&str> = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').map(|operand| operand.trim()).collect()
+    };
+    if operands.len() != layout.len() {
+        return Err(AsmError::OperandCount(line_nr, layout.len()));
+    }
+
+    let mut instruction = Instruction::new(opcode);
+    for (kind, operand) in layout.iter().zip(operands) {
+        let invalid = || AsmError::InvalidOperand(line_nr, String::from(operand));
+        let number = parse_number(operand);
+        instruction = match kind {
+            OperandKind::Register => instruction.with_operand::<u8>(parse_register(operand).ok_or_else(invalid)?),
+            OperandKind::Byte => instruction.with_operand::<u8>(number.and_then(|n| u8::try_from(n).ok()).ok_or_else(invalid)?),
+            OperandKind::Offset => instruction.with_operand::<i16>(number.and_then(|n| i16::try_from(n).ok()).ok_or_else(invalid)?),
+            OperandKind::Count => instruction.with_operand::<u32>(number.and_then(|n| u32::try_from(n).ok()).ok_or_else(invalid)?),
+            OperandKind::Int => instruction.with_operand::<i64>(number.and_then(|n| i64::try_from(n).ok()).ok_or_else(invalid)?),
+            OperandKind::Float => instruction.with_operand::<f32>(operand.parse().map_err(|_| invalid())?),
+            OperandKind::Double => instruction.with_operand::<f64>(operand.parse().map_err(|_| invalid())?),
+            OperandKind::Bool => instruction.with_operand::<bool>(operand.parse().map_err(|_| invalid())?),
+            OperandKind::Uint => {
+                if let Some(data_offset) = operand.strip_prefix("data+") {
+                    let data_offset = parse_number(data_offset)
+                        .and_then(|n| u64::try_from(n).ok())
+                        .ok_or_else(invalid)?;
+                    instruction.with_data_operand(data_offset)
+                } else {
+                    instruction.with_operand::<u64>(number.and_then(|n| u64::try_from(n).ok()).ok_or_else(invalid)?)
+                }
+            },
+            OperandKind::CodeAddress => {
+                if let Some(label) = labels.get(operand) {
+                    instruction.with_label_operand(*label)
+                } else {
+                    let addr = number.and_then(|n| u64::try_from(n).ok())
+                        .ok_or(AsmError::UnknownLabel(line_nr, String::from(operand)))?;
+                    instruction.with_operand::<u64>(addr)
+                }
+            },
+            OperandKind::FunctionUid => {
+                let uid = match functions.get(operand) {
+                    Some(uid) => *uid,
+                    None => number.and_then(|n| u64::try_from(n).ok())
+                        .ok_or(AsmError::UnknownFunction(line_nr, String::from(operand)))?
+                };
+                instruction.with_operand::<u64>(uid)
+            }
+        };
+    }
+
+    Ok(instruction)
+}
+
+fn parse_mnemonic(mnemonic: &str) -> Option<Opcode> {
+    (0..=u8::MAX)
+        .filter_map(|byte| Opcode::try_from(byte).ok())
+        .find(|opcode| format!("{:?}", opcode) == mnemonic)
+}
+
+fn parse_register(operand: &str) -> Option<u8> {
+    (0..=u8::MAX)
+        .filter_map(Register::from_u8)
+        .find(|reg| format!("{:?}", reg).to_lowercase() == operand)
+        .map(|reg| reg.into())
+        .or_else(|| parse_number(operand).and_then(|n| u8::try_from(n).ok()))
+}
+
+/// Parses a decimal or "0x" prefixed hexadecimal number
+fn parse_number(text: &str) -> Option<i128> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_data_string(line: &str) -> Option<Vec<u8>> {
+    if line.len() < 2 || !line.ends_with('"') {
+        return None;
+    }
+    let inner = &line[1..line.len() - 1];
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            '"' => bytes.push(b'"'),
+            '\\' => bytes.push(b'\\'),
+            'n' => bytes.push(b'\n'),
+            't' => bytes.push(b'\t'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            },
+            _ => return None
+        };
+    }
+    Some(bytes)
+}
+
+/// Removes a ";" comment, which may not start inside of a data string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_string && !escaped => {
+                escaped = true;
+                continue;
+            },
+            '"' if !escaped => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        };
+        escaped = false;
+    }
+    line
+}
@@ -1,19 +1,25 @@
 use super::{
     instruction::{
         Instruction
+    },
+    asm::emit_builder_asm,
+    compiler::{
+        CompilerResult,
+        CompilerError
     }
 };
-use crate::{
-    vm::{
-        is::Opcode
-    }
-};
-
 use std::{
     collections::{
         HashMap
     },
-    ops::DerefMut
+    ops::DerefMut,
+    convert::TryFrom,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult
+    },
+    error::Error
 };
 
 use serde::{
@@ -21,13 +27,56 @@ use serde::{
 };
 use bincode::serialize;
 
+use crate::vm::is::{
+    Opcode,
+    decode::opcode_operand_layout
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuilderError {
+    /// A named label pushed while a label of the same name exists
+    DuplicateLabel(String),
+    /// Byte offset into raw bytecode of an unknown opcode or an instruction missing operands
+    InvalidRawBytecode(usize)
+}
+
+impl Display for BuilderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            BuilderError::DuplicateLabel(label) => write!(f, "duplicate label {}", label),
+            BuilderError::InvalidRawBytecode(offset) => write!(f, "invalid raw bytecode at byte {}", offset)
+        }
+    }
+}
+
+impl Error for BuilderError {}
+
+/// A jump target inside the code of a builder, resolved to an address on build
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Label(usize);
+
+impl Label {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// An address operand which is only known once the program layout is final
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Relocation {
+    /// Address of a label in the code segment
+    Code(Label),
+    /// Offset into the data segment
+    Data(u64)
+}
+
 #[derive(Clone)]
 pub struct Builder {
     data: Vec<u8>,
     pub instructions: Vec<Instruction>,
-    pub jmp_instructions: Vec<usize>,
     pub labels: HashMap<String, usize>,
-    pub tags: HashMap<u64, Vec<usize>>
+    /// Instruction positions of placed jump labels, indexed by label
+    label_positions: Vec<Option<usize>>
 }
 
 impl Builder {
@@ -36,8 +85,7 @@ impl Builder {
             data: Vec::new(),
             instructions: Vec::new(),
             labels: HashMap::new(),
-            tags: HashMap::new(),
-            jmp_instructions: Vec::new()
+            label_positions: Vec::new()
         }
     }
 
@@ -45,24 +93,30 @@ impl Builder {
         self.labels.insert(label, self.instructions.len());
     }
 
-    pub fn tag(&mut self, tag: u64) {
-        let pos = self.instructions.len();
-        if let Some(tag_list) = self.tags.get_mut(&tag) {
-            if !tag_list.contains(&pos) {
-                tag_list.push(pos);
-            }
-        } else {
-            let mut tag_list = Vec::new();
-            tag_list.push(pos);
-            self.tags.insert(tag, tag_list);
+    /// Pushes a named label, unless a label of the same name exists
+    pub fn push_label_unique(&mut self, label: String) -> Result<(), BuilderError> {
+        if self.labels.contains_key(&label) {
+            return Err(BuilderError::DuplicateLabel(label));
         }
+        self.push_label(label);
+        Ok(())
     }
 
-    pub fn get_tag(&mut self, tag: &u64) -> Option<Vec<usize>> {
-        let pos_list = self.tags.get(tag)
-            .cloned()
-            .or(None)?;
-        Some(pos_list)
+    /// Removes a named label, returns whether it existed.
+    /// The instructions behind it stay in place.
+    pub fn remove_label(&mut self, label: &str) -> bool {
+        self.labels.remove(label).is_some()
+    }
+
+    /// Creates a new, not yet placed jump label
+    pub fn new_label(&mut self) -> Label {
+        self.label_positions.push(None);
+        Label(self.label_positions.len() - 1)
+    }
+
+    /// Places a jump label at the position of the next instruction
+    pub fn place_label(&mut self, label: Label) {
+        self.label_positions[label.0] = Some(self.instructions.len());
     }
 
     pub fn get_instr(&mut self, offset: &usize) -> Option<&mut Instruction> {
@@ -70,11 +124,6 @@ impl Builder {
     }
 
     pub fn push_instr(&mut self, instruction: Instruction) {
-        if instruction.opcode == Opcode::JMP ||
-            instruction.opcode == Opcode::JMPT ||
-            instruction.opcode == Opcode::JMPF {
-            self.jmp_instructions.push(self.instructions.len());
-        }
         self.instructions.push(instruction);
     }
 
@@ -82,12 +131,72 @@ impl Builder {
         self.instructions.append(&mut instructions);
     }
 
+    /// Appends hand-crafted instructions, which have to be complete but are not checked otherwise.
+    /// Nothing is relocated, so code addresses have to be absolute and the registers
+    /// in use by the surrounding code may be overwritten.
+    pub fn push_raw(&mut self, bytes: &[u8]) -> Result<(), BuilderError> {
+        let mut instructions = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let opcode = Opcode::try_from(bytes[offset])
+                .map_err(|_| BuilderError::InvalidRawBytecode(offset))?;
+            let size: usize = opcode_operand_layout(&opcode).iter()
+                .map(|kind| kind.size())
+                .sum();
+            let operands = bytes.get(offset + 1..offset + 1 + size)
+                .ok_or(BuilderError::InvalidRawBytecode(offset))?;
+
+            let mut instruction = Instruction::new(opcode);
+            instruction.operands.extend_from_slice(operands);
+            instructions.push(instruction);
+            offset += 1 + size;
+        }
+
+        self.append_instr(instructions);
+        Ok(())
+    }
+
     pub fn push_data<T: Serialize>(&mut self, data: T) {
         let mut data = serialize(&data).expect("Could not serialize builder data!");
         self.data.append(&mut data);
     }
 
-    pub fn build(mut self) -> Vec<u8> {
+    pub fn build(self) -> CompilerResult<Vec<u8>> {
+        self.build_at(0, 0)
+    }
+
+    /// Builds the code, resolving relocations as if the code was placed at code_base
+    /// and the data segment at data_base
+    pub fn build_at(mut self, code_base: usize, data_base: usize) -> CompilerResult<Vec<u8>> {
+        // First pass: lay out the instructions
+        let mut instr_offsets = Vec::with_capacity(self.instructions.len() + 1);
+        let mut offset = code_base + self.data.len();
+        for instruction in self.instructions.iter() {
+            instr_offsets.push(offset);
+            offset += instruction.get_size();
+        }
+        // A label may be placed behind the last instruction
+        instr_offsets.push(offset);
+
+        // Second pass: apply the relocations
+        for instruction in self.instructions.iter_mut() {
+            let (operand_offset, relocation) = match instruction.relocation {
+                Some(relocation) => relocation,
+                None => continue
+            };
+            let addr = match relocation {
+                Relocation::Code(label) => {
+                    let instr_pos = self.label_positions[label.0]
+                        .ok_or(CompilerError::UnplacedLabel(label.0))?;
+                    instr_offsets[instr_pos] as u64
+                },
+                Relocation::Data(data_offset) => data_base as u64 + data_offset
+            };
+            let addr_bytes = serialize(&addr).expect("Could not serialize relocated address!");
+            instruction.operands[operand_offset..operand_offset + addr_bytes.len()]
+                .copy_from_slice(&addr_bytes);
+        }
+
         let mut code = Vec::new();
 
         code.append(&mut self.data);
@@ -97,7 +206,7 @@ impl Builder {
             code.append(&mut instr_code);
         }
 
-        code
+        Ok(code)
     }
 
     pub fn get_label_offset(&mut self, label: &String) -> Option<usize> {
@@ -111,6 +220,23 @@ impl Builder {
 
         Some(code_before_size)
     }
+    /// Gets all placed jump labels together with their instruction positions
+    pub fn get_placed_labels(&self) -> Vec<(Label, usize)> {
+        self.label_positions.iter()
+            .enumerate()
+            .filter_map(|(i, pos)| pos.map(|pos| (Label(i), pos)))
+            .collect()
+    }
+
+    /// Gets the code offset of a placed jump label
+    pub fn get_jump_label_offset(&self, label: Label) -> Option<usize> {
+        let instr_pos = (*self.label_positions.get(label.0)?)?;
+        let offset = self.instructions[..instr_pos].iter()
+            .map(|instr| instr.get_size())
+            .sum();
+        Some(offset)
+    }
+
     pub fn get_current_offset(&self) -> usize {
         let mut offset = 0;
         for instr in self.instructions.iter() {
@@ -118,4 +244,11 @@ impl Builder {
         }
         offset
     }
+}
+
+/// Lists the instructions with their labels, see emit_builder_asm
+impl Display for Builder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", emit_builder_asm(self))
+    }
 }
\ No newline at end of file
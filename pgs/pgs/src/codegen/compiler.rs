@@ -1,7 +1,8 @@
 use crate::{
     api::{
         module::Module,
-        function::Function
+        function::Function,
+        builtin
     },
     codegen::{
         context::{
@@ -16,8 +17,12 @@ use crate::{
             FunctionDef
         },
         builder::{
-            Builder
+            Builder,
+            BuilderError,
+            Label
         },
+        asm::emit_builder_ir,
+        suggest::suggest_name,
         register::{
             Register
         },
@@ -28,7 +33,13 @@ use crate::{
             Data
         },
         program::{
-            Program
+            Program,
+            DebugInfo,
+            SymbolTable,
+            Symbol,
+            ContainerInfo,
+            MemberInfo,
+            LocalVariable
         }
     },
     parser::{
@@ -37,12 +48,28 @@ use crate::{
             Statement,
             Type,
             Expression,
-            IfStatementArgs
+            IfStatementArgs,
+            FunctionDeclArgs
         }
     },
     vm::{
         is::{
             Opcode
+        },
+        strings::{
+            StringRepr,
+            StringTable
+        },
+        core::{
+            ASSERT_ERR_CODE,
+            ASSERT_MESSAGE_ERR_CODE,
+            SLICE_BOUNDS_ERR_CODE,
+            UNREACHABLE_TRAP_CODE,
+            YIELD_NONE,
+            YIELD_INT,
+            YIELD_FLOAT,
+            YIELD_DOUBLE,
+            YIELD_BOOL
         }
     }
 };
@@ -61,11 +88,15 @@ use std::{
     },
     ops::{
         Deref,
-        DerefMut
+        DerefMut,
+        Range
     },
     collections::{
         BTreeMap
-    }
+    },
+    convert::TryFrom,
+    fs,
+    path::PathBuf
 };
 
 #[derive(Debug, Clone)]
@@ -78,48 +109,294 @@ pub enum CompilerError {
     DuplicateModule(String),
     DuplicateContainer(String),
     DuplicateImport(String),
-    UnknownFunction(String),
+    /// An unknown function, and the closest name in scope if there is one
+    UnknownFunction(String, Option<String>),
     UnknownContainer(String),
-    UnknownVariable(String),
+    /// An unknown variable, and the closest name in scope if there is one
+    UnknownVariable(String, Option<String>),
     UnknownModule(String),
     UnknownType(Type),
-    UnknownMember(String),
+    /// An unknown member, and the closest member of the container if there is one
+    UnknownMember(String, Option<String>),
     UnsupportedExpression(Expression),
     InvalidModulePath(String),
     AlreadyContainsContainer(String),
     AlreadyContainsModule(String),
     NotAMemberFunction(String),
     ArgumentMismatch(String),
+    /// A call, by function name, with another number of arguments than the function takes
+    ArgumentCountMismatch {
+        name: String,
+        expected: usize,
+        found: usize
+    },
     MemberAccessOnNonContainer,
     TypeMismatch(Type, Type),
     CannotDerefNonPointer,
     CannotDerefSlice,
-    RegisterMapping
+    RegisterMapping,
+    InvalidProgram(String),
+    UnplacedLabel(usize),
+    RecursiveType(String),
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    NotCallable(String),
+    /// A variable or argument declared with the zero sized type void
+    VoidVariable(String),
+    /// A comptime_assert whose condition folded to false, with its optional message
+    ComptimeAssertionFailed(Option<String>),
+    /// Indexing into a value which is not an array or a reference to one
+    NotIndexable(Type),
+    /// An internal stack, by description, accessed at an index it does not have
+    InternalIndexOutOfBounds(String, usize),
+    /// A constant index, and the size of the array it is outside of
+    IndexOutOfBounds(i64, usize),
+    /// A reloaded function, by full name, whose arguments or return type differ from the compiled one
+    SignatureChanged(String),
+    /// A variable of the enclosing function used in a local function, which cannot capture it
+    CapturedVariable(String),
+    DuplicateConstant(String),
+    /// A constant, by name, whose value is not known at compile time
+    NonConstantExpression(String),
+    /// The size of an array at the span is not known at compile time
+    NonConstantArraySize(Range<usize>),
+    /// The size of an array at the span is negative or larger than MAX_ARRAY_SIZE
+    InvalidArraySize(i64, Range<usize>),
+    /// A value which does not fit into its instruction operand, named by the construct it belongs to
+    OperandOverflow {
+        what: String,
+        value: i64,
+        max: i64
+    },
+    /// A file of include_str or include_bytes, with the span of the include, which could not be included
+    IncludeFailed {
+        path: String,
+        span: Range<usize>,
+        reason: String
+    },
+    /// An unqualified function name, and the full names of all functions it could refer to
+    AmbiguousFunction(String, Vec<String>),
+    /// A callback function, by full name, with an argument or return type which is no int, float or bool
+    InvalidCallbackType(String, Type),
+    Builder(BuilderError)
+}
+
+/// A problem in a program which still compiles
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompilerWarning {
+    /// A variable which is assigned but whose value is never read
+    WriteOnlyVariable(String),
+    /// A `while true` loop without a break or return, in the given function
+    InfiniteLoop(String)
+}
+
+impl Display for CompilerWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            CompilerWarning::WriteOnlyVariable(name) => write!(f, "variable {} is assigned but never read", name),
+            CompilerWarning::InfiniteLoop(name) => write!(f, "loop in function {} never exits, mark it with @infinite if this is intended", name)
+        }
+    }
 }
 
 impl Display for CompilerError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{:?}", self)
+        match self {
+            CompilerError::BreakOutsideLoop => write!(f, "break outside loop"),
+            CompilerError::ContinueOutsideLoop => write!(f, "continue outside loop"),
+            CompilerError::VoidVariable(name) => write!(f, "variable {} cannot be of type void", name),
+            CompilerError::ComptimeAssertionFailed(Some(message)) => write!(f, "comptime assertion failed: {}", message),
+            CompilerError::ComptimeAssertionFailed(None) => write!(f, "comptime assertion failed"),
+            CompilerError::NotIndexable(var_type) => write!(f, "cannot index into {}", var_type),
+            CompilerError::TypeMismatch(lhs_type, rhs_type) => {
+                write!(f, "mismatched types {} and {}", lhs_type, rhs_type)
+            },
+            CompilerError::InternalIndexOutOfBounds(desc, index) => {
+                write!(f, "internal compiler error: index {} out of bounds of {}", index, desc)
+            },
+            CompilerError::IndexOutOfBounds(index, size) => {
+                write!(f, "index {} is out of bounds of an array of size {}", index, size)
+            },
+            CompilerError::Builder(err) => write!(f, "{}", err),
+            CompilerError::SignatureChanged(name) => {
+                write!(f, "signature of {} changed, its callers were compiled against the old one", name)
+            },
+            CompilerError::ArgumentCountMismatch { name, expected, found } => {
+                write!(f, "{} takes {} arguments, but was called with {}", name, expected, found)
+            },
+            CompilerError::IncludeFailed { path, span, reason } => {
+                write!(f, "cannot include {} at {}..{}: {}", path, span.start, span.end, reason)
+            },
+            CompilerError::NonConstantArraySize(span) => {
+                write!(f, "size of the array at {}..{} is not a constant", span.start, span.end)
+            },
+            CompilerError::InvalidArraySize(size, span) => {
+                write!(f, "invalid size {} of the array at {}..{}", size, span.start, span.end)
+            },
+            CompilerError::OperandOverflow { what, value, max } => {
+                write!(f, "{} of {} exceeds the maximum of {} an instruction can encode", what, value, max)
+            },
+            CompilerError::UnknownFunction(name, suggestion) => {
+                write!(f, "unknown function '{}'", name)?;
+                write_suggestion(f, suggestion)
+            },
+            CompilerError::UnknownVariable(name, suggestion) => {
+                write!(f, "unknown variable '{}'", name)?;
+                write_suggestion(f, suggestion)
+            },
+            CompilerError::UnknownMember(name, suggestion) => {
+                write!(f, "unknown member '{}'", name)?;
+                write_suggestion(f, suggestion)
+            },
+            CompilerError::AmbiguousFunction(name, candidates) => {
+                write!(f, "function {} is ambiguous, it could be any of {}", name, candidates.join(", "))
+            },
+            CompilerError::InvalidCallbackType(name, value_type) => {
+                write!(f, "callback {} cannot pass a value of type {}", name, value_type)
+            },
+            _ => write!(f, "{:?}", self)
+        }
     }
 }
 
 impl Error for CompilerError {}
 
+/// Appends the suggestion for an unknown name to its error message
+fn write_suggestion(f: &mut Formatter<'_>, suggestion: &Option<String>) -> FmtResult {
+    match suggestion {
+        Some(suggestion) => write!(f, "; did you mean '{}'?", suggestion),
+        None => Ok(())
+    }
+}
+
+/// Writes the code addresses of the jump table entries into the data, the code of the builder starts at code_base
+fn patch_jump_tables(jump_tables: &[(u64, Vec<Label>)], builder: &Builder, data: &mut [u8], code_base: usize) -> CompilerResult<()> {
+    for (table_offset, entries) in jump_tables.iter() {
+        for (index, label) in entries.iter().enumerate() {
+            let target = builder.get_jump_label_offset(*label)
+                .ok_or(CompilerError::UnplacedLabel(label.index()))? + code_base;
+            let entry_offset = *table_offset as usize + index * 8;
+            data[entry_offset..entry_offset + 8].copy_from_slice(&(target as u64).to_le_bytes());
+        }
+    }
+    Ok(())
+}
+
 /// Convenience type for Results returned by a compilation process
 pub type CompilerResult<T> = Result<T, CompilerError>;
 
+/// Converts an address offset into an i16 operand
+fn offset_operand(what: &str, offset: i64) -> CompilerResult<i16> {
+    i16::try_from(offset).map_err(|_| CompilerError::OperandOverflow {
+        what: String::from(what),
+        value: offset,
+        max: i16::MAX as i64
+    })
+}
+
+/// Converts a size into the i16 operand of the offset size bytes below an address
+fn neg_offset_operand(what: &str, size: usize) -> CompilerResult<i16> {
+    let max = -(i16::MIN as i64);
+    if size as u64 > max as u64 {
+        return Err(CompilerError::OperandOverflow {
+            what: String::from(what),
+            value: size as i64,
+            max
+        });
+    }
+    Ok((-(size as i64)) as i16)
+}
+
+/// Converts a byte count into a u32 operand
+fn size_operand(what: &str, size: usize) -> CompilerResult<u32> {
+    u32::try_from(size).map_err(|_| CompilerError::OperandOverflow {
+        what: String::from(what),
+        value: size as i64,
+        max: u32::MAX as i64
+    })
+}
+
+/// Checks that a recompiled function takes and returns the same types as before,
+/// only the argument names may change
+fn check_signature_unchanged(fn_def: &FunctionDef, old_fn_def: &FunctionDef, full_fn_name: &str) -> CompilerResult<()> {
+    let args_eq = fn_def.arguments.iter()
+        .map(|(_, arg_type)| arg_type)
+        .eq(old_fn_def.arguments.iter().map(|(_, arg_type)| arg_type));
+    if !args_eq || fn_def.ret_type != old_fn_def.ret_type {
+        return Err(CompilerError::SignatureChanged(String::from(full_fn_name)));
+    }
+    Ok(())
+}
+
+/// Error for a missing function context, which is always a bug in the compiler
+fn fn_stack_error(index: usize) -> CompilerError {
+    CompilerError::InternalIndexOutOfBounds(String::from("fn_context_stack"), index)
+}
+
+/// Error for a missing module context, which is always a bug in the compiler
+fn mod_stack_error(index: usize) -> CompilerError {
+    CompilerError::InternalIndexOutOfBounds(String::from("mod_context_stack"), index)
+}
+
+/// Name of the function a standalone expression is compiled into, see Compiler::compile_expression
+pub const EXPRESSION_FN_NAME: &str = "__expression";
+
+/// Scope local functions are declared in, inside of the name of their enclosing function
+pub const LOCAL_FN_SCOPE: &str = "__local";
+
+/// Largest number of elements of an array whose size is a constant expression
+pub const MAX_ARRAY_SIZE: usize = 1 << 24;
+
+/// Least number of branches of an if/else if chain which is compiled into a jump table
+pub const JUMP_TABLE_MIN_BRANCHES: usize = 4;
+
+/// Default largest count of an array repeat initializer which is filled by a store per element,
+/// larger arrays are filled in a loop
+pub const ARRAY_UNROLL_LIMIT: usize = 16;
+
 /// The compiler
 pub struct Compiler {
     fn_context_stack: VecDeque<FunctionContext>,
     mod_context_stack: VecDeque<ModuleContext>,
     loop_ctx_stack: VecDeque<LoopContext>,
+    /// Data offsets of the jump tables with the labels of their entries, filled in once the program layout is known
+    jump_tables: Vec<(u64, Vec<Label>)>,
     fn_uid_map: HashMap<String, u64>,
+    /// Definitions of invalidated functions by full name, until they are recompiled
+    stale_functions: HashMap<String, FunctionDef>,
     foreign_functions: Option<HashMap<u64, Function>>,
     foreign_function_uids: HashSet<u64>,
+    /// Uids of the foreign functions which are called with CALL_CALLBACK
+    callback_uids: HashSet<u64>,
     uid_generator: UIDGenerator,
     builder: Builder,
     current_cont: Option<String>,
-    data: Data
+    data: Data,
+    debug_symbols: bool,
+    /// Floats are 64 bit wide and use the double precision opcodes if enabled
+    f64_floats: bool,
+    string_repr: StringRepr,
+    /// Strings of the program, interned by the core on load with StringRepr::Handle
+    strings: StringTable,
+    /// Asserts compile to nothing if disabled
+    asserts: bool,
+    /// Largest count of an array repeat initializer which is unrolled
+    array_unroll_limit: usize,
+    /// Flags of conditional compilation, code gated by any other flag is skipped
+    defines: HashSet<String>,
+    /// Directory included files are resolved against, they cannot be outside of it
+    include_root: Option<PathBuf>,
+    /// Whether values live across a call are kept in callee-saved registers instead of the stack
+    callee_saved_temps: bool,
+    /// Size of the arguments of each compiled script function by uid, see Program::arg_sizes
+    arg_sizes: HashMap<u64, usize>,
+    /// Source spans of the compiled functions, only recorded with debug symbols
+    fn_spans: HashMap<String, Range<usize>>,
+    /// Source lines of the compiled statements by code offset, only recorded with debug symbols
+    line_table: BTreeMap<usize, usize>,
+    /// Variables in scope at the start of each function and statement by code offset, only recorded with debug symbols
+    locals_table: BTreeMap<usize, Vec<LocalVariable>>,
+    warnings: Vec<CompilerWarning>
 }
 
 impl Compiler {
@@ -128,18 +405,130 @@ impl Compiler {
         let root_mod_ctx = ModuleContext::new(String::from("root"));
         let mut mod_context_stack = VecDeque::new();
         mod_context_stack.push_front(root_mod_ctx);
-        Compiler {
+        let mut compiler = Compiler {
             fn_context_stack: VecDeque::new(),
             mod_context_stack: mod_context_stack,
             loop_ctx_stack: VecDeque::new(),
+            jump_tables: Vec::new(),
             fn_uid_map: HashMap::new(),
+            stale_functions: HashMap::new(),
             foreign_functions: Some(HashMap::new()),
             foreign_function_uids: HashSet::new(),
+            callback_uids: HashSet::new(),
             uid_generator: UIDGenerator::new(),
             builder: Builder::new(),
             current_cont: None,
-            data: Data::new()
+            data: Data::new(),
+            debug_symbols: false,
+            f64_floats: false,
+            string_repr: StringRepr::FatPointer,
+            strings: StringTable::new(),
+            asserts: true,
+            array_unroll_limit: ARRAY_UNROLL_LIMIT,
+            defines: HashSet::new(),
+            include_root: None,
+            callee_saved_temps: false,
+            arg_sizes: HashMap::new(),
+            fn_spans: HashMap::new(),
+            line_table: BTreeMap::new(),
+            locals_table: BTreeMap::new(),
+            warnings: Vec::new()
+        };
+        let builtin_mod_ctx = compiler.builtin_module_context(builtin::builtin_module(), "root::");
+        if let Some(root_mod_ctx) = compiler.mod_context_stack.front_mut() {
+            root_mod_ctx.modules.insert(builtin_mod_ctx.name.clone(), builtin_mod_ctx);
         }
+        compiler
+    }
+
+    /// Enables emitting a symbol table with the program, builder style
+    pub fn with_debug_symbols(mut self, debug_symbols: bool) -> Compiler {
+        self.debug_symbols = debug_symbols;
+        self
+    }
+
+    /// Compiles floats as 64 bit instead of 32 bit values, builder style
+    pub fn with_f64_floats(mut self, f64_floats: bool) -> Compiler {
+        self.f64_floats = f64_floats;
+        self
+    }
+
+    /// Sets how strings are represented, builder style
+    pub fn with_string_repr(mut self, string_repr: StringRepr) -> CompilerResult<Compiler> {
+        self.set_string_repr(string_repr)?;
+        Ok(self)
+    }
+
+    /// Sets how strings are represented, before anything is compiled.
+    /// The argument layouts of the foreign functions registered so far are updated.
+    pub fn set_string_repr(&mut self, string_repr: StringRepr) -> CompilerResult<()> {
+        self.string_repr = string_repr;
+        let mut foreign_functions = self.foreign_functions.take()
+            .ok_or(CompilerError::Unknown)?;
+        let layout_res = foreign_functions.values_mut()
+            .try_for_each(|function| self.set_foreign_arg_layout(function));
+        self.foreign_functions = Some(foreign_functions);
+        layout_res
+    }
+
+    /// Enables or disables the compilation of asserts, builder style
+    pub fn with_asserts(mut self, asserts: bool) -> Compiler {
+        self.asserts = asserts;
+        self
+    }
+
+    /// Sets the largest count of an array repeat initializer which is unrolled, builder style.
+    /// Larger arrays are filled in a loop, a limit of 0 always uses the loop.
+    pub fn with_array_unroll_limit(mut self, array_unroll_limit: usize) -> Compiler {
+        self.array_unroll_limit = array_unroll_limit;
+        self
+    }
+
+    /// Keeps values which have to survive a call in the callee-saved registers,
+    /// instead of spilling them to the stack, builder style.
+    /// The callee-saved registers are then no longer used as temporary registers.
+    pub fn with_callee_saved_temps(mut self, callee_saved_temps: bool) -> Compiler {
+        self.callee_saved_temps = callee_saved_temps;
+        self
+    }
+
+    /// Checks if values live across calls are kept in callee-saved registers
+    pub fn has_callee_saved_temps(&self) -> bool {
+        self.callee_saved_temps
+    }
+
+    /// Defines a flag, which enables the code gated by "@if flag { ... }", builder style
+    pub fn with_define<T>(mut self, flag: T) -> Compiler
+        where String: From<T> {
+        self.defines.insert(String::from(flag));
+        self
+    }
+
+    /// Sets the directory files are included from, builder style
+    pub fn with_include_root<P: Into<PathBuf>>(mut self, include_root: P) -> Compiler {
+        self.set_include_root(include_root);
+        self
+    }
+
+    /// Sets the directory files are included from.
+    /// Without one, include_str and include_bytes fail to compile.
+    pub fn set_include_root<P: Into<PathBuf>>(&mut self, include_root: P) {
+        self.include_root = Some(include_root.into());
+    }
+
+    /// Checks if a directory to include files from is set
+    pub fn has_include_root(&self) -> bool {
+        self.include_root.is_some()
+    }
+
+    /// Checks if a flag of conditional compilation is defined
+    pub fn is_defined(&self, flag: &str) -> bool {
+        self.defines.contains(flag)
+    }
+
+    /// Gets the warnings of everything compiled so far
+    pub fn get_warnings(&self) -> &[CompilerWarning] {
+        &self.warnings
     }
 
     /// Retrieves a reference to the underlying builder
@@ -147,53 +536,368 @@ impl Compiler {
         &self.builder
     }
 
+    /// Lists the instructions compiled so far, before they are built into bytecode.
+    /// Every function starts with a .fn line, jumps go to symbolic labels and calls name their function.
+    pub fn dump_ir(&self) -> String {
+        let functions = self.fn_uid_map.iter()
+            .map(|(fn_name, fn_uid)| (*fn_uid, fn_name.clone()))
+            .collect();
+        emit_builder_ir(&self.builder, functions)
+    }
+
     /// Retrieves the program instance compiled by this compiler instance.
+    /// Checks that every declared script function has been compiled to a label.
+    /// Returns the checked function names, sorted.
+    pub fn validate_labels(&self) -> CompilerResult<Vec<String>> {
+        let mut checked = Vec::new();
+        let mut missing = Vec::new();
+        for (fn_name, fn_uid) in self.fn_uid_map.iter() {
+            if self.is_function_foreign(*fn_uid)? {
+                continue;
+            }
+            if self.builder.labels.contains_key(fn_name) {
+                checked.push(fn_name.clone());
+            } else {
+                missing.push(fn_name.clone());
+            }
+        }
+
+        if !missing.is_empty() {
+            missing.sort();
+            return Err(CompilerError::UnknownFunction(missing.join(", "), None));
+        }
+        checked.sort();
+        Ok(checked)
+    }
+
     pub fn get_program(&mut self) -> CompilerResult<Program> {
+        self.validate_labels()?;
         let mut builder = self.builder.clone();
         let data = self.data.clone();
         let data_len = data.bytes.len();
 
-        // Modify target jump addresses of JMP instructions accordingly 
-        for offset in builder.jmp_instructions.clone().iter() {
-            let instr = builder.get_instr(offset)
-                .ok_or(CompilerError::Unknown)?;
-            let addr: u64 = match instr.opcode {
-                Opcode::JMP => instr.get_operand(0, 8),
-                Opcode::JMPF => instr.get_operand(1, 8),
-                Opcode::JMPT => instr.get_operand(1, 8),
-                _ => return Err(CompilerError::Unknown)
-            };
-            instr.remove_operand_bytes(8);
-            instr.append_operand(addr + data_len as u64);
-        }
-
-        let mut functions: HashMap<u64, usize> = HashMap::new();
+        let mut program_builder = Program::builder();
+        let mut debug_info = DebugInfo::new();
 
-        // correctly set function offsets
-        for (fn_name, fn_uid) in self.fn_uid_map.iter() {
+        // correctly set function offsets, in name order for reproducible output
+        let mut fn_uids: Vec<(&String, &u64)> = self.fn_uid_map.iter().collect();
+        fn_uids.sort();
+        for (fn_name, fn_uid) in fn_uids {
+            debug_info = debug_info.with_function_name(*fn_uid, fn_name.clone());
             if self.is_function_foreign(*fn_uid)? {
                 continue;
             }
             let fn_offset = builder.get_label_offset(fn_name)
                 .ok_or(CompilerError::Unknown)?;
-            functions.insert(fn_uid.clone(), fn_offset + data_len);
+            program_builder = program_builder.add_function(*fn_uid, fn_offset + data_len);
+        }
+
+        // Copy the definitions out of the module tree, so the program can be inspected on its own
+        let mut fn_defs = Vec::new();
+        let mut containers = Vec::new();
+        self.collect_module_info(&mut fn_defs, &mut containers)?;
+        for fn_def in fn_defs {
+            debug_info = debug_info.with_function_def(fn_def);
+        }
+        for container in containers {
+            debug_info = debug_info.with_container(container);
+        }
+        for (offset, string) in data.get_strings() {
+            debug_info = debug_info.with_data_string(offset, string);
         }
 
         let foreign_functions = self.foreign_functions.take()
             .ok_or(CompilerError::Unknown)?;
+        // Laid out again, the string repr and the float size may have changed since registering
+        for (fn_uid, mut function) in foreign_functions {
+            self.set_foreign_arg_layout(&mut function)?;
+            program_builder = program_builder.add_foreign_function(fn_uid, function);
+        }
 
+        if self.debug_symbols {
+            let symbol_table = self.get_symbol_table(&mut builder, data_len)?;
+            program_builder = program_builder.symbol_table(symbol_table);
+            for (offset, line) in self.line_table.iter() {
+                debug_info = debug_info.with_line(offset + data_len, *line);
+            }
+            for (offset, locals) in self.locals_table.iter() {
+                debug_info = debug_info.with_locals(offset + data_len, locals.clone());
+            }
+        }
 
         let mut code = data.bytes;
-        let mut builder_code = builder.build();
+        patch_jump_tables(&self.jump_tables, &builder, &mut code, data_len)?;
+        // The data is placed at the start of the program, the code behind it
+        let mut builder_code = builder.build_at(data_len, 0)?;
         //println!("Data length: {}", code.len());
         code.append(&mut builder_code);
 
-        let program = Program::new()
-            .with_code(code)
-            .with_functions(functions)
-            .with_foreign_functions(foreign_functions);
-        
-        Ok(program)
+        program_builder
+            .code(code)
+            .code_start(data_len)
+            .debug_info(debug_info)
+            .strings(self.string_repr, self.strings.strings().to_vec())
+            .saves_callee_registers(self.callee_saved_temps)
+            .arg_sizes(self.arg_sizes.clone())
+            .build()
+    }
+
+    /// Collects the function definitions and container layouts of the current module and its submodules
+    fn collect_module_info(&mut self, fn_defs: &mut Vec<FunctionDef>, containers: &mut Vec<ContainerInfo>) -> CompilerResult<()> {
+        let mod_ctx = self.get_current_module()?.clone();
+        fn_defs.extend(mod_ctx.functions.values().cloned());
+
+        for cont_def in mod_ctx.containers.values() {
+            fn_defs.extend(cont_def.member_functions.values().cloned());
+
+            let mut members = Vec::new();
+            let mut member_names: Vec<(&String, &usize)> = cont_def.member_indices.iter().collect();
+            member_names.sort_by_key(|(_, index)| **index);
+            for (member_name, _) in member_names {
+                // Member types are resolved relative to the module of the container
+                let (offset, member_type) = cont_def.member_offset(self, member_name)?;
+                let size = self.get_size_of_type(&member_type)?;
+                members.push(MemberInfo {
+                    name: member_name.clone(),
+                    member_type: member_type,
+                    offset: offset,
+                    size: size
+                });
+            }
+            containers.push(ContainerInfo {
+                name: cont_def.canonical_name.clone(),
+                members: members,
+                size: cont_def.get_size(self)?
+            });
+        }
+
+        for (_, sub_mod_ctx) in mod_ctx.modules {
+            self.push_module_context(sub_mod_ctx);
+            let collect_res = self.collect_module_info(fn_defs, containers);
+            self.pop_module_context()?;
+            collect_res?;
+        }
+
+        Ok(())
+    }
+
+    /// Collects the code ranges of all compiled functions.
+    /// A function ranges up to the start of the next one.
+    fn get_symbol_table(&self, builder: &mut Builder, data_len: usize) -> CompilerResult<SymbolTable> {
+        let mut fn_starts = Vec::new();
+        for (fn_name, source_span) in self.fn_spans.iter() {
+            let fn_offset = builder.get_label_offset(fn_name)
+                .ok_or(CompilerError::Unknown)?;
+            fn_starts.push((fn_offset + data_len, fn_name, source_span));
+        }
+        fn_starts.sort_by_key(|(fn_start, _, _)| *fn_start);
+
+        let code_end = data_len + builder.get_current_offset();
+        let mut symbol_table = SymbolTable::new();
+        for (i, (fn_start, fn_name, source_span)) in fn_starts.iter().enumerate() {
+            let fn_end = fn_starts.get(i + 1)
+                .map(|(next_start, _, _)| *next_start)
+                .unwrap_or(code_end);
+            symbol_table = symbol_table.with_symbol(Symbol {
+                function_name: (*fn_name).clone(),
+                code_range: *fn_start..fn_end,
+                source_span: (*source_span).clone()
+            });
+        }
+        Ok(symbol_table)
+    }
+
+    /// Recompiles script functions of a program compiled by this compiler and appends their code to it.
+    /// The next call of a function runs the new code, frames still in the old code return into it.
+    /// Only the bodies may change, callers were compiled against the old signatures.
+    pub fn reload_decl_list(&mut self, decl_list: &[Declaration], program: &mut Program) -> CompilerResult<()> {
+        for decl in decl_list.iter() {
+            match decl {
+                Declaration::Function(_) => self.reload_fn_decl(decl, program)?,
+                Declaration::Impl(impl_type, impl_for, impl_decl_list) if impl_type == impl_for => {
+                    self.current_cont = Some(impl_type.clone());
+                    let reload_res = self.reload_decl_list(impl_decl_list, program);
+                    self.current_cont = None;
+                    reload_res?;
+                },
+                Declaration::Cfg(flag, cfg_decl_list) => {
+                    if self.is_defined(flag) {
+                        self.reload_decl_list(cfg_decl_list, program)?;
+                    }
+                },
+                _ => return Err(CompilerError::Unimplemented(format!("Only functions and impls can be reloaded!")))
+            };
+        }
+        Ok(())
+    }
+
+    /// Recompiles a single script function into its own code and data, appended to the program
+    fn reload_fn_decl(&mut self, decl: &Declaration, program: &mut Program) -> CompilerResult<()> {
+        let fn_decl_args = match decl {
+            Declaration::Function(fn_decl_args) => fn_decl_args,
+            _ => return Err(CompilerError::Unknown)
+        };
+
+        let mut full_fn_name = self.get_module_path();
+        if let Some(cont_name) = self.current_cont.as_ref() {
+            full_fn_name += cont_name;
+            full_fn_name += "::";
+        }
+        full_fn_name += &fn_decl_args.name;
+
+        let old_fn_def = match self.current_cont.as_ref() {
+            Some(cont_name) => self.resolve_container(cont_name)?
+                .get_member_function(&fn_decl_args.name)?
+                .clone(),
+            None => self.get_current_module()?
+                .get_function(&fn_decl_args.name)?
+                .clone()
+        };
+        if fn_decl_args.code_block.is_none() || self.is_function_foreign(old_fn_def.uid)? {
+            return Err(CompilerError::NotCallable(full_fn_name));
+        }
+
+        let mut fn_def = FunctionDef::from(fn_decl_args)
+            .with_uid(old_fn_def.uid);
+        for (_, arg_type) in fn_def.arguments.iter_mut() {
+            self.canonize_type(arg_type)?;
+        }
+        self.resolve_array_sizes(&mut fn_def.ret_type)?;
+        check_signature_unchanged(&fn_def, &old_fn_def, &full_fn_name)?;
+
+        // Argument names may change, the body is compiled against the new ones
+        match self.current_cont.clone() {
+            Some(cont_name) => {
+                self.get_current_module_mut()?
+                    .get_container_mut(&cont_name)?
+                    .member_functions.insert(fn_def.name.clone(), fn_def);
+            },
+            None => {
+                self.get_current_module_mut()?
+                    .functions.insert(fn_def.name.clone(), fn_def);
+            }
+        };
+
+        // Compile into an empty builder and data, which are placed behind the current end of the program
+        let builder = std::mem::replace(&mut self.builder, Builder::new());
+        let data = std::mem::replace(&mut self.data, Data::new());
+        let line_table = std::mem::take(&mut self.line_table);
+        let locals_table = std::mem::take(&mut self.locals_table);
+        let jump_tables_len = self.jump_tables.len();
+        let compile_res = self.compile_fn_decl(decl);
+        let mut fn_builder = std::mem::replace(&mut self.builder, builder);
+        let mut fn_data = std::mem::replace(&mut self.data, data);
+        let fn_line_table = std::mem::replace(&mut self.line_table, line_table);
+        let fn_locals_table = std::mem::replace(&mut self.locals_table, locals_table);
+        let fn_jump_tables = self.jump_tables.split_off(jump_tables_len);
+        compile_res?;
+
+        // The cores running the program only know the strings it was loaded with
+        if self.strings.len() > program.strings.len() {
+            self.strings = StringTable::from(program.strings.as_slice());
+            return Err(CompilerError::Unimplemented(String::from("Reloading functions with new strings represented by handles")));
+        }
+
+        let data_base = program.code.len();
+        let code_base = data_base + fn_data.bytes.len();
+        patch_jump_tables(&fn_jump_tables, &fn_builder, &mut fn_data.bytes, code_base)?;
+        let fn_offset = fn_builder.get_label_offset(&full_fn_name)
+            .ok_or(CompilerError::Unknown)? + code_base;
+        let mut fn_code = fn_builder.build_at(code_base, data_base)?;
+        program.code.extend_from_slice(&fn_data.bytes);
+        program.code.append(&mut fn_code);
+
+        if self.debug_symbols {
+            for (offset, line) in fn_line_table.iter() {
+                program.debug_info.line_table.insert(offset + code_base, *line);
+            }
+            for (offset, locals) in fn_locals_table {
+                program.debug_info.locals.insert(offset + code_base, locals);
+            }
+            if let Some(symbol_table) = program.symbol_table.take() {
+                program.symbol_table = Some(symbol_table.with_symbol(Symbol {
+                    function_name: full_fn_name.clone(),
+                    code_range: fn_offset..program.code.len(),
+                    source_span: fn_decl_args.span.clone()
+                }));
+            }
+        }
+
+        program.functions.insert(old_fn_def.uid, fn_offset);
+        Ok(())
+    }
+
+    /// Invalidates a compiled script function of the current module by full name, see recompile_function.
+    /// Its label and definition are removed, its uid stays reserved for the recompiled function.
+    pub fn invalidate_function(&mut self, full_name: &str) -> CompilerResult<()> {
+        let fn_uid = *self.fn_uid_map.get(full_name)
+            .ok_or_else(|| CompilerError::UnknownFunction(String::from(full_name), None))?;
+        if self.is_function_foreign(fn_uid)? {
+            return Err(CompilerError::NotCallable(String::from(full_name)));
+        }
+        let module_path = self.get_module_path();
+        let fn_name = match full_name.strip_prefix(&module_path) {
+            Some(fn_name) if !fn_name.contains("::") => String::from(fn_name),
+            _ => return Err(CompilerError::Unimplemented(format!("Only functions of the current module can be invalidated!")))
+        };
+
+        let fn_def = self.get_current_module_mut()?
+            .functions.remove(&fn_name)
+            .ok_or_else(|| CompilerError::UnknownFunction(String::from(full_name), None))?;
+        self.builder.remove_label(full_name);
+        self.fn_spans.remove(full_name);
+        // Local functions are declared again with the function
+        let local_prefix = format!("{}::{}::", fn_name, LOCAL_FN_SCOPE);
+        let local_fn_names: Vec<String> = self.get_current_module()?
+            .functions.keys()
+            .filter(|name| name.starts_with(&local_prefix))
+            .cloned()
+            .collect();
+        for local_fn_name in local_fn_names {
+            self.get_current_module_mut()?.functions.remove(&local_fn_name);
+            let full_local_fn_name = module_path.clone() + &local_fn_name;
+            self.builder.remove_label(&full_local_fn_name);
+            self.fn_spans.remove(&full_local_fn_name);
+        }
+        self.stale_functions.insert(String::from(full_name), fn_def);
+        Ok(())
+    }
+
+    /// Declares and compiles a function of the current module, usually one invalidated before.
+    /// The code is appended to the builder, the old code stays in place but is no longer reachable.
+    /// Callers are not recompiled, an invalidated function has to keep its signature.
+    pub fn recompile_function(&mut self, decl: &Declaration) -> CompilerResult<()> {
+        let fn_decl_args = match decl {
+            Declaration::Function(fn_decl_args) => fn_decl_args,
+            _ => return Err(CompilerError::Unimplemented(format!("Only functions can be recompiled!")))
+        };
+        let full_fn_name = self.get_module_path() + &fn_decl_args.name;
+
+        // The uid is derived from the full name, so callers still find the function
+        self.declare_fn_decl(decl)?;
+        let fn_def = self.get_current_module()?
+            .get_function(&fn_decl_args.name)?
+            .clone();
+        let compile_res = match self.stale_functions.get(&full_fn_name) {
+            Some(old_fn_def) => check_signature_unchanged(&fn_def, old_fn_def, &full_fn_name)
+                .and_then(|_| self.compile_fn_decl(decl)),
+            None => self.compile_fn_decl(decl)
+        };
+
+        match compile_res {
+            Ok(()) => {
+                self.stale_functions.remove(&full_fn_name);
+                Ok(())
+            },
+            Err(err) => {
+                // The function stays invalidated
+                let fn_def = self.get_current_module_mut()?
+                    .functions.remove(&fn_decl_args.name)
+                    .ok_or(CompilerError::Unknown)?;
+                self.stale_functions.entry(full_fn_name).or_insert(fn_def);
+                Err(err)
+            }
+        }
     }
 
     // #region helpers
@@ -211,37 +915,39 @@ impl Compiler {
     /// Gets the current module context (the one at the top of the stack)
     pub fn get_current_module(&self) -> CompilerResult<&ModuleContext> {
         self.mod_context_stack.get(0)
-            .ok_or(CompilerError::Unknown)
+            .ok_or_else(|| mod_stack_error(0))
     }
 
     /// Gets the root module context (mutable)
     pub fn get_root_module_mut(&mut self) -> CompilerResult<&mut ModuleContext> {
-        self.mod_context_stack.get_mut(self.mod_context_stack.len() - 1)
-            .ok_or(CompilerError::Unknown)
+        let root_index = self.mod_context_stack.len().saturating_sub(1);
+        self.mod_context_stack.back_mut()
+            .ok_or_else(|| mod_stack_error(root_index))
     }
 
     /// Gets the root module context
     pub fn get_root_module(&self) -> CompilerResult<&ModuleContext> {
-        self.mod_context_stack.get(self.mod_context_stack.len() - 1)
-            .ok_or(CompilerError::Unknown)
+        let root_index = self.mod_context_stack.len().saturating_sub(1);
+        self.mod_context_stack.back()
+            .ok_or_else(|| mod_stack_error(root_index))
     } 
 
     /// Gets the current module context (the one at the top of the stack) as a mutable reference
     pub fn get_current_module_mut(&mut self) -> CompilerResult<&mut ModuleContext> {
         self.mod_context_stack.get_mut(0)
-            .ok_or(CompilerError::Unknown)
+            .ok_or_else(|| mod_stack_error(0))
     }
 
     /// Gets the current function context as a reference
     pub fn get_current_function(&self) -> CompilerResult<&FunctionContext> {
         self.fn_context_stack.get(0)
-            .ok_or(CompilerError::Unknown)
+            .ok_or_else(|| fn_stack_error(0))
     }
 
     /// Gets the current function context as a mutable reference
     pub fn get_current_function_mut(&mut self) -> CompilerResult<&mut FunctionContext> {
         self.fn_context_stack.get_mut(0)
-            .ok_or(CompilerError::Unknown)
+            .ok_or_else(|| fn_stack_error(0))
     }
 
     /// Gets the next temporary register from the current context
@@ -265,7 +971,7 @@ impl Compiler {
     /// Gets the function context at stack index
     pub fn get_function(&self, index: usize) -> CompilerResult<&FunctionContext> {
         self.fn_context_stack.get(index)
-            .ok_or(CompilerError::Unknown)
+            .ok_or_else(|| fn_stack_error(index))
     }
 
     /// Returns true if the given function uid is foreign
@@ -273,10 +979,20 @@ impl Compiler {
         Ok(self.foreign_function_uids.contains(&uid))
     }
 
+    /// Creates the call of a function, callback functions are called with CALL_CALLBACK
+    fn new_call_instr(&self, uid: u64) -> Instruction {
+        if self.callback_uids.contains(&uid) {
+            Instruction::call_callback(uid)
+        } else {
+            Instruction::call(uid)
+        }
+    }
+
     /// Gets the first parent non-weak function context
     pub fn get_parent_function(&self) -> CompilerResult<&FunctionContext> {
+        // Without a non-weak context the search runs past the end of the stack
         self.fn_context_stack.iter().find(|fn_ctx| !fn_ctx.weak)
-            .ok_or(CompilerError::Unknown)
+            .ok_or_else(|| fn_stack_error(self.fn_context_stack.len()))
     }
 
     /// Pushes a module context on the stack
@@ -295,6 +1011,14 @@ impl Compiler {
         self.fn_context_stack.push_front(fn_ctx);
     }
 
+    /// Gets the context of the function being compiled, below its block and loop contexts
+    fn get_root_function_mut(&mut self) -> CompilerResult<&mut FunctionContext> {
+        let stack_len = self.fn_context_stack.len();
+        self.fn_context_stack.iter_mut()
+            .find(|fn_ctx| !fn_ctx.weak)
+            .ok_or_else(|| fn_stack_error(stack_len))
+    }
+
     /// Pops the front function context off the stack
     pub fn pop_function_context(&mut self) -> CompilerResult<FunctionContext> {
         self.fn_context_stack.pop_front()
@@ -312,11 +1036,33 @@ impl Compiler {
             .ok_or(CompilerError::Unknown)
     }
 
-    /// Gets a functions uid  by name
+    /// Gets a functions uid by name. Either the full name, or an unqualified
+    /// name which belongs to exactly one function.
     pub fn get_function_uid(&self, name: &String) -> CompilerResult<u64> {
-        self.fn_uid_map.get(name)
+        if let Some(uid) = self.fn_uid_map.get(name) {
+            return Ok(*uid);
+        }
+        if name.contains("::") {
+            return Err(CompilerError::UnknownFunction(name.clone(), None));
+        }
+
+        let mut candidates = self.get_qualified_function_names(name);
+        match candidates.len() {
+            0 => Err(CompilerError::UnknownFunction(name.clone(), None)),
+            1 => Ok(self.fn_uid_map[&candidates.remove(0)]),
+            _ => Err(CompilerError::AmbiguousFunction(name.clone(), candidates))
+        }
+    }
+
+    /// Gets the sorted full names of all functions with the given unqualified name
+    fn get_qualified_function_names(&self, name: &str) -> Vec<String> {
+        let suffix = format!("::{}", name);
+        let mut names: Vec<String> = self.fn_uid_map.keys()
+            .filter(|full_name| full_name.ends_with(&suffix))
             .cloned()
-            .ok_or(CompilerError::UnknownFunction(name.clone()))
+            .collect();
+        names.sort();
+        names
     }
 
     /// Resolves a function by name to a FunctionDef
@@ -366,16 +1112,43 @@ impl Compiler {
                 //println!("Resolved {}. Was in module!", name);
                 let mod_ctx = mod_ctx_opt.unwrap();
                 //println!("Blub");
+                if !mod_ctx.functions.contains_key(last_path) &&
+                    (mod_ctx.containers.contains_key(last_path) || mod_ctx.modules.contains_key(last_path)) {
+                    return Err(CompilerError::NotCallable(name.clone()));
+                }
                 return mod_ctx.functions.get(last_path)
                     .cloned()
-                    .ok_or(CompilerError::UnknownFunction(name.clone()));
+                    .ok_or_else(|| {
+                        // Suggests the full path of the closest function of the module
+                        let suggestion = suggest_name(last_path, mod_ctx.functions.keys())
+                            .map(|fn_name| {
+                                let mut path = path_fragments[..path_fragments.len() - 1].join("::");
+                                path += "::";
+                                path + &fn_name
+                            });
+                        CompilerError::UnknownFunction(name.clone(), suggestion)
+                    });
             }
         } else {
+            let local_fn = self.get_current_function().ok()
+                .and_then(|fn_ctx| fn_ctx.get_local_function(name));
+            if let Some(module_name) = local_fn {
+                return self.get_current_module()?
+                    .get_function(module_name)
+                    .cloned();
+            }
+
+            let local_import = self.get_current_function().ok()
+                .and_then(|fn_ctx| fn_ctx.get_local_import(name));
+            if let Some(import_path) = local_import {
+                return self.resolve_function(import_path);
+            }
+
             let mod_ctx = self.get_current_module()?;
             if mod_ctx.functions.contains_key(name) {
                 return mod_ctx.functions.get(name)
                     .cloned()
-                    .ok_or(CompilerError::UnknownFunction(name.clone()));
+                    .ok_or(CompilerError::UnknownFunction(name.clone(), None));
             }
             if mod_ctx.imports.contains_key(name) {
                 let import_path = mod_ctx.imports.get(name)
@@ -383,10 +1156,40 @@ impl Compiler {
                 return self.resolve_function(import_path);
             }
 
-            return Err(CompilerError::UnknownFunction(name.clone()));
+            // The name exists, but does not refer to a function
+            let is_var = self.get_current_function()
+                .map(|fn_ctx| fn_ctx.get_var_type(name).is_ok())
+                .unwrap_or(false);
+            if is_var || mod_ctx.containers.contains_key(name) || mod_ctx.modules.contains_key(name) {
+                return Err(CompilerError::NotCallable(name.clone()));
+            }
+
+            // Unqualified names only refer to the current module, but point out the candidates elsewhere
+            let candidates = self.get_qualified_function_names(name);
+            if candidates.len() > 1 {
+                return Err(CompilerError::AmbiguousFunction(name.clone(), candidates));
+            }
+
+            let mut fn_names: Vec<&String> = mod_ctx.functions.keys()
+                .chain(mod_ctx.imports.keys())
+                .collect();
+            if let Ok(fn_ctx) = self.get_current_function() {
+                fn_names.extend(fn_ctx.get_local_function_names());
+                fn_names.extend(fn_ctx.get_local_import_names());
+            }
+            let suggestion = suggest_name(name, fn_names);
+            Err(CompilerError::UnknownFunction(name.clone(), suggestion))
         }
     }
 
+    /// Finds a function by name anywhere in the module hierarchy, starting at the root module
+    pub fn find_function_anywhere(&self, name: &str) -> CompilerResult<FunctionDef> {
+        self.get_root_module()?
+            .find_function_anywhere(name)
+            .cloned()
+            .ok_or(CompilerError::UnknownFunction(String::from(name), None))
+    }
+
     /// Resolves a container by name to a ContainerDef
     pub fn resolve_container(&self, name: &String) -> CompilerResult<ContainerDef> {
         //println!("Resolving container by name {}", name);
@@ -437,27 +1240,50 @@ impl Compiler {
 
     /// Returns the byte size of a given Type
     pub fn get_size_of_type(&self, var_type: &Type) -> CompilerResult<usize> {
+        self.get_size_of_type_checked(var_type, &mut Vec::new())
+    }
+
+    /// Returns the size of a type, keeping track of the containers being sized
+    /// to reject containers which contain themselves by value
+    fn get_size_of_type_checked(&self, var_type: &Type, cont_stack: &mut Vec<String>) -> CompilerResult<usize> {
         //println!("Getting size of type");
         let size = match var_type {
-            Type::String => 16,
+            Type::String => match self.string_repr {
+                StringRepr::FatPointer => 16,
+                StringRepr::Handle => 8
+            },
             Type::Void => 0,
             Type::Int => 8,
-            Type::Reference(inner) => {
-                match inner.deref() {
-                    Type::AutoArray(_) => 16,
-                    _ => 8
+            Type::Reference(_) => {
+                if var_type.is_fat_pointer() {
+                    16
+                } else {
+                    8
                 }
             },
-            Type::Float => 4,
+            Type::Float => if self.f64_floats { 8 } else { 4 },
             Type::Bool => 4,
             Type::Other(cont_name) => {
                 let cont_def = self.resolve_container(&cont_name)?;
-                cont_def.get_size(self)?
+                if cont_stack.contains(&cont_def.canonical_name) {
+                    return Err(CompilerError::RecursiveType(cont_def.canonical_name.clone()));
+                }
+                cont_stack.push(cont_def.canonical_name.clone());
+                let mut cont_size = 0;
+                for member_type in cont_def.member_variables.values() {
+                    cont_size += self.get_size_of_type_checked(member_type, cont_stack)?;
+                }
+                cont_stack.pop();
+                cont_size
             },
             Type::Array(inner_type, size) => {
-                let inner_type_size = self.get_size_of_type(&inner_type)?;
+                let inner_type_size = self.get_size_of_type_checked(&inner_type, cont_stack)?;
                 inner_type_size * size
             },
+            Type::ConstArray(inner_type, size_expr, span) => {
+                let inner_type_size = self.get_size_of_type_checked(inner_type, cont_stack)?;
+                inner_type_size * self.eval_array_size(size_expr, span)?
+            },
             _ => {
                 //println!("Error in get_size_of_type()!");
                 return Err(CompilerError::UnknownType(var_type.clone()));
@@ -479,21 +1305,35 @@ impl Compiler {
             }
         }
 
-        type_opt.ok_or(CompilerError::UnknownVariable(var_name.clone()))
+        type_opt.ok_or_else(|| {
+            let mut var_names = Vec::new();
+            for fn_ctx in self.fn_context_stack.iter() {
+                var_names.extend(fn_ctx.get_var_names());
+            }
+            if let Ok(mod_ctx) = self.get_current_module() {
+                var_names.extend(mod_ctx.constants.keys());
+            }
+            CompilerError::UnknownVariable(var_name.clone(), suggest_name(var_name, var_names))
+        })
     }
 
-    /// Returns the offset to SP for a given variable
-    pub fn get_sp_offset_of_var(&self, var_name: &String) -> CompilerResult<i64> {
+    /// Returns the offset to FP for a given variable
+    pub fn get_fp_offset_of_var(&self, var_name: &String) -> CompilerResult<i64> {
         let fn_ctx = self.get_current_function()?;
-        let stack_pos = fn_ctx.get_var_pos(var_name)?;
-        let stack_size = fn_ctx.stack_size as i64;
-        let mut offset = stack_size - stack_pos;
-        if offset > 0 {
-            offset = -offset;
-        }
-        Ok(
-            offset
-        )
+        fn_ctx.get_var_pos(var_name)
+    }
+
+    /// Creates the instruction computing the address of a FP relative offset into a register
+    pub fn new_fp_address_instr(&self, offset: i64, target_reg: Register) -> Instruction {
+        let opcode = if offset < 0 {
+            Opcode::SUBU_I
+        } else {
+            Opcode::ADDU_I
+        };
+        Instruction::new(opcode)
+            .with_operand::<u8>(Register::FP.into())
+            .with_operand::<u64>(offset.abs() as u64)
+            .with_operand::<u8>(target_reg.into())
     }
 
     /// Increments the stack of the current function context
@@ -505,6 +1345,20 @@ impl Compiler {
         Ok(fn_ctx.stack_size)
     }
 
+    /// Pushes an instruction growing the stack, unless the size is zero
+    pub fn push_inc_stack_instr(&mut self, size: usize) {
+        if size > 0 {
+            self.builder.push_instr(Instruction::new_inc_stack(size));
+        }
+    }
+
+    /// Pushes an instruction shrinking the stack, unless the size is zero
+    pub fn push_dec_stack_instr(&mut self, size: usize) {
+        if size > 0 {
+            self.builder.push_instr(Instruction::new_dec_stack(size));
+        }
+    }
+
     /// Decrements the stack of the current function context
     pub fn dec_stack(&mut self, size: usize) -> CompilerResult<usize> {
         let fn_ctx = self.get_current_function_mut()?;
@@ -529,6 +1383,46 @@ impl Compiler {
         Ok(())
     }
 
+    /// Registers a foreign module in the root, builder style
+    pub fn with_foreign_module(mut self, module: Module) -> CompilerResult<Compiler> {
+        self.register_foreign_root_module(module)?;
+        Ok(self)
+    }
+
+    /// Registers the functions of a built-in module and returns its context.
+    /// Unlike register_foreign_module this cannot fail, the module is new and its names are distinct.
+    /// The argument layouts are set once the program is built.
+    fn builtin_module_context(&mut self, module: Module, path: &str) -> ModuleContext {
+        let path = format!("{}{}::", path, module.name);
+        let mut mod_ctx = ModuleContext::new(module.name.clone());
+
+        // Register in name order, so uids are assigned independently of hash map order
+        let mut functions: Vec<Function> = module.functions.into_values().collect();
+        functions.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        for function in functions {
+            let full_fn_name = path.clone() + &function.name;
+            let fn_uid = self.uid_generator.get_function_uid(&full_fn_name);
+            let fn_args: Vec<(String, Type)> = function.arg_types.iter().map(|t| (String::new(), t.clone())).collect();
+            let fn_def = FunctionDef::new(function.name.clone())
+                .with_arguments(&fn_args)
+                .with_ret_type(function.return_type.clone())
+                .with_uid(fn_uid);
+            mod_ctx.functions.insert(fn_def.name.clone(), fn_def);
+            self.fn_uid_map.insert(full_fn_name, fn_uid);
+            self.foreign_function_uids.insert(fn_uid);
+            self.foreign_functions.get_or_insert_with(HashMap::new)
+                .insert(fn_uid, function);
+        }
+
+        let mut modules: Vec<Module> = module.modules.into_values().collect();
+        modules.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        for module in modules {
+            let sub_mod_ctx = self.builtin_module_context(module, &path);
+            mod_ctx.modules.insert(sub_mod_ctx.name.clone(), sub_mod_ctx);
+        }
+        mod_ctx
+    }
+
     /// Registers a foreign module
     fn register_foreign_module(&mut self, module: Module, path: &String) -> CompilerResult<()> {
         let path = format!("{}{}::", path, module.name.clone());
@@ -536,11 +1430,20 @@ impl Compiler {
 
         self.push_module_context(mod_ctx);
 
-        for (_, function) in module.functions {
+        // Register in name order, so uids are assigned independently of hash map order
+        let mut functions: Vec<Function> = module.functions.into_iter()
+            .map(|(_, function)| function)
+            .collect();
+        functions.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        for function in functions {
             self.register_foreign_function(function, &path)?;
         }
 
-        for (_, module) in module.modules {
+        let mut modules: Vec<Module> = module.modules.into_iter()
+            .map(|(_, module)| module)
+            .collect();
+        modules.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        for module in modules {
             self.register_foreign_module(module, &path)?;
         }
 
@@ -552,34 +1455,52 @@ impl Compiler {
         Ok(())
     }
 
-    fn register_foreign_function(&mut self, mut function: Function, path: &String) -> CompilerResult<()> {
-        if self.foreign_functions.is_none() {
-            self.foreign_functions = Some(HashMap::new());
-        }
-
-        let full_fn_name = path.clone() + &function.name;
-        let fn_uid = self.uid_generator.get_function_uid(&full_fn_name);
-        let function_clone = function.clone();
-
+    /// Sets the offsets and sizes of the arguments of a foreign function on the stack
+    fn set_foreign_arg_layout(&self, function: &mut Function) -> CompilerResult<()> {
         let mut arg_offset_sum: i64 = 0;
         let mut arg_sizes = Vec::new();
         let mut arg_offsets = Vec::new();
         arg_sizes.resize(function.arg_types.len(), 0);
         arg_offsets.resize(function.arg_types.len(), 0);
-        let mut i = arg_sizes.len() - 1;
-        for arg_type in function_clone.arg_types.iter().rev() {
-            let arg_size = self.get_size_of_type(&arg_type)?;
+        for (i, arg_type) in function.arg_types.iter().enumerate().rev() {
+            let arg_size = self.get_size_of_type(arg_type)?;
             arg_sizes[i] = arg_size;
             arg_offset_sum -= arg_size as i64;
             arg_offsets[i] = arg_offset_sum;
-            //println!("Registering arg i={}", i);
-            if i > 0 {
-                i -= 1;
-            }
         }
 
         function.set_arg_offsets(arg_offsets);
         function.set_arg_sizes(arg_sizes);
+        // Only the core calling callbacks needs the size of the return value
+        if function.is_callback() {
+            let ret_size = self.get_size_of_type(&function.return_type)?;
+            function.set_ret_size(ret_size);
+        }
+        Ok(())
+    }
+
+    fn register_foreign_function(&mut self, mut function: Function, path: &String) -> CompilerResult<()> {
+        if self.foreign_functions.is_none() {
+            self.foreign_functions = Some(HashMap::new());
+        }
+
+        let full_fn_name = path.clone() + &function.name;
+        let fn_uid = self.uid_generator.get_function_uid(&full_fn_name);
+        let function_clone = function.clone();
+
+        // Callbacks pass their arguments and return value as Value
+        if function.is_callback() {
+            let is_value = |value_type: &Type| matches!(value_type, Type::Int | Type::Float | Type::Bool);
+            let invalid_type = function.arg_types.iter()
+                .find(|arg_type| !is_value(arg_type))
+                .or(Some(&function.return_type).filter(|ret_type| !is_value(ret_type) && **ret_type != Type::Void));
+            if let Some(invalid_type) = invalid_type {
+                return Err(CompilerError::InvalidCallbackType(full_fn_name, invalid_type.clone()));
+            }
+            self.callback_uids.insert(fn_uid);
+        }
+
+        self.set_foreign_arg_layout(&mut function)?;
 
         self.fn_uid_map.insert(full_fn_name, fn_uid);
         self.foreign_function_uids.insert(fn_uid);
@@ -601,6 +1522,7 @@ impl Compiler {
 
     /// Canonizes (adds module path when necessary) a given Type
     pub fn canonize_type(&self, var_type: &mut Type) -> CompilerResult<()> {
+        self.resolve_array_sizes(var_type)?;
         let new_type_opt = match var_type {
             Type::Reference(inner_type) => {
                 let inner_type = inner_type.deref_mut();
@@ -623,13 +1545,68 @@ impl Compiler {
         Ok(())
     }
 
+    /// Resolves the sizes of arrays given as constant expressions, also inside of other types
+    pub fn resolve_array_sizes(&self, var_type: &mut Type) -> CompilerResult<()> {
+        let new_type_opt = match var_type {
+            Type::Reference(inner_type) |
+            Type::Array(inner_type, _) |
+            Type::AutoArray(inner_type) => {
+                self.resolve_array_sizes(inner_type.deref_mut())?;
+                None
+            },
+            Type::Tuple(types) => {
+                for inner_type in types.iter_mut() {
+                    self.resolve_array_sizes(inner_type)?;
+                }
+                None
+            },
+            Type::ConstArray(inner_type, size_expr, span) => {
+                let size = self.eval_array_size(size_expr, span)?;
+                let mut inner_type = inner_type.as_ref().clone();
+                self.resolve_array_sizes(&mut inner_type)?;
+                Some(
+                    Type::Array(Box::new(inner_type), size)
+                )
+            },
+            _ => None
+        };
+        if let Some(new_type) = new_type_opt {
+            *var_type = new_type;
+        }
+        Ok(())
+    }
+
+    /// Evaluates the size of an array at compile time, the span is the one of the array type
+    fn eval_array_size(&self, size_expr: &Expression, span: &Range<usize>) -> CompilerResult<usize> {
+        match self.try_fold_const(size_expr)? {
+            Some(Expression::IntLiteral(size)) if size >= 0 && size as usize <= MAX_ARRAY_SIZE => Ok(size as usize),
+            Some(Expression::IntLiteral(size)) => Err(CompilerError::InvalidArraySize(size, span.clone())),
+            _ => Err(CompilerError::NonConstantArraySize(span.clone()))
+        }
+    }
+
+    /// Gets the value of a constant of the current module.
+    /// None if there is no such constant or a variable shadows it.
+    pub fn get_constant(&self, name: &String) -> Option<Expression> {
+        if self.get_type_of_var(name).is_ok() {
+            return None;
+        }
+        self.get_current_module().ok()?
+            .constants.get(name)
+            .cloned()
+    }
+
     // #endregion
 
     // #region declare functions
 
-    /// (Pre-)declares a given declaration list
+    /// (Pre-)declares a given declaration list.
+    /// Constants are declared first, in order, so that the types of all other declarations may use them.
     pub fn declare_decl_list(&mut self, decl_list: &[Declaration]) -> CompilerResult<()> {
-        for decl in decl_list.iter() {
+        for decl in decl_list.iter().filter(|decl| matches!(decl, Declaration::Const(_, _))) {
+            self.declare_decl(decl)?;
+        }
+        for decl in decl_list.iter().filter(|decl| !matches!(decl, Declaration::Const(_, _))) {
             self.declare_decl(decl)?;
         }
         Ok(())
@@ -643,7 +1620,13 @@ impl Compiler {
             Declaration::Container(_) => self.declare_cont_decl(decl)?,
             Declaration::Import(_, _) => self.declare_import_decl(decl)?,
             Declaration::Impl(_, _, _) => self.declare_impl_decl(decl)?,
-            Declaration::StaticVar(_) => self.declare_static_var(decl)?
+            Declaration::StaticVar(_) => self.declare_static_var(decl)?,
+            Declaration::Const(_, _) => self.declare_const_decl(decl)?,
+            Declaration::Cfg(flag, decl_list) => {
+                if self.is_defined(flag) {
+                    self.declare_decl_list(decl_list)?;
+                }
+            }
         };
         Ok(())
     }
@@ -653,6 +1636,18 @@ impl Compiler {
         Ok(())
     }
 
+    /// Declares a given constant declaration, evaluating its value
+    pub fn declare_const_decl(&mut self, decl: &Declaration) -> CompilerResult<()> {
+        let (const_name, const_expr) = match decl {
+            Declaration::Const(const_name, const_expr) => (const_name, const_expr),
+            _ => return Err(CompilerError::Unknown)
+        };
+        let value = self.try_fold_const(const_expr)?
+            .ok_or_else(|| CompilerError::NonConstantExpression(const_name.clone()))?;
+        self.get_current_module_mut()?
+            .add_constant(const_name.clone(), value)
+    }
+
     /// (Pre-)declares a given function declaration
     pub fn declare_fn_decl(&mut self, decl: &Declaration) -> CompilerResult<()> {
         let fn_decl_args = match decl {
@@ -674,8 +1669,12 @@ impl Compiler {
             .with_uid(uid);
 
         for (arg_name, arg_type) in fn_def.arguments.iter_mut() {
+            if *arg_type == Type::Void {
+                return Err(CompilerError::VoidVariable(arg_name.clone()));
+            }
             self.canonize_type(arg_type)?;
         }
+        self.resolve_array_sizes(&mut fn_def.ret_type)?;
 
         if let Some(cont_name) = self.current_cont.as_ref().cloned() {
             let mod_ctx = self.get_current_module_mut()?;
@@ -686,9 +1685,73 @@ impl Compiler {
             mod_ctx.add_function(fn_def)?;
         }
 
+        // Local functions are hoisted into the module, e.g. root::outer::__local::helper
+        let local_fn_decls = self.get_local_fn_decls(fn_decl_args);
+        if !local_fn_decls.is_empty() && self.current_cont.is_some() {
+            return Err(CompilerError::Unimplemented(format!("Local functions in member functions")));
+        }
+        for local_fn_decl in local_fn_decls {
+            self.declare_fn_decl(&Declaration::Function(local_fn_decl))?;
+        }
+
         Ok(())
     }
 
+    /// Gets the local functions declared in the body of a function, named as in the module
+    fn get_local_fn_decls(&self, fn_decl_args: &FunctionDeclArgs) -> Vec<FunctionDeclArgs> {
+        let mut local_fn_decls = Vec::new();
+        if let Some(stmt_list) = fn_decl_args.code_block.as_ref() {
+            self.for_each_stmt(stmt_list, &mut |stmt| {
+                if let Statement::LocalFunction(local_fn_decl) = stmt {
+                    let mut local_fn_decl = local_fn_decl.clone();
+                    local_fn_decl.name = format!("{}::{}::{}", fn_decl_args.name, LOCAL_FN_SCOPE, local_fn_decl.name);
+                    local_fn_decls.push(local_fn_decl);
+                }
+            });
+        }
+        local_fn_decls
+    }
+
+    /// Gets the names of the arguments and variables of a function, without the ones of its local functions
+    fn get_fn_var_names(&self, fn_decl_args: &FunctionDeclArgs) -> HashSet<String> {
+        let mut var_names: HashSet<String> = fn_decl_args.arguments.iter()
+            .map(|(arg_name, _)| arg_name.clone())
+            .collect();
+        if let Some(stmt_list) = fn_decl_args.code_block.as_ref() {
+            self.for_each_stmt(stmt_list, &mut |stmt| {
+                if let Statement::VariableDecl(var_decl_args) = stmt {
+                    var_names.insert(var_decl_args.name.clone());
+                }
+            });
+        }
+        var_names
+    }
+
+    /// Visits the statements of a statement list and of its nested blocks which are compiled.
+    /// The bodies of local functions are not visited.
+    fn for_each_stmt<'s>(&self, stmt_list: &'s [Statement], f: &mut dyn FnMut(&'s Statement)) {
+        for stmt in stmt_list.iter() {
+            f(stmt);
+            match stmt {
+                Statement::CodeBlock(block) |
+                Statement::Loop(block) |
+                Statement::While(_, block) => self.for_each_stmt(block, f),
+                Statement::If(if_stmt_args) => {
+                    self.for_each_stmt(&if_stmt_args.if_block, f);
+                    for (_, block) in if_stmt_args.else_if_list.iter().flatten() {
+                        self.for_each_stmt(block, f);
+                    }
+                    if let Some(block) = if_stmt_args.else_block.as_ref() {
+                        self.for_each_stmt(block, f);
+                    }
+                },
+                Statement::Cfg(flag, block) if self.is_defined(flag) => self.for_each_stmt(block, f),
+                Statement::Infinite(while_stmt) => self.for_each_stmt(std::slice::from_ref(while_stmt), f),
+                _ => {}
+            };
+        }
+    }
+
     /// (Pre-)declares a given module declaration
     pub fn declare_mod_decl(&mut self, decl: &Declaration) -> CompilerResult<()> {
         let (mod_name, decl_list) = match decl {
@@ -723,12 +1786,21 @@ impl Compiler {
         };
 
         //println!("Declaring cont: {:?}", cont_decl_args);
+        let mut cont_decl_args = cont_decl_args.clone();
+        for (_, member_type) in cont_decl_args.members.iter_mut() {
+            self.resolve_array_sizes(member_type)?;
+        }
+        let cont_decl_args = &cont_decl_args;
         let mut canon_name = self.get_module_path();
         canon_name += &cont_decl_args.name;
         let mod_ctx = self.get_current_module_mut()?;
         if mod_ctx.containers.contains_key(&cont_decl_args.name) {
             let cont_def = mod_ctx.containers.get_mut(&cont_decl_args.name)
                 .ok_or(CompilerError::UnknownContainer(cont_decl_args.name.clone()))?;
+            // Only a definition created by an impl may be completed
+            if cont_def.declared {
+                return Err(CompilerError::DuplicateContainer(cont_decl_args.name.clone()));
+            }
             cont_def.merge_cont_decl(cont_decl_args);
         } else {
             let cont_def = ContainerDef::from_decl(cont_decl_args, canon_name);
@@ -808,13 +1880,48 @@ impl Compiler {
             Declaration::Function(_) => self.compile_fn_decl(decl)?,
             Declaration::Impl(_, _, _) => self.compile_impl_decl(decl)?,
             Declaration::Module(_, _) => self.compile_mod_decl(decl)?,
+            Declaration::Cfg(flag, decl_list) => {
+                if self.is_defined(flag) {
+                    self.compile_decl_list(decl_list)?;
+                }
+            },
             _ => {}
         };
         Ok(())
     }
 
-    /// Compiles a function declaration
+    /// Compiles a standalone expression into the function EXPRESSION_FN_NAME of the current module.
+    /// Running the function leaves the value of the expression in R0. Returns the type of the expression.
+    pub fn compile_expression(&mut self, expr: &Expression) -> CompilerResult<Type> {
+        // The expression is typed like it is seen from the body, without any variables
+        let check_fn_def = FunctionDef::new(String::from(EXPRESSION_FN_NAME));
+        let check_fn_ctx = FunctionContext::new(self, check_fn_def)?;
+        self.push_function_context(check_fn_ctx);
+        let type_res = self.check_expr_type(expr);
+        self.pop_function_context()?;
+        let expr_type = type_res?;
+
+        let fn_decl = Declaration::Function(FunctionDeclArgs {
+            name: String::from(EXPRESSION_FN_NAME),
+            arguments: Vec::new(),
+            returns: expr_type.clone(),
+            code_block: Some(vec![Statement::Return(Some(expr.clone()))]),
+            stmt_lines: vec![1],
+            span: 0..0
+        });
+        self.declare_fn_decl(&fn_decl)?;
+        self.compile_fn_decl(&fn_decl)?;
+
+        Ok(expr_type)
+    }
+
+    /// Compiles a function declaration, followed by its local functions
     pub fn compile_fn_decl(&mut self, decl: &Declaration) -> CompilerResult<()> {
+        self.compile_fn_decl_in_scope(decl, &HashMap::new())
+    }
+
+    /// Compiles a function declaration, which sees the local functions of its enclosing functions
+    fn compile_fn_decl_in_scope(&mut self, decl: &Declaration, enclosing_local_fns: &HashMap<String, String>) -> CompilerResult<()> {
         let fn_decl_args = match decl {
             Declaration::Function(fn_decl_args) => fn_decl_args,
             _ => return Err(CompilerError::Unknown)
@@ -838,9 +1945,24 @@ impl Compiler {
         //println!("Fn def: {:?}", fn_def);
 
         let fn_ret_type = fn_def.ret_type.clone();
+        let arg_size = fn_def.arguments.iter()
+            .map(|(_, arg_type)| self.get_size_of_type(arg_type))
+            .sum::<CompilerResult<usize>>()?;
+        self.arg_sizes.insert(fn_def.uid, arg_size);
 
         let mut fn_ctx = FunctionContext::new(self, fn_def)?;
 
+        let local_fn_decls = self.get_local_fn_decls(fn_decl_args);
+        let mut local_fns = enclosing_local_fns.clone();
+        for local_fn_decl in local_fn_decls.iter() {
+            let name = local_fn_decl.name.rsplit("::").next()
+                .ok_or(CompilerError::Unknown)?;
+            local_fns.insert(String::from(name), local_fn_decl.name.clone());
+        }
+        for (name, module_name) in local_fns.iter() {
+            fn_ctx.add_local_function(name.clone(), module_name.clone());
+        }
+
         let mut full_fn_name = self.get_module_path();
         if self.current_cont.is_some() {
             full_fn_name += self.current_cont.as_ref().unwrap();
@@ -851,31 +1973,103 @@ impl Compiler {
         //println!("Compiling fn decl with label {}", full_fn_name);
 
         
-        self.builder.push_label(full_fn_name);
+        self.builder.push_label_unique(full_fn_name.clone())
+            .map_err(CompilerError::Builder)?;
+        if self.debug_symbols {
+            self.fn_spans.insert(full_fn_name.clone(), fn_decl_args.span.clone());
+        }
 
+        let fn_ctx_depth = self.fn_context_stack.len();
+        let loop_ctx_depth = self.loop_ctx_stack.len();
         self.push_function_context(fn_ctx);
 
+        let body_res = self.compile_fn_body(fn_decl_args, &fn_ret_type);
+
+        // Pop this function's context, and any context an error left behind,
+        // so no state leaks into the next function
+        while self.fn_context_stack.len() > fn_ctx_depth + 1 {
+            self.pop_function_context()?;
+        }
+        let fn_ctx = self.pop_function_context()?;
+        if body_res.is_ok() {
+            for var_name in fn_ctx.get_write_only_vars() {
+                self.warnings.push(CompilerWarning::WriteOnlyVariable(var_name));
+            }
+        } else {
+            // The label must not point to the partial code of an abandoned function
+            self.builder.remove_label(&full_fn_name);
+        }
+        while self.loop_ctx_stack.len() > loop_ctx_depth {
+            self.pop_loop_context()?;
+        }
+        body_res?;
+
+        // The code of local functions follows the one of their enclosing function
+        let var_names = self.get_fn_var_names(fn_decl_args);
+        for local_fn_decl in local_fn_decls {
+            self.compile_fn_decl_in_scope(&Declaration::Function(local_fn_decl), &local_fns)
+                .map_err(|err| match err {
+                    CompilerError::UnknownVariable(var_name, _) if var_names.contains(&var_name) => {
+                        CompilerError::CapturedVariable(var_name)
+                    },
+                    err => err
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the body of a function, its context has to be on top of the stack
+    fn compile_fn_body(&mut self, fn_decl_args: &FunctionDeclArgs, fn_ret_type: &Type) -> CompilerResult<()> {
+        // The arguments are in scope from the start
+        self.record_locals()?;
         if let Some(stmt_list) = &fn_decl_args.code_block {
-            self.compile_stmt_list(stmt_list)?;
+            if self.debug_symbols {
+                for (stmt, line) in stmt_list.iter().zip(fn_decl_args.stmt_lines.iter()) {
+                    self.line_table.insert(self.builder.get_current_offset(), *line);
+                    self.compile_stmt(stmt)?;
+                }
+            } else {
+                self.compile_stmt_list(stmt_list)?;
+            }
         }
 
         // If the type is void, automatically add a return Statement
-        if fn_ret_type == Type::Void {
+        if *fn_ret_type == Type::Void {
             let ret_stmt = Statement::Return(None);
             self.compile_return_stmt(&ret_stmt)?;
         }
 
-        // Instruction in case the function didnt return a value
-        let halt_instr = Instruction::new(Opcode::HALT)
-            .with_operand::<u8>(1);
-        self.builder.push_instr(halt_instr);
+        let ends_with_return = *fn_ret_type == Type::Void || matches!(
+            fn_decl_args.code_block.as_ref().and_then(|stmt_list| stmt_list.last()),
+            Some(Statement::Return(_))
+        );
+        if ends_with_return {
+            self.compile_trap(UNREACHABLE_TRAP_CODE);
+        } else {
+            // Instruction in case the function didnt return a value
+            let halt_instr = Instruction::new(Opcode::HALT)
+                .with_operand::<u8>(1);
+            self.builder.push_instr(halt_instr);
+        }
 
         Ok(())
     }
 
+    /// Compiles a TRAP without message, for positions which a correct program never reaches.
+    /// The code tells them apart from a function without return value, which halts.
+    fn compile_trap(&mut self, trap_code: u8) {
+        let trap_instr = Instruction::new(Opcode::TRAP)
+            .with_operand::<u8>(trap_code)
+            .with_operand::<u64>(0)
+            .with_operand::<u64>(0);
+        self.builder.push_instr(trap_instr);
+    }
+
     /// Compiles the proper SUBU_I instruction for a break statement
-    pub fn compile_stack_loop(&mut self) -> CompilerResult<()> {
+    pub fn compile_stack_loop(&mut self, outside_loop_err: CompilerError) -> CompilerResult<()> {
         let mut pop_size = 0;
+        let mut found_loop = false;
 
         // Pop all values until the first loop context is hit
         for i in 0..self.fn_context_stack.len() {
@@ -883,14 +2077,22 @@ impl Compiler {
                 .ok_or(CompilerError::Unknown)?;
             pop_size += fn_ctx.stack_size;
             if fn_ctx.is_loop {
+                found_loop = true;
+                break;
+            }
+            // Never look past the function boundary
+            if !fn_ctx.weak {
                 break;
             }
         }
 
+        if !found_loop {
+            return Err(outside_loop_err);
+        }
+
         //println!("Compiling loop stack cleanup with pop size {}", pop_size);
 
-        let stack_instr = Instruction::new_dec_stack(pop_size);
-        self.builder.push_instr(stack_instr);
+        self.push_dec_stack_instr(pop_size);
 
         Ok(())
     }
@@ -928,26 +2130,26 @@ impl Compiler {
         let ret_type = parent_fn_ctx.get_ret_type()?;
         let ret_size = self.get_size_of_type(&ret_type)?;
         let mut pop_size = stack_size;
-        let stack_begin_offset = -(stack_size as i16);
+        let stack_begin_offset = neg_offset_operand("stack frame size", stack_size)?;
         
         if !ret_type.is_primitive() {
             //println!("fn return type is non-primitive.");
             pop_size -= ret_size;
-            if pop_size > 0 {
-                let mov_stack_instr = Instruction::new(Opcode::MOVN_A)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(-(ret_size as i16))
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(stack_begin_offset)
-                    .with_operand::<u32>(ret_size as u32);
+            if pop_size > 0 && ret_size > 0 {
+                let mov_stack_instr = Instruction::movn(
+                    Register::SP, neg_offset_operand("return value size", ret_size)?,
+                    Register::SP, stack_begin_offset,
+                    size_operand("return value size", ret_size)?
+                );
                 self.builder.push_instr(mov_stack_instr);
             }
         }
 
+        // Like break and continue, this only emits the cleanup. The stack sizes
+        // of the contexts stay untouched for the code following in other branches.
         if pop_size > 0 {
             //println!("Popping {} off the stack at return.", pop_size);
             let pop_stack_instr = Instruction::new_dec_stack(pop_size);
-            self.dec_stack(pop_size)?;
             self.builder.push_instr(pop_stack_instr);
         }
 
@@ -1013,19 +2215,88 @@ impl Compiler {
 
     /// Compiles a statement
     pub fn compile_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
+        self.record_locals()?;
         match stmt {
             Statement::VariableDecl(_) => self.compile_var_decl_stmt(stmt)?,
             Statement::Expression(_) => self.compile_expr_stmt(stmt)?,
             Statement::Return(_) => self.compile_return_stmt(stmt)?,
             Statement::If(_) => self.compile_if_stmt(stmt)?,
-            Statement::While(_, _) => self.compile_while_stmt(stmt)?, 
+            Statement::While(while_expr, while_stmt_list) => {
+                if while_expr.deref() == &Expression::BoolLiteral(true) && !self.can_exit_loop(while_stmt_list, false) {
+                    let fn_name = self.get_current_function_name()?;
+                    self.warnings.push(CompilerWarning::InfiniteLoop(fn_name));
+                }
+                self.compile_while_stmt(stmt)?
+            },
+            Statement::Infinite(while_stmt) => self.compile_while_stmt(while_stmt)?,
             Statement::Continue => self.compile_continue_stmt(stmt)?,
             Statement::Break => self.compile_break_stmt(stmt)?,
+            Statement::Assert(_, _) => self.compile_assert_stmt(stmt)?,
+            Statement::ComptimeAssert(_, _) => self.compile_comptime_assert_stmt(stmt)?,
+            Statement::Yield(_) => self.compile_yield_stmt(stmt)?,
+            Statement::Import(_, _) => self.compile_import_stmt(stmt)?,
+            // Gated statements are neither type checked nor compiled, unless their flag is defined
+            Statement::Cfg(flag, stmt_list) => {
+                if self.is_defined(flag) {
+                    self.compile_stmt_list(stmt_list)?;
+                }
+            },
+            // Compiled after the enclosing function, see compile_fn_decl
+            Statement::LocalFunction(_) => {},
             _ => return Err(CompilerError::Unimplemented(format!("Compilation of {:?} not implemented!", stmt)))
         };
         Ok(())
     }
 
+    /// Checks if a break or return in a statement list can leave the loop it is in.
+    /// Breaks in nested loops only leave those.
+    fn can_exit_loop(&self, stmt_list: &[Statement], nested: bool) -> bool {
+        stmt_list.iter().any(|stmt| {
+            match stmt {
+                Statement::Return(_) => true,
+                Statement::Break => !nested,
+                Statement::CodeBlock(block) => self.can_exit_loop(block, nested),
+                Statement::Loop(block) |
+                Statement::While(_, block) => self.can_exit_loop(block, true),
+                Statement::Infinite(while_stmt) => self.can_exit_loop(std::slice::from_ref(while_stmt), nested),
+                Statement::If(if_stmt_args) => {
+                    self.can_exit_loop(&if_stmt_args.if_block, nested) ||
+                    if_stmt_args.else_if_list.iter()
+                        .flatten()
+                        .any(|(_, block)| self.can_exit_loop(block, nested)) ||
+                    if_stmt_args.else_block.as_ref()
+                        .is_some_and(|block| self.can_exit_loop(block, nested))
+                },
+                Statement::Cfg(flag, block) => self.is_defined(flag) && self.can_exit_loop(block, nested),
+                _ => false
+            }
+        })
+    }
+
+    /// Gets the name of the function being compiled
+    fn get_current_function_name(&self) -> CompilerResult<String> {
+        self.fn_context_stack.iter()
+            .rev()
+            .find_map(|fn_ctx| fn_ctx.def.as_ref())
+            .map(|fn_def| fn_def.name.clone())
+            .ok_or(CompilerError::Unknown)
+    }
+
+    /// Gets the variables in scope of the function being compiled, sorted by their offset
+    pub fn get_locals(&self) -> CompilerResult<Vec<LocalVariable>> {
+        self.get_current_function()?
+            .get_locals()
+    }
+
+    /// Records the variables in scope at the current code offset, if debug symbols are enabled
+    fn record_locals(&mut self) -> CompilerResult<()> {
+        if self.debug_symbols {
+            let locals = self.get_locals()?;
+            self.locals_table.insert(self.builder.get_current_offset(), locals);
+        }
+        Ok(())
+    }
+
     /// Compiles a variable declaration statement
     pub fn compile_var_decl_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
         let var_decl_args = match stmt {
@@ -1037,6 +2308,7 @@ impl Compiler {
         let var_name = var_decl_args.name.clone();
         // The variable type
         let mut var_type = var_decl_args.var_type.clone();
+        self.resolve_array_sizes(&mut var_type)?;
         // The assignment expression
         let assignment_expr = &var_decl_args.assignment;
         let assignment_expr_type = self.check_expr_type(&assignment_expr)?;
@@ -1044,7 +2316,19 @@ impl Compiler {
         //println!("var decl assign expr type: {:?}", assignment_expr_type);
         // Special handling for auto typed vars
         if var_type == Type::Auto {
-            var_type = assignment_expr_type;
+            var_type = assignment_expr_type.clone();
+        }
+        // Void values have no size, so they cannot be stored
+        if var_type == Type::Void {
+            return Err(CompilerError::VoidVariable(var_name));
+        }
+        // Cannot declare a variable whose type could not be inferred
+        if var_type == Type::Auto {
+            return Err(CompilerError::UnknownType(var_type));
+        }
+        // The array is laid out by its initializer, so the sizes have to match
+        if matches!(var_type, Type::Array(_, _)) && var_type != assignment_expr_type {
+            return Err(CompilerError::TypeMismatch(var_type, assignment_expr_type));
         }
 
         //println!("Var type: {:?}", var_type);
@@ -1063,34 +2347,21 @@ impl Compiler {
                 fn_ctx.register_allocator.get_last_temp_register()?
             };
             //println!("Last reg: {:?}", last_reg);
-            let var_sp_offset = -(var_size as i16);
-            let stack_inc_instr = Instruction::new_inc_stack(var_size);
-            self.builder.push_instr(stack_inc_instr);
+            let var_sp_offset = neg_offset_operand("variable size", var_size)?;
+            self.push_inc_stack_instr(var_size);
             self.inc_stack(var_size)?;
             let mov_instr = match var_type {
                 Type::Int => {
-                    Instruction::new(Opcode::MOVI_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(var_sp_offset)
+                    Instruction::mov_store(Opcode::MOVI_RA, last_reg, Register::SP, var_sp_offset)
                 },
                 Type::Float => {
-                    Instruction::new(Opcode::MOVF_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(var_sp_offset)
+                    Instruction::mov_store(self.float_opcode(Opcode::MOVF_RA), last_reg, Register::SP, var_sp_offset)
                 },
                 Type::Reference(_) => {
-                    Instruction::new(Opcode::MOVA_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(var_sp_offset)
+                    Instruction::mov_store(Opcode::MOVA_RA, last_reg, Register::SP, var_sp_offset)
                 },
                 Type::Bool => {
-                    Instruction::new(Opcode::MOVB_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(var_sp_offset)
+                    Instruction::mov_store(Opcode::MOVB_RA, last_reg, Register::SP, var_sp_offset)
                 },
                 _ => {
                     //println!("Error in compile_var_decl_stmt()!");
@@ -1102,7 +2373,8 @@ impl Compiler {
         // Otherwise, the value is already on the top of the stack.
         // Set the variable in the context.
         let fn_ctx = self.get_current_function_mut()?;
-        fn_ctx.set_stack_var((var_name.clone(), var_type.clone()), (fn_ctx.stack_size - var_size) as i64)?;
+        let var_pos = fn_ctx.frame_offset + (fn_ctx.stack_size - var_size) as i64;
+        fn_ctx.set_stack_var((var_name.clone(), var_type.clone()), var_pos)?;
         //println!("Setting var {}: {:?} to position {}", var_name, var_type, fn_ctx.stack_size - var_size);
         Ok(())
     }
@@ -1115,12 +2387,23 @@ impl Compiler {
         };
 
         match stmt_expr {
-            Expression::Call(_, _) => self.compile_expr(stmt_expr)?,
+            Expression::Call(_, _) => {
+                let before_stack_size = self.get_stack_size()?;
+                self.compile_expr(stmt_expr)?;
+                // Discard the return value if it was left on the stack
+                let stack_diff = self.get_stack_size()? - before_stack_size;
+                if stack_diff > 0 {
+                    let stack_dec_instr = Instruction::new_dec_stack(stack_diff);
+                    self.dec_stack(stack_diff)?;
+                    self.builder.push_instr(stack_dec_instr);
+                }
+            },
             Expression::Assign(_, _) => self.compile_var_assign_stmt_expr(stmt_expr)?,
             Expression::AddAssign(_, _) => self.compile_var_assign_stmt_expr(stmt_expr)?,
             Expression::SubAssign(_, _) => self.compile_var_assign_stmt_expr(stmt_expr)?,
             Expression::MulAssign(_, _) => self.compile_var_assign_stmt_expr(stmt_expr)?,
             Expression::DivAssign(_, _) => self.compile_var_assign_stmt_expr(stmt_expr)?,
+            Expression::RawBytecode(_) => self.compile_expr(stmt_expr)?,
             _ => return Err(CompilerError::UnsupportedExpression(stmt_expr.clone()))
         };
 
@@ -1136,200 +2419,207 @@ impl Compiler {
             _ => return Err(CompilerError::Unknown)
         };
 
-        // Generate an instruction tag to fill in the end of this if/else chain
-        let tag_end = self.uid_generator.generate();
-        // Generate an instruction tag for the next branch
-        let mut tag_next = self.uid_generator.generate();
-
-        let expr_type = self.check_expr_type(&if_stmt_args.if_expr)?;
-        // Only boolean expressions are allowed
-        if expr_type != Type::Bool {
-            return Err(CompilerError::TypeMismatch(expr_type, Type::Bool));
+        if let Some((var_name, values)) = self.get_jump_table_branches(if_stmt_args)? {
+            return self.compile_jump_table(if_stmt_args, &var_name, &values);
         }
-        // Compile the if expression
-        self.compile_expr(&if_stmt_args.if_expr)?;
-        // Get the register the result of this boolean expression was saved in
-        let last_reg = {
-            self.get_current_function()?
-                .register_allocator
-                .get_last_temp_register()?
-        };
 
-        // Instruction for this if expr
-        let jmpf_instr = Instruction::new(Opcode::JMPF)
-            .with_operand::<u8>(last_reg.into())
-            .with_operand(tag_next);
-        self.builder.tag(tag_next);
-        self.builder.push_instr(jmpf_instr);
+        // Label at the end of this if/else chain
+        let label_end = self.builder.new_label();
 
-        // Create new weak function context
-        let mut if_fn_ctx = {
-            let fn_ctx = self.get_current_function()?;
-            FunctionContext::new_weak(fn_ctx)?
-        };
-        // And push it on the stack
-        self.push_function_context(if_fn_ctx);
-
-        // Compile the if statement list
-        self.compile_stmt_list(&if_stmt_args.if_block)?;
+        let mut branches = vec![(&if_stmt_args.if_expr, &if_stmt_args.if_block)];
+        if let Some(else_if_list) = if_stmt_args.else_if_list.as_ref() {
+            branches.extend(else_if_list.iter().map(|(expr, stmt_list)| (expr, stmt_list)));
+        }
 
-        // Pop the function context off the stack again
-        if_fn_ctx = self.pop_function_context()?;
+        for (branch_expr, branch_stmt_list) in branches {
+            // Only boolean expressions are allowed
+            let expr_type = self.check_expr_type(branch_expr)?;
+            if expr_type != Type::Bool {
+                return Err(CompilerError::TypeMismatch(expr_type, Type::Bool));
+            }
 
-        self.compile_stack_cleanup_block(&if_fn_ctx)?;
+            // Branches with constant conditions are decided here, their dropped blocks are not compiled
+            match self.try_fold_const(branch_expr)? {
+                Some(Expression::BoolLiteral(false)) => continue,
+                Some(Expression::BoolLiteral(true)) => {
+                    // The following branches and the else block can never be reached
+                    self.compile_if_block(branch_stmt_list)?;
+                    self.builder.place_label(label_end);
+                    return Ok(());
+                },
+                _ => {}
+            };
 
-        // Instruction for jumping to the end
-        let jmp_end_instr = Instruction::new(Opcode::JMP)
-            .with_operand(tag_end);
-        self.builder.tag(tag_end);
-        self.builder.push_instr(jmp_end_instr);
+            // Label of the next branch
+            let label_next = self.builder.new_label();
 
-        if if_stmt_args.else_if_list.is_some() {
-            let else_if_list = if_stmt_args.else_if_list
-                .as_ref()
-                .ok_or(CompilerError::Unknown)?;
-            for (else_if_expr, else_if_stmt_list) in else_if_list.iter() {
-                // Current instruction position
-                let pos = self.builder.get_current_offset();
-                // Set the last JMPF to jump to this instruction
-                {
-                    // Retrieve the position list
-                    let jmp_next_instr_pos_list = self.builder.get_tag(&tag_next)
-                        .ok_or(CompilerError::Unknown)?;
-                    // Retrieve the position
-                    // (Only one instruction should exist with this tag)
-                    let jmp_next_instr_pos = jmp_next_instr_pos_list.get(0)
-                        .ok_or(CompilerError::Unknown)?;
-                    // Get the mutable reference to this instruction
-                    let jmp_next_instr = self.builder.get_instr(&jmp_next_instr_pos)
-                        .ok_or(CompilerError::Unknown)?;
-                    
-                    // Update the jump destination
-                    jmp_next_instr.remove_operand_bytes(8);
-                    jmp_next_instr.append_operand(pos);
-                }
-                // Only boolean expressions are allowed
-                let expr_type = self.check_expr_type(else_if_expr)?;
-                if expr_type != Type::Bool {
-                    return Err(CompilerError::TypeMismatch(expr_type, Type::Bool));
-                }
-                // Compile the expression
-                self.compile_expr(else_if_expr)?;
-                // Get the result register
-                let last_reg = {
-                    self.get_current_function()?
-                        .register_allocator
-                        .get_last_temp_register()?
-                };
-                // Generate new tag for the next jump
-                tag_next = self.uid_generator.generate();
-                // Instruction for jumping to next or inside statement list
-                let jmpf_instr = Instruction::new(Opcode::JMPF)
-                    .with_operand::<u8>(last_reg.into())
-                    .with_operand(tag_next);
-                self.builder.tag(tag_next);
-                self.builder.push_instr(jmpf_instr);
-
-                // Create a new weak function context
-                let mut else_if_fn_ctx = {
-                    let fn_ctx = self.get_current_function()?;
-                    FunctionContext::new_weak(fn_ctx)?
-                };
-                // and push it on the stack
-                self.push_function_context(else_if_fn_ctx);
+            // Compile the branch expression
+            self.compile_expr(branch_expr)?;
+            // Get the register the result of this boolean expression was saved in
+            let last_reg = {
+                self.get_current_function()?
+                    .register_allocator
+                    .get_last_temp_register()?
+            };
 
-                // Compile this "else if"s statement list
-                self.compile_stmt_list(else_if_stmt_list)?;
+            // Instruction for jumping to the next branch if the expression is false
+            let jmpf_instr = Instruction::jmpf(last_reg, label_next);
+            self.builder.push_instr(jmpf_instr);
 
-                // Pop the context off the stack again
-                else_if_fn_ctx = self.pop_function_context()?;
+            self.compile_if_block(branch_stmt_list)?;
 
-                self.compile_stack_cleanup_block(&else_if_fn_ctx)?;
+            // Instruction for jumping to the end
+            let jmp_end_instr = Instruction::jmp(label_end);
+            self.builder.push_instr(jmp_end_instr);
 
-                // Instruction for jumping to the end
-                let jmp_end_instr = Instruction::new(Opcode::JMP)
-                    .with_operand(tag_end);
-                self.builder.tag(tag_end);
-                self.builder.push_instr(jmp_end_instr);
-            }
+            // The JMPF jumps to the next branch, the else block or the end if there is none
+            self.builder.place_label(label_next);
         }
 
         // If an "else" block exists
-        if if_stmt_args.else_block.is_some() {
-            let else_stmt_list = if_stmt_args.else_block.as_ref()
-                .ok_or(CompilerError::Unknown)?;
-            // Set the last JMPF to jump to this instruction
-            let pos = self.builder.get_current_offset();
-            {
-                // Retrieve the position list
-                let jmp_next_instr_pos_list = self.builder.get_tag(&tag_next)
-                    .ok_or(CompilerError::Unknown)?;
-                // Retrieve the position
-                // (Only one instruction should exist with this tag)
-                let jmp_next_instr_pos = jmp_next_instr_pos_list.get(0)
-                    .ok_or(CompilerError::Unknown)?;
-                // Get the mutable reference to this instruction
-                let jmp_next_instr = self.builder.get_instr(&jmp_next_instr_pos)
-                    .ok_or(CompilerError::Unknown)?;
-                    
-                // Update the jump destination
-                jmp_next_instr.remove_operand_bytes(8);
-                jmp_next_instr.append_operand(pos);
-            }
+        if let Some(else_stmt_list) = if_stmt_args.else_block.as_ref() {
+            self.compile_if_block(else_stmt_list)?;
+        }
 
-            // Create a new weak function context
-            let mut else_fn_ctx = {
-                let fn_ctx = self.get_current_function()?;
-                FunctionContext::new_weak(fn_ctx)?
-            };
-            // And push it on the stack
-            self.push_function_context(else_fn_ctx);
+        // End of the entire if/else if/else chain
+        self.builder.place_label(label_end);
 
-            // Compile the statement list for this else block
-            self.compile_stmt_list(else_stmt_list)?;
+        Ok(())
+    }
 
-            // Pop it off the stack again
-            else_fn_ctx = self.pop_function_context()?;
+    /// Checks if every branch of an if/else if chain compares the same int variable to another constant,
+    /// and enough of the values in between are covered to be worth a jump table.
+    /// Returns the variable and the constant of each branch.
+    fn get_jump_table_branches(&self, if_stmt_args: &IfStatementArgs) -> CompilerResult<Option<(String, Vec<i64>)>> {
+        let mut branch_exprs = vec![&if_stmt_args.if_expr];
+        if let Some(else_if_list) = if_stmt_args.else_if_list.as_ref() {
+            branch_exprs.extend(else_if_list.iter().map(|(expr, _)| expr));
+        }
+        if branch_exprs.len() < JUMP_TABLE_MIN_BRANCHES {
+            return Ok(None);
+        }
 
-            self.compile_stack_cleanup_block(&else_fn_ctx)?;
-        } else {
-            // Set the last JMPF to jump to this instruction
-            let pos = self.builder.get_current_offset();
-            {
-                // Retrieve the position list
-                let jmp_next_instr_pos_list = self.builder.get_tag(&tag_next)
-                    .ok_or(CompilerError::Unknown)?;
-                // Retrieve the position
-                // (Only one instruction should exist with this tag)
-                let jmp_next_instr_pos = jmp_next_instr_pos_list.get(0)
-                    .ok_or(CompilerError::Unknown)?;
-                // Get the mutable reference to this instruction
-                let jmp_next_instr = self.builder.get_instr(&jmp_next_instr_pos)
-                    .ok_or(CompilerError::Unknown)?;
-                    
-                // Update the jump destination
-                jmp_next_instr.remove_operand_bytes(8);
-                jmp_next_instr.append_operand(pos);
+        let mut table_var: Option<&String> = None;
+        let mut values = Vec::new();
+        for branch_expr in branch_exprs {
+            let (var_name, value) = match branch_expr {
+                Expression::Equals(lhs, rhs) => match (lhs.deref(), rhs.deref()) {
+                    (Expression::Variable(var_name), Expression::IntLiteral(value)) |
+                    (Expression::IntLiteral(value), Expression::Variable(var_name)) => (var_name, *value),
+                    _ => return Ok(None)
+                },
+                _ => return Ok(None)
+            };
+            if table_var.map(|table_var| table_var != var_name).unwrap_or(false) || values.contains(&value) {
+                return Ok(None);
             }
+            table_var = Some(var_name);
+            values.push(value);
         }
 
-        // Current position is at the end of the entire if/else if/else chain
-        let pos_end = self.builder.get_current_offset();
+        let var_name = match table_var {
+            Some(var_name) => var_name.clone(),
+            None => return Ok(None)
+        };
+        if self.get_type_of_var(&var_name)? != Type::Int {
+            return Ok(None);
+        }
+        // At least every other value of the table has its own branch
+        let min = *values.iter().min().unwrap_or(&0);
+        let max = *values.iter().max().unwrap_or(&0);
+        let span = (max as i128) - (min as i128) + 1;
+        if span > (values.len() * 2) as i128 {
+            return Ok(None);
+        }
+        Ok(Some((var_name, values)))
+    }
 
-        let jmp_end_pos_list = self.builder.get_tag(&tag_end)
-            .ok_or(CompilerError::Unknown)?;
+    /// Compiles an if/else if chain comparing a variable to constants into a jump through a table
+    /// in the data, indexed by the value. Values without a branch jump to the else block.
+    fn compile_jump_table(&mut self, if_stmt_args: &IfStatementArgs, var_name: &String, values: &[i64]) -> CompilerResult<()> {
+        let min = *values.iter().min().ok_or(CompilerError::Unknown)?;
+        let max = *values.iter().max().ok_or(CompilerError::Unknown)?;
+        let span = (max - min + 1) as usize;
+
+        let label_end = self.builder.new_label();
+        let label_else = self.builder.new_label();
+
+        self.compile_expr(&Expression::Variable(var_name.clone()))?;
+        let value_reg = self.get_last_register()?;
+
+        // Values outside of the table go to the else block. They are checked before
+        // subtracting the minimum, which would overflow for values far below it.
+        for (bound, opcode) in [(min, Opcode::LTI), (max, Opcode::GTI)] {
+            let bound_reg = self.get_next_register()?;
+            let ldi_instr = Instruction::new(Opcode::LDI)
+                .with_operand::<i64>(bound)
+                .with_operand::<u8>(bound_reg.clone().into());
+            self.builder.push_instr(ldi_instr);
+            let cmp_reg = self.get_next_register()?;
+            let cmp_instr = Instruction::new(opcode)
+                .with_operand::<u8>(value_reg.clone().into())
+                .with_operand::<u8>(bound_reg.into())
+                .with_operand::<u8>(cmp_reg.clone().into());
+            self.builder.push_instr(cmp_instr);
+            let jmpt_instr = Instruction::jmpt(cmp_reg, label_else);
+            self.builder.push_instr(jmpt_instr);
+        }
 
-        // Make all the jump instructions jump to the end properly
-        for jmp_end_pos in jmp_end_pos_list.iter() {
-            let jmp_instr = self.builder.get_instr(jmp_end_pos)
-                .ok_or(CompilerError::Unknown)?;
-            jmp_instr.remove_operand_bytes(8);
-            jmp_instr.append_operand(pos_end);
+        let index_reg = self.get_next_register()?;
+        let subi_instr = Instruction::new(Opcode::SUBI_I)
+            .with_operand::<u8>(value_reg.into())
+            .with_operand::<i64>(min)
+            .with_operand::<u8>(index_reg.clone().into());
+        self.builder.push_instr(subi_instr);
+
+        let table_offset = self.data.add_bytes(&vec![0; span * 8]);
+        let jmp_tbl_instr = Instruction::new(Opcode::JMP_TBL)
+            .with_operand::<u8>(index_reg.into())
+            .with_data_operand(table_offset);
+        self.builder.push_instr(jmp_tbl_instr);
+
+        let mut branch_blocks = vec![&if_stmt_args.if_block];
+        if let Some(else_if_list) = if_stmt_args.else_if_list.as_ref() {
+            branch_blocks.extend(else_if_list.iter().map(|(_, stmt_list)| stmt_list));
+        }
+        let mut entries = vec![label_else; span];
+        for (value, branch_stmt_list) in values.iter().zip(branch_blocks) {
+            let label_branch = self.builder.new_label();
+            entries[(value - min) as usize] = label_branch;
+            self.builder.place_label(label_branch);
+            self.compile_if_block(branch_stmt_list)?;
+            let jmp_end_instr = Instruction::jmp(label_end);
+            self.builder.push_instr(jmp_end_instr);
+        }
+        self.jump_tables.push((table_offset, entries));
+
+        self.builder.place_label(label_else);
+        if let Some(else_stmt_list) = if_stmt_args.else_block.as_ref() {
+            self.compile_if_block(else_stmt_list)?;
         }
+        self.builder.place_label(label_end);
 
         Ok(())
     }
 
+    /// Compiles the statement list of an if, else if or else branch in its own scope
+    fn compile_if_block(&mut self, stmt_list: &[Statement]) -> CompilerResult<()> {
+        // Create a new weak function context
+        let mut block_fn_ctx = {
+            let fn_ctx = self.get_current_function()?;
+            FunctionContext::new_weak(fn_ctx)?
+        };
+        // And push it on the stack
+        self.push_function_context(block_fn_ctx);
+
+        self.compile_stmt_list(stmt_list)?;
+
+        // Pop it off the stack again
+        block_fn_ctx = self.pop_function_context()?;
+
+        self.compile_stack_cleanup_block(&block_fn_ctx)
+    }
+
     /// Compiles a while statement
     pub fn compile_while_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
         let (while_expr, while_stmt_list) = match stmt {
@@ -1339,9 +2629,10 @@ impl Compiler {
 
         let while_fn_ctx = FunctionContext::new_loop(self.get_current_function()?)?;
         self.push_function_context(while_fn_ctx);
-        let while_start_pos = self.builder.get_current_offset();
-        let tag_end = self.uid_generator.generate();
-        let mut while_loop_ctx = LoopContext::new(while_start_pos, tag_end);
+        let label_start = self.builder.new_label();
+        let label_end = self.builder.new_label();
+        self.builder.place_label(label_start);
+        let while_loop_ctx = LoopContext::new(label_start, label_end);
         self.push_loop_context(while_loop_ctx);
 
         // Check type of while expr
@@ -1360,10 +2651,7 @@ impl Compiler {
                 .get_last_temp_register()?
         };
 
-        self.builder.tag(tag_end);
-        let jmpf_instr = Instruction::new(Opcode::JMPF)
-            .with_operand::<u8>(last_reg.into())
-            .with_operand(tag_end);
+        let jmpf_instr = Instruction::jmpf(last_reg, label_end);
         self.builder.push_instr(jmpf_instr);
 
         // Compile the statement list
@@ -1372,21 +2660,11 @@ impl Compiler {
         // Compile a continue statement
         self.compile_continue_stmt(&Statement::Continue)?;
 
-        // This is the end of this while loop
-        let while_end_pos = self.builder.get_current_offset();
-        
         // Pop the while loop off the stack
-        while_loop_ctx = self.pop_loop_context()?;
-        let instr_pos_list = self.builder.get_tag(&while_loop_ctx.tag_end)
-            .ok_or(CompilerError::Unknown)?;
-        
-        // Update with correct end position
-        for instr_pos in instr_pos_list {
-            let jmpf_instr = self.builder.get_instr(&instr_pos)
-                .ok_or(CompilerError::Unknown)?;
-            jmpf_instr.remove_operand_bytes(8);
-            jmpf_instr.append_operand::<u64>(while_end_pos as u64);
-        }
+        self.pop_loop_context()?;
+
+        // This is the end of this while loop
+        self.builder.place_label(label_end);
 
         // Pop this while loops fn context off the stack
         self.pop_function_context()?;
@@ -1394,25 +2672,150 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compiles an import inside a function body, which emits no code.
+    /// The import is visible in the rest of the enclosing block.
+    pub fn compile_import_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
+        let (import_path, import_as) = match stmt {
+            Statement::Import(import_path, import_as) => (import_path, import_as),
+            _ => return Err(CompilerError::Unknown)
+        };
+
+        let fn_ctx = self.get_current_function_mut()?;
+        fn_ctx.add_local_import(import_as.clone(), import_path.clone())?;
+
+        Ok(())
+    }
+
+    /// Compiles a yield, which suspends the core until the host resumes it
+    pub fn compile_yield_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
+        let yield_expr = match stmt {
+            Statement::Yield(yield_expr) => yield_expr,
+            _ => return Err(CompilerError::Unknown)
+        };
+
+        let (kind, value_reg) = match yield_expr {
+            Some(yield_expr) => {
+                let expr_type = self.check_expr_type(yield_expr)?;
+                // Only primitive values can be handed to the host
+                let kind = match expr_type {
+                    Type::Int => YIELD_INT,
+                    Type::Float if self.f64_floats => YIELD_DOUBLE,
+                    Type::Float => YIELD_FLOAT,
+                    Type::Bool => YIELD_BOOL,
+                    _ => return Err(CompilerError::UnsupportedExpression(yield_expr.clone()))
+                };
+                self.compile_expr(yield_expr)?;
+                (kind, self.get_last_register()?)
+            },
+            None => (YIELD_NONE, Register::R0)
+        };
+
+        let yield_instr = Instruction::new(Opcode::YIELD)
+            .with_operand::<u8>(kind)
+            .with_operand::<u8>(value_reg.into());
+        self.builder.push_instr(yield_instr);
+
+        Ok(())
+    }
+
+    /// Compiles an assert statement, which halts the core if its condition is false.
+    /// The failure message is passed to the core as string slice in r0 (size) and r1 (address).
+    pub fn compile_assert_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
+        let (assert_expr, message) = match stmt {
+            Statement::Assert(assert_expr, message) => (assert_expr, message),
+            _ => return Err(CompilerError::Unknown)
+        };
+
+        if !self.asserts {
+            return Ok(());
+        }
+
+        let expr_type = self.check_expr_type(assert_expr)?;
+        // Only boolean expressions are allowed
+        if expr_type != Type::Bool {
+            return Err(CompilerError::TypeMismatch(Type::Bool, expr_type));
+        }
+
+        self.compile_expr(assert_expr)?;
+        let last_reg = {
+            self.get_current_function()?
+                .register_allocator
+                .get_last_temp_register()?
+        };
+
+        let label_ok = self.builder.new_label();
+        let jmpt_instr = Instruction::jmpt(last_reg, label_ok);
+        self.builder.push_instr(jmpt_instr);
+
+        let err_code = match message {
+            Some(message) => {
+                // Equal messages share the same data
+                let (message_size, message_addr) = self.data.get_string_slice(message);
+                let size_lda_instr = Instruction::new(Opcode::LDA)
+                    .with_operand(message_size)
+                    .with_operand::<u8>(Register::R0.into());
+                let addr_lda_instr = Instruction::new(Opcode::LDA)
+                    .with_data_operand(message_addr)
+                    .with_operand::<u8>(Register::R1.into());
+                self.builder.push_instr(size_lda_instr);
+                self.builder.push_instr(addr_lda_instr);
+                ASSERT_MESSAGE_ERR_CODE
+            },
+            None => ASSERT_ERR_CODE
+        };
+        let halt_instr = Instruction::new(Opcode::HALT)
+            .with_operand::<u8>(err_code);
+        self.builder.push_instr(halt_instr);
+
+        self.builder.place_label(label_ok);
+
+        Ok(())
+    }
+
+    /// Compiles a comptime_assert statement, which fails the compilation if its condition
+    /// folds to false. Conditions which are no compile time constants are checked at runtime.
+    pub fn compile_comptime_assert_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
+        let (assert_expr, message) = match stmt {
+            Statement::ComptimeAssert(assert_expr, message) => (assert_expr, message),
+            _ => return Err(CompilerError::Unknown)
+        };
+
+        let expr_type = self.check_expr_type(assert_expr)?;
+        // Only boolean expressions are allowed
+        if expr_type != Type::Bool {
+            return Err(CompilerError::TypeMismatch(Type::Bool, expr_type));
+        }
+
+        match self.try_fold_const(assert_expr)? {
+            Some(Expression::BoolLiteral(true)) => Ok(()),
+            Some(_) => Err(CompilerError::ComptimeAssertionFailed(message.clone())),
+            None => {
+                let assert_stmt = Statement::Assert(assert_expr.clone(), message.clone());
+                self.compile_assert_stmt(&assert_stmt)
+            }
+        }
+    }
+
     /// Compiles a break statement
     pub fn compile_break_stmt(&mut self, stmt: &Statement) -> CompilerResult<()> {
         if *stmt != Statement::Break {
             return Err(CompilerError::Unknown);
         }
 
+        if self.loop_ctx_stack.is_empty() {
+            return Err(CompilerError::BreakOutsideLoop);
+        }
+
         // Compile the stack cleanup
-        self.compile_stack_loop()?;
+        self.compile_stack_loop(CompilerError::BreakOutsideLoop)?;
 
-        let tag_end = {
+        let label_end = {
             self.get_current_loop()?
-                .tag_end
+                .label_end
         };
 
-        // Tag this instruction
-        self.builder.tag(tag_end);
         // JMP to end instr
-        let jmp_end_instr = Instruction::new(Opcode::JMP)
-            .with_operand::<u64>(tag_end);
+        let jmp_end_instr = Instruction::jmp(label_end);
         self.builder.push_instr(jmp_end_instr);
 
         Ok(())
@@ -1424,17 +2827,20 @@ impl Compiler {
             return Err(CompilerError::Unknown);
         }
 
+        if self.loop_ctx_stack.is_empty() {
+            return Err(CompilerError::ContinueOutsideLoop);
+        }
+
         // Compile the stack cleanup
-        self.compile_stack_loop()?;
+        self.compile_stack_loop(CompilerError::ContinueOutsideLoop)?;
 
-        let loop_start_pos = {
+        let label_start = {
             self.get_current_loop()?
-                .pos_start
+                .label_start
         };
 
         // JMP to begin instr
-        let jmp_begin_instr = Instruction::new(Opcode::JMP)
-            .with_operand::<u64>(loop_start_pos as u64);
+        let jmp_begin_instr = Instruction::jmp(label_start);
         self.builder.push_instr(jmp_begin_instr);
         
         Ok(())
@@ -1479,9 +2885,7 @@ impl Compiler {
                             fn_ctx.register_allocator.get_last_temp_register()?
                         };
                         // Instruction for doing so
-                        let mov_ret_instr = Instruction::new(Opcode::MOVI)
-                            .with_operand::<u8>(last_reg.into())
-                            .with_operand::<u8>(Register::R0.into());
+                        let mov_ret_instr = Instruction::mov(Opcode::MOVI, last_reg, Register::R0);
                         self.builder.push_instr(mov_ret_instr);
                     },
                     Type::Float => {
@@ -1490,9 +2894,7 @@ impl Compiler {
                             fn_ctx.register_allocator.get_last_temp_register()?
                         };
                         // Instruction for doing so
-                        let mov_ret_instr = Instruction::new(Opcode::MOVF)
-                            .with_operand::<u8>(last_reg.into())
-                            .with_operand::<u8>(Register::R0.into());
+                        let mov_ret_instr = Instruction::mov(self.float_opcode(Opcode::MOVF), last_reg, Register::R0);
                         self.builder.push_instr(mov_ret_instr);
                     },
                     Type::Bool => {
@@ -1501,9 +2903,7 @@ impl Compiler {
                             fn_ctx.register_allocator.get_last_temp_register()?
                         };
                         // Instruction for doing so
-                        let mov_ret_instr = Instruction::new(Opcode::MOVB)
-                            .with_operand::<u8>(last_reg.into())
-                            .with_operand::<u8>(Register::R0.into());
+                        let mov_ret_instr = Instruction::mov(Opcode::MOVB, last_reg, Register::R0);
                         self.builder.push_instr(mov_ret_instr);
                     },
                     Type::Reference(_) => {
@@ -1512,9 +2912,7 @@ impl Compiler {
                             fn_ctx.register_allocator.get_last_temp_register()?
                         };
                         // Instruction for doing so
-                        let mov_ret_instr = Instruction::new(Opcode::MOVA)
-                            .with_operand::<u8>(last_reg.into())
-                            .with_operand::<u8>(Register::R0.into());
+                        let mov_ret_instr = Instruction::mov(Opcode::MOVA, last_reg, Register::R0);
                         self.builder.push_instr(mov_ret_instr);
                     },
                     _ => {}
@@ -1532,7 +2930,8 @@ impl Compiler {
         Ok(())
     }
 
-    /// Compiles a variable assign statement expression
+    /// Compiles a variable assign statement expression.
+    /// Containers are copied byte by byte, including nested containers, the destination never shares memory with the source.
     pub fn compile_var_assign_stmt_expr(&mut self, assign_expr: &Expression) -> CompilerResult<()> {
         let (lhs_expr, rhs_expr) = match assign_expr {
             Expression::Assign(lhs, rhs) => (lhs.deref().clone(), rhs.deref().clone()),
@@ -1569,10 +2968,7 @@ impl Compiler {
         let stack_inc_instr = Instruction::new_inc_stack(8);
         self.inc_stack(8)?;
 
-        let save_stack_instr = Instruction::new(Opcode::MOVA_RA)
-            .with_operand::<u8>(lhs_reg.into())
-            .with_operand::<u8>(Register::SP.into())
-            .with_operand::<i16>(-8);
+        let save_stack_instr = Instruction::mov_store(Opcode::MOVA_RA, lhs_reg, Register::SP, -8);
         
         self.builder.push_instr(stack_inc_instr);
         self.builder.push_instr(save_stack_instr);
@@ -1606,63 +3002,38 @@ impl Compiler {
 
         let stack_offset: i16 = {
             let curr_stack_size = self.get_stack_size()?;
-            -((curr_stack_size - lhs_ptr_pos) as i16)
+            neg_offset_operand("assignment stack offset", curr_stack_size - lhs_ptr_pos)?
         };
 
         // Move the pointer from the stack into the lhs register
-        let mov_stack_instr = Instruction::new(Opcode::MOVA_AR)
-            .with_operand::<u8>(Register::SP.into())
-            .with_operand::<i16>(stack_offset)
-            .with_operand::<u8>(lhs_reg.clone().into());
+        let mov_stack_instr = Instruction::mov_load(Opcode::MOVA_AR, Register::SP, stack_offset, lhs_reg.clone());
         self.builder.push_instr(mov_stack_instr);
 
         // Move the value to the assignment destination
         let assign_instr = match rhs_expr_type {
             Type::Int => {
                 //println!("Moving value from {:?} to the address in {:?}", rhs_reg, lhs_reg);
-                Instruction::new(Opcode::MOVI_RA)
-                    .with_operand::<u8>(rhs_reg.into())
-                    .with_operand::<u8>(lhs_reg.into())
-                    .with_operand::<i16>(0)
+                Instruction::mov_store(Opcode::MOVI_RA, rhs_reg, lhs_reg, 0)
             },
             Type::Float => {
-                Instruction::new(Opcode::MOVF_RA)
-                    .with_operand::<u8>(rhs_reg.into())
-                    .with_operand::<u8>(lhs_reg.into())
-                    .with_operand::<i16>(0)
+                Instruction::mov_store(self.float_opcode(Opcode::MOVF_RA), rhs_reg, lhs_reg, 0)
             },
             Type::Bool => {
-                Instruction::new(Opcode::MOVB_RA)
-                    .with_operand::<u8>(rhs_reg.into())
-                    .with_operand::<u8>(lhs_reg.into())
-                    .with_operand::<i16>(0)
+                Instruction::mov_store(Opcode::MOVB_RA, rhs_reg, lhs_reg, 0)
             },
-            Type::Reference(inner) => {
-                match inner.deref() {
-                    Type::AutoArray(_) => {
-                        Instruction::new(Opcode::MOVN_A)
-                            .with_operand::<u8>(Register::SP.into())
-                            .with_operand::<i16>(-16)
-                            .with_operand::<u8>(lhs_reg.into())
-                            .with_operand::<i16>(0)
-                            .with_operand::<u32>(16)
-                    },
-                    _ => {
-                        Instruction::new(Opcode::MOVA_RA)
-                            .with_operand::<u8>(rhs_reg.into())
-                            .with_operand::<u8>(lhs_reg.into())
-                            .with_operand::<i16>(0)
-                    }
-                }
+            Type::Reference(_) if rhs_expr_type.is_fat_pointer() => {
+                Instruction::movn(Register::SP, -16, lhs_reg, 0, 16)
+            },
+            Type::Reference(_) => {
+                Instruction::mov_store(Opcode::MOVA_RA, rhs_reg, lhs_reg, 0)
             },
             _ => {
                 let size = self.get_size_of_type(&rhs_expr_type)?;
-                Instruction::new(Opcode::MOVN_A)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(-(size as i16))
-                    .with_operand::<u8>(lhs_reg.into())
-                    .with_operand::<i16>(0)
-                    .with_operand::<u32>(size as u32)
+                Instruction::movn(
+                    Register::SP, neg_offset_operand("assigned value size", size)?,
+                    lhs_reg, 0,
+                    size_operand("assigned value size", size)?
+                )
             }
         };
 
@@ -1675,16 +3046,14 @@ impl Compiler {
     pub fn compile_lhs_assign_expr(&mut self, expr: &Expression) -> CompilerResult<Type> {
         let expr_type = match expr {
             Expression::Variable(var_name) => {
-                let stack_offset = self.get_sp_offset_of_var(var_name)?.abs() as u64;
+                self.get_root_function_mut()?.count_write(var_name);
+                let var_offset = self.get_fp_offset_of_var(var_name)?;
                 let target_reg = {
                     let fn_ctx = self.get_current_function_mut()?;
                     fn_ctx.register_allocator.get_temp_register()?
                 };
                 // Instruction for assign
-                let stack_offset_instr = Instruction::new(Opcode::SUBU_I)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<u64>(stack_offset)
-                    .with_operand::<u8>(target_reg.into());
+                let stack_offset_instr = self.new_fp_address_instr(var_offset, target_reg);
                 self.builder.push_instr(stack_offset_instr);
                 self.get_type_of_var(var_name)?
             },
@@ -1693,26 +3062,31 @@ impl Compiler {
                     Expression::Variable(var_name) => var_name,
                     _ => return Err(CompilerError::UnsupportedExpression(lhs_expr.deref().clone()))
                 };
-                let var_offset = self.get_sp_offset_of_var(var_name)?;
+                let var_offset = self.get_fp_offset_of_var(var_name)?;
                 let var_type = self.get_type_of_var(var_name)?;
                 let lhs_ptr_reg = self.get_next_register()?;
 
+                // Assigning through a reference reads the reference itself
+                if let Type::Reference(_) = var_type {
+                    self.get_root_function_mut()?.count_read(var_name);
+                } else {
+                    self.get_root_function_mut()?.count_write(var_name);
+                }
+
                 let cont_def = match var_type {
                     Type::Other(cont_name) => {
-                        let subui_instr = Instruction::new(Opcode::SUBU_I)
-                            .with_operand::<u8>(Register::SP.into())
-                            .with_operand::<u64>(var_offset.abs() as u64)
-                            .with_operand::<u8>(lhs_ptr_reg.into());
-                        self.builder.push_instr(subui_instr);
+                        let addr_instr = self.new_fp_address_instr(var_offset, lhs_ptr_reg);
+                        self.builder.push_instr(addr_instr);
                         self.resolve_container(&cont_name)?
                     },
                     Type::Reference(inner_type) => {
                         match inner_type.deref() {
                             Type::Other(cont_name) => {
-                                let mova_instr = Instruction::new(Opcode::MOVA_AR)
-                                    .with_operand::<u8>(Register::SP.into())
-                                    .with_operand::<i16>(var_offset as i16)
-                                    .with_operand::<u8>(lhs_ptr_reg.into());
+                                let mova_instr = Instruction::mov_load(
+                                    Opcode::MOVA_AR,
+                                    Register::FP, offset_operand("variable offset", var_offset)?,
+                                    lhs_ptr_reg
+                                );
                                 self.builder.push_instr(mova_instr);
                                 self.resolve_container(cont_name)?
                             },
@@ -1724,11 +3098,222 @@ impl Compiler {
 
                 self.compile_lhs_assign_member_expr(rhs_expr, &cont_def)?
             },
+            Expression::Index(array_expr, index_expr) => {
+                self.compile_index_address(array_expr, index_expr, true)?
+            },
+            Expression::Deref(op_expr) => {
+                let op_type = self.check_expr_type(op_expr)?;
+                let inner_type = match op_type {
+                    Type::Reference(inner_type) => match inner_type.deref() {
+                        Type::AutoArray(_) => return Err(CompilerError::CannotDerefSlice),
+                        _ => inner_type.deref().clone()
+                    },
+                    _ => return Err(CompilerError::CannotDerefNonPointer)
+                };
+                // The pointer itself is read, it is the destination address
+                self.compile_expr(op_expr)?;
+                inner_type
+            },
             _ => return Err(CompilerError::UnsupportedExpression(expr.clone()))
         };
         Ok(expr_type)
     }
 
+    /// Returns the type of the elements of an indexable type
+    fn get_index_elem_type(&self, array_type: &Type) -> CompilerResult<Type> {
+        match array_type {
+            Type::Array(elem_type, _) => Ok(elem_type.deref().clone()),
+            Type::Reference(inner_type) => match inner_type.deref() {
+                Type::Array(elem_type, _) => Ok(elem_type.deref().clone()),
+                _ => Err(CompilerError::NotIndexable(array_type.clone()))
+            },
+            _ => Err(CompilerError::NotIndexable(array_type.clone()))
+        }
+    }
+
+    /// Computes the address of an array element into the last temporary register,
+    /// returns the type of the element
+    fn compile_index_address(&mut self, array_expr: &Expression, index_expr: &Expression, is_write: bool) -> CompilerResult<Type> {
+        let index_type = self.check_expr_type(index_expr)?;
+        if index_type != Type::Int {
+            return Err(CompilerError::TypeMismatch(Type::Int, index_type));
+        }
+        let array_type = self.check_expr_type(array_expr)?;
+        let elem_type = self.get_index_elem_type(&array_type)?;
+        let elem_size = self.get_size_of_type(&elem_type)?;
+
+        // Constant indices into arrays of known size are checked right away
+        let array_size = match &array_type {
+            Type::Array(_, size) => Some(*size),
+            Type::Reference(inner_type) => match inner_type.deref() {
+                Type::Array(_, size) => Some(*size),
+                _ => None
+            },
+            _ => None
+        };
+        if let (Some(array_size), Some(Expression::IntLiteral(index))) = (array_size, self.try_fold_const(index_expr)?) {
+            if index < 0 || index as usize >= array_size {
+                return Err(CompilerError::IndexOutOfBounds(index, array_size));
+            }
+        }
+
+        // Offset of the element first, the base address only needs a single register
+        self.compile_expr(index_expr)?;
+        let index_reg = self.get_last_register()?;
+        let offset_reg = self.get_next_register()?;
+        let mului_instr = Instruction::new(Opcode::MULU_I)
+            .with_operand::<u8>(index_reg.into())
+            .with_operand::<u64>(elem_size as u64)
+            .with_operand::<u8>(offset_reg.clone().into());
+        self.builder.push_instr(mului_instr);
+
+        match (&array_type, array_expr) {
+            // Arrays in arrays are addressed through the element of the outer array
+            (Type::Array(_, _), Expression::Index(outer_array_expr, outer_index_expr)) => {
+                self.compile_index_address(outer_array_expr, outer_index_expr, is_write)?;
+            },
+            (Type::Array(_, _), Expression::Variable(var_name)) => {
+                if is_write {
+                    self.get_root_function_mut()?.count_write(var_name);
+                } else {
+                    self.get_root_function_mut()?.count_read(var_name);
+                }
+                let var_offset = self.get_fp_offset_of_var(var_name)?;
+                let base_reg = self.get_next_register()?;
+                let addr_instr = self.new_fp_address_instr(var_offset, base_reg);
+                self.builder.push_instr(addr_instr);
+            },
+            (Type::Array(_, _), _) => return Err(CompilerError::UnsupportedExpression(array_expr.clone())),
+            // Load the pointer, indexing through a reference reads it
+            _ => self.compile_expr(array_expr)?
+        };
+
+        let base_reg = self.get_last_register()?;
+        let target_reg = self.get_next_register()?;
+        let addu_instr = Instruction::new(Opcode::ADDU)
+            .with_operand::<u8>(base_reg.into())
+            .with_operand::<u8>(offset_reg.into())
+            .with_operand::<u8>(target_reg.into());
+        self.builder.push_instr(addu_instr);
+        Ok(elem_type)
+    }
+
+    /// Returns the size of the elements of a sliceable type, strings are sliced by bytes
+    fn get_slice_elem_size(&self, slice_type: &Type) -> CompilerResult<usize> {
+        match slice_type {
+            Type::String => Ok(1),
+            Type::Reference(inner_type) => match inner_type.deref() {
+                Type::AutoArray(elem_type) => self.get_size_of_type(elem_type),
+                _ => Err(CompilerError::NotIndexable(slice_type.clone()))
+            },
+            _ => Err(CompilerError::NotIndexable(slice_type.clone()))
+        }
+    }
+
+    /// Compiles a slice of a string or an array reference by a range.
+    /// The slice (size, address) on the stack is replaced with a view of the range,
+    /// bounds which are out of order or outside of the slice halt with SLICE_BOUNDS_ERR_CODE.
+    fn compile_slice_expr(&mut self, slice_expr: &Expression, range_expr: &Expression) -> CompilerResult<()> {
+        let (start_expr, end_expr) = match range_expr {
+            Expression::Range(start_expr, end_expr) => (start_expr, end_expr),
+            _ => return Err(CompilerError::Unknown)
+        };
+        let slice_type = self.check_expr_type(slice_expr)?;
+        if slice_type == Type::String && self.string_repr == StringRepr::Handle {
+            return self.compile_string_handle_slice(slice_expr, start_expr, end_expr);
+        }
+        let elem_size = self.get_slice_elem_size(&slice_type)?;
+
+        self.compile_expr(slice_expr)?;
+        let (start_reg, end_reg) = self.compile_binary_operands(start_expr, end_expr)?;
+
+        let size_reg = self.get_next_register()?;
+        let load_size_instr = Instruction::mov_load(Opcode::MOVA_AR, Register::SP, -16, size_reg.clone());
+        self.builder.push_instr(load_size_instr);
+        let zero_reg = self.get_next_register()?;
+        let ldi_instr = Instruction::new(Opcode::LDI)
+            .with_operand::<i64>(0)
+            .with_operand::<u8>(zero_reg.clone().into());
+        self.builder.push_instr(ldi_instr);
+
+        let label_trap = self.builder.new_label();
+        let bound_checks = [
+            (Opcode::LTI, start_reg.clone(), zero_reg),
+            (Opcode::LTI, end_reg.clone(), start_reg.clone()),
+            (Opcode::GTI, end_reg.clone(), size_reg)
+        ];
+        for (opcode, lhs_reg, rhs_reg) in bound_checks {
+            let cmp_reg = self.get_next_register()?;
+            let cmp_instr = Instruction::new(opcode)
+                .with_operand::<u8>(lhs_reg.into())
+                .with_operand::<u8>(rhs_reg.into())
+                .with_operand::<u8>(cmp_reg.clone().into());
+            self.builder.push_instr(cmp_instr);
+            let jmpt_instr = Instruction::jmpt(cmp_reg, label_trap);
+            self.builder.push_instr(jmpt_instr);
+        }
+
+        let new_size_reg = self.get_next_register()?;
+        let subi_instr = Instruction::new(Opcode::SUBI)
+            .with_operand::<u8>(end_reg.into())
+            .with_operand::<u8>(start_reg.clone().into())
+            .with_operand::<u8>(new_size_reg.clone().into());
+        let store_size_instr = Instruction::mov_store(Opcode::MOVA_RA, new_size_reg, Register::SP, -16);
+        self.builder.push_instr(subi_instr);
+        self.builder.push_instr(store_size_instr);
+
+        let offset_reg = self.get_next_register()?;
+        let mului_instr = Instruction::new(Opcode::MULU_I)
+            .with_operand::<u8>(start_reg.into())
+            .with_operand::<u64>(elem_size as u64)
+            .with_operand::<u8>(offset_reg.clone().into());
+        let addr_reg = self.get_next_register()?;
+        let load_addr_instr = Instruction::mov_load(Opcode::MOVA_AR, Register::SP, -8, addr_reg.clone());
+        let new_addr_reg = self.get_next_register()?;
+        let addu_instr = Instruction::new(Opcode::ADDU)
+            .with_operand::<u8>(addr_reg.into())
+            .with_operand::<u8>(offset_reg.into())
+            .with_operand::<u8>(new_addr_reg.clone().into());
+        let store_addr_instr = Instruction::mov_store(Opcode::MOVA_RA, new_addr_reg, Register::SP, -8);
+        self.builder.push_instr(mului_instr);
+        self.builder.push_instr(load_addr_instr);
+        self.builder.push_instr(addu_instr);
+        self.builder.push_instr(store_addr_instr);
+
+        let label_end = self.builder.new_label();
+        let jmp_end_instr = Instruction::jmp(label_end);
+        self.builder.push_instr(jmp_end_instr);
+        self.builder.place_label(label_trap);
+        let halt_instr = Instruction::new(Opcode::HALT)
+            .with_operand::<u8>(SLICE_BOUNDS_ERR_CODE);
+        self.builder.push_instr(halt_instr);
+        self.builder.place_label(label_end);
+
+        Ok(())
+    }
+
+    /// Compiles a slice of a string represented by a handle.
+    /// The core interns the slice, the handle on the stack is replaced with the handle of the slice.
+    /// Ranges the core can not slice the string by fail with CoreError::SliceOutOfBounds.
+    fn compile_string_handle_slice(&mut self, string_expr: &Expression, start_expr: &Expression, end_expr: &Expression) -> CompilerResult<()> {
+        self.compile_expr(string_expr)?;
+        let (start_reg, end_reg) = self.compile_binary_operands(start_expr, end_expr)?;
+
+        let handle_reg = self.get_next_register()?;
+        let slice_reg = self.get_next_register()?;
+        let load_handle_instr = Instruction::mov_load(Opcode::MOVI_AR, Register::SP, -8, handle_reg.clone());
+        let slice_instr = Instruction::new(Opcode::SLICE_STR)
+            .with_register_operand(handle_reg)
+            .with_register_operand(start_reg)
+            .with_register_operand(end_reg)
+            .with_register_operand(slice_reg.clone());
+        let store_handle_instr = Instruction::mov_store(Opcode::MOVI_RA, slice_reg, Register::SP, -8);
+        self.builder.push_instr(load_handle_instr);
+        self.builder.push_instr(slice_instr);
+        self.builder.push_instr(store_handle_instr);
+        Ok(())
+    }
+
     pub fn compile_lhs_assign_member_expr(&mut self, rhs_expr: &Expression, cont_def: &ContainerDef) -> CompilerResult<Type> {
         match rhs_expr {
             Expression::Variable(var_name) => {
@@ -1756,38 +3341,386 @@ impl Compiler {
                 let member_offset = cont_def.get_member_offset(self, var_name)?;
                 let member_type = cont_def.get_member_type(var_name)?;
 
-                let last_reg = self.get_last_register()?;
-                let next_reg = self.get_next_register()?;
+                let last_reg = self.get_last_register()?;
+                let next_reg = self.get_next_register()?;
+
+                let cont_def = match member_type {
+                    Type::Other(cont_name) => {
+                        let addui_instr = Instruction::new(Opcode::ADDU_I)
+                            .with_operand::<u8>(last_reg.into())
+                            .with_operand::<u64>(member_offset as u64)
+                            .with_operand::<u8>(next_reg.into());
+                        self.builder.push_instr(addui_instr);
+                        self.resolve_container(&cont_name)?
+                    },
+                    Type::Reference(inner_type) => {
+                        match inner_type.deref() {
+                            Type::Other(cont_name) => {
+                                let mova_instr = Instruction::mov_load(
+                                    Opcode::MOVA_AR,
+                                    last_reg, offset_operand("member offset", member_offset as i64)?,
+                                    next_reg
+                                );
+                                self.builder.push_instr(mova_instr);
+                                self.resolve_container(cont_name)?
+                            },
+                            _ => return Err(CompilerError::Unknown)
+                        }
+                    },
+                    _ => return Err(CompilerError::Unknown)
+                };
+
+                self.compile_lhs_assign_member_expr(rhs_expr, &cont_def)
+            },
+            _ => return Err(CompilerError::UnsupportedExpression(rhs_expr.deref().clone()))
+        }
+    }
+
+    /// Compiles both operands of a binary expression and returns their registers.
+    /// If the rhs contains a call, the lhs value is spilled to the stack,
+    /// as the callee is free to use every caller-saved register.
+    /// With callee-saved temps, it is moved into a free callee-saved register instead.
+    pub fn compile_binary_operands(&mut self, lhs: &Expression, rhs: &Expression) -> CompilerResult<(Register, Register)> {
+        self.compile_expr(lhs)?;
+        let lhs_reg = self.get_last_register()?;
+
+        if !rhs.contains_call() {
+            self.compile_expr(rhs)?;
+            let rhs_reg = self.get_last_register()?;
+            return Ok((lhs_reg, rhs_reg));
+        }
+
+        let lhs_type = self.check_expr_type(lhs)?;
+        let lhs_size = self.get_size_of_type(&lhs_type)?;
+        let (store_opcode, load_opcode, move_opcode) = match lhs_type {
+            Type::Int => (Opcode::MOVI_RA, Opcode::MOVI_AR, Opcode::MOVI),
+            Type::Float => (self.float_opcode(Opcode::MOVF_RA), self.float_opcode(Opcode::MOVF_AR), self.float_opcode(Opcode::MOVF)),
+            Type::Bool => (Opcode::MOVB_RA, Opcode::MOVB_AR, Opcode::MOVB),
+            Type::Reference(_) if lhs_type.is_primitive() => (Opcode::MOVA_RA, Opcode::MOVA_AR, Opcode::MOVA),
+            _ => return Err(CompilerError::UnsupportedExpression(lhs.clone()))
+        };
+
+        // Keep the lhs value in a callee-saved register, if one is free
+        let saved_reg = self.get_current_function_mut()?
+            .register_allocator
+            .take_callee_saved();
+        if let Some(saved_reg) = saved_reg {
+            let move_instr = Instruction::mov(move_opcode, lhs_reg, saved_reg.clone());
+            self.builder.push_instr(move_instr);
+
+            let rhs_res = self.compile_expr(rhs);
+            // The register is free again once the operands are used
+            self.get_current_function_mut()?
+                .register_allocator
+                .release_callee_saved(saved_reg.clone())?;
+            rhs_res?;
+            let rhs_reg = self.get_last_register()?;
+            return Ok((saved_reg, rhs_reg));
+        }
+
+        // Spill the lhs value
+        self.push_inc_stack_instr(lhs_size);
+        self.inc_stack(lhs_size)?;
+        let store_instr = Instruction::new(store_opcode)
+            .with_operand::<u8>(lhs_reg.into())
+            .with_operand::<u8>(Register::SP.into())
+            .with_operand::<i16>(neg_offset_operand("operand size", lhs_size)?);
+        self.builder.push_instr(store_instr);
+
+        self.compile_expr(rhs)?;
+        let rhs_reg = self.get_last_register()?;
+
+        // Reload the lhs value into a fresh temp register
+        let lhs_reg = self.get_next_register()?;
+        let load_instr = Instruction::new(load_opcode)
+            .with_operand::<u8>(Register::SP.into())
+            .with_operand::<i16>(neg_offset_operand("operand size", lhs_size)?)
+            .with_operand::<u8>(lhs_reg.clone().into());
+        self.builder.push_instr(load_instr);
+        self.push_dec_stack_instr(lhs_size);
+        self.dec_stack(lhs_size)?;
+
+        Ok((lhs_reg, rhs_reg))
+    }
+
+    /// Evaluates an expression at compile time.
+    /// Returns the resulting literal or None if the expression is no compile time constant.
+    pub fn try_fold_const(&self, expr: &Expression) -> CompilerResult<Option<Expression>> {
+        let folded = match expr {
+            Expression::IntLiteral(_) |
+            Expression::BoolLiteral(_) => Some(expr.clone()),
+            Expression::FloatLiteral(float) => Some(Expression::FloatLiteral(self.round_float(*float))),
+            Expression::Variable(var_name) => self.get_constant(var_name),
+            Expression::SizeOf(size_type) => {
+                let size = self.get_size_of_type(size_type)?;
+                Some(Expression::IntLiteral(size as i64))
+            },
+            Expression::Negate(op) => match self.try_fold_const(op)? {
+                Some(Expression::IntLiteral(int)) => int.checked_neg().map(Expression::IntLiteral),
+                Some(Expression::FloatLiteral(float)) => Some(Expression::FloatLiteral(-float)),
+                _ => None
+            },
+            Expression::Not(op) => match self.try_fold_const(op)? {
+                Some(Expression::BoolLiteral(boolean)) => Some(Expression::BoolLiteral(!boolean)),
+                _ => None
+            },
+            Expression::Addition(lhs, rhs) |
+            Expression::Subtraction(lhs, rhs) |
+            Expression::Multiplication(lhs, rhs) |
+            Expression::Division(lhs, rhs) |
+            Expression::And(lhs, rhs) |
+            Expression::Or(lhs, rhs) |
+            Expression::Equals(lhs, rhs) |
+            Expression::NotEquals(lhs, rhs) |
+            Expression::GreaterThan(lhs, rhs) |
+            Expression::LessThan(lhs, rhs) |
+            Expression::GreaterThanEquals(lhs, rhs) |
+            Expression::LessThanEquals(lhs, rhs) => {
+                let lhs = self.try_fold_const(lhs)?;
+                let rhs = self.try_fold_const(rhs)?;
+                match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => self.fold_binary_const(expr, lhs, rhs),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+        Ok(folded)
+    }
+
+    /// Folds a binary operation on two literals, None if the runtime result
+    /// cannot be known at compile time (overflow, division by zero)
+    fn fold_binary_const(&self, expr: &Expression, lhs: Expression, rhs: Expression) -> Option<Expression> {
+        let folded = match (expr, lhs, rhs) {
+            (Expression::Addition(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::IntLiteral(l.checked_add(r)?),
+            (Expression::Subtraction(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::IntLiteral(l.checked_sub(r)?),
+            (Expression::Multiplication(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::IntLiteral(l.checked_mul(r)?),
+            (Expression::Division(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::IntLiteral(l.checked_div(r)?),
+            (Expression::Addition(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::FloatLiteral(self.round_float(l + r)),
+            (Expression::Subtraction(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::FloatLiteral(self.round_float(l - r)),
+            (Expression::Multiplication(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::FloatLiteral(self.round_float(l * r)),
+            (Expression::Division(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::FloatLiteral(self.round_float(l / r)),
+            (Expression::And(_, _), Expression::BoolLiteral(l), Expression::BoolLiteral(r)) => Expression::BoolLiteral(l && r),
+            (Expression::Or(_, _), Expression::BoolLiteral(l), Expression::BoolLiteral(r)) => Expression::BoolLiteral(l || r),
+            (Expression::Equals(_, _), l, r) => Expression::BoolLiteral(l == r),
+            (Expression::NotEquals(_, _), l, r) => Expression::BoolLiteral(l != r),
+            (Expression::GreaterThan(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::BoolLiteral(l > r),
+            (Expression::LessThan(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::BoolLiteral(l < r),
+            (Expression::GreaterThanEquals(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::BoolLiteral(l >= r),
+            (Expression::LessThanEquals(_, _), Expression::IntLiteral(l), Expression::IntLiteral(r)) => Expression::BoolLiteral(l <= r),
+            (Expression::GreaterThan(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::BoolLiteral(l > r),
+            (Expression::LessThan(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::BoolLiteral(l < r),
+            (Expression::GreaterThanEquals(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::BoolLiteral(l >= r),
+            (Expression::LessThanEquals(_, _), Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => Expression::BoolLiteral(l <= r),
+            _ => return None
+        };
+        Some(folded)
+    }
+
+    /// Rounds a folded float to the precision floats have at runtime
+    fn round_float(&self, float: f64) -> f64 {
+        if self.f64_floats {
+            float
+        } else {
+            float as f32 as f64
+        }
+    }
+
+    /// Maps a 32 bit float opcode to its 64 bit variant if floats are 64 bit wide
+    fn float_opcode(&self, opcode: Opcode) -> Opcode {
+        if !self.f64_floats {
+            return opcode;
+        }
+        match opcode {
+            Opcode::MOVF => Opcode::MOVD,
+            Opcode::MOVF_A => Opcode::MOVD_A,
+            Opcode::MOVF_AR => Opcode::MOVD_AR,
+            Opcode::MOVF_RA => Opcode::MOVD_RA,
+            Opcode::ADDF => Opcode::ADDD,
+            Opcode::SUBF => Opcode::SUBD,
+            Opcode::MULF => Opcode::MULD,
+            Opcode::DIVF => Opcode::DIVD,
+            Opcode::ADDF_I => Opcode::ADDD_I,
+            Opcode::SUBF_I => Opcode::SUBD_I,
+            Opcode::MULF_I => Opcode::MULD_I,
+            Opcode::DIVF_I => Opcode::DIVD_I,
+            Opcode::EQF => Opcode::EQD,
+            Opcode::NEQF => Opcode::NEQD,
+            Opcode::LTF => Opcode::LTD,
+            Opcode::GTF => Opcode::GTD,
+            Opcode::LTEQF => Opcode::LTEQD,
+            Opcode::GTEQF => Opcode::GTEQD,
+            _ => opcode
+        }
+    }
+
+    /// Creates the instruction loading a float literal with the configured width
+    fn new_load_float_instr(&self, float: f64, reg: Register) -> Instruction {
+        if self.f64_floats {
+            Instruction::new(Opcode::LDD)
+                .with_operand::<f64>(float)
+                .with_operand::<u8>(reg.into())
+        } else {
+            Instruction::new(Opcode::LDF)
+                .with_operand::<f32>(float as f32)
+                .with_operand::<u8>(reg.into())
+        }
+    }
+
+    /// Compiles an array of a repeated element onto the stack, the element is evaluated once.
+    /// Arrays of up to array_unroll_limit elements get a copy per element, larger ones are filled in a loop.
+    fn compile_array_repeat_expr(&mut self, elem_expr: &Expression, count_expr: &Expression, span: &Range<usize>) -> CompilerResult<()> {
+        let elem_type = self.check_expr_type(elem_expr)?;
+        let elem_size = self.get_size_of_type(&elem_type)?;
+        let count = self.eval_array_size(count_expr, span)?;
+        if count == 0 || elem_size == 0 {
+            return Ok(());
+        }
+
+        // The first element, values in registers are stored from there to every element
+        self.compile_expr(elem_expr)?;
+        let elem_reg = self.get_last_register()?;
+        let store_opcode = match elem_type {
+            Type::Int => Some(Opcode::MOVI_RA),
+            Type::Float => Some(self.float_opcode(Opcode::MOVF_RA)),
+            Type::Bool => Some(Opcode::MOVB_RA),
+            Type::Reference(_) if elem_type.is_primitive() => Some(Opcode::MOVA_RA),
+            _ => None
+        };
+        if let Some(store_opcode) = store_opcode.clone() {
+            self.push_inc_stack_instr(elem_size);
+            self.inc_stack(elem_size)?;
+            let store_instr = Instruction::mov_store(
+                store_opcode, elem_reg.clone(),
+                Register::SP, neg_offset_operand("array element size", elem_size)?
+            );
+            self.builder.push_instr(store_instr);
+        }
+
+        let rest_size = (count - 1) * elem_size;
+        self.push_inc_stack_instr(rest_size);
+        self.inc_stack(rest_size)?;
+
+        if count <= self.array_unroll_limit {
+            for i in 1..count {
+                let elem_offset = neg_offset_operand("array size", rest_size - (i - 1) * elem_size)?;
+                let copy_instr = self.new_repeat_copy_instr(store_opcode.clone(), elem_reg.clone(), Register::SP, elem_offset, elem_size)?;
+                self.builder.push_instr(copy_instr);
+            }
+            return Ok(());
+        }
+
+        // The address of the next element runs up to the end of the array
+        let addr_reg = self.get_next_register()?;
+        let addr_instr = Instruction::new(Opcode::SUBU_I)
+            .with_register_operand(Register::SP)
+            .with_operand::<u64>(rest_size as u64)
+            .with_register_operand(addr_reg.clone());
+        self.builder.push_instr(addr_instr);
+
+        let label_loop = self.builder.new_label();
+        self.builder.place_label(label_loop);
+        let copy_instr = self.new_repeat_copy_instr(store_opcode, elem_reg, addr_reg.clone(), 0, elem_size)?;
+        let next_instr = Instruction::new(Opcode::ADDU_I)
+            .with_register_operand(addr_reg.clone())
+            .with_operand::<u64>(elem_size as u64)
+            .with_register_operand(addr_reg.clone());
+        let cond_reg = self.get_next_register()?;
+        let cond_instr = Instruction::new(Opcode::LTI)
+            .with_register_operand(addr_reg)
+            .with_register_operand(Register::SP)
+            .with_register_operand(cond_reg.clone());
+        self.builder.push_instr(copy_instr);
+        self.builder.push_instr(next_instr);
+        self.builder.push_instr(cond_instr);
+        self.builder.push_instr(Instruction::jmpt(cond_reg, label_loop));
+
+        Ok(())
+    }
+
+    /// Creates the instruction filling the element of a repeated array at an address.
+    /// Values in registers are stored, others are copied from the element before.
+    fn new_repeat_copy_instr(&self, store_opcode: Option<Opcode>, elem_reg: Register, addr_reg: Register, offset: i16, elem_size: usize) -> CompilerResult<Instruction> {
+        let copy_instr = match store_opcode {
+            Some(store_opcode) => Instruction::mov_store(store_opcode, elem_reg, addr_reg, offset),
+            None => Instruction::movn(
+                addr_reg.clone(), offset_operand("array element offset", offset as i64 - elem_size as i64)?,
+                addr_reg, offset,
+                size_operand("array element size", elem_size)?
+            )
+        };
+        Ok(copy_instr)
+    }
+
+    /// Interns a string in the data and pushes its slice (size, address) on the stack
+    fn compile_string_slice(&mut self, string: &String) -> CompilerResult<()> {
+        if self.string_repr == StringRepr::Handle {
+            return self.compile_string_handle(string);
+        }
+        let (string_size, string_addr) = self.data.get_string_slice(string);
+        let stack_inc_instr = Instruction::new_inc_stack(16);
+        self.inc_stack(16)?;
+
+        let size_reg = self.get_next_register()?;
+        let addr_reg = self.get_next_register()?;
+        
+        let size_lda_instr = Instruction::new(Opcode::LDA)
+            .with_operand(string_size)
+            .with_operand::<u8>(size_reg.clone().into());
+        let addr_lda_instr = Instruction::new(Opcode::LDA)
+            .with_data_operand(string_addr)
+            .with_operand::<u8>(addr_reg.clone().into());
+        let mov_size_instr = Instruction::mov_store(Opcode::MOVA_RA, size_reg, Register::SP, -16);
+        let mov_addr_instr = Instruction::mov_store(Opcode::MOVA_RA, addr_reg, Register::SP, -8);
+
+        self.builder.push_instr(stack_inc_instr);
+        self.builder.push_instr(size_lda_instr);
+        self.builder.push_instr(addr_lda_instr);
+        self.builder.push_instr(mov_size_instr);
+        self.builder.push_instr(mov_addr_instr);
+
+        Ok(())
+    }
+
+    /// Compiles a string as its handle on the stack, the string is interned on load
+    fn compile_string_handle(&mut self, string: &str) -> CompilerResult<()> {
+        let handle = self.strings.intern(string);
+        let stack_inc_instr = Instruction::new_inc_stack(8);
+        self.inc_stack(8)?;
+
+        let handle_reg = self.get_next_register()?;
+        let ldi_instr = Instruction::new(Opcode::LDI)
+            .with_operand::<i64>(handle as i64)
+            .with_register_operand(handle_reg.clone());
+        let mov_instr = Instruction::mov_store(Opcode::MOVI_RA, handle_reg, Register::SP, -8);
 
-                let cont_def = match member_type {
-                    Type::Other(cont_name) => {
-                        let addui_instr = Instruction::new(Opcode::ADDU_I)
-                            .with_operand::<u8>(last_reg.into())
-                            .with_operand::<u64>(member_offset as u64)
-                            .with_operand::<u8>(next_reg.into());
-                        self.builder.push_instr(addui_instr);
-                        self.resolve_container(&cont_name)?
-                    },
-                    Type::Reference(inner_type) => {
-                        match inner_type.deref() {
-                            Type::Other(cont_name) => {
-                                let mova_instr = Instruction::new(Opcode::MOVA_AR)
-                                    .with_operand::<u8>(last_reg.into())
-                                    .with_operand::<i16>(member_offset as i16)
-                                    .with_operand::<u8>(next_reg.into());
-                                self.builder.push_instr(mova_instr);
-                                self.resolve_container(cont_name)?
-                            },
-                            _ => return Err(CompilerError::Unknown)
-                        }
-                    },
-                    _ => return Err(CompilerError::Unknown)
-                };
+        self.builder.push_instr(stack_inc_instr);
+        self.builder.push_instr(ldi_instr);
+        self.builder.push_instr(mov_instr);
 
-                self.compile_lhs_assign_member_expr(rhs_expr, &cont_def)
-            },
-            _ => return Err(CompilerError::UnsupportedExpression(rhs_expr.deref().clone()))
+        Ok(())
+    }
+
+    /// Reads a file of include_str or include_bytes, relative to the include root
+    fn read_include(&self, path: &str, span: &Range<usize>) -> CompilerResult<Vec<u8>> {
+        let include_error = |reason: String| CompilerError::IncludeFailed {
+            path: String::from(path),
+            span: span.clone(),
+            reason
+        };
+        let include_root = self.include_root.as_ref()
+            .ok_or_else(|| include_error(String::from("no include root is set")))?
+            .canonicalize()
+            .map_err(|err| include_error(err.to_string()))?;
+        let full_path = include_root.join(path)
+            .canonicalize()
+            .map_err(|err| include_error(err.to_string()))?;
+        // Also catches absolute paths, joining replaces the root with them
+        if !full_path.starts_with(&include_root) {
+            return Err(include_error(String::from("the file is outside of the include root")));
         }
+        fs::read(&full_path)
+            .map_err(|err| include_error(err.to_string()))
     }
 
     /// Compiles an expression
@@ -1815,10 +3748,7 @@ impl Compiler {
                     fn_ctx.register_allocator.get_temp_register()?
                 };
 
-                let ldf_instr = Instruction::new(Opcode::LDF)
-                    .with_operand::<f32>(*float)
-                    .with_operand::<u8>(reg.into());
-                    
+                let ldf_instr = self.new_load_float_instr(*float, reg);
                 self.builder.push_instr(ldf_instr);
             },
             Expression::BoolLiteral(boolean) => {
@@ -1835,42 +3765,82 @@ impl Compiler {
             },
             Expression::StringLiteral(string) => {
                 let string = String::from(&string[1..string.len() - 1]);
-                let (string_size, string_addr) = self.data.get_string_slice(&string);
-                let stack_inc_instr = Instruction::new_inc_stack(16);
-                self.inc_stack(16)?;
+                self.compile_string_slice(&string)?;
+            },
+            Expression::TypeOf(op) => {
+                // Only the type is needed, the expression itself is never compiled
+                let op_type = self.check_expr_type(op)?;
+                self.compile_string_slice(&op_type.display())?;
+            },
+            Expression::SizeOf(size_type) => {
+                let size = self.get_size_of_type(size_type)?;
+                let reg = {
+                    let fn_ctx = self.get_current_function_mut()?;
+                    fn_ctx.register_allocator.get_temp_register()?
+                };
 
-                let size_reg = self.get_next_register()?;
-                let addr_reg = self.get_next_register()?;
-                
-                let size_lda_instr = Instruction::new(Opcode::LDA)
-                    .with_operand(string_size)
-                    .with_operand::<u8>(size_reg.clone().into());
-                let addr_lda_instr = Instruction::new(Opcode::LDA)
-                    .with_operand(string_addr)
-                    .with_operand::<u8>(addr_reg.clone().into());
-                let mov_size_instr = Instruction::new(Opcode::MOVA_RA)
-                    .with_operand::<u8>(size_reg.into())
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(-16);
-                let mov_addr_instr = Instruction::new(Opcode::MOVA_RA)
-                    .with_operand::<u8>(addr_reg.into())
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(-8);
-
-                self.builder.push_instr(stack_inc_instr);
-                self.builder.push_instr(size_lda_instr);
-                self.builder.push_instr(addr_lda_instr);
-                self.builder.push_instr(mov_size_instr);
-                self.builder.push_instr(mov_addr_instr);
+                let ldi_instr = Instruction::new(Opcode::LDI)
+                    .with_operand::<i64>(size as i64)
+                    .with_operand::<u8>(reg.into());
+
+                self.builder.push_instr(ldi_instr);
+            },
+            Expression::RawBytecode(bytes) => {
+                // Emitted as is, the expression has no result
+                self.builder.push_raw(bytes)
+                    .map_err(CompilerError::Builder)?;
+            },
+            Expression::IncludeStr(path, span) => {
+                let bytes = self.read_include(path, span)?;
+                let string = String::from_utf8(bytes)
+                    .map_err(|_| CompilerError::IncludeFailed {
+                        path: path.clone(),
+                        span: span.clone(),
+                        reason: String::from("the file is not valid UTF-8")
+                    })?;
+                self.compile_string_slice(&string)?;
+            },
+            Expression::IncludeBytes(path, span) => {
+                // Every byte is widened to an int, so the data can be indexed like any int array
+                let elems: Vec<u8> = self.read_include(path, span)?.into_iter()
+                    .flat_map(|byte| (byte as i64).to_le_bytes().to_vec())
+                    .collect();
+                let (_, elems_addr) = self.data.intern_bytes(&elems);
+                let reg = {
+                    let fn_ctx = self.get_current_function_mut()?;
+                    fn_ctx.register_allocator.get_temp_register()?
+                };
+                let lda_instr = Instruction::new(Opcode::LDA)
+                    .with_data_operand(elems_addr)
+                    .with_operand::<u8>(reg.into());
+                self.builder.push_instr(lda_instr);
             },
             Expression::ContainerInstance(_, _) => {
                 self.compile_cont_instance_expr(expr)?;
             },
-            Expression::Variable(_) => {
-                self.compile_var_expr(expr)?;
+            Expression::ArrayRepeat(elem_expr, count_expr, span) => {
+                self.compile_array_repeat_expr(elem_expr, count_expr, span)?;
+            },
+            Expression::Variable(var_name) => {
+                match self.get_constant(var_name) {
+                    Some(value) => self.compile_expr(&value)?,
+                    None => self.compile_var_expr(expr)?
+                };
             },
             Expression::Ref(op_expr) => {
                 self.compile_lhs_assign_expr(op_expr)?;
+                // The variable may be read through the reference
+                let ref_var = match op_expr.deref() {
+                    Expression::Variable(var_name) => Some(var_name),
+                    Expression::MemberAccess(lhs_expr, _) => match lhs_expr.deref() {
+                        Expression::Variable(var_name) => Some(var_name),
+                        _ => None
+                    },
+                    _ => None
+                };
+                if let Some(var_name) = ref_var {
+                    self.get_root_function_mut()?.count_read(var_name);
+                }
             },
             Expression::Deref(op_expr) => {
                 let expr_type = self.check_expr_type(op_expr)?;
@@ -1881,24 +3851,19 @@ impl Compiler {
                     let next_reg = self.get_next_register()?;
                     match ref_type {
                         Type::Int => {
-                            let movi_instr = Instruction::new(Opcode::MOVI_AR)
-                                .with_operand::<u8>(last_reg.into())
-                                .with_operand::<i16>(0)
-                                .with_operand::<u8>(next_reg.into());
+                            let movi_instr = Instruction::mov_load(Opcode::MOVI_AR, last_reg, 0, next_reg);
                             self.builder.push_instr(movi_instr);
                         },
                         Type::Float => {
-                            let movf_instr = Instruction::new(Opcode::MOVF_AR)
-                                .with_operand::<u8>(last_reg.into())
-                                .with_operand::<i16>(0)
-                                .with_operand::<u8>(next_reg.into());
+                            let movf_instr = Instruction::mov_load(
+                                self.float_opcode(Opcode::MOVF_AR),
+                                last_reg, 0,
+                                next_reg
+                            );
                             self.builder.push_instr(movf_instr);
                         },
                         Type::Bool => {
-                            let movb_instr = Instruction::new(Opcode::MOVB_AR)
-                                .with_operand::<u8>(last_reg.into())
-                                .with_operand::<i16>(0)
-                                .with_operand::<u8>(next_reg.into());
+                            let movb_instr = Instruction::mov_load(Opcode::MOVB_AR, last_reg, 0, next_reg);
                             self.builder.push_instr(movb_instr);
                         },
                         Type::Reference(inner_type) => {
@@ -1911,10 +3876,39 @@ impl Compiler {
                         },
                         _ => {}
                     };
+                } else if let Type::Other(_) = ref_type {
+                    // The container is copied onto the stack
+                    let last_reg = self.get_last_register()?;
+                    let size = self.get_size_of_type(&ref_type)?;
+                    self.push_inc_stack_instr(size);
+                    self.inc_stack(size)?;
+                    let movn_instr = Instruction::movn(
+                        last_reg, 0,
+                        Register::SP, neg_offset_operand("dereferenced container size", size)?,
+                        size_operand("dereferenced container size", size)?
+                    );
+                    self.builder.push_instr(movn_instr);
                 } else {
                     return Err(CompilerError::Unimplemented(format!("Deref of non-primitive pointer types")));
                 }
             },
+            Expression::Index(array_expr, index_expr) if matches!(index_expr.deref(), Expression::Range(_, _)) => {
+                self.compile_slice_expr(array_expr, index_expr)?;
+            },
+            Expression::Index(array_expr, index_expr) => {
+                let elem_type = self.compile_index_address(array_expr, index_expr, false)?;
+                let last_reg = self.get_last_register()?;
+                let opcode = match elem_type {
+                    Type::Int => Opcode::MOVI_AR,
+                    Type::Float => self.float_opcode(Opcode::MOVF_AR),
+                    Type::Bool => Opcode::MOVB_AR,
+                    Type::Reference(ref inner_type) if !matches!(inner_type.deref(), Type::AutoArray(_)) => Opcode::MOVA_AR,
+                    _ => return Err(CompilerError::Unimplemented(format!("Indexing into arrays of non-primitive types")))
+                };
+                let next_reg = self.get_next_register()?;
+                let mov_instr = Instruction::mov_load(opcode, last_reg, 0, next_reg);
+                self.builder.push_instr(mov_instr);
+            },
             Expression::MemberAccess(_, _) => {
                 //println!("Stack size before member access: {}", self.get_stack_size()?);
                 let expr_type = self.check_expr_type(expr)?;
@@ -1926,28 +3920,39 @@ impl Compiler {
                     match expr_type {
                         Type::Int => {
                             //println!("Saving member access return value int into {:?}", next_reg);
-                            let movi_instr = Instruction::new(Opcode::MOVI_AR)
-                                .with_operand::<u8>(last_reg.into())
-                                .with_operand::<i16>(0)
-                                .with_operand::<u8>(next_reg.into());
+                            let movi_instr = Instruction::mov_load(Opcode::MOVI_AR, last_reg, 0, next_reg);
                             self.builder.push_instr(movi_instr);
                         },
                         Type::Float => {
                             //println!("Saving member access return value int into {:?}", next_reg);
-                            let movf_instr = Instruction::new(Opcode::MOVF_AR)
-                                .with_operand::<u8>(last_reg.into())
-                                .with_operand::<i16>(0)
-                                .with_operand::<u8>(next_reg.into());
+                            let movf_instr = Instruction::mov_load(
+                                self.float_opcode(Opcode::MOVF_AR),
+                                last_reg, 0,
+                                next_reg
+                            );
                             self.builder.push_instr(movf_instr);
                         },
                         Type::Bool => {
-                        
+                            let movb_instr = Instruction::mov_load(Opcode::MOVB_AR, last_reg, 0, next_reg);
+                            self.builder.push_instr(movb_instr);
                         },
                         Type::Reference(_) => {
-
+                            let mova_instr = Instruction::mov_load(Opcode::MOVA_AR, last_reg, 0, next_reg);
+                            self.builder.push_instr(mova_instr);
                         },
                         _ => {}
                     };
+                } else if !expr.is_member_call() {
+                    // Other members are copied onto the stack
+                    let size = self.get_size_of_type(&expr_type)?;
+                    self.push_inc_stack_instr(size);
+                    self.inc_stack(size)?;
+                    let movn_instr = Instruction::movn(
+                        last_reg, 0,
+                        Register::SP, neg_offset_operand("member size", size)?,
+                        size_operand("member size", size)?
+                    );
+                    self.builder.push_instr(movn_instr);
                 }
                 //println!("Stack size after member access: {}", self.get_stack_size()?);
             },
@@ -1959,24 +3964,13 @@ impl Compiler {
                     fn_def.ret_type.clone()
                 };
                 if fn_ret_type.is_primitive() {
-                    self.get_current_function_mut()?
-                        .register_allocator
-                        .force_temp_register(Register::R0);
+                    self.compile_move_ret_value(&fn_ret_type)?;
                 }
                 //println!("Stack size after call expr: {}", self.get_stack_size()?);
             },
             Expression::Addition(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 //println!("Adding registers {:?} and {:?}", lhs_reg, rhs_reg);
                 match expr_type {
                     Type::Int => {
@@ -1996,7 +3990,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let addf_instr = Instruction::new(Opcode::ADDF)
+                        let addf_instr = Instruction::new(self.float_opcode(Opcode::ADDF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2007,16 +4001,7 @@ impl Compiler {
             },
             Expression::Subtraction(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 match expr_type {
                     Type::Int => {
                         let res_reg = {
@@ -2034,7 +4019,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let subf_instr = Instruction::new(Opcode::SUBF)
+                        let subf_instr = Instruction::new(self.float_opcode(Opcode::SUBF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2045,16 +4030,7 @@ impl Compiler {
             },
             Expression::Multiplication(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 match expr_type {
                     Type::Int => {
                         let res_reg = {
@@ -2072,7 +4048,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let mulf_instr = Instruction::new(Opcode::MULF)
+                        let mulf_instr = Instruction::new(self.float_opcode(Opcode::MULF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2083,16 +4059,7 @@ impl Compiler {
             },
             Expression::Division(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 match expr_type {
                     Type::Int => {
                         let res_reg = {
@@ -2110,7 +4077,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let divf_instr = Instruction::new(Opcode::DIVF)
+                        let divf_instr = Instruction::new(self.float_opcode(Opcode::DIVF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2121,16 +4088,7 @@ impl Compiler {
             },
             Expression::LessThan(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 match expr_type {
                     Type::Int => {
                         let res_reg = {
@@ -2148,7 +4106,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let ltf_instr = Instruction::new(Opcode::LTF)
+                        let ltf_instr = Instruction::new(self.float_opcode(Opcode::LTF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2160,16 +4118,7 @@ impl Compiler {
 
             Expression::GreaterThan(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 match expr_type {
                     Type::Int => {
                         let res_reg = {
@@ -2187,7 +4136,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let gtf_instr = Instruction::new(Opcode::GTF)
+                        let gtf_instr = Instruction::new(self.float_opcode(Opcode::GTF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2199,16 +4148,7 @@ impl Compiler {
 
             Expression::LessThanEquals(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 match expr_type {
                     Type::Int => {
                         let res_reg = {
@@ -2226,7 +4166,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let lteqf_instr = Instruction::new(Opcode::LTEQF)
+                        let lteqf_instr = Instruction::new(self.float_opcode(Opcode::LTEQF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2238,16 +4178,7 @@ impl Compiler {
 
             Expression::GreaterThanEquals(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 match expr_type {
                     Type::Int => {
                         let res_reg = {
@@ -2265,7 +4196,7 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let gteqf_instr = Instruction::new(Opcode::GTEQF)
+                        let gteqf_instr = Instruction::new(self.float_opcode(Opcode::GTEQF))
                             .with_operand::<u8>(lhs_reg.into())
                             .with_operand::<u8>(rhs_reg.into())
                             .with_operand::<u8>(res_reg.into());
@@ -2277,79 +4208,69 @@ impl Compiler {
 
             Expression::Equals(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                match expr_type {
-                    Type::Int => {
-                        let res_reg = {
-                            let fn_ctx = self.get_current_function_mut()?;
-                            fn_ctx.register_allocator.get_temp_register()?
-                        };
-                        let eqi_instr = Instruction::new(Opcode::EQI)
-                            .with_operand::<u8>(lhs_reg.into())
-                            .with_operand::<u8>(rhs_reg.into())
-                            .with_operand::<u8>(res_reg.into());
-                        self.builder.push_instr(eqi_instr);
-                    },
-                    Type::Float => {
-                        let res_reg = {
-                            let fn_ctx = self.get_current_function_mut()?;
-                            fn_ctx.register_allocator.get_temp_register()?
-                        };
-                        let eqf_instr = Instruction::new(Opcode::EQF)
-                            .with_operand::<u8>(lhs_reg.into())
-                            .with_operand::<u8>(rhs_reg.into())
-                            .with_operand::<u8>(res_reg.into());
-                        self.builder.push_instr(eqf_instr);
-                    },
-                    _ => return Err(CompilerError::UnsupportedExpression(lhs.deref().clone()))
-                };
+                if expr_type == Type::String {
+                    self.compile_string_equals(lhs, rhs, false)?;
+                } else {
+                    let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
+                    match expr_type {
+                        Type::Int => {
+                            let res_reg = {
+                                let fn_ctx = self.get_current_function_mut()?;
+                                fn_ctx.register_allocator.get_temp_register()?
+                            };
+                            let eqi_instr = Instruction::new(Opcode::EQI)
+                                .with_operand::<u8>(lhs_reg.into())
+                                .with_operand::<u8>(rhs_reg.into())
+                                .with_operand::<u8>(res_reg.into());
+                            self.builder.push_instr(eqi_instr);
+                        },
+                        Type::Float => {
+                            let res_reg = {
+                                let fn_ctx = self.get_current_function_mut()?;
+                                fn_ctx.register_allocator.get_temp_register()?
+                            };
+                            let eqf_instr = Instruction::new(self.float_opcode(Opcode::EQF))
+                                .with_operand::<u8>(lhs_reg.into())
+                                .with_operand::<u8>(rhs_reg.into())
+                                .with_operand::<u8>(res_reg.into());
+                            self.builder.push_instr(eqf_instr);
+                        },
+                        _ => return Err(CompilerError::UnsupportedExpression(lhs.deref().clone()))
+                    };
+                }
             },
             Expression::NotEquals(lhs, rhs) => {
                 let expr_type = self.check_expr_type(lhs)?;
-                self.compile_expr(lhs)?;
-                let lhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                self.compile_expr(rhs)?;
-                let rhs_reg = {
-                    let fn_ctx = self.get_current_function()?;
-                    fn_ctx.register_allocator.get_last_temp_register()?
-                };
-                match expr_type {
-                    Type::Int => {
-                        let res_reg = {
-                            let fn_ctx = self.get_current_function_mut()?;
-                            fn_ctx.register_allocator.get_temp_register()?
-                        };
-                        let neqi_instr = Instruction::new(Opcode::NEQI)
-                            .with_operand::<u8>(lhs_reg.into())
-                            .with_operand::<u8>(rhs_reg.into())
-                            .with_operand::<u8>(res_reg.into());
-                        self.builder.push_instr(neqi_instr);
-                    },
-                    Type::Float => {
-                        let res_reg = {
-                            let fn_ctx = self.get_current_function_mut()?;
-                            fn_ctx.register_allocator.get_temp_register()?
-                        };
-                        let neqf_instr = Instruction::new(Opcode::NEQF)
-                            .with_operand::<u8>(lhs_reg.into())
-                            .with_operand::<u8>(rhs_reg.into())
-                            .with_operand::<u8>(res_reg.into());
-                        self.builder.push_instr(neqf_instr);
-                    },
-                    _ => return Err(CompilerError::UnsupportedExpression(lhs.deref().clone()))
-                };
+                if expr_type == Type::String {
+                    self.compile_string_equals(lhs, rhs, true)?;
+                } else {
+                    let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
+                    match expr_type {
+                        Type::Int => {
+                            let res_reg = {
+                                let fn_ctx = self.get_current_function_mut()?;
+                                fn_ctx.register_allocator.get_temp_register()?
+                            };
+                            let neqi_instr = Instruction::new(Opcode::NEQI)
+                                .with_operand::<u8>(lhs_reg.into())
+                                .with_operand::<u8>(rhs_reg.into())
+                                .with_operand::<u8>(res_reg.into());
+                            self.builder.push_instr(neqi_instr);
+                        },
+                        Type::Float => {
+                            let res_reg = {
+                                let fn_ctx = self.get_current_function_mut()?;
+                                fn_ctx.register_allocator.get_temp_register()?
+                            };
+                            let neqf_instr = Instruction::new(self.float_opcode(Opcode::NEQF))
+                                .with_operand::<u8>(lhs_reg.into())
+                                .with_operand::<u8>(rhs_reg.into())
+                                .with_operand::<u8>(res_reg.into());
+                            self.builder.push_instr(neqf_instr);
+                        },
+                        _ => return Err(CompilerError::UnsupportedExpression(lhs.deref().clone()))
+                    };
+                }
             },
             Expression::Not(op) => {
                 self.compile_expr(op)?;
@@ -2364,11 +4285,39 @@ impl Compiler {
                     .with_operand::<u8>(target_reg.into());
                 self.builder.push_instr(not_instr);
             },
+            Expression::Negate(op) => {
+                let op_type = self.check_expr_type(op)?;
+                self.compile_expr(op)?;
+                let (op_reg, zero_reg, target_reg) = {
+                    let fn_ctx = self.get_current_function_mut()?;
+                    let op_reg = fn_ctx.register_allocator.get_last_temp_register()?;
+                    let zero_reg = fn_ctx.register_allocator.get_temp_register()?;
+                    let target_reg = fn_ctx.register_allocator.get_temp_register()?;
+                    (op_reg, zero_reg, target_reg)
+                };
+                // Negation is a subtraction from zero
+                let (ld_instr, sub_opcode) = match op_type {
+                    Type::Int => {
+                        let ldi_instr = Instruction::new(Opcode::LDI)
+                            .with_operand::<i64>(0)
+                            .with_operand::<u8>(zero_reg.clone().into());
+                        (ldi_instr, Opcode::SUBI)
+                    },
+                    Type::Float => {
+                        let ldf_instr = self.new_load_float_instr(0.0, zero_reg.clone());
+                        (ldf_instr, self.float_opcode(Opcode::SUBF))
+                    },
+                    _ => return Err(CompilerError::UnsupportedExpression(op.deref().clone()))
+                };
+                let sub_instr = Instruction::new(sub_opcode)
+                    .with_operand::<u8>(zero_reg.into())
+                    .with_operand::<u8>(op_reg.into())
+                    .with_operand::<u8>(target_reg.into());
+                self.builder.push_instr(ld_instr);
+                self.builder.push_instr(sub_instr);
+            },
             Expression::And(lhs, rhs) => {
-                self.compile_expr(lhs)?;
-                let lhs_reg = self.get_last_register()?;
-                self.compile_expr(rhs)?;
-                let rhs_reg = self.get_last_register()?;
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 let target_reg = self.get_next_register()?;
                 let and_instr = Instruction::new(Opcode::AND)
                     .with_operand::<u8>(lhs_reg.into())
@@ -2377,10 +4326,7 @@ impl Compiler {
                 self.builder.push_instr(and_instr);
             },
             Expression::Or(lhs, rhs) => {
-                self.compile_expr(lhs)?;
-                let lhs_reg = self.get_last_register()?;
-                self.compile_expr(rhs)?;
-                let rhs_reg = self.get_last_register()?;
+                let (lhs_reg, rhs_reg) = self.compile_binary_operands(lhs, rhs)?;
                 let target_reg = self.get_next_register()?;
                 let or_instr = Instruction::new(Opcode::OR)
                     .with_operand::<u8>(lhs_reg.into())
@@ -2399,13 +4345,12 @@ impl Compiler {
 
         if !expr_type.is_primitive() {
             pop_size -= expr_size;
-            if pop_size > 0 {
-                let mov_stack_instr = Instruction::new(Opcode::MOVN_A)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(-(expr_size as i16))
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(-(stack_diff as i16))
-                    .with_operand::<u32>(16);
+            if pop_size > 0 && expr_size > 0 {
+                let mov_stack_instr = Instruction::movn(
+                    Register::SP, neg_offset_operand("expression size", expr_size)?,
+                    Register::SP, neg_offset_operand("temporary stack size", stack_diff)?,
+                    size_operand("expression size", expr_size)?
+                );
                 self.builder.push_instr(mov_stack_instr);
             }
         }
@@ -2428,13 +4373,35 @@ impl Compiler {
         };
 
         let last_reg = self.get_last_register()?;
-        let lhs_reg = self.get_next_register()?;
+        let mut lhs_reg = self.get_next_register()?;
 
         let var_type = match lhs_expr {
+            // The returned container stays on the stack until compile_expr pops it
+            Expression::Call(fn_name, _) if cont_def.is_none() => {
+                self.compile_call_expr(lhs_expr)?;
+                let ret_type = self.resolve_function(fn_name)?.ret_type.clone();
+                match &ret_type {
+                    Type::Other(_) => {
+                        let ret_size = self.get_size_of_type(&ret_type)?;
+                        lhs_reg = self.get_next_register()?;
+                        let addr_instr = Instruction::new(Opcode::SUBU_I)
+                            .with_operand::<u8>(Register::SP.into())
+                            .with_operand::<u64>(ret_size as u64)
+                            .with_operand::<u8>(lhs_reg.clone().into());
+                        self.builder.push_instr(addr_instr);
+                    },
+                    Type::Reference(inner_type) if matches!(inner_type.deref(), Type::Other(_)) => {
+                        lhs_reg = self.compile_move_ret_value(&ret_type)?;
+                    },
+                    _ => return Err(CompilerError::MemberAccessOnNonContainer)
+                };
+                ret_type
+            },
             Expression::Variable(var_name) => {
                 // If variable is on stack
                 if cont_def.is_none() {
-                    let var_offset = self.get_sp_offset_of_var(var_name)?;
+                    self.get_root_function_mut()?.count_read(var_name);
+                    let var_offset = self.get_fp_offset_of_var(var_name)?;
                     //println!("Member access of stack variable {}. Saving [SP]-{} into register {:?}.", var_name, var_offset.abs(), lhs_reg);
                     let var_type = self.get_type_of_var(var_name)?;
                     //println!("Compiling member access for var {}:{:?} at offset {}", var_name, var_type, var_offset);
@@ -2442,21 +4409,19 @@ impl Compiler {
                         Type::Other(cont_name) => {
                             //println!("Doing this by subtracting {} from SP.", var_offset.abs());
                             //println!("Converting [SP]-8 to pointer in register {:?}", lhs_reg);
-                            let subui_instr = Instruction::new(Opcode::SUBU_I)
-                                .with_operand::<u8>(Register::SP.into())
-                                .with_operand::<u64>(var_offset.abs() as u64)
-                                .with_operand::<u8>(lhs_reg.clone().into());
-                            self.builder.push_instr(subui_instr);
+                            let addr_instr = self.new_fp_address_instr(var_offset, lhs_reg.clone());
+                            self.builder.push_instr(addr_instr);
                         },
                         Type::Reference(inner_type) => {
                             match inner_type.deref() {
                                 Type::Other(cont_name) => {
                                     //println!("Doing this by moving pointer at [SP]-{}.", var_offset.abs());
                                     //println!("Saving pointer at [SP]-8 to register {:?}", lhs_reg);
-                                    let mova_instr = Instruction::new(Opcode::MOVA_AR)
-                                        .with_operand::<u8>(Register::SP.into())
-                                        .with_operand::<i16>(var_offset as i16)
-                                        .with_operand::<u8>(lhs_reg.clone().into());
+                                    let mova_instr = Instruction::mov_load(
+                                        Opcode::MOVA_AR,
+                                        Register::FP, offset_operand("variable offset", var_offset)?,
+                                        lhs_reg.clone()
+                                    );
                                     self.builder.push_instr(mova_instr);
                                     //println!("Is reference. moving pointer into register {:?}", lhs_reg);
                                 },
@@ -2478,10 +4443,11 @@ impl Compiler {
                             match inner_type.deref() {
                                 _ => {
                                     //println!("Doing this by moving the pointer at [{:?}]+{} into {:?}.", last_reg, member_offset, lhs_reg);
-                                    let mova_instr = Instruction::new(Opcode::MOVA_AR)
-                                        .with_operand::<u8>(last_reg.into())
-                                        .with_operand::<i16>(member_offset as i16)
-                                        .with_operand::<u8>(lhs_reg.clone().into());
+                                    let mova_instr = Instruction::mov_load(
+                                        Opcode::MOVA_AR,
+                                        last_reg, offset_operand("member offset", member_offset as i64)?,
+                                        lhs_reg.clone()
+                                    );
                                     self.builder.push_instr(mova_instr);
                                 }
                             };
@@ -2536,36 +4502,16 @@ impl Compiler {
                     fn_def.ret_type.clone()
                 };
                 if ret_type.is_primitive() {
-                    //println!("Forcing temp register to R0");
-                    self.get_current_function_mut()?
-                        .register_allocator
-                        .force_temp_register(Register::R0);
+                    self.compile_move_ret_value(&ret_type)?;
                 }
                 //println!("Stack size after member call expr: {}", self.get_stack_size()?);
             },
-            Expression::MemberAccess(member_expr, _) => {
+            // The lhs of the nested access is a member of this container, addressed from its pointer
+            Expression::MemberAccess(_, _) => {
                 let rhs_reg = self.get_next_register()?;
-                let member_name = match member_expr.deref() {
-                    Expression::Variable(var_name) => var_name,
-                    _ => return Err(CompilerError::UnsupportedExpression(member_expr.deref().clone()))
-                };
-                let member_type = cont_def.get_member_type(member_name)?;
-                let cont_name = match &member_type {
-                    Type::Other(cont_name) => cont_name,
-                    Type::Reference(inner_type) => {
-                        match inner_type.deref() {
-                            Type::Other(cont_name) => cont_name,
-                            _ => return Err(CompilerError::MemberAccessOnNonContainer)
-                        }
-                    },
-                    _ => return Err(CompilerError::MemberAccessOnNonContainer)
-                };
-                let inner_cont_def = self.resolve_container(cont_name)?;
-                let mova_instr = Instruction::new(Opcode::MOVA)
-                    .with_operand::<u8>(lhs_reg.into())
-                    .with_operand::<u8>(rhs_reg.into());
+                let mova_instr = Instruction::mov(Opcode::MOVA, lhs_reg, rhs_reg);
                 self.builder.push_instr(mova_instr);
-                self.compile_member_access_expr(rhs_expr, Some(&inner_cont_def))?;
+                self.compile_member_access_expr(rhs_expr, Some(&cont_def))?;
             },
             _ => return Err(CompilerError::UnsupportedExpression(rhs_expr.clone()))
         };
@@ -2573,6 +4519,54 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compiles a string comparison as a call of the built-in `__builtin::string::equals`
+    fn compile_string_equals(&mut self, lhs: &Expression, rhs: &Expression, negate: bool) -> CompilerResult<()> {
+        if self.string_repr == StringRepr::Handle {
+            return self.compile_string_handles_equal(lhs, rhs, negate);
+        }
+        let equals_call = Expression::Call(
+            format!("root::{}::string::equals", builtin::BUILTIN_MODULE),
+            vec![lhs.clone(), rhs.clone()]
+        );
+        self.compile_expr(&equals_call)?;
+        if negate {
+            let (op_reg, target_reg) = {
+                let fn_ctx = self.get_current_function_mut()?;
+                let op_reg = fn_ctx.register_allocator.get_last_temp_register()?;
+                let target_reg = fn_ctx.register_allocator.get_temp_register()?;
+                (op_reg, target_reg)
+            };
+            let not_instr = Instruction::new(Opcode::NOT)
+                .with_operand::<u8>(op_reg.into())
+                .with_operand::<u8>(target_reg.into());
+            self.builder.push_instr(not_instr);
+        }
+        Ok(())
+    }
+
+    /// Compiles a string comparison as a comparison of the handles, equal strings share one
+    fn compile_string_handles_equal(&mut self, lhs: &Expression, rhs: &Expression, negate: bool) -> CompilerResult<()> {
+        // Both handles are put onto the stack by their expressions
+        self.compile_expr(lhs)?;
+        self.compile_expr(rhs)?;
+
+        let lhs_reg = self.get_next_register()?;
+        let rhs_reg = self.get_next_register()?;
+        let res_reg = self.get_next_register()?;
+        let load_lhs_instr = Instruction::mov_load(Opcode::MOVI_AR, Register::SP, -16, lhs_reg.clone());
+        let load_rhs_instr = Instruction::mov_load(Opcode::MOVI_AR, Register::SP, -8, rhs_reg.clone());
+        let cmp_instr = Instruction::new(if negate { Opcode::NEQI } else { Opcode::EQI })
+            .with_register_operand(lhs_reg)
+            .with_register_operand(rhs_reg)
+            .with_register_operand(res_reg);
+        self.builder.push_instr(load_lhs_instr);
+        self.builder.push_instr(load_rhs_instr);
+        self.push_dec_stack_instr(16);
+        self.dec_stack(16)?;
+        self.builder.push_instr(cmp_instr);
+        Ok(())
+    }
+
     /// Compiles a member call expression
     pub fn compile_member_call_expr(&mut self, expr: &Expression, cont_def: &ContainerDef) -> CompilerResult<()> {
         //println!("Line 2718");
@@ -2589,8 +4583,13 @@ impl Compiler {
 
         let fn_ret_size = self.get_size_of_type(&fn_def.ret_type)?;
 
+        // this is not counted, it is never passed explicitly
         if fn_arg_exprs.len() + 1 != fn_def.arguments.len() {
-            return Err(CompilerError::UnknownFunction(fn_name.clone()));
+            return Err(CompilerError::ArgumentCountMismatch {
+                name: fn_name.clone(),
+                expected: fn_def.arguments.len().saturating_sub(1),
+                found: fn_arg_exprs.len()
+            });
         }
 
         let fn_def_first_arg_type = {
@@ -2609,10 +4608,7 @@ impl Compiler {
         //println!("Address of container should be in {:?}", last_reg);
         let stack_inc_instr = Instruction::new_inc_stack(8);
         self.inc_stack(8)?;
-        let mova_instr = Instruction::new(Opcode::MOVA_RA)
-            .with_operand::<u8>(last_reg.into())
-            .with_operand::<u8>(Register::SP.into())
-            .with_operand::<i16>(-8);
+        let mova_instr = Instruction::mov_store(Opcode::MOVA_RA, last_reg, Register::SP, -8);
         self.builder.push_instr(stack_inc_instr);
         self.builder.push_instr(mova_instr);
 
@@ -2641,12 +4637,11 @@ impl Compiler {
             if !fn_arg_type.is_primitive() {
                 pop_size -= size;
                 if pop_size > 0 {
-                    let mov_stack_instr = Instruction::new(Opcode::MOVN_A)
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16))
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(stack_diff as i16))
-                        .with_operand::<u32>(size as u32);
+                    let mov_stack_instr = Instruction::movn(
+                        Register::SP, -(size as i16),
+                        Register::SP, -(stack_diff as i16),
+                        size as u32
+                    );
                     self.builder.push_instr(mov_stack_instr);
                 }
             }
@@ -2666,43 +4661,40 @@ impl Compiler {
 
             if expr_type.is_primitive() {
                 //println!("incrementing stack for primitive type arg");
-                let stack_instr = Instruction::new_inc_stack(size);
-                self.builder.push_instr(stack_instr);
+                self.push_inc_stack_instr(size);
                 self.inc_stack(size)?;
             }
 
             let mov_instr_opt = match expr_type {
                 Type::Int => {
-                    Some(Instruction::new(Opcode::MOVI_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16)))
+                    Some(Instruction::mov_store(
+                        Opcode::MOVI_RA, last_reg,
+                        Register::SP, neg_offset_operand("argument size", size)?
+                    ))
                 },
                 Type::Float => {
-                    Some(Instruction::new(Opcode::MOVF_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16)))
+                    Some(Instruction::mov_store(
+                        self.float_opcode(Opcode::MOVF_RA), last_reg,
+                        Register::SP, neg_offset_operand("argument size", size)?
+                    ))
                 },
                 Type::Bool => {
-                    Some(Instruction::new(Opcode::MOVB_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16)))
+                    Some(Instruction::mov_store(
+                        Opcode::MOVB_RA, last_reg,
+                        Register::SP, neg_offset_operand("argument size", size)?
+                    ))
                 },
                 Type::String => None,
-                Type::Reference(inner_type) => {
-                    match inner_type.deref() {
-                        Type::AutoArray(_) => None,
-                        _ => {
-                            Some(
-                                Instruction::new(Opcode::MOVA_RA)
-                                    .with_operand::<u8>(last_reg.into())
-                                    .with_operand::<u8>(Register::SP.into())
-                                    .with_operand::<i16>(-(size as i16))
-                            )
-                        }
-                    }
+                // Containers are copied onto the stack by their expression
+                Type::Other(_) => None,
+                Type::Reference(_) if expr_type.is_fat_pointer() => None,
+                Type::Reference(_) => {
+                    Some(
+                        Instruction::mov_store(
+                            Opcode::MOVA_RA, last_reg,
+                            Register::SP, neg_offset_operand("argument size", size)?
+                        )
+                    )
                 },
                 _ => {
                     //println!("Error in compile_call_expr()!");
@@ -2717,8 +4709,7 @@ impl Compiler {
             stack_size = self.get_stack_size()?;
         }
 
-        let call_instr = Instruction::new(Opcode::CALL)
-            .with_operand::<u64>(fn_def.uid);
+        let call_instr = self.new_call_instr(fn_def.uid);
         self.builder.push_instr(call_instr);
         if !fn_def.ret_type.is_primitive() {
             self.inc_stack(fn_ret_size)?;
@@ -2727,13 +4718,13 @@ impl Compiler {
         let stack_diff = self.get_stack_size()? - before_stack_size;
         //println!("Stack diff after member call expr: {}", stack_diff);
         let mut pop_size = stack_diff;
-        if !fn_def.ret_type.is_primitive() {
-            let mov_stack_instr = Instruction::new(Opcode::MOVN_A)
-                .with_operand::<u8>(Register::SP.into())
-                .with_operand::<i16>(-(fn_ret_size as i16))
-                .with_operand::<u8>(Register::SP.into())
-                .with_operand::<i16>(-(stack_diff as i16))
-                .with_operand::<u32>(fn_ret_size as u32);
+        // A zero sized return value, like void, is never moved
+        if !fn_def.ret_type.is_primitive() && fn_ret_size > 0 {
+            let mov_stack_instr = Instruction::movn(
+                Register::SP, neg_offset_operand("return value size", fn_ret_size)?,
+                Register::SP, neg_offset_operand("temporary stack size", stack_diff)?,
+                size_operand("return value size", fn_ret_size)?
+            );
             pop_size -= fn_ret_size;
             self.builder.push_instr(mov_stack_instr);
         }
@@ -2780,30 +4771,30 @@ impl Compiler {
                 Type::Int => {
                     let stack_inc_instr = Instruction::new_inc_stack(8);
                     self.inc_stack(8)?;
-                    let movi_instr = Instruction::new(Opcode::MOVI_RA)
-                        .with_operand::<u8>(last_reg.clone().into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-8);
+                    let movi_instr = Instruction::mov_store(Opcode::MOVI_RA, last_reg.clone(), Register::SP, -8);
                     self.builder.push_instr(stack_inc_instr);
                     self.builder.push_instr(movi_instr);
                 },
                 Type::Bool => {
-                    let stack_inc_instr = Instruction::new_inc_stack(1);
-                    self.inc_stack(1)?;
-                    let movb_instr = Instruction::new(Opcode::MOVB_RA)
-                        .with_operand::<u8>(last_reg.clone().into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-1);
+                    // Bools occupy the full size of their type inside a container
+                    let bool_size = self.get_size_of_type(&Type::Bool)?;
+                    let stack_inc_instr = Instruction::new_inc_stack(bool_size);
+                    self.inc_stack(bool_size)?;
+                    let movb_instr = Instruction::mov_store(
+                        Opcode::MOVB_RA, last_reg.clone(),
+                        Register::SP, neg_offset_operand("member size", bool_size)?
+                    );
                     self.builder.push_instr(stack_inc_instr);
                     self.builder.push_instr(movb_instr);
                 },
                 Type::Float => {
-                    let stack_inc_instr = Instruction::new_inc_stack(4);
-                    self.inc_stack(4)?;
-                    let movf_instr = Instruction::new(Opcode::MOVF_RA)
-                        .with_operand::<u8>(last_reg.clone().into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-4);
+                    let float_size = self.get_size_of_type(&Type::Float)?;
+                    let stack_inc_instr = Instruction::new_inc_stack(float_size);
+                    self.inc_stack(float_size)?;
+                    let movf_instr = Instruction::mov_store(
+                        self.float_opcode(Opcode::MOVF_RA), last_reg.clone(),
+                        Register::SP, neg_offset_operand("member size", float_size)?
+                    );
                     self.builder.push_instr(stack_inc_instr);
                     self.builder.push_instr(movf_instr);
                 },
@@ -2813,10 +4804,10 @@ impl Compiler {
                         _ => {
                             let stack_inc_instr = Instruction::new_inc_stack(8);
                             self.inc_stack(8)?;
-                            let mova_instr = Instruction::new(Opcode::MOVA_RA)
-                                .with_operand::<u8>(last_reg.clone().into())
-                                .with_operand::<u8>(Register::SP.into())
-                                .with_operand::<i16>(-8);
+                            let mova_instr = Instruction::mov_store(
+                                Opcode::MOVA_RA, last_reg.clone(),
+                                Register::SP, -8
+                            );
                             self.builder.push_instr(stack_inc_instr);
                             self.builder.push_instr(mova_instr);
                         }
@@ -2829,6 +4820,25 @@ impl Compiler {
         Ok(())
     }
 
+    /// Moves a primitive return value out of R0 into a fresh temp register,
+    /// so it is not overwritten by following calls
+    pub fn compile_move_ret_value(&mut self, ret_type: &Type) -> CompilerResult<Register> {
+        let opcode = match ret_type {
+            Type::Int => Opcode::MOVI,
+            Type::Float => self.float_opcode(Opcode::MOVF),
+            Type::Bool => Opcode::MOVB,
+            Type::Reference(_) => Opcode::MOVA,
+            _ => return Err(CompilerError::UnknownType(ret_type.clone()))
+        };
+        let temp_reg = {
+            let fn_ctx = self.get_current_function_mut()?;
+            fn_ctx.register_allocator.get_temp_register()?
+        };
+        let mov_instr = Instruction::mov(opcode, Register::R0, temp_reg.clone());
+        self.builder.push_instr(mov_instr);
+        Ok(temp_reg)
+    }
+
     /// Compiles a call expresion
     pub fn compile_call_expr(&mut self, expr: &Expression) -> CompilerResult<()> {
         //println!("Line 2718");
@@ -2844,7 +4854,11 @@ impl Compiler {
         let fn_ret_size = self.get_size_of_type(&fn_def.ret_type)?;
 
         if fn_arg_exprs.len() != fn_def.arguments.len() {
-            return Err(CompilerError::UnknownFunction(fn_name.clone()));
+            return Err(CompilerError::ArgumentCountMismatch {
+                name: fn_name.clone(),
+                expected: fn_def.arguments.len(),
+                found: fn_arg_exprs.len()
+            });
         }
         
         let before_call_stack_size = self.get_stack_size()?;
@@ -2876,13 +4890,13 @@ impl Compiler {
 
             if !expr_type.is_primitive() {
                 pop_size -= size;
-                if pop_size > 0 {
-                    let mov_stack_instr = Instruction::new(Opcode::MOVN_A)
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16))
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(stack_diff as i16))
-                        .with_operand::<u32>(size as u32);
+                // Zero sized values are never moved
+                if pop_size > 0 && size > 0 {
+                    let mov_stack_instr = Instruction::movn(
+                        Register::SP, neg_offset_operand("argument size", size)?,
+                        Register::SP, neg_offset_operand("temporary stack size", stack_diff)?,
+                        size_operand("argument size", size)?
+                    );
                     self.builder.push_instr(mov_stack_instr);
                 }
             }
@@ -2902,43 +4916,40 @@ impl Compiler {
 
             if expr_type.is_primitive() {
                 //println!("incrementing stack for primitive type arg");
-                let stack_instr = Instruction::new_inc_stack(size);
-                self.builder.push_instr(stack_instr);
+                self.push_inc_stack_instr(size);
                 self.inc_stack(size)?;
             }
 
             let mov_instr_opt = match expr_type {
                 Type::Int => {
-                    Some(Instruction::new(Opcode::MOVI_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16)))
+                    Some(Instruction::mov_store(
+                        Opcode::MOVI_RA, last_reg,
+                        Register::SP, neg_offset_operand("argument size", size)?
+                    ))
                 },
                 Type::Float => {
-                    Some(Instruction::new(Opcode::MOVF_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16)))
+                    Some(Instruction::mov_store(
+                        self.float_opcode(Opcode::MOVF_RA), last_reg,
+                        Register::SP, neg_offset_operand("argument size", size)?
+                    ))
                 },
                 Type::Bool => {
-                    Some(Instruction::new(Opcode::MOVB_RA)
-                        .with_operand::<u8>(last_reg.into())
-                        .with_operand::<u8>(Register::SP.into())
-                        .with_operand::<i16>(-(size as i16)))
+                    Some(Instruction::mov_store(
+                        Opcode::MOVB_RA, last_reg,
+                        Register::SP, neg_offset_operand("argument size", size)?
+                    ))
                 },
                 Type::String => None,
-                Type::Reference(inner_type) => {
-                    match inner_type.deref() {
-                        Type::AutoArray(_) => None,
-                        _ => {
-                            Some(
-                                Instruction::new(Opcode::MOVA_RA)
-                                    .with_operand::<u8>(last_reg.into())
-                                    .with_operand::<u8>(Register::SP.into())
-                                    .with_operand::<i16>(-(size as i16))
-                            )
-                        }
-                    }
+                // Containers are copied onto the stack by their expression
+                Type::Other(_) => None,
+                Type::Reference(_) if expr_type.is_fat_pointer() => None,
+                Type::Reference(_) => {
+                    Some(
+                        Instruction::mov_store(
+                            Opcode::MOVA_RA, last_reg,
+                            Register::SP, neg_offset_operand("argument size", size)?
+                        )
+                    )
                 },
                 _ => {
                     //println!("Error in compile_call_expr()!");
@@ -2953,8 +4964,7 @@ impl Compiler {
             stack_size = self.get_stack_size()?;
         }
 
-        let call_instr = Instruction::new(Opcode::CALL)
-            .with_operand::<u64>(fn_def.uid);
+        let call_instr = self.new_call_instr(fn_def.uid);
         self.builder.push_instr(call_instr);
         if !fn_def.ret_type.is_primitive() {
             self.inc_stack(fn_ret_size)?;
@@ -2964,20 +4974,19 @@ impl Compiler {
         //println!("Stack diff after args + call: {}", stack_diff);
         let mut pop_size = stack_diff;
 
-        if !fn_def.ret_type.is_primitive() {
-            let mov_stack_instr = Instruction::new(Opcode::MOVN_A)
-                .with_operand::<u8>(Register::SP.into())
-                .with_operand::<i16>(-(fn_ret_size as i16))
-                .with_operand::<u8>(Register::SP.into())
-                .with_operand::<i16>(-(stack_diff as i16))
-                .with_operand::<u32>(fn_ret_size as u32);
+        // A zero sized return value, like void, is never moved
+        if !fn_def.ret_type.is_primitive() && fn_ret_size > 0 {
+            let mov_stack_instr = Instruction::movn(
+                Register::SP, neg_offset_operand("return value size", fn_ret_size)?,
+                Register::SP, neg_offset_operand("temporary stack size", stack_diff)?,
+                size_operand("return value size", fn_ret_size)?
+            );
             pop_size -= fn_ret_size;
             self.builder.push_instr(mov_stack_instr);
         }
         
-        let stack_dec_instr = Instruction::new_dec_stack(pop_size);
         self.dec_stack(pop_size)?;
-        self.builder.push_instr(stack_dec_instr);
+        self.push_dec_stack_instr(pop_size);
 
         Ok(())
     }
@@ -2991,18 +5000,20 @@ impl Compiler {
 
         //println!("Compiling var expr");
 
+        self.get_root_function_mut()?.count_read(var_name);
         let var_type = self.get_type_of_var(var_name)?;
-        let mut var_offset = self.get_sp_offset_of_var(var_name)?;
+        let var_offset = self.get_fp_offset_of_var(var_name)?;
         match var_type {
             Type::Int => {
                 let reg = {
                     let fn_ctx = self.get_current_function_mut()?;
                     fn_ctx.register_allocator.get_temp_register()?
                 };
-                let movi_instr = Instruction::new(Opcode::MOVI_AR)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(var_offset as i16)
-                    .with_operand::<u8>(reg.into());
+                let movi_instr = Instruction::mov_load(
+                    Opcode::MOVI_AR,
+                    Register::FP, offset_operand("variable offset", var_offset)?,
+                    reg
+                );
                 self.builder.push_instr(movi_instr);
             },
             Type::Float => {
@@ -3010,10 +5021,11 @@ impl Compiler {
                     let fn_ctx = self.get_current_function_mut()?;
                     fn_ctx.register_allocator.get_temp_register()?
                 };
-                let movf_instr = Instruction::new(Opcode::MOVF_AR)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(var_offset as i16)
-                    .with_operand::<u8>(reg.into());
+                let movf_instr = Instruction::mov_load(
+                    self.float_opcode(Opcode::MOVF_AR),
+                    Register::FP, offset_operand("variable offset", var_offset)?,
+                    reg
+                );
                 self.builder.push_instr(movf_instr);
             },
             Type::Bool => {
@@ -3021,10 +5033,11 @@ impl Compiler {
                     let fn_ctx = self.get_current_function_mut()?;
                     fn_ctx.register_allocator.get_temp_register()?
                 };
-                let movb_instr = Instruction::new(Opcode::MOVB_AR)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(var_offset as i16)
-                    .with_operand::<u8>(reg.into());
+                let movb_instr = Instruction::mov_load(
+                    Opcode::MOVB_AR,
+                    Register::FP, offset_operand("variable offset", var_offset)?,
+                    reg
+                );
                 self.builder.push_instr(movb_instr);
             },
             Type::Reference(inner_type) => {
@@ -3032,13 +5045,11 @@ impl Compiler {
                     Type::AutoArray(_) => {
                         let stack_inc_instr = Instruction::new_inc_stack(16);
                         self.inc_stack(16)?;
-                        var_offset -= 16;
-                        let movn_instr = Instruction::new(Opcode::MOVN_A)
-                            .with_operand::<u8>(Register::SP.into())
-                            .with_operand::<i16>(var_offset as i16)
-                            .with_operand::<u8>(Register::SP.into())
-                            .with_operand::<i16>(-16)
-                            .with_operand::<u32>(16);
+                        let movn_instr = Instruction::movn(
+                            Register::FP, offset_operand("variable offset", var_offset)?,
+                            Register::SP, -16,
+                            16
+                        );
                         self.builder.push_instr(stack_inc_instr);
                         self.builder.push_instr(movn_instr);
                     },
@@ -3047,10 +5058,11 @@ impl Compiler {
                             let fn_ctx = self.get_current_function_mut()?;
                             fn_ctx.register_allocator.get_temp_register()?
                         };
-                        let mova_instr = Instruction::new(Opcode::MOVA_AR)
-                            .with_operand::<u8>(Register::SP.into())
-                            .with_operand::<i16>(var_offset as i16)
-                            .with_operand::<u8>(reg.into());
+                        let mova_instr = Instruction::mov_load(
+                            Opcode::MOVA_AR,
+                            Register::FP, offset_operand("variable offset", var_offset)?,
+                            reg
+                        );
                         self.builder.push_instr(mova_instr);
                     }
                 };
@@ -3059,19 +5071,15 @@ impl Compiler {
                 let cont_def = self.resolve_container(&cont_name)?;
                 let size = cont_def.get_size(self)?;
 
-                let stack_inc_instr = Instruction::new_inc_stack(size);
                 self.inc_stack(size)?;
 
-                var_offset -= size as i64;
-
-                let movn_instr = Instruction::new(Opcode::MOVN_A)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(var_offset as i16)
-                    .with_operand::<u8>(Register::SP.into())
-                    .with_operand::<i16>(-(size as i16))
-                    .with_operand::<u32>(size as u32);
+                let movn_instr = Instruction::movn(
+                    Register::FP, offset_operand("variable offset", var_offset)?,
+                    Register::SP, neg_offset_operand("variable size", size)?,
+                    size_operand("variable size", size)?
+                );
                 
-                self.builder.push_instr(stack_inc_instr);
+                self.push_inc_stack_instr(size);
                 self.builder.push_instr(movn_instr);
             },
             _ => {
@@ -3107,12 +5115,32 @@ impl Compiler {
                     _ => return Err(CompilerError::CannotDerefNonPointer)
                 };
             },
+            Expression::Index(array_expr, index_expr) => {
+                let array_type = self.check_expr_type(array_expr)?;
+                match index_expr.deref() {
+                    Expression::Range(start_expr, end_expr) => {
+                        for bound_expr in [start_expr, end_expr] {
+                            let bound_type = self.check_expr_type(bound_expr)?;
+                            if bound_type != Type::Int {
+                                return Err(CompilerError::TypeMismatch(Type::Int, bound_type));
+                            }
+                        }
+                        // A slice is a view of the same type
+                        self.get_slice_elem_size(&array_type)?;
+                        array_type
+                    },
+                    _ => self.get_index_elem_type(&array_type)?
+                }
+            },
             Expression::Call(fn_name, _) => {
                 let fn_def = self.resolve_function(fn_name)?;
                 fn_def.ret_type
             },
             Expression::Variable(var_name) => {
-                self.get_type_of_var(var_name)?
+                match self.get_constant(var_name) {
+                    Some(value) => self.check_expr_type(&value)?,
+                    None => self.get_type_of_var(var_name)?
+                }
             },
             Expression::MemberAccess(_, _) => {
                 self.check_member_access_expr_type(expr, None)?
@@ -3215,6 +5243,34 @@ impl Compiler {
                 }
                 Type::Bool
             },
+            Expression::Negate(op) => {
+                let op_type = self.check_expr_type(op)?;
+                if op_type != Type::Int && op_type != Type::Float {
+                    return Err(CompilerError::TypeMismatch(Type::Int, op_type));
+                }
+                op_type
+            },
+            Expression::TypeOf(op) => {
+                // The inner expression still has to be well typed
+                self.check_expr_type(op)?;
+                Type::String
+            },
+            Expression::SizeOf(size_type) => {
+                // Unknown types are rejected
+                self.get_size_of_type(size_type)?;
+                Type::Int
+            },
+            Expression::RawBytecode(_) => Type::Void,
+            Expression::ArrayRepeat(elem_expr, count_expr, span) => {
+                let elem_type = self.check_expr_type(elem_expr)?;
+                let count = self.eval_array_size(count_expr, span)?;
+                Type::Array(Box::new(elem_type), count)
+            },
+            Expression::IncludeStr(_, _) => Type::String,
+            Expression::IncludeBytes(path, span) => {
+                let len = self.read_include(path, span)?.len();
+                Type::Reference(Box::new(Type::Array(Box::new(Type::Int), len)))
+            },
             Expression::And(lhs, rhs) => {
                 let lhs_type = self.check_expr_type(lhs)?;
                 let rhs_type = self.check_expr_type(rhs)?;
@@ -3255,6 +5311,9 @@ impl Compiler {
                     cont_def.get_member_type(var_name)?
                 }
             },
+            Expression::Call(fn_name, _) if cont_def.is_none() => {
+                self.resolve_function(fn_name)?.ret_type.clone()
+            },
             _ => return Err(CompilerError::UnsupportedExpression(lhs_expr.clone()))
         };
 
@@ -3279,24 +5338,9 @@ impl Compiler {
                 let fn_def = cont_def.get_member_function(fn_name)?;
                 Ok(fn_def.ret_type.clone())
             },
-            Expression::MemberAccess(member_expr, _) => {
-                let member_name = match member_expr.deref() {
-                    Expression::Variable(var_name) => var_name,
-                    _ => return Err(CompilerError::UnsupportedExpression(member_expr.deref().clone()))
-                };
-                let member_type = cont_def.get_member_type(member_name)?;
-                let child_cont_name = match &member_type {
-                    Type::Other(cont_name) => cont_name,
-                    Type::Reference(inner_type) => {
-                        match inner_type.deref() {
-                            Type::Other(cont_name) => cont_name,
-                            _ => return Err(CompilerError::MemberAccessOnNonContainer)
-                        }
-                    },
-                    _ => return Err(CompilerError::MemberAccessOnNonContainer)
-                };
-                let child_cont_def = self.resolve_container(child_cont_name)?;
-                self.check_member_access_expr_type(rhs_expr, Some(&child_cont_def))
+            // The lhs of the nested access is a member of this container
+            Expression::MemberAccess(_, _) => {
+                self.check_member_access_expr_type(rhs_expr, Some(&cont_def))
             },
             _ => return Err(CompilerError::MemberAccessOnNonContainer)
         }
@@ -12,11 +12,15 @@ use crate::{
             CompilerResult,
             CompilerError,
             Compiler
-        }
+        },
+        builder::Label,
+        program::LocalVariable,
+        suggest::suggest_name
     },
     parser::{
         ast::{
-            Type
+            Type,
+            Expression
         }
     }
 };
@@ -24,16 +28,19 @@ use crate::{
 use std::{
     collections::{
         HashMap
-    }
+    },
+    thread
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ModuleContext {
     pub name: String,
     pub modules: HashMap<String, ModuleContext>,
     pub functions: HashMap<String, FunctionDef>,
     pub containers: HashMap<String, ContainerDef>,
-    pub imports: HashMap<String, String>
+    pub imports: HashMap<String, String>,
+    /// Values of the module constants, as literals
+    pub constants: HashMap<String, Expression>
 }
 
 impl ModuleContext {
@@ -44,7 +51,8 @@ impl ModuleContext {
             modules: HashMap::new(),
             functions: HashMap::new(),
             containers: HashMap::new(),
-            imports: HashMap::new()
+            imports: HashMap::new(),
+            constants: HashMap::new()
         }
     }
 
@@ -92,6 +100,17 @@ impl ModuleContext {
         Ok(())
     }
 
+    /// Adds the value of a constant to a module context.
+    /// Throws a DuplicateConstant error if a constant with the
+    /// same name already exists.
+    pub fn add_constant(&mut self, name: String, value: Expression) -> CompilerResult<()> {
+        if self.constants.contains_key(&name) {
+            return Err(CompilerError::DuplicateConstant(name));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
     /// Gets a mutable reference to a container definition, given the name
     pub fn get_container_mut(&mut self, name: &String) -> CompilerResult<&mut ContainerDef> {
         self.containers.get_mut(name)
@@ -107,7 +126,16 @@ impl ModuleContext {
     /// Gets a reference to the function definition, given the name
     pub fn get_function(&self, name: &String) -> CompilerResult<&FunctionDef> {
         self.functions.get(name)
-            .ok_or(CompilerError::UnknownFunction(name.clone()))
+            .ok_or_else(|| CompilerError::UnknownFunction(name.clone(), suggest_name(name, self.functions.keys())))
+    }
+
+    /// Searches this module and all of its submodules (depth first) for a function
+    pub fn find_function_anywhere(&self, name: &str) -> Option<&FunctionDef> {
+        if let Some(fn_def) = self.functions.get(name) {
+            return Some(fn_def);
+        }
+        self.modules.values()
+            .find_map(|mod_ctx| mod_ctx.find_function_anywhere(name))
     }
 }
 
@@ -123,8 +151,18 @@ pub struct FunctionContext {
     pub weak: bool,
     pub is_loop: bool,
     pub stack_size: usize,
+    /// Offset of the beginning of this context to the frame pointer
+    pub frame_offset: i64,
     variable_types: HashMap<String, Type>,
     variable_positions: HashMap<String, i64>,
+    /// How often each variable was read, only counted in the context of the function itself
+    read_count: HashMap<String, usize>,
+    /// How often each variable was assigned, only counted in the context of the function itself
+    write_count: HashMap<String, usize>,
+    /// Imports of the function body, by the name they are imported as
+    local_imports: HashMap<String, String>,
+    /// Local functions of the function body and the enclosing ones, by name to their name in the module
+    local_functions: HashMap<String, String>,
     pub register_allocator: RegisterAllocator
 }
 
@@ -152,65 +190,57 @@ impl FunctionContext {
                 weak: false,
                 is_loop: false,
                 stack_size: 0,
+                frame_offset: 0,
                 variable_types: variable_types,
                 variable_positions: variable_positions,
-                register_allocator: RegisterAllocator::new()
+                read_count: HashMap::new(),
+                write_count: HashMap::new(),
+                local_imports: HashMap::new(),
+                local_functions: HashMap::new(),
+                register_allocator: new_register_allocator(compiler.has_callee_saved_temps())?
             }
         )
     }
 
     pub fn new_weak(fn_ctx: &FunctionContext) -> CompilerResult<FunctionContext> {
-        let stack_size = fn_ctx.stack_size as i64;
-
-        let mut variable_positions = HashMap::new();
-
-        for (var_name, var_pos) in fn_ctx.variable_positions.iter() {
-            let var_offset = var_pos - stack_size;
-            if var_offset >= 0 {
-                return Err(CompilerError::Unknown);
-            }
-            variable_positions.insert(var_name.clone(), var_offset);
-        }
-
         Ok(
             FunctionContext {
                 def: None,
                 weak: true,
                 is_loop: false,
                 stack_size: 0,
+                frame_offset: fn_ctx.frame_offset + fn_ctx.stack_size as i64,
                 variable_types: fn_ctx.variable_types.clone(),
-                variable_positions: variable_positions,
-                register_allocator: RegisterAllocator::new()
+                variable_positions: fn_ctx.variable_positions.clone(),
+                read_count: HashMap::new(),
+                write_count: HashMap::new(),
+                local_imports: fn_ctx.local_imports.clone(),
+                local_functions: fn_ctx.local_functions.clone(),
+                register_allocator: new_register_allocator(fn_ctx.register_allocator.reserves_callee_saved())?
             }
         )
     }
 
     pub fn new_loop(fn_ctx: &FunctionContext) -> CompilerResult<FunctionContext> {
-        let stack_size = fn_ctx.stack_size as i64;
-
-        let mut variable_positions = HashMap::new();
-
-        for (var_name, var_pos) in fn_ctx.variable_positions.iter() {
-            let var_offset = var_pos - stack_size;
-            if var_offset >= 0 {
-                return Err(CompilerError::Unknown);
-            }
-            variable_positions.insert(var_name.clone(), var_offset);
-        }
-
         Ok(
             FunctionContext {
                 def: None,
                 weak: true,
                 is_loop: true,
                 stack_size: 0,
+                frame_offset: fn_ctx.frame_offset + fn_ctx.stack_size as i64,
                 variable_types: fn_ctx.variable_types.clone(),
-                variable_positions: variable_positions,
-                register_allocator: RegisterAllocator::new()
+                variable_positions: fn_ctx.variable_positions.clone(),
+                read_count: HashMap::new(),
+                write_count: HashMap::new(),
+                local_imports: fn_ctx.local_imports.clone(),
+                local_functions: fn_ctx.local_functions.clone(),
+                register_allocator: new_register_allocator(fn_ctx.register_allocator.reserves_callee_saved())?
             }
         )
     }
 
+    /// Declares a variable at a frame pointer relative position
     pub fn set_stack_var(&mut self, (var_name, var_type): (String, Type), stack_pos: i64) -> CompilerResult<()> {
         if self.variable_types.contains_key(&var_name) {
             return Err(CompilerError::DuplicateVariable(var_name));
@@ -225,7 +255,12 @@ impl FunctionContext {
     pub fn get_var_type(&self, var_name: &String) -> CompilerResult<Type> {
         self.variable_types.get(var_name)
             .cloned()
-            .ok_or(CompilerError::UnknownVariable(var_name.clone()))
+            .ok_or(CompilerError::UnknownVariable(var_name.clone(), None))
+    }
+
+    /// Gets the names of all variables in scope
+    pub fn get_var_names(&self) -> impl Iterator<Item = &String> {
+        self.variable_types.keys()
     }
 
     pub fn get_var_loc(&self, var_name: &String) -> CompilerResult<VariableLocation> {
@@ -234,7 +269,7 @@ impl FunctionContext {
             return Ok(VariableLocation::Register(reg_res.unwrap()));
         }*/
         let position = self.variable_positions.get(var_name)
-            .ok_or(CompilerError::UnknownVariable(var_name.clone()))?;
+            .ok_or(CompilerError::UnknownVariable(var_name.clone(), None))?;
         Ok(
             VariableLocation::Stack(*position)
         )
@@ -243,7 +278,76 @@ impl FunctionContext {
     pub fn get_var_pos(&self, var_name: &String) -> CompilerResult<i64> {
         self.variable_positions.get(var_name)
             .cloned()
-            .ok_or(CompilerError::UnknownVariable(var_name.clone()))
+            .ok_or(CompilerError::UnknownVariable(var_name.clone(), None))
+    }
+
+    /// Gets all variables in scope, sorted by their offset
+    pub fn get_locals(&self) -> CompilerResult<Vec<LocalVariable>> {
+        let mut locals = Vec::new();
+        for var_name in self.variable_types.keys() {
+            locals.push(LocalVariable {
+                name: var_name.clone(),
+                var_type: self.get_var_type(var_name)?,
+                offset: self.get_var_pos(var_name)?
+            });
+        }
+        locals.sort_by(|lhs, rhs| lhs.offset.cmp(&rhs.offset).then_with(|| lhs.name.cmp(&rhs.name)));
+        Ok(locals)
+    }
+
+    /// Adds an import of the function body.
+    /// Throws a DuplicateImport error if the name is already imported in this context.
+    pub fn add_local_import(&mut self, import_as: String, import_path: String) -> CompilerResult<()> {
+        if self.local_imports.contains_key(&import_as) {
+            return Err(CompilerError::DuplicateImport(import_as));
+        }
+        self.local_imports.insert(import_as, import_path);
+        Ok(())
+    }
+
+    /// Gets the path of an import of the function body
+    pub fn get_local_import(&self, import_as: &String) -> Option<&String> {
+        self.local_imports.get(import_as)
+    }
+
+    /// Gets the names of all imports of the function body
+    pub fn get_local_import_names(&self) -> impl Iterator<Item = &String> {
+        self.local_imports.keys()
+    }
+
+    /// Makes a local function visible in the function body, shadowing functions of the module
+    pub fn add_local_function(&mut self, name: String, module_name: String) {
+        self.local_functions.insert(name, module_name);
+    }
+
+    /// Gets the name in the module of a local function
+    pub fn get_local_function(&self, name: &String) -> Option<&String> {
+        self.local_functions.get(name)
+    }
+
+    /// Gets the names of all local functions visible in the function body
+    pub fn get_local_function_names(&self) -> impl Iterator<Item = &String> {
+        self.local_functions.keys()
+    }
+
+    /// Counts a read of a variable
+    pub fn count_read(&mut self, var_name: &String) {
+        *self.read_count.entry(var_name.clone()).or_insert(0) += 1;
+    }
+
+    /// Counts an assignment to a variable
+    pub fn count_write(&mut self, var_name: &String) {
+        *self.write_count.entry(var_name.clone()).or_insert(0) += 1;
+    }
+
+    /// Gets the variables which were assigned but never read, sorted by name
+    pub fn get_write_only_vars(&self) -> Vec<String> {
+        let mut var_names: Vec<String> = self.write_count.keys()
+            .filter(|var_name| !self.read_count.contains_key(*var_name))
+            .cloned()
+            .collect();
+        var_names.sort();
+        var_names
     }
 
     pub fn get_ret_type(&self) -> CompilerResult<Type> {
@@ -255,16 +359,34 @@ impl FunctionContext {
     }
 }
 
+/// Creates the register allocator of a function context
+fn new_register_allocator(callee_saved_temps: bool) -> CompilerResult<RegisterAllocator> {
+    let mut register_allocator = RegisterAllocator::new();
+    if callee_saved_temps {
+        register_allocator.reserve_callee_saved()?;
+    }
+    Ok(register_allocator)
+}
+
+impl Drop for FunctionContext {
+    fn drop(&mut self) {
+        // Don't panic again while unwinding
+        if !thread::panicking() {
+            debug_assert!(self.register_allocator.all_free(), "Function context dropped with blocked registers");
+        }
+    }
+}
+
 pub struct LoopContext {
-    pub pos_start: usize,
-    pub tag_end: u64
+    pub label_start: Label,
+    pub label_end: Label
 }
 
 impl LoopContext {
-    pub fn new(start: usize, tag_end: u64) -> LoopContext {
+    pub fn new(label_start: Label, label_end: Label) -> LoopContext {
         LoopContext {
-            pos_start: start,
-            tag_end: tag_end
+            label_start: label_start,
+            label_end: label_end
         }
     }
 }
\ No newline at end of file
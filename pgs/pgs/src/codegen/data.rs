@@ -1,5 +1,6 @@
 use std::{
     collections::{
+        BTreeMap,
         HashMap
     }
 };
@@ -8,7 +9,9 @@ use std::{
 #[derive(Clone)]
 pub struct Data {
     pub bytes: Vec<u8>,
-    strings: HashMap<String, usize>
+    strings: HashMap<String, usize>,
+    /// Offsets of the interned blobs, by their content
+    blobs: HashMap<Vec<u8>, usize>
 }
 
 impl Data {
@@ -16,21 +19,41 @@ impl Data {
     pub fn new() -> Data {
         Self {
             bytes: Vec::new(),
-            strings: HashMap::new()
+            strings: HashMap::new(),
+            blobs: HashMap::new()
         }
     }
 
     pub fn get_string_slice(&mut self, string: &String) -> (u64, u64) {
-        if self.strings.contains_key(string) {
-            let byte_len = string.as_bytes().len() as u64;
-            let addr = *self.strings.get(string).unwrap() as u64;
-            return (byte_len, addr);
+        let (byte_len, addr) = self.intern_bytes(string.as_bytes());
+        self.strings.entry(string.clone())
+            .or_insert(addr as usize);
+        (byte_len, addr)
+    }
+
+    /// Stores read-only bytes, identical blobs are only stored once.
+    /// Returns the size and the offset of the blob.
+    pub fn intern_bytes(&mut self, bytes: &[u8]) -> (u64, u64) {
+        let size = bytes.len() as u64;
+        if let Some(addr) = self.blobs.get(bytes) {
+            return (size, *addr as u64);
         }
-        let bytes = string.as_bytes();
-        let byte_len = bytes.len() as u64;
+        let addr = self.add_bytes(bytes);
+        self.blobs.insert(bytes.to_vec(), addr as usize);
+        (size, addr)
+    }
+
+    /// Appends raw bytes which are never shared, like data which is written later, returns their offset
+    pub fn add_bytes(&mut self, bytes: &[u8]) -> u64 {
         let addr = self.bytes.len();
         self.bytes.extend_from_slice(bytes);
-        self.strings.insert(string.clone(), addr);
-        (byte_len, addr as u64)
+        addr as u64
+    }
+
+    /// Gets all stored strings by their offset
+    pub fn get_strings(&self) -> BTreeMap<usize, String> {
+        self.strings.iter()
+            .map(|(string, offset)| (*offset, string.clone()))
+            .collect()
     }
 }
\ No newline at end of file
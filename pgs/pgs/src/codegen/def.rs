@@ -11,7 +11,8 @@ use crate::{
             CompilerResult,
             CompilerError,
             Compiler
-        }
+        },
+        suggest::suggest_name
     }
 };
 
@@ -64,6 +65,14 @@ impl FunctionDef {
         self.uid = uid;
         self
     }
+
+    /// Returns a readable signature, e.g. `foo(int, float) -> bool`
+    pub fn signature_string(&self) -> String {
+        let arg_types: Vec<String> = self.arguments.iter()
+            .map(|(_, arg_type)| arg_type.to_string())
+            .collect();
+        format!("{}({}) -> {}", self.name, arg_types.join(", "), self.ret_type)
+    }
 }
 
 impl From<&FunctionDeclArgs> for FunctionDef {
@@ -86,7 +95,10 @@ pub struct ContainerDef {
     /// Map of member variable indices
     pub member_indices: BTreeMap<String, usize>,
     /// Map of member functions
-    pub member_functions: HashMap<String, FunctionDef>
+    pub member_functions: HashMap<String, FunctionDef>,
+    /// Whether a container declaration defined the members,
+    /// an impl declared before it only creates the definition
+    pub declared: bool
 }
 
 impl ContainerDef {
@@ -97,7 +109,8 @@ impl ContainerDef {
             canonical_name: canon_name,
             member_indices: BTreeMap::new(),
             member_functions: HashMap::new(),
-            member_variables: HashMap::new()
+            member_variables: HashMap::new(),
+            declared: false
         }
     }
 
@@ -123,24 +136,29 @@ impl ContainerDef {
 
     /// Gets the byte offset of a member
     pub fn get_member_offset(&self, compiler: &Compiler, var_name: &String) -> CompilerResult<usize> {
-        let target_index = self.get_member_index(var_name)?;
+        let (offset, _) = self.member_offset(compiler, var_name)?;
+        Ok(offset)
+    }
+
+    /// Gets the byte offset and type of a member, members are laid out in declaration order
+    pub fn member_offset(&self, compiler: &Compiler, name: &str) -> CompilerResult<(usize, Type)> {
+        let name = String::from(name);
+        let target_index = self.get_member_index(&name)?;
         let mut offset = 0;
         for (member_name, member_index) in self.member_indices.iter() {
-            let member_type = self.get_member_type(member_name)?;
-            let member_size = compiler.get_size_of_type(&member_type)?;
-            if *member_index == target_index {
-                break;
+            if *member_index < target_index {
+                let member_type = self.get_member_type(member_name)?;
+                offset += compiler.get_size_of_type(&member_type)?;
             }
-            offset += member_size;
         }
-        Ok(offset)
+        Ok((offset, self.get_member_type(&name)?))
     }
 
     /// Returns the type of a member
     pub fn get_member_type(&self, var_name: &String) -> CompilerResult<Type> {
         self.member_variables.get(var_name)
             .cloned()
-            .ok_or(CompilerError::UnknownMember(var_name.clone()))
+            .ok_or_else(|| self.unknown_member(var_name))
     }
 
     /// Returns the byte size of this container
@@ -156,13 +174,22 @@ impl ContainerDef {
     pub fn get_member_index(&self, name: &String) -> CompilerResult<usize> {
         self.member_indices.get(name)
             .cloned()
-            .ok_or(CompilerError::UnknownMember(name.clone()))
+            .ok_or_else(|| self.unknown_member(name))
     }
 
     /// Returns a function definition 
     pub fn get_member_function(&self, name: &String) -> CompilerResult<&FunctionDef> {
         self.member_functions.get(name)
-            .ok_or(CompilerError::UnknownMember(name.clone()))
+            .ok_or_else(|| {
+                let suggestion = suggest_name(name, self.member_functions.keys());
+                CompilerError::UnknownMember(name.clone(), suggestion)
+            })
+    }
+
+    /// Error for an unknown member variable, suggesting the closest one
+    fn unknown_member(&self, name: &str) -> CompilerError {
+        let suggestion = suggest_name(name, self.member_variables.keys());
+        CompilerError::UnknownMember(String::from(name), suggestion)
     }
 
     /// Merges a container declaration into an existing containerdef
@@ -170,6 +197,7 @@ impl ContainerDef {
         for member in item.members.iter() {
             self.add_member_variable(member.clone()).unwrap();
         }
+        self.declared = true;
     }
 
     /// Creates a new ContainerDef from a declaration
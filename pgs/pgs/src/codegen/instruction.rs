@@ -1,9 +1,19 @@
 use crate::{
     vm::{
-        is::Opcode
+        is::{
+            Opcode,
+            decode::{
+                OperandKind,
+                opcode_operand_layout
+            }
+        }
     },
     codegen::{
-        register::Register
+        register::Register,
+        builder::{
+            Label,
+            Relocation
+        }
     }
 };
 
@@ -22,13 +32,16 @@ use bincode::{
 pub struct Instruction {
     pub opcode: Opcode,
     pub operands: Vec<u8>,
+    /// Operand offset and relocation of an address operand, filled in by the builder
+    pub relocation: Option<(usize, Relocation)>
 }
 
 impl Instruction {
     pub fn new(opcode: Opcode) -> Instruction {
         Instruction {
             opcode: opcode,
-            operands: Vec::new()
+            operands: Vec::new(),
+            relocation: None
         }
     }
 
@@ -46,19 +59,144 @@ impl Instruction {
             .with_operand::<u8>(Register::SP.into())
     }
 
+    /// Jumps to a label
+    pub fn jmp(label: Label) -> Instruction {
+        Instruction::new(Opcode::JMP)
+            .with_label_operand(label)
+    }
+
+    /// Jumps to a label if the bool in the register is true
+    pub fn jmpt(reg: Register, label: Label) -> Instruction {
+        Instruction::new(Opcode::JMPT)
+            .with_register_operand(reg)
+            .with_label_operand(label)
+    }
+
+    /// Jumps to a label if the bool in the register is false
+    pub fn jmpf(reg: Register, label: Label) -> Instruction {
+        Instruction::new(Opcode::JMPF)
+            .with_register_operand(reg)
+            .with_label_operand(label)
+    }
+
+    /// Calls the function with the uid
+    pub fn call(uid: u64) -> Instruction {
+        Instruction::new(Opcode::CALL)
+            .with_uid_operand(uid)
+    }
+
+    /// Calls the callback registered for the callback function with the uid
+    pub fn call_callback(uid: u64) -> Instruction {
+        Instruction::new(Opcode::CALL_CALLBACK)
+            .with_uid_operand(uid)
+    }
+
+    /// Moves a value between registers, opcode is one of MOVB, MOVF, MOVI, MOVA and MOVD
+    pub fn mov(opcode: Opcode, src: Register, dst: Register) -> Instruction {
+        Instruction::new(opcode)
+            .with_register_operand(src)
+            .with_register_operand(dst)
+    }
+
+    /// Moves a value from memory into a register, opcode is one of the _AR moves
+    pub fn mov_load(opcode: Opcode, src: Register, src_offset: i16, dst: Register) -> Instruction {
+        Instruction::new(opcode)
+            .with_register_operand(src)
+            .with_offset_operand(src_offset)
+            .with_register_operand(dst)
+    }
+
+    /// Moves a value from a register into memory, opcode is one of the _RA moves
+    pub fn mov_store(opcode: Opcode, src: Register, dst: Register, dst_offset: i16) -> Instruction {
+        Instruction::new(opcode)
+            .with_register_operand(src)
+            .with_register_operand(dst)
+            .with_offset_operand(dst_offset)
+    }
+
+    /// Moves a value inside of memory, opcode is one of the _A moves
+    pub fn mov_mem(opcode: Opcode, src: Register, src_offset: i16, dst: Register, dst_offset: i16) -> Instruction {
+        Instruction::new(opcode)
+            .with_register_operand(src)
+            .with_offset_operand(src_offset)
+            .with_register_operand(dst)
+            .with_offset_operand(dst_offset)
+    }
+
+    /// Moves size bytes inside of memory
+    pub fn movn(src: Register, src_offset: i16, dst: Register, dst_offset: i16, size: u32) -> Instruction {
+        Instruction::mov_mem(Opcode::MOVN_A, src, src_offset, dst, dst_offset)
+            .with_count_operand(size)
+    }
+
+    /// Appends an operand of any type.
+    /// In debug builds, panics if its size does not match the next operand of the opcode.
     pub fn with_operand<T: Serialize>(mut self, operand: T) -> Instruction {
         let mut data = serialize(&operand).expect("ERROR Serializing operand!");
+        debug_assert!(
+            self.next_operand_kind().map(|kind| kind.size()) == Some(data.len()),
+            "{:?} does not take an operand of {} bytes after {} bytes of operands", self.opcode, data.len(), self.operands.len()
+        );
         self.operands.append(&mut data);
         self
     }
 
-    pub fn append_operand<T: Serialize>(&mut self, operand: T) {
-        let mut data = serialize(&operand).expect("ERROR Serializing operand!");
-        self.operands.append(&mut data);
+    /// Appends a register operand
+    pub fn with_register_operand(self, reg: Register) -> Instruction {
+        let reg: u8 = reg.into();
+        self.with_checked_operand(OperandKind::Register, reg)
+    }
+
+    /// Appends an address offset operand
+    pub fn with_offset_operand(self, offset: i16) -> Instruction {
+        self.with_checked_operand(OperandKind::Offset, offset)
+    }
+
+    /// Appends a byte count operand
+    pub fn with_count_operand(self, count: u32) -> Instruction {
+        self.with_checked_operand(OperandKind::Count, count)
+    }
+
+    /// Appends the uid of a function as operand
+    pub fn with_uid_operand(self, uid: u64) -> Instruction {
+        self.with_checked_operand(OperandKind::FunctionUid, uid)
     }
 
-    pub fn remove_operand_bytes(&mut self, n: usize) {
-        self.operands.truncate(self.operands.len() - n);
+    /// Appends an address operand which is resolved to the position of the label on build
+    pub fn with_label_operand(self, label: Label) -> Instruction {
+        self.with_relocated_operand(OperandKind::CodeAddress, Relocation::Code(label))
+    }
+
+    /// Appends an address operand which is resolved to an offset into the data segment on build
+    pub fn with_data_operand(self, data_offset: u64) -> Instruction {
+        self.with_relocated_operand(OperandKind::Uint, Relocation::Data(data_offset))
+    }
+
+    fn with_relocated_operand(mut self, kind: OperandKind, relocation: Relocation) -> Instruction {
+        self.relocation = Some((self.operands.len(), relocation));
+        self.with_checked_operand::<u64>(kind, 0)
+    }
+
+    /// Appends an operand, in debug builds panics if the opcode expects another kind of operand next
+    fn with_checked_operand<T: Serialize>(self, kind: OperandKind, operand: T) -> Instruction {
+        debug_assert!(
+            self.next_operand_kind() == Some(kind),
+            "{:?} does not take a {:?} operand after {} bytes of operands", self.opcode, kind, self.operands.len()
+        );
+        self.with_operand(operand)
+    }
+
+    /// Gets the kind of the next operand the opcode expects.
+    /// None if all operands are appended, or the operands so far do not match the opcode.
+    pub fn next_operand_kind(&self) -> Option<OperandKind> {
+        let mut offset = 0;
+        for kind in opcode_operand_layout(&self.opcode) {
+            if offset == self.operands.len() {
+                return Some(*kind);
+            }
+            offset += kind.size();
+        }
+        None
     }
 
     pub fn clear_operands(&mut self) {
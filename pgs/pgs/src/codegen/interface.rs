@@ -0,0 +1,175 @@
+use crate::parser::ast::Type;
+
+use std::fmt::{
+    Display,
+    Formatter,
+    Result as FmtResult
+};
+
+/// The signature of a function, by full path name
+#[derive(PartialEq, Debug, Clone)]
+pub struct FunctionSignature {
+    pub name: String,
+    pub arguments: Vec<Type>,
+    pub ret_type: Type
+}
+
+impl FunctionSignature {
+    /// Creates a signature without arguments, returning void.
+    /// Names without a path are looked up in the root module.
+    pub fn new<T>(name: T) -> FunctionSignature
+        where String: From<T> {
+        let mut name = String::from(name);
+        if !name.starts_with("root::") {
+            name = format!("root::{}", name);
+        }
+        FunctionSignature {
+            name,
+            arguments: Vec::new(),
+            ret_type: Type::Void
+        }
+    }
+
+    pub fn with_arg(mut self, arg_type: Type) -> FunctionSignature {
+        self.arguments.push(arg_type);
+        self
+    }
+
+    pub fn with_ret_type(mut self, ret_type: Type) -> FunctionSignature {
+        self.ret_type = ret_type;
+        self
+    }
+}
+
+impl Display for FunctionSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let arg_types: Vec<String> = self.arguments.iter()
+            .map(|arg_type| arg_type.to_string())
+            .collect();
+        write!(f, "{}({}) -> {}", self.name, arg_types.join(", "), self.ret_type)
+    }
+}
+
+/// A difference between a required function and the one a program implements
+#[derive(PartialEq, Debug, Clone)]
+pub enum InterfaceMismatch {
+    /// The program has no function of that name
+    MissingFunction(String),
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize
+    },
+    /// The argument at the index has another type
+    ArgumentType {
+        name: String,
+        index: usize,
+        expected: Type,
+        found: Type
+    },
+    ReturnType {
+        name: String,
+        expected: Type,
+        found: Type
+    }
+}
+
+impl Display for InterfaceMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            InterfaceMismatch::MissingFunction(name) => write!(f, "missing function {}", name),
+            InterfaceMismatch::ArgumentCount { name, expected, found } => {
+                write!(f, "{} takes {} arguments, expected {}", name, found, expected)
+            },
+            InterfaceMismatch::ArgumentType { name, index, expected, found } => {
+                write!(f, "argument {} of {} is {}, expected {}", index, name, found, expected)
+            },
+            InterfaceMismatch::ReturnType { name, expected, found } => {
+                write!(f, "{} returns {}, expected {}", name, found, expected)
+            }
+        }
+    }
+}
+
+/// The functions a host expects a program to implement
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct RequiredInterface {
+    pub functions: Vec<FunctionSignature>
+}
+
+impl RequiredInterface {
+    pub fn new() -> RequiredInterface {
+        RequiredInterface {
+            functions: Vec::new()
+        }
+    }
+
+    pub fn with_function(mut self, function: FunctionSignature) -> RequiredInterface {
+        self.functions.push(function);
+        self
+    }
+}
+
+/// The script functions of a program, see Program::interface
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Interface {
+    /// Sorted by name
+    pub functions: Vec<FunctionSignature>
+}
+
+impl Interface {
+    /// Gets a function by full path name
+    pub fn get_function(&self, name: &str) -> Option<&FunctionSignature> {
+        self.functions.iter()
+            .find(|function| function.name == name)
+    }
+
+    /// Checks that every required function is implemented with the same signature.
+    /// Returns all mismatches in the order of the required functions.
+    pub fn satisfies(&self, required: &RequiredInterface) -> Result<(), Vec<InterfaceMismatch>> {
+        let mut mismatches = Vec::new();
+        for expected in required.functions.iter() {
+            let found = match self.get_function(&expected.name) {
+                Some(found) => found,
+                None => {
+                    mismatches.push(InterfaceMismatch::MissingFunction(expected.name.clone()));
+                    continue;
+                }
+            };
+
+            if found.arguments.len() != expected.arguments.len() {
+                mismatches.push(InterfaceMismatch::ArgumentCount {
+                    name: expected.name.clone(),
+                    expected: expected.arguments.len(),
+                    found: found.arguments.len()
+                });
+            } else {
+                let arg_pairs = expected.arguments.iter().zip(found.arguments.iter());
+                for (index, (expected_arg, found_arg)) in arg_pairs.enumerate() {
+                    if expected_arg != found_arg {
+                        mismatches.push(InterfaceMismatch::ArgumentType {
+                            name: expected.name.clone(),
+                            index,
+                            expected: expected_arg.clone(),
+                            found: found_arg.clone()
+                        });
+                    }
+                }
+            }
+
+            if found.ret_type != expected.ret_type {
+                mismatches.push(InterfaceMismatch::ReturnType {
+                    name: expected.name.clone(),
+                    expected: expected.ret_type.clone(),
+                    found: found.ret_type.clone()
+                });
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches)
+        }
+    }
+}
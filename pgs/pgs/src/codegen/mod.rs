@@ -8,10 +8,16 @@ pub mod context;
 
 pub mod program;
 
+pub mod interface;
+
 pub mod data;
 
 pub mod uid_generator;
 
 pub mod def;
 
-pub mod register;
\ No newline at end of file
+pub mod register;
+
+pub mod asm;
+
+pub mod suggest;
@@ -2,6 +2,19 @@ use crate::{
     api::{
         function::Function
     },
+    codegen::{
+        compiler::{
+            CompilerResult,
+            CompilerError
+        },
+        def::FunctionDef,
+        interface::{
+            Interface,
+            FunctionSignature
+        }
+    },
+    parser::ast::Type,
+    vm::strings::StringRepr
 };
 
 use std::{
@@ -12,21 +25,221 @@ use std::{
     ops::Range
 };
 
-#[derive(PartialEq, Debug)]
+/// Identifies a foreign function of a program, by uid or by full path name
+#[derive(PartialEq, Debug, Clone)]
+pub enum ForeignFunctionId {
+    Uid(u64),
+    Name(String)
+}
+
+impl From<u64> for ForeignFunctionId {
+    fn from(uid: u64) -> ForeignFunctionId {
+        ForeignFunctionId::Uid(uid)
+    }
+}
+
+impl From<&str> for ForeignFunctionId {
+    fn from(name: &str) -> ForeignFunctionId {
+        ForeignFunctionId::Name(String::from(name))
+    }
+}
+
+impl From<String> for ForeignFunctionId {
+    fn from(name: String) -> ForeignFunctionId {
+        ForeignFunctionId::Name(name)
+    }
+}
+
+/// A function of a program, see Program::functions
+#[derive(PartialEq, Debug, Clone)]
+pub struct FunctionInfo {
+    /// Full path name of the function
+    pub name: String,
+    pub uid: u64,
+    /// Code offset of the function, None for foreign functions
+    pub offset: Option<usize>,
+    pub arguments: Vec<(String, Type)>,
+    pub ret_type: Type,
+    pub foreign: bool
+}
+
+impl FunctionInfo {
+    /// Formats the signature like "root::foo(int, float) -> bool"
+    pub fn signature_string(&self) -> String {
+        let arg_types: Vec<String> = self.arguments.iter()
+            .map(|(_, arg_type)| arg_type.to_string())
+            .collect();
+        format!("{}({}) -> {}", self.name, arg_types.join(", "), self.ret_type)
+    }
+}
+
+/// A member of a container, see ContainerInfo
+#[derive(PartialEq, Debug, Clone)]
+pub struct MemberInfo {
+    pub name: String,
+    pub member_type: Type,
+    /// Byte offset into the container
+    pub offset: usize,
+    pub size: usize
+}
+
+/// A container of a program, see Program::containers
+#[derive(PartialEq, Debug, Clone)]
+pub struct ContainerInfo {
+    /// Full path name of the container
+    pub name: String,
+    /// Members in layout order
+    pub members: Vec<MemberInfo>,
+    pub size: usize
+}
+
+/// A variable in scope of a function, see Program::locals_at
+#[derive(PartialEq, Debug, Clone)]
+pub struct LocalVariable {
+    pub name: String,
+    pub var_type: Type,
+    /// Offset of the value to the frame pointer, arguments are below it
+    pub offset: i64
+}
+
+/// Information about a program that is not needed for execution
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct DebugInfo {
+    pub function_names: HashMap<u64, String>,
+    /// Source line, starting at 1, of the function body statement whose code starts at an offset.
+    /// Only filled if the program was compiled with debug symbols.
+    pub line_table: BTreeMap<usize, usize>,
+    /// Definitions of all functions by uid, only filled by the compiler
+    pub function_defs: HashMap<u64, FunctionDef>,
+    /// Containers sorted by name, only filled by the compiler
+    pub containers: Vec<ContainerInfo>,
+    /// String literals by their offset into the data
+    pub data_strings: BTreeMap<usize, String>,
+    /// Variables in scope at the start of each function and statement by code offset.
+    /// Only filled if the program was compiled with debug symbols.
+    pub locals: BTreeMap<usize, Vec<LocalVariable>>
+}
+
+impl DebugInfo {
+    pub fn new() -> DebugInfo {
+        DebugInfo {
+            function_names: HashMap::new(),
+            line_table: BTreeMap::new(),
+            function_defs: HashMap::new(),
+            containers: Vec::new(),
+            data_strings: BTreeMap::new(),
+            locals: BTreeMap::new()
+        }
+    }
+
+    pub fn with_function_name(mut self, uid: u64, name: String) -> DebugInfo {
+        self.function_names.insert(uid, name);
+        self
+    }
+
+    pub fn with_line(mut self, offset: usize, line: usize) -> DebugInfo {
+        self.line_table.insert(offset, line);
+        self
+    }
+
+    pub fn with_function_def(mut self, fn_def: FunctionDef) -> DebugInfo {
+        self.function_defs.insert(fn_def.uid, fn_def);
+        self
+    }
+
+    pub fn with_container(mut self, container: ContainerInfo) -> DebugInfo {
+        let index = self.containers.iter()
+            .position(|other| other.name > container.name)
+            .unwrap_or(self.containers.len());
+        self.containers.insert(index, container);
+        self
+    }
+
+    pub fn with_data_string(mut self, offset: usize, string: String) -> DebugInfo {
+        self.data_strings.insert(offset, string);
+        self
+    }
+
+    pub fn with_locals(mut self, offset: usize, locals: Vec<LocalVariable>) -> DebugInfo {
+        self.locals.insert(offset, locals);
+        self
+    }
+}
+
+/// A function in the code of a program
+#[derive(PartialEq, Debug, Clone)]
+pub struct Symbol {
+    /// Full path name of the function
+    pub function_name: String,
+    /// Code offsets covered by the function
+    pub code_range: Range<usize>,
+    /// Byte range of the function declaration in the source
+    pub source_span: Range<usize>
+}
+
+/// Maps code offsets back to the functions they belong to
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: BTreeMap<usize, Symbol>
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            symbols: BTreeMap::new()
+        }
+    }
+
+    pub fn with_symbol(mut self, symbol: Symbol) -> SymbolTable {
+        self.symbols.insert(symbol.code_range.start, symbol);
+        self
+    }
+
+    /// Gets the symbol whose code range contains the offset
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        self.symbols.range(..=offset)
+            .next_back()
+            .map(|(_, symbol)| symbol)
+            .filter(|symbol| symbol.code_range.contains(&offset))
+    }
+}
+
+/// A compiled program. It is Send and Sync, so one program can be shared
+/// between cores on several threads as an Arc<Program>.
+#[derive(PartialEq, Debug, Clone)]
 pub struct Program {
     pub code: Vec<u8>,
+    /// Offset of the first instruction, everything before it is data
+    pub code_start: usize,
     pub functions: HashMap<u64, usize>,
+    pub debug_info: DebugInfo,
     pub foreign_functions: HashMap<u64, Function>,
-    pub static_pointers: BTreeMap<usize, Range<usize>> 
+    pub static_pointers: BTreeMap<usize, Range<usize>>,
+    /// Only present if the program was compiled with debug symbols
+    pub symbol_table: Option<SymbolTable>,
+    pub string_repr: StringRepr,
+    /// Distinct strings the core interns on load, the handle of a string is its index
+    pub strings: Vec<String>,
+    /// Whether calls save and restore the callee-saved registers, only needed if the code keeps values in them
+    pub saves_callee_registers: bool,
+    /// Size of the arguments of each script function by uid, the memory checks place a canary below them
+    pub arg_sizes: HashMap<u64, usize>
 }
 
 impl Program {
     pub fn new() -> Program {
         Program {
             code: Vec::new(),
+            code_start: 0,
             functions: HashMap::new(),
+            debug_info: DebugInfo::new(),
             foreign_functions: HashMap::new(),
-            static_pointers: BTreeMap::new() 
+            static_pointers: BTreeMap::new(),
+            symbol_table: None,
+            string_repr: StringRepr::FatPointer,
+            strings: Vec::new(),
+            saves_callee_registers: false,
+            arg_sizes: HashMap::new()
         }
     }
 
@@ -35,11 +248,21 @@ impl Program {
         self
     }
 
+    pub fn with_code_start(mut self, code_start: usize) -> Program {
+        self.code_start = code_start;
+        self
+    }
+
     pub fn with_functions(mut self, functions: HashMap<u64, usize>) -> Program {
         self.functions = functions;
         self
     }
 
+    pub fn with_debug_info(mut self, debug_info: DebugInfo) -> Program {
+        self.debug_info = debug_info;
+        self
+    }
+
     pub fn with_foreign_functions(mut self, functions: HashMap<u64, Function>) -> Program {
         self.foreign_functions = functions;
         self
@@ -50,7 +273,262 @@ impl Program {
         self
     }
 
+    pub fn with_symbol_table(mut self, symbol_table: SymbolTable) -> Program {
+        self.symbol_table = Some(symbol_table);
+        self
+    }
+
+    pub fn with_arg_sizes(mut self, arg_sizes: HashMap<u64, usize>) -> Program {
+        self.arg_sizes = arg_sizes;
+        self
+    }
+
     pub fn get_size(&self) -> usize {
         self.code.len()
     }
-}
\ No newline at end of file
+
+    /// Gets the full path name of a function by uid
+    pub fn get_function_name(&self, uid: u64) -> Option<&String> {
+        self.debug_info.function_names.get(&uid)
+    }
+
+    /// Gets the uid and full path name of every foreign function the program requires, sorted by name
+    pub fn get_foreign_functions(&self) -> Vec<(u64, &String)> {
+        let mut foreign_functions: Vec<(u64, &String)> = self.foreign_functions.iter()
+            .map(|(uid, function)| (*uid, self.get_function_name(*uid).unwrap_or(&function.name)))
+            .collect();
+        foreign_functions.sort_by(|(_, lhs), (_, rhs)| lhs.cmp(rhs));
+        foreign_functions
+    }
+
+    /// Resolves the uid of a foreign function, names may omit the leading "root::"
+    fn resolve_foreign_uid(&self, id: ForeignFunctionId) -> CompilerResult<u64> {
+        match id {
+            ForeignFunctionId::Uid(uid) if self.foreign_functions.contains_key(&uid) => Ok(uid),
+            ForeignFunctionId::Uid(uid) => Err(CompilerError::UnknownFunction(format!("{:X}", uid), None)),
+            ForeignFunctionId::Name(name) => {
+                let root_name = format!("root::{}", name);
+                self.get_foreign_functions().iter()
+                    .find(|(_, fn_name)| **fn_name == name || **fn_name == root_name)
+                    .map(|(uid, _)| *uid)
+                    .ok_or(CompilerError::UnknownFunction(name, None))
+            }
+        }
+    }
+
+    /// Binds the closure of a function to a foreign function of this program.
+    /// The function has to have the signature the program was compiled against.
+    pub fn rebind_foreign<T: Into<ForeignFunctionId>>(&mut self, id: T, function: Function) -> CompilerResult<()> {
+        let uid = self.resolve_foreign_uid(id.into())?;
+        let bound_function = self.foreign_functions.get_mut(&uid)
+            .ok_or(CompilerError::Unknown)?;
+        if !bound_function.signature_eq(&function) {
+            return Err(CompilerError::ArgumentMismatch(
+                format!("{} cannot be rebound to {}", bound_function.signature_string(), function.signature_string())
+            ));
+        }
+        bound_function.rebind(function);
+        Ok(())
+    }
+
+    /// Removes the closure of a foreign function of this program, calling it fails at runtime
+    pub fn unbind_foreign<T: Into<ForeignFunctionId>>(&mut self, id: T) -> CompilerResult<()> {
+        let uid = self.resolve_foreign_uid(id.into())?;
+        self.foreign_functions.get_mut(&uid)
+            .ok_or(CompilerError::Unknown)?
+            .unbind();
+        Ok(())
+    }
+
+    /// Gets every function of the program with its signature, sorted by name.
+    /// Empty if the program was not compiled from source, e.g. assembled.
+    pub fn functions(&self) -> impl Iterator<Item = FunctionInfo> + '_ {
+        let mut functions: Vec<FunctionInfo> = self.debug_info.function_defs.values()
+            .map(|fn_def| FunctionInfo {
+                name: self.get_function_name(fn_def.uid)
+                    .cloned()
+                    .unwrap_or_else(|| fn_def.name.clone()),
+                uid: fn_def.uid,
+                offset: self.functions.get(&fn_def.uid).cloned(),
+                arguments: fn_def.arguments.clone(),
+                ret_type: fn_def.ret_type.clone(),
+                foreign: self.foreign_functions.contains_key(&fn_def.uid)
+            })
+            .collect();
+        functions.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        functions.into_iter()
+    }
+
+    /// Gets the signatures of all script functions, for checking them against a RequiredInterface.
+    /// Empty if the program was not compiled from source.
+    pub fn interface(&self) -> Interface {
+        let functions = self.functions()
+            .filter(|function| !function.foreign)
+            .map(|function| FunctionSignature {
+                name: function.name,
+                arguments: function.arguments.into_iter()
+                    .map(|(_, arg_type)| arg_type)
+                    .collect(),
+                ret_type: function.ret_type
+            })
+            .collect();
+        Interface {
+            functions
+        }
+    }
+
+    /// Gets the layout of every container, sorted by name.
+    /// Empty if the program was not compiled from source.
+    pub fn containers(&self) -> impl Iterator<Item = &ContainerInfo> {
+        self.debug_info.containers.iter()
+    }
+
+    /// Gets the string literals in the data, by offset
+    pub fn data_strings(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.debug_info.data_strings.iter()
+            .map(|(offset, string)| (*offset, string.as_str()))
+    }
+
+    /// Gets the variables in scope at a code offset, those of the last statement starting at or before it.
+    /// Empty if the program was compiled without debug symbols.
+    pub fn locals_at(&self, offset: usize) -> &[LocalVariable] {
+        self.debug_info.locals.range(..=offset)
+            .next_back()
+            .map(|(_, locals)| locals.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Gets the symbol of the function containing the code offset.
+    /// Returns None if the program carries no symbol table.
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        self.symbol_table.as_ref()?
+            .symbol_at(offset)
+    }
+
+    /// Creates a new ProgramBuilder
+    pub fn builder() -> ProgramBuilder {
+        ProgramBuilder::new()
+    }
+}
+
+/// Builds and validates a Program
+pub struct ProgramBuilder {
+    code: Vec<u8>,
+    code_start: usize,
+    functions: HashMap<u64, usize>,
+    foreign_functions: HashMap<u64, Function>,
+    static_pointers: BTreeMap<usize, Range<usize>>,
+    debug_info: DebugInfo,
+    symbol_table: Option<SymbolTable>,
+    string_repr: StringRepr,
+    strings: Vec<String>,
+    saves_callee_registers: bool,
+    arg_sizes: HashMap<u64, usize>
+}
+
+impl ProgramBuilder {
+    pub fn new() -> ProgramBuilder {
+        ProgramBuilder {
+            code: Vec::new(),
+            code_start: 0,
+            functions: HashMap::new(),
+            foreign_functions: HashMap::new(),
+            static_pointers: BTreeMap::new(),
+            debug_info: DebugInfo::new(),
+            symbol_table: None,
+            string_repr: StringRepr::FatPointer,
+            strings: Vec::new(),
+            saves_callee_registers: false,
+            arg_sizes: HashMap::new()
+        }
+    }
+
+    pub fn code(mut self, code: Vec<u8>) -> ProgramBuilder {
+        self.code = code;
+        self
+    }
+
+    pub fn code_start(mut self, code_start: usize) -> ProgramBuilder {
+        self.code_start = code_start;
+        self
+    }
+
+    pub fn add_function(mut self, uid: u64, offset: usize) -> ProgramBuilder {
+        self.functions.insert(uid, offset);
+        self
+    }
+
+    pub fn add_foreign_function(mut self, uid: u64, function: Function) -> ProgramBuilder {
+        self.foreign_functions.insert(uid, function);
+        self
+    }
+
+    pub fn static_pointers(mut self, static_pointers: BTreeMap<usize, Range<usize>>) -> ProgramBuilder {
+        self.static_pointers = static_pointers;
+        self
+    }
+
+    pub fn debug_info(mut self, debug_info: DebugInfo) -> ProgramBuilder {
+        self.debug_info = debug_info;
+        self
+    }
+
+    pub fn symbol_table(mut self, symbol_table: SymbolTable) -> ProgramBuilder {
+        self.symbol_table = Some(symbol_table);
+        self
+    }
+
+    /// Sets how strings are represented, and the strings interned on load for StringRepr::Handle
+    pub fn strings(mut self, string_repr: StringRepr, strings: Vec<String>) -> ProgramBuilder {
+        self.string_repr = string_repr;
+        self.strings = strings;
+        self
+    }
+
+    /// Sets whether calls save and restore the callee-saved registers
+    pub fn saves_callee_registers(mut self, saves_callee_registers: bool) -> ProgramBuilder {
+        self.saves_callee_registers = saves_callee_registers;
+        self
+    }
+
+    /// Sets the size of the arguments of each script function by uid
+    pub fn arg_sizes(mut self, arg_sizes: HashMap<u64, usize>) -> ProgramBuilder {
+        self.arg_sizes = arg_sizes;
+        self
+    }
+
+    /// Validates and builds the Program.
+    /// An empty program without any functions or code is valid.
+    pub fn build(self) -> CompilerResult<Program> {
+        if self.code_start > self.code.len() {
+            return Err(CompilerError::InvalidProgram(format!("Code starts behind the end of the program")));
+        }
+        let has_code = self.code.len() > self.code_start;
+        if self.functions.is_empty() && has_code {
+            return Err(CompilerError::InvalidProgram(String::from("Program contains no functions")));
+        }
+        if !self.functions.is_empty() && !has_code {
+            return Err(CompilerError::InvalidProgram(String::from("Program contains no code")));
+        }
+        for (uid, offset) in self.functions.iter() {
+            if *offset < self.code_start || *offset >= self.code.len() {
+                return Err(CompilerError::InvalidProgram(format!("Function {:X} starts outside of the code", uid)));
+            }
+        }
+        Ok(
+            Program {
+                code: self.code,
+                code_start: self.code_start,
+                functions: self.functions,
+                debug_info: self.debug_info,
+                foreign_functions: self.foreign_functions,
+                static_pointers: self.static_pointers,
+                symbol_table: self.symbol_table,
+                string_repr: self.string_repr,
+                strings: self.strings,
+                saves_callee_registers: self.saves_callee_registers,
+                arg_sizes: self.arg_sizes
+            }
+        )
+    }
+}
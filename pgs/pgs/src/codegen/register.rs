@@ -41,7 +41,26 @@ pub enum Register {
     R14 = 14,
     R15 = 15,
     SP = 16,
-    IP = 17
+    IP = 17,
+    FP = 18
+}
+
+/// Registers which keep their value across calls, the core saves them on CALL
+/// and restores them on RET if the program keeps values in them, see Program::saves_callee_registers.
+/// All other general purpose registers are caller-saved,
+/// a callee may overwrite them and R0 holds its return value.
+pub const CALLEE_SAVED_REGISTERS: [Register; 4] = [
+    Register::R11,
+    Register::R12,
+    Register::R13,
+    Register::R14
+];
+
+impl Register {
+    /// Checks if the register keeps its value across calls
+    pub fn is_callee_saved(&self) -> bool {
+        CALLEE_SAVED_REGISTERS.contains(self)
+    }
 }
 
 impl From<u8> for Register {
@@ -60,7 +79,9 @@ impl Into<u8> for Register {
 pub struct RegisterAllocator {
     register_queue: VecDeque<Register>,
     blocked_registers: HashSet<Register>,
-    forced_temp: Option<Register>
+    forced_temp: Option<Register>,
+    /// The free callee-saved registers, if they are reserved
+    callee_saved: Option<Vec<Register>>
 }
 
 impl RegisterAllocator {
@@ -73,7 +94,8 @@ impl RegisterAllocator {
         let mut reg_alloc = RegisterAllocator {
             register_queue: register_queue,
             blocked_registers: HashSet::new(),
-            forced_temp: None
+            forced_temp: None,
+            callee_saved: None
         };
         // Block the R0 register, as it is used for function return values
         reg_alloc.block_register(Register::R0).unwrap();
@@ -123,4 +145,53 @@ impl RegisterAllocator {
     pub fn force_temp_register(&mut self, reg: Register) {
         self.forced_temp = Some(reg);
     }
+
+    /// Reserves the callee-saved registers for values which have to survive calls,
+    /// they are no longer handed out as temporary registers
+    pub fn reserve_callee_saved(&mut self) -> CompilerResult<()> {
+        if self.callee_saved.is_some() {
+            return Ok(());
+        }
+        for reg in CALLEE_SAVED_REGISTERS.iter() {
+            let queue_index = self.register_queue.iter().position(|r| r == reg)
+                .ok_or(CompilerError::RegisterMapping)?;
+            self.register_queue.remove(queue_index);
+        }
+        self.callee_saved = Some(CALLEE_SAVED_REGISTERS.to_vec());
+        Ok(())
+    }
+
+    /// Checks if the callee-saved registers are reserved
+    pub fn reserves_callee_saved(&self) -> bool {
+        self.callee_saved.is_some()
+    }
+
+    /// Takes a free callee-saved register, None if all are taken or none are reserved
+    pub fn take_callee_saved(&mut self) -> Option<Register> {
+        let callee_saved = self.callee_saved.as_mut()?;
+        if callee_saved.is_empty() {
+            return None;
+        }
+        Some(callee_saved.remove(0))
+    }
+
+    /// Gives back a register taken with take_callee_saved
+    pub fn release_callee_saved(&mut self, reg: Register) -> CompilerResult<()> {
+        let callee_saved = self.callee_saved.as_mut()
+            .ok_or(CompilerError::RegisterMapping)?;
+        if !reg.is_callee_saved() || callee_saved.contains(&reg) {
+            return Err(CompilerError::RegisterMapping);
+        }
+        callee_saved.insert(0, reg);
+        Ok(())
+    }
+
+    /// Checks if all temporary registers are free, only the reserved R0 may be blocked
+    pub fn all_free(&self) -> bool {
+        let callee_saved_free = self.callee_saved.as_ref()
+            .map(|callee_saved| callee_saved.len() == CALLEE_SAVED_REGISTERS.len())
+            .unwrap_or(true);
+        callee_saved_free && self.blocked_registers.iter()
+            .all(|reg| *reg == Register::R0)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,54 @@
+/// Largest edit distance of a name suggested for an unknown one
+pub const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// Finds the candidate closest to an unknown name, within MAX_SUGGESTION_DISTANCE.
+/// Names are never suggested for names of a single character, or for themselves.
+pub fn suggest_name<'a, I>(name: &str, candidates: I) -> Option<String>
+    where I: IntoIterator<Item = &'a String> {
+    let mut best: Option<(usize, &String)> = None;
+    for candidate in candidates {
+        if candidate == name {
+            continue;
+        }
+        let distance = edit_distance(name, candidate);
+        if distance > MAX_SUGGESTION_DISTANCE || distance >= name.chars().count() {
+            continue;
+        }
+        // Ties go to the alphabetically first name, so suggestions do not depend on map order
+        let is_better = match best {
+            Some((best_distance, best_name)) => (distance, candidate) < (best_distance, best_name),
+            None => true
+        };
+        if is_better {
+            best = Some((distance, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate.clone())
+}
+
+/// Number of inserted, deleted, substituted or swapped adjacent chars between two strings
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let lhs: Vec<char> = lhs.chars().collect();
+    let rhs: Vec<char> = rhs.chars().collect();
+    // distances[i][j] is the distance between the first i chars of lhs and the first j chars of rhs
+    let mut distances = vec![vec![0; rhs.len() + 1]; lhs.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=rhs.len() {
+        distances[0][j] = j;
+    }
+    for i in 1..=lhs.len() {
+        for j in 1..=rhs.len() {
+            let cost = if lhs[i - 1] == rhs[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && lhs[i - 1] == rhs[j - 2] && lhs[i - 2] == rhs[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[lhs.len()][rhs.len()]
+}
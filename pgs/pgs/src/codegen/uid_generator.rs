@@ -34,13 +34,30 @@ impl UIDGenerator {
         uid
     }
 
+    /// Gets the uid of a function, derived from a stable hash of its full path.
+    /// The same path yields the same uid across compilations.
     pub fn get_function_uid(&mut self, name: &String) -> u64 {
         if self.functions.contains_key(name) {
             let uid = self.functions.get(name).unwrap();
             return *uid;
         }
-        let uid = self.generate();
+        let mut uid = stable_hash(name.as_bytes());
+        // Rehash deterministically on collisions
+        while self.uid_set.contains(&uid) {
+            uid = stable_hash(&uid.to_le_bytes());
+        }
+        self.uid_set.insert(uid);
         self.functions.insert(name.clone(), uid);
         uid
     }
-}
\ No newline at end of file
+}
+
+/// 64 bit FNV-1a, independent of the platform and the Rust version
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
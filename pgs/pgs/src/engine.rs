@@ -2,8 +2,14 @@ use crate::{
     vm::{
         core::{
             Core,
-            CoreError
+            CoreError,
+            VmExit
         },
+        is::Opcode,
+        interrupt::VmInterrupt,
+        strings::StringRepr,
+        buffer::BufferHandle,
+        trace::Trace,
         register::{
             RegisterAccess,
             Register as RegisterUnion
@@ -27,7 +33,11 @@ use crate::{
         register::Register
     },
     api::{
-        module::Module
+        module::Module,
+        function::{
+            FunctionClosureType,
+            ScriptCallback
+        }
     }
 };
 
@@ -39,7 +49,8 @@ use std::{
         File
     },
     path::{
-        Path
+        Path,
+        PathBuf
     },
     error::Error,
     fmt::{
@@ -88,14 +99,34 @@ impl Engine {
         }
     }
 
-    pub fn run_code(&mut self, code: &str) -> EngineResult<()> {
+    pub fn run_code(&mut self, code: &str) -> EngineResult<VmExit> {
         self.load_code(code)?;
         self.run_fn(&String::from("root::main"))
     }
 
     pub fn load_code(&mut self, code: &str) -> EngineResult<()> {
+        let decl_list = self.parse_code(code)?;
+        self.compiler.compile_root(&decl_list)
+            .map_err(|c| Box::new(EngineError::CompileError(c)))?;
+        let program = self.compiler.get_program()
+            .map_err(|c| Box::new(EngineError::CompileError(c)))?;
+        self.core.load_program(program);
+        Ok(())
+    }
+
+    /// Replaces the bodies of functions of the loaded program by the ones in the code.
+    /// Their signatures have to stay the same, nothing else is recompiled.
+    pub fn reload_code(&mut self, code: &str) -> EngineResult<()> {
+        let decl_list = self.parse_code(code)?;
+        let program = self.core.get_program_mut()
+            .map_err(|c| Box::new(EngineError::CoreError(c)))?;
+        self.compiler.reload_decl_list(&decl_list, program)
+            .map_err(|c| Box::new(EngineError::CompileError(c)))
+    }
+
+    fn parse_code(&self, code: &str) -> EngineResult<Vec<Declaration>> {
         let parser = Parser::new(String::from(code));
-        let decl_list = parser.parse_root_decl_list()
+        parser.parse_root_decl_list()
             .map_err(|p| {
                 let mut offset = 0;
                 let token_range = p.token_pos.clone();
@@ -108,19 +139,22 @@ impl Engine {
                     line_nr += 1;
                 }
                 Box::new(EngineError::ParseError(p))
-            })?;
-        self.compiler.compile_root(&decl_list)
-            .map_err(|c| Box::new(EngineError::CompileError(c)))?;
-        let program = self.compiler.get_program()
-            .map_err(|c| Box::new(EngineError::CompileError(c)))?;
-        self.core.load_program(program);
-        Ok(())
+            })
     }
 
-    pub fn run_file(&mut self, path: &Path) -> EngineResult<()> {
+    /// Runs the main function of a script file.
+    /// Files are included relative to its directory, unless an include root was set.
+    pub fn run_file(&mut self, path: &Path) -> EngineResult<VmExit> {
         let mut file = File::open(path)
             .map_err(|_| Box::new(EngineError::Unknown))?;
 
+        if !self.compiler.has_include_root() {
+            let script_dir = path.parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            self.compiler.set_include_root(script_dir);
+        }
+
         let mut file_content = String::new();
         file.read_to_string(&mut file_content)
             .map_err(|_| Box::new(EngineError::Unknown))?;
@@ -128,10 +162,21 @@ impl Engine {
         self.run_code(&file_content)
     }
 
-    pub fn run_stream(&mut self, readable: Box<dyn Read>) -> EngineResult<()> {
+    pub fn run_stream(&mut self, readable: Box<dyn Read>) -> EngineResult<VmExit> {
         Err(Box::new(EngineError::Unknown))
     }
 
+    /// Sets how strings are represented, before any code is loaded
+    pub fn set_string_repr(&mut self, string_repr: StringRepr) -> EngineResult<()> {
+        self.compiler.set_string_repr(string_repr)
+            .map_err(|c| Box::new(EngineError::CompileError(c)))
+    }
+
+    /// Sets the directory include_str and include_bytes read files from, see Compiler::set_include_root
+    pub fn set_include_root<P: Into<PathBuf>>(&mut self, include_root: P) {
+        self.compiler.set_include_root(include_root);
+    }
+
     pub fn push_stack<T: Serialize>(&mut self, item: T) -> EngineResult<()> {
         self.core.push_stack(item)
             .map_err(|c| Box::new(EngineError::CoreError(c)))
@@ -154,7 +199,56 @@ impl Engine {
         self.core.get_stack_size()
     }
 
-    pub fn run_fn<T>(&mut self, name: T) -> EngineResult<()>
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.core.set_max_call_depth(max_call_depth);
+    }
+
+    pub fn set_opcode_stats(&mut self, enabled: bool) {
+        self.core.set_opcode_stats(enabled);
+    }
+
+    pub fn opcode_stats(&self) -> Vec<(Opcode, u64)> {
+        self.core.opcode_stats()
+    }
+
+    pub fn reset_opcode_stats(&mut self) {
+        self.core.reset_opcode_stats();
+    }
+
+    pub fn set_memory_checks(&mut self, enabled: bool) {
+        self.core.set_memory_checks(enabled);
+    }
+
+    /// Calls the closure instead of the one registered for the foreign function, by full path name
+    pub fn set_foreign_override<T>(&mut self, name: T, closure: Box<FunctionClosureType>) -> EngineResult<()>
+        where String: From<T> {
+        let name = String::from(name);
+        let fn_uid = self.compiler.get_function_uid(&name)
+            .map_err(|ce| EngineError::CompileError(ce))?;
+        self.core.set_foreign_override(fn_uid, closure);
+        Ok(())
+    }
+
+    /// Registers the host function scripts run when calling the callback function, by full path name
+    pub fn register_callback<T>(&mut self, name: T, callback: ScriptCallback) -> EngineResult<()>
+        where String: From<T> {
+        let name = String::from(name);
+        let fn_uid = self.compiler.get_function_uid(&name)
+            .map_err(EngineError::CompileError)?;
+        self.core.register_callback(fn_uid, callback);
+        Ok(())
+    }
+
+    /// Calls the registered closure of the foreign function again, returns whether it was overridden
+    pub fn remove_foreign_override<T>(&mut self, name: T) -> EngineResult<bool>
+        where String: From<T> {
+        let name = String::from(name);
+        let fn_uid = self.compiler.get_function_uid(&name)
+            .map_err(|ce| EngineError::CompileError(ce))?;
+        Ok(self.core.remove_foreign_override(fn_uid))
+    }
+
+    pub fn run_fn<T>(&mut self, name: T) -> EngineResult<VmExit>
         where String: From<T> {
         let name = String::from(name);
         let fn_uid = self.compiler.get_function_uid(&name)
@@ -163,6 +257,49 @@ impl Engine {
             .map_err(|c| Box::new(EngineError::CoreError(c)))
     }
 
+    /// Continues a run suspended at a yield or an interrupt, see Core::resume
+    pub fn resume(&mut self) -> EngineResult<VmExit> {
+        self.core.resume()
+            .map_err(|c| Box::new(EngineError::CoreError(c)))
+    }
+
+    /// Abandons a suspended run, see Core::reset
+    pub fn reset(&mut self) {
+        self.core.reset();
+    }
+
+    /// Records the results of all following foreign calls, see Core::record_trace
+    pub fn record_trace(&mut self) {
+        self.core.record_trace();
+    }
+
+    /// Replays the results of recorded foreign calls, see Core::replay_trace
+    pub fn replay_trace(&mut self, trace: Trace) {
+        self.core.replay_trace(trace);
+    }
+
+    /// Stops recording or replaying, returns the trace
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.core.take_trace()
+    }
+
+    /// Gets a handle which stops a run from another thread, see Core::interrupt_handle
+    pub fn interrupt_handle(&self) -> VmInterrupt {
+        self.core.interrupt_handle()
+    }
+
+    /// Maps a host buffer into the memory of scripts until the handle is dropped or unmapped, see Core::map_buffer
+    pub fn map_buffer<T: Into<String>>(&mut self, name: T, buffer: Vec<u8>) -> BufferHandle {
+        self.core.map_buffer(name, buffer)
+    }
+
+    /// Continues a run suspended on a pending foreign call, see Core::resume_with
+    pub fn resume_with<T>(&mut self, token: u64, value: T) -> EngineResult<VmExit>
+        where RegisterUnion: RegisterAccess<T> {
+        self.core.resume_with(token, value)
+            .map_err(|c| Box::new(EngineError::CoreError(c)))
+    }
+
     pub fn register_module(&mut self, module: Module) -> EngineResult<()> {
         self.compiler.register_foreign_root_module(module)
             .map_err(|ce| Box::new(EngineError::CompileError(ce)))
@@ -3,13 +3,21 @@ use std::{
         HashMap,
         BTreeMap
     },
-    ops::Deref
+    ops::{
+        Deref,
+        Range
+    },
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult
+    }
 };
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Expression {
     IntLiteral(i64),
-    FloatLiteral(f32),
+    FloatLiteral(f64),
     StringLiteral(String),
     BoolLiteral(bool),
     Variable(String),
@@ -17,12 +25,33 @@ pub enum Expression {
     MemberAccess(Box<Expression>, Box<Expression>),
     Deref(Box<Expression>),
     Ref(Box<Expression>),
+    /// Element of an array at an index
+    Index(Box<Expression>, Box<Expression>),
+    /// Array of an element repeated a constant count of times, and the span of the expression
+    ArrayRepeat(Box<Expression>, Box<Expression>, Range<usize>),
+    /// Half-open range of ints from start to end, only valid as index of a slice
+    Range(Box<Expression>, Box<Expression>),
     Call(String, Vec<Expression>),
+    /// Anonymous function of its parameters, the body extends as far as the enclosing expression
+    Lambda(Vec<(String, Type)>, Box<Expression>),
     Addition(Box<Expression>, Box<Expression>),
     Subtraction(Box<Expression>, Box<Expression>),
     Multiplication(Box<Expression>, Box<Expression>),
     Division(Box<Expression>, Box<Expression>),
     Not(Box<Expression>),
+    Negate(Box<Expression>),
+    /// Name of the type of the inner expression, which is never evaluated
+    TypeOf(Box<Expression>),
+    /// Size of a type in bytes, known at compile time
+    SizeOf(Type),
+    /// Hand-crafted instructions, emitted as they are.
+    /// Unsafe: the compiler neither checks nor preserves registers, stack or control flow around them.
+    RawBytecode(Vec<u8>),
+    /// Contents of a file as string, read at compile time. The path without quotes and the span of the include.
+    IncludeStr(String, Range<usize>),
+    /// Contents of a file as reference to an int array with an element per byte, read at compile time.
+    /// The path without quotes and the span of the include.
+    IncludeBytes(String, Range<usize>),
     And(Box<Expression>, Box<Expression>),
     Or(Box<Expression>, Box<Expression>),
     Equals(Box<Expression>, Box<Expression>),
@@ -131,7 +160,50 @@ impl Expression {
     pub fn is_member_call(&self) -> bool {
         match self {
             Expression::MemberAccess(_, rhs) => {
-                rhs.is_member_call()
+                match rhs.deref() {
+                    Expression::Call(_, _) => true,
+                    _ => rhs.is_member_call()
+                }
+            },
+            _ => false
+        }
+    }
+
+    /// Checks if an expression contains a call expr anywhere in its tree
+    pub fn contains_call(&self) -> bool {
+        match self {
+            Expression::Call(_, _) => true,
+            Expression::ContainerInstance(_, members) => {
+                members.values().any(|expr| expr.contains_call())
+            },
+            Expression::Deref(op) |
+            Expression::Ref(op) |
+            Expression::Lambda(_, op) |
+            Expression::Not(op) |
+            Expression::Negate(op) => op.contains_call(),
+            // The count is a constant
+            Expression::ArrayRepeat(elem, _, _) => elem.contains_call(),
+            Expression::MemberAccess(lhs, rhs) |
+            Expression::Index(lhs, rhs) |
+            Expression::Range(lhs, rhs) |
+            Expression::Addition(lhs, rhs) |
+            Expression::Subtraction(lhs, rhs) |
+            Expression::Multiplication(lhs, rhs) |
+            Expression::Division(lhs, rhs) |
+            Expression::And(lhs, rhs) |
+            Expression::Or(lhs, rhs) |
+            Expression::Equals(lhs, rhs) |
+            Expression::NotEquals(lhs, rhs) |
+            Expression::GreaterThan(lhs, rhs) |
+            Expression::LessThan(lhs, rhs) |
+            Expression::GreaterThanEquals(lhs, rhs) |
+            Expression::LessThanEquals(lhs, rhs) |
+            Expression::Assign(lhs, rhs) |
+            Expression::AddAssign(lhs, rhs) |
+            Expression::SubAssign(lhs, rhs) |
+            Expression::MulAssign(lhs, rhs) |
+            Expression::DivAssign(lhs, rhs) => {
+                lhs.contains_call() || rhs.contains_call()
             },
             _ => false
         }
@@ -160,7 +232,11 @@ pub struct FunctionDeclArgs {
     pub name: String,
     pub arguments: Vec<(String, Type)>,
     pub returns: Type,
-    pub code_block: Option<Vec<Statement>>
+    pub code_block: Option<Vec<Statement>>,
+    /// Source line, starting at 1, of each statement of the code block
+    pub stmt_lines: Vec<usize>,
+    /// Byte range of the declaration in the source
+    pub span: Range<usize>
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -176,7 +252,11 @@ pub enum Declaration {
     Container(ContainerDeclArgs),
     Import(String, String),
     Impl(String, String, Vec<Declaration>),
-    StaticVar(VariableDeclArgs)
+    StaticVar(VariableDeclArgs),
+    /// Module constant, the name and its value which is evaluated at compile time
+    Const(String, Expression),
+    /// Declarations which are only declared and compiled if the flag is defined
+    Cfg(String, Vec<Declaration>)
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -206,7 +286,21 @@ pub enum Statement {
     Break,
     Continue,
     Expression(Expression),
-    If(IfStatementArgs)
+    If(IfStatementArgs),
+    /// Condition and optional failure message, without quotes
+    Assert(Box<Expression>, Option<String>),
+    /// Condition checked at compile time and optional failure message, without quotes
+    ComptimeAssert(Box<Expression>, Option<String>),
+    /// Suspends the core, optionally handing a value to the host
+    Yield(Option<Expression>),
+    /// Import path and the name it is imported as, visible for the rest of the enclosing block
+    Import(String, String),
+    /// Statements which are only compiled if the flag is defined, in the enclosing block
+    Cfg(String, Vec<Statement>),
+    /// Function declared in a function body, only visible inside of it
+    LocalFunction(FunctionDeclArgs),
+    /// While loop which is meant to never exit, so it is not warned about
+    Infinite(Box<Statement>)
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -218,6 +312,9 @@ pub enum Type {
     Bool,
     Auto,
     Array(Box<Type>, usize),
+    /// Array whose size is a constant expression, resolved to an Array by the compiler.
+    /// The element type, the size expression and the span of the type.
+    ConstArray(Box<Type>, Box<Expression>, Range<usize>),
     AutoArray(Box<Type>),
     Other(String),
     Tuple(Vec<Type>),
@@ -225,21 +322,40 @@ pub enum Type {
 }
 
 impl Type {
+    /// Checks if a value of this type is passed around in registers instead of on the stack
     pub fn is_primitive(&self) -> bool {
+        self.fits_in_register()
+    }
+
+    /// Checks if a value of this type fits into a single register
+    pub fn fits_in_register(&self) -> bool {
         match self {
             Type::Bool => true,
             Type::Int => true,
             Type::Float => true,
+            Type::Reference(_) => !self.is_fat_pointer(),
+            _ => false
+        }
+    }
+
+    /// Checks if this type is a slice reference, consisting of a pointer and a length
+    pub fn is_fat_pointer(&self) -> bool {
+        match self {
             Type::Reference(inner_type) => {
                 match inner_type.deref() {
-                    Type::AutoArray(_) => false,
-                    _ => true
+                    Type::AutoArray(_) => true,
+                    _ => false
                 }
             },
             _ => false
         }
     }
 
+    /// Gets the name of this type, as used by typeof
+    pub fn display(&self) -> String {
+        self.to_string()
+    }
+
     pub fn get_ref_type(&self) -> Type {
         match self {
             Type::Reference(inner_type) => {
@@ -249,3 +365,25 @@ impl Type {
         }
     }
 }
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Int => write!(f, "int"),
+            Type::String => write!(f, "string"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Auto => write!(f, "auto"),
+            Type::Array(inner_type, size) => write!(f, "{}[{}]", inner_type, size),
+            Type::ConstArray(inner_type, _, _) => write!(f, "{}[const]", inner_type),
+            Type::AutoArray(inner_type) => write!(f, "{}[]", inner_type),
+            Type::Other(name) => write!(f, "{}", name),
+            Type::Tuple(types) => {
+                let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", types.join(", "))
+            },
+            Type::Reference(inner_type) => write!(f, "&{}", inner_type)
+        }
+    }
+}
@@ -0,0 +1,550 @@
+//! Table driven LL(1) verifier for the grammar of the language.
+//!
+//! The verifier only checks whether a token stream is syntactically valid,
+//! it does not build an AST. Operator precedence does not change the validity
+//! of a program, so all binary operators share a single level here.
+//! Conditions of `if` and `while` cannot contain container instances,
+//! otherwise `if a { ... }` would need more than one token of lookahead.
+//!
+//! ```text
+//! program        = { decl } ;
+//! decl           = fn_decl | cont_decl | mod_decl | impl_decl | import_decl | const_decl | "@if" IDENT "{" { decl } "}" ;
+//! fn_decl        = "fn" ":" IDENT "(" [ arg { "," arg } [ "," ] ] ")" [ "~" type ] ( ";" | block ) ;
+//! arg            = IDENT ":" type | "&" "this" ;
+//! cont_decl      = "cont" ":" IDENT "{" { IDENT ":" type ";" } "}" ;
+//! mod_decl       = "mod" [ ":" ] IDENT "{" { decl } "}" ;
+//! impl_decl      = "impl" [ ":" ] path [ "for" path ] "{" { decl } "}" ;
+//! import_decl    = "import" [ ":" ] import_item { "," import_item } ";" ;
+//! import_item    = IDENT [ "::" ( import_item | "*" | "{" import_item { "," import_item } "}" ) | "=" IDENT ] ;
+//! const_decl     = "const" ":" IDENT "=" expr ";" ;
+//! type           = "int" | "float" | "bool" | "string" | "&" type | "[" type [ ";" expr ] "]" | path ;
+//! path           = IDENT { "::" IDENT } ;
+//! block          = "{" { stmt } "}" ;
+//! stmt           = "var" IDENT [ ":" type ] "=" expr ";"
+//!                | "return" expr ";"
+//!                | "if" cond block [ "else" ( "if" cond block ... | block ) ]
+//!                | [ "@infinite" ] "while" cond ( block | ";" )
+//!                | "loop" block
+//!                | "break" ";" | "continue" ";"
+//!                | "assert" expr [ "," STRING ] ";"
+//!                | "comptime_assert" expr [ "," STRING ] ";"
+//!                | "yield" [ expr ] ";"
+//!                | import_decl
+//!                | "@if" IDENT block
+//!                | "fn" ":" IDENT "(" [ arg { "," arg } [ "," ] ] ")" [ "~" type ] block
+//!                | expr ";" ;
+//! expr           = unary { binary_op unary } ;
+//! unary          = ( "!" | "~" | "&" | "-" ) unary | lambda | primary ;
+//! lambda         = ( "|" [ arg { "," arg } [ "," ] ] "|" | "||" ) unary ;
+//! primary        = literal | "(" expr ")" | "typeof" "(" expr ")" | "sizeof" "(" type ")"
+//!                | "raw_bc" "!" "(" [ byte { "," byte } [ "," ] ] ")"
+//!                | ( "include_str" | "include_bytes" ) "(" STRING ")"
+//!                | "[" expr ";" expr "]"
+//!                | path [ "(" [ expr { "," expr } [ "," ] ] ")" | "{" [ IDENT ":" expr { "," IDENT ":" expr } [ "," ] ] "}" | index { index } ] ;
+//! index          = "[" expr "]" ;
+//! cond           = expr without container instances outside of parentheses ;
+//! literal        = INT | FLOAT | STRING | "true" | "false" ;
+//! byte           = INT | HEX ;
+//! binary_op      = "+" | "-" | "*" | "/" | "==" | "!=" | "<" | ">" | "<=" | ">="
+//!                | "&&" | "||" | "=" | "+=" | "-=" | "*=" | "/=" | "." | ".." ;
+//! IDENT          = [a-zA-Z_][a-zA-Z0-9_]* ;
+//! ```
+
+use super::lexer::Token;
+
+use std::{
+    collections::{
+        HashMap,
+        HashSet
+    }
+};
+
+use pglex::prelude::Lexable;
+
+/// Terminals of the grammar are the tokens of the lexer
+pub type Terminal = Token;
+
+/// Nonterminals of the grammar
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum NonTerminal {
+    Program,
+    DeclList,
+    Decl,
+    FnDecl,
+    ArgList,
+    ArgTail,
+    Arg,
+    RetType,
+    FnBody,
+    ContDecl,
+    MemberList,
+    ModDecl,
+    OptColon,
+    ImplDecl,
+    ImplFor,
+    ImportDecl,
+    ImportItems,
+    ImportItemsTail,
+    ImportItem,
+    ImportPathTail,
+    ImportSegment,
+    Type,
+    ArraySize,
+    Path,
+    PathTail,
+    Block,
+    StmtList,
+    Stmt,
+    VarType,
+    WhileBody,
+    AssertTail,
+    YieldTail,
+    ElseTail,
+    ElseBody,
+    Expr,
+    ExprTail,
+    Unary,
+    Primary,
+    PrimaryTail,
+    Cond,
+    CondTail,
+    CondUnary,
+    CondPrimary,
+    CondPrimaryTail,
+    IndexTail,
+    CallArgs,
+    CallArgsTail,
+    InstanceMembers,
+    InstanceMembersTail,
+    RawBytes,
+    RawBytesTail,
+    Byte,
+    UnaryOp,
+    BinaryOp,
+    Literal
+}
+
+/// A symbol on the right hand side of a rule
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Symbol {
+    Terminal(Terminal),
+    NonTerminal(NonTerminal)
+}
+
+/// A production rule, an empty right hand side derives the empty word
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rule {
+    pub lhs: NonTerminal,
+    pub rhs: Vec<Symbol>
+}
+
+impl Rule {
+    pub fn new(lhs: NonTerminal, rhs: Vec<Symbol>) -> Rule {
+        Rule {
+            lhs: lhs,
+            rhs: rhs
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrammarError {
+    /// Two rules of the nonterminal are predicted by the same terminal
+    Conflict(NonTerminal, Terminal)
+}
+
+/// An LL(1) grammar together with its parse table
+pub struct Grammar {
+    rules: Vec<Rule>,
+    start: NonTerminal,
+    table: HashMap<(NonTerminal, Terminal), usize>
+}
+
+impl Grammar {
+    /// Creates the parse table of the rules, fails if the rules are not LL(1)
+    pub fn new(rules: Vec<Rule>, start: NonTerminal) -> Result<Grammar, GrammarError> {
+        let nullable = compute_nullable(&rules);
+        let first = compute_first(&rules, &nullable);
+        let follow = compute_follow(&rules, start, &nullable, &first);
+
+        let mut table = HashMap::new();
+        for (i, rule) in rules.iter().enumerate() {
+            let (mut predict, rhs_nullable) = first_of_sequence(&rule.rhs, &nullable, &first);
+            if rhs_nullable {
+                if let Some(lhs_follow) = follow.get(&rule.lhs) {
+                    predict.extend(lhs_follow.iter().cloned());
+                }
+            }
+            for terminal in predict {
+                if table.insert((rule.lhs, terminal.clone()), i).is_some() {
+                    return Err(GrammarError::Conflict(rule.lhs, terminal));
+                }
+            }
+        }
+
+        Ok(
+            Grammar {
+                rules: rules,
+                start: start,
+                table: table
+            }
+        )
+    }
+
+    /// Creates the grammar of the language
+    pub fn pgs() -> Grammar {
+        Grammar::new(pgs_rules(), NonTerminal::Program)
+            .expect("The grammar of the language is not LL(1)")
+    }
+
+    /// Checks whether the token stream is derivable from the start symbol.
+    /// A missing trailing Token::End is implied.
+    pub fn verify(&self, tokens: &[Token]) -> bool {
+        let mut stack = vec![
+            Symbol::Terminal(Token::End),
+            Symbol::NonTerminal(self.start)
+        ];
+        let mut pos = 0;
+
+        while let Some(symbol) = stack.pop() {
+            let token = tokens.get(pos).unwrap_or(&Token::End);
+            match symbol {
+                Symbol::Terminal(terminal) => {
+                    if terminal != *token {
+                        return false;
+                    }
+                    if terminal == Token::End {
+                        return tokens[pos.min(tokens.len())..].iter()
+                            .all(|token| *token == Token::End);
+                    }
+                    pos += 1;
+                },
+                Symbol::NonTerminal(non_terminal) => {
+                    let rule_index = match self.table.get(&(non_terminal, token.clone())) {
+                        Some(rule_index) => *rule_index,
+                        None => return false
+                    };
+                    stack.extend(self.rules[rule_index].rhs.iter().rev().cloned());
+                }
+            };
+        }
+
+        false
+    }
+}
+
+/// Checks whether the token stream is a syntactically valid program
+pub fn verify_grammar(tokens: &[Token]) -> bool {
+    Grammar::pgs().verify(tokens)
+}
+
+/// Lexes the code into a token stream ending with Token::End
+pub fn tokenize(code: &str) -> Vec<Token> {
+    let mut lexer = Token::lexer(code);
+    let mut tokens = Vec::new();
+    while lexer.token != Token::End {
+        tokens.push(lexer.token.clone());
+        if lexer.token == Token::Error {
+            break;
+        }
+        lexer.advance();
+    }
+    tokens.push(Token::End);
+    tokens
+}
+
+fn compute_nullable(rules: &[Rule]) -> HashSet<NonTerminal> {
+    let mut nullable = HashSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in rules.iter() {
+            if nullable.contains(&rule.lhs) {
+                continue;
+            }
+            let rhs_nullable = rule.rhs.iter().all(|symbol| match symbol {
+                Symbol::Terminal(_) => false,
+                Symbol::NonTerminal(non_terminal) => nullable.contains(non_terminal)
+            });
+            if rhs_nullable {
+                nullable.insert(rule.lhs);
+                changed = true;
+            }
+        }
+    }
+    nullable
+}
+
+fn compute_first(rules: &[Rule], nullable: &HashSet<NonTerminal>) -> HashMap<NonTerminal, HashSet<Terminal>> {
+    let mut first: HashMap<NonTerminal, HashSet<Terminal>> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in rules.iter() {
+            let (rhs_first, _) = first_of_sequence(&rule.rhs, nullable, &first);
+            let lhs_first = first.entry(rule.lhs).or_insert_with(HashSet::new);
+            for terminal in rhs_first {
+                changed |= lhs_first.insert(terminal);
+            }
+        }
+    }
+    first
+}
+
+fn compute_follow(rules: &[Rule], start: NonTerminal, nullable: &HashSet<NonTerminal>,
+    first: &HashMap<NonTerminal, HashSet<Terminal>>) -> HashMap<NonTerminal, HashSet<Terminal>> {
+    let mut follow: HashMap<NonTerminal, HashSet<Terminal>> = HashMap::new();
+    follow.entry(start).or_insert_with(HashSet::new).insert(Token::End);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in rules.iter() {
+            for (i, symbol) in rule.rhs.iter().enumerate() {
+                let non_terminal = match symbol {
+                    Symbol::NonTerminal(non_terminal) => *non_terminal,
+                    Symbol::Terminal(_) => continue
+                };
+                let (mut rest_first, rest_nullable) = first_of_sequence(&rule.rhs[i + 1..], nullable, first);
+                if rest_nullable {
+                    if let Some(lhs_follow) = follow.get(&rule.lhs) {
+                        rest_first.extend(lhs_follow.iter().cloned());
+                    }
+                }
+                let symbol_follow = follow.entry(non_terminal).or_insert_with(HashSet::new);
+                for terminal in rest_first {
+                    changed |= symbol_follow.insert(terminal);
+                }
+            }
+        }
+    }
+    follow
+}
+
+/// Returns the FIRST set of a symbol sequence and whether it is nullable
+fn first_of_sequence(symbols: &[Symbol], nullable: &HashSet<NonTerminal>,
+    first: &HashMap<NonTerminal, HashSet<Terminal>>) -> (HashSet<Terminal>, bool) {
+    let mut ret = HashSet::new();
+    for symbol in symbols.iter() {
+        match symbol {
+            Symbol::Terminal(terminal) => {
+                ret.insert(terminal.clone());
+                return (ret, false);
+            },
+            Symbol::NonTerminal(non_terminal) => {
+                if let Some(non_terminal_first) = first.get(non_terminal) {
+                    ret.extend(non_terminal_first.iter().cloned());
+                }
+                if !nullable.contains(non_terminal) {
+                    return (ret, false);
+                }
+            }
+        };
+    }
+    (ret, true)
+}
+
+fn t(token: Token) -> Symbol {
+    Symbol::Terminal(token)
+}
+
+fn n(non_terminal: NonTerminal) -> Symbol {
+    Symbol::NonTerminal(non_terminal)
+}
+
+/// The rules of the language, see the module documentation for the EBNF form
+pub fn pgs_rules() -> Vec<Rule> {
+    use NonTerminal as N;
+    use Token as T;
+
+    let mut rules = vec![
+        Rule::new(N::Program, vec![n(N::DeclList)]),
+        Rule::new(N::DeclList, vec![n(N::Decl), n(N::DeclList)]),
+        Rule::new(N::DeclList, vec![]),
+        Rule::new(N::Decl, vec![n(N::FnDecl)]),
+        Rule::new(N::Decl, vec![n(N::ContDecl)]),
+        Rule::new(N::Decl, vec![n(N::ModDecl)]),
+        Rule::new(N::Decl, vec![n(N::ImplDecl)]),
+        Rule::new(N::Decl, vec![n(N::ImportDecl)]),
+        Rule::new(N::Decl, vec![t(T::Const), t(T::Colon), t(T::Text), t(T::Assign), n(N::Expr), t(T::Semicolon)]),
+        Rule::new(N::Decl, vec![t(T::CfgIf), t(T::Text), t(T::OpenBlock), n(N::DeclList), t(T::CloseBlock)]),
+
+        // Functions
+        Rule::new(N::FnDecl, vec![
+            t(T::Fn), t(T::Colon), t(T::Text),
+            t(T::OpenParan), n(N::ArgList), t(T::CloseParan),
+            n(N::RetType), n(N::FnBody)
+        ]),
+        Rule::new(N::ArgList, vec![n(N::Arg), n(N::ArgTail)]),
+        Rule::new(N::ArgList, vec![]),
+        Rule::new(N::ArgTail, vec![t(T::Comma), n(N::ArgList)]),
+        Rule::new(N::ArgTail, vec![]),
+        Rule::new(N::Arg, vec![t(T::Text), t(T::Colon), n(N::Type)]),
+        Rule::new(N::Arg, vec![t(T::And), t(T::Text)]),
+        Rule::new(N::RetType, vec![t(T::Tilde), n(N::Type)]),
+        Rule::new(N::RetType, vec![]),
+        Rule::new(N::FnBody, vec![t(T::Semicolon)]),
+        Rule::new(N::FnBody, vec![n(N::Block)]),
+
+        // Containers, modules and impls
+        Rule::new(N::ContDecl, vec![
+            t(T::Container), t(T::Colon), t(T::Text),
+            t(T::OpenBlock), n(N::MemberList), t(T::CloseBlock)
+        ]),
+        Rule::new(N::MemberList, vec![t(T::Text), t(T::Colon), n(N::Type), t(T::Semicolon), n(N::MemberList)]),
+        Rule::new(N::MemberList, vec![]),
+        Rule::new(N::ModDecl, vec![
+            t(T::Mod), n(N::OptColon), t(T::Text),
+            t(T::OpenBlock), n(N::DeclList), t(T::CloseBlock)
+        ]),
+        Rule::new(N::OptColon, vec![t(T::Colon)]),
+        Rule::new(N::OptColon, vec![]),
+        Rule::new(N::ImplDecl, vec![
+            t(T::Impl), n(N::OptColon), n(N::Path), n(N::ImplFor),
+            t(T::OpenBlock), n(N::DeclList), t(T::CloseBlock)
+        ]),
+        Rule::new(N::ImplFor, vec![t(T::For), n(N::Path)]),
+        Rule::new(N::ImplFor, vec![]),
+
+        // Imports
+        Rule::new(N::ImportDecl, vec![t(T::Import), n(N::OptColon), n(N::ImportItems), t(T::Semicolon)]),
+        Rule::new(N::ImportItems, vec![n(N::ImportItem), n(N::ImportItemsTail)]),
+        Rule::new(N::ImportItemsTail, vec![t(T::Comma), n(N::ImportItems)]),
+        Rule::new(N::ImportItemsTail, vec![]),
+        Rule::new(N::ImportItem, vec![t(T::Text), n(N::ImportPathTail)]),
+        Rule::new(N::ImportPathTail, vec![t(T::DoubleColon), n(N::ImportSegment)]),
+        Rule::new(N::ImportPathTail, vec![t(T::Assign), t(T::Text)]),
+        Rule::new(N::ImportPathTail, vec![]),
+        Rule::new(N::ImportSegment, vec![n(N::ImportItem)]),
+        Rule::new(N::ImportSegment, vec![t(T::Times)]),
+        Rule::new(N::ImportSegment, vec![t(T::OpenBlock), n(N::ImportItems), t(T::CloseBlock)]),
+
+        // Types
+        Rule::new(N::Type, vec![t(T::Int)]),
+        Rule::new(N::Type, vec![t(T::Float)]),
+        Rule::new(N::Type, vec![t(T::Bool)]),
+        Rule::new(N::Type, vec![t(T::String)]),
+        Rule::new(N::Type, vec![t(T::And), n(N::Type)]),
+        Rule::new(N::Type, vec![t(T::OpenBracket), n(N::Type), n(N::ArraySize), t(T::CloseBracket)]),
+        Rule::new(N::Type, vec![n(N::Path)]),
+        Rule::new(N::ArraySize, vec![t(T::Semicolon), n(N::Expr)]),
+        Rule::new(N::ArraySize, vec![]),
+        Rule::new(N::Path, vec![t(T::Text), n(N::PathTail)]),
+        Rule::new(N::PathTail, vec![t(T::DoubleColon), t(T::Text), n(N::PathTail)]),
+        Rule::new(N::PathTail, vec![]),
+
+        // Statements
+        Rule::new(N::Block, vec![t(T::OpenBlock), n(N::StmtList), t(T::CloseBlock)]),
+        Rule::new(N::StmtList, vec![n(N::Stmt), n(N::StmtList)]),
+        Rule::new(N::StmtList, vec![]),
+        Rule::new(N::Stmt, vec![t(T::Var), t(T::Text), n(N::VarType), t(T::Assign), n(N::Expr), t(T::Semicolon)]),
+        Rule::new(N::Stmt, vec![t(T::Return), n(N::Expr), t(T::Semicolon)]),
+        Rule::new(N::Stmt, vec![t(T::If), n(N::Cond), n(N::Block), n(N::ElseTail)]),
+        Rule::new(N::Stmt, vec![t(T::While), n(N::Cond), n(N::WhileBody)]),
+        Rule::new(N::Stmt, vec![t(T::Infinite), t(T::While), n(N::Cond), n(N::WhileBody)]),
+        Rule::new(N::Stmt, vec![t(T::Loop), n(N::Block)]),
+        Rule::new(N::Stmt, vec![t(T::Break), t(T::Semicolon)]),
+        Rule::new(N::Stmt, vec![t(T::Continue), t(T::Semicolon)]),
+        Rule::new(N::Stmt, vec![t(T::Assert), n(N::Expr), n(N::AssertTail)]),
+        Rule::new(N::Stmt, vec![t(T::ComptimeAssert), n(N::Expr), n(N::AssertTail)]),
+        Rule::new(N::Stmt, vec![t(T::Yield), n(N::YieldTail)]),
+        Rule::new(N::Stmt, vec![n(N::ImportDecl)]),
+        Rule::new(N::Stmt, vec![t(T::CfgIf), t(T::Text), n(N::Block)]),
+        Rule::new(N::Stmt, vec![
+            t(T::Fn), t(T::Colon), t(T::Text),
+            t(T::OpenParan), n(N::ArgList), t(T::CloseParan),
+            n(N::RetType), n(N::Block)
+        ]),
+        Rule::new(N::Stmt, vec![n(N::Expr), t(T::Semicolon)]),
+        Rule::new(N::VarType, vec![t(T::Colon), n(N::Type)]),
+        Rule::new(N::VarType, vec![]),
+        Rule::new(N::WhileBody, vec![n(N::Block)]),
+        Rule::new(N::WhileBody, vec![t(T::Semicolon)]),
+        Rule::new(N::AssertTail, vec![t(T::Comma), t(T::StringLiteral), t(T::Semicolon)]),
+        Rule::new(N::AssertTail, vec![t(T::Semicolon)]),
+        Rule::new(N::YieldTail, vec![n(N::Expr), t(T::Semicolon)]),
+        Rule::new(N::YieldTail, vec![t(T::Semicolon)]),
+        Rule::new(N::ElseTail, vec![t(T::Else), n(N::ElseBody)]),
+        Rule::new(N::ElseTail, vec![]),
+        Rule::new(N::ElseBody, vec![t(T::If), n(N::Cond), n(N::Block), n(N::ElseTail)]),
+        Rule::new(N::ElseBody, vec![n(N::Block)]),
+
+        // Expressions
+        Rule::new(N::Expr, vec![n(N::Unary), n(N::ExprTail)]),
+        Rule::new(N::ExprTail, vec![n(N::BinaryOp), n(N::Unary), n(N::ExprTail)]),
+        Rule::new(N::ExprTail, vec![]),
+        Rule::new(N::Unary, vec![n(N::UnaryOp), n(N::Unary)]),
+        Rule::new(N::Unary, vec![n(N::Primary)]),
+        Rule::new(N::Unary, vec![t(T::Pipe), n(N::ArgList), t(T::Pipe), n(N::Unary)]),
+        Rule::new(N::Unary, vec![t(T::PipePipe), n(N::Unary)]),
+        Rule::new(N::Primary, vec![n(N::Literal)]),
+        Rule::new(N::Primary, vec![t(T::OpenParan), n(N::Expr), t(T::CloseParan)]),
+        Rule::new(N::Primary, vec![t(T::TypeOf), t(T::OpenParan), n(N::Expr), t(T::CloseParan)]),
+        Rule::new(N::Primary, vec![t(T::SizeOf), t(T::OpenParan), n(N::Type), t(T::CloseParan)]),
+        Rule::new(N::Primary, vec![t(T::IncludeStr), t(T::OpenParan), t(T::StringLiteral), t(T::CloseParan)]),
+        Rule::new(N::Primary, vec![t(T::IncludeBytes), t(T::OpenParan), t(T::StringLiteral), t(T::CloseParan)]),
+        Rule::new(N::Primary, vec![t(T::RawBytecode), t(T::Not), t(T::OpenParan), n(N::RawBytes), t(T::CloseParan)]),
+        Rule::new(N::Primary, vec![t(T::OpenBracket), n(N::Expr), t(T::Semicolon), n(N::Expr), t(T::CloseBracket)]),
+        Rule::new(N::Primary, vec![n(N::Path), n(N::PrimaryTail)]),
+        Rule::new(N::PrimaryTail, vec![t(T::OpenParan), n(N::CallArgs), t(T::CloseParan)]),
+        Rule::new(N::PrimaryTail, vec![t(T::OpenBlock), n(N::InstanceMembers), t(T::CloseBlock)]),
+        Rule::new(N::PrimaryTail, vec![t(T::OpenBracket), n(N::Expr), t(T::CloseBracket), n(N::IndexTail)]),
+        Rule::new(N::PrimaryTail, vec![]),
+
+        // Conditions, expressions without container instances
+        Rule::new(N::Cond, vec![n(N::CondUnary), n(N::CondTail)]),
+        Rule::new(N::CondTail, vec![n(N::BinaryOp), n(N::CondUnary), n(N::CondTail)]),
+        Rule::new(N::CondTail, vec![]),
+        Rule::new(N::CondUnary, vec![n(N::UnaryOp), n(N::CondUnary)]),
+        Rule::new(N::CondUnary, vec![n(N::CondPrimary)]),
+        Rule::new(N::CondPrimary, vec![n(N::Literal)]),
+        Rule::new(N::CondPrimary, vec![t(T::OpenParan), n(N::Expr), t(T::CloseParan)]),
+        Rule::new(N::CondPrimary, vec![t(T::TypeOf), t(T::OpenParan), n(N::Expr), t(T::CloseParan)]),
+        Rule::new(N::CondPrimary, vec![t(T::SizeOf), t(T::OpenParan), n(N::Type), t(T::CloseParan)]),
+        Rule::new(N::CondPrimary, vec![t(T::IncludeStr), t(T::OpenParan), t(T::StringLiteral), t(T::CloseParan)]),
+        Rule::new(N::CondPrimary, vec![t(T::IncludeBytes), t(T::OpenParan), t(T::StringLiteral), t(T::CloseParan)]),
+        Rule::new(N::CondPrimary, vec![t(T::RawBytecode), t(T::Not), t(T::OpenParan), n(N::RawBytes), t(T::CloseParan)]),
+        Rule::new(N::CondPrimary, vec![n(N::Path), n(N::CondPrimaryTail)]),
+        Rule::new(N::CondPrimaryTail, vec![t(T::OpenParan), n(N::CallArgs), t(T::CloseParan)]),
+        Rule::new(N::CondPrimaryTail, vec![t(T::OpenBracket), n(N::Expr), t(T::CloseBracket), n(N::IndexTail)]),
+        Rule::new(N::CondPrimaryTail, vec![]),
+        Rule::new(N::IndexTail, vec![t(T::OpenBracket), n(N::Expr), t(T::CloseBracket), n(N::IndexTail)]),
+        Rule::new(N::IndexTail, vec![]),
+
+        Rule::new(N::CallArgs, vec![n(N::Expr), n(N::CallArgsTail)]),
+        Rule::new(N::CallArgs, vec![]),
+        Rule::new(N::CallArgsTail, vec![t(T::Comma), n(N::CallArgs)]),
+        Rule::new(N::CallArgsTail, vec![]),
+        Rule::new(N::InstanceMembers, vec![t(T::Text), t(T::Colon), n(N::Expr), n(N::InstanceMembersTail)]),
+        Rule::new(N::InstanceMembers, vec![]),
+        Rule::new(N::InstanceMembersTail, vec![t(T::Comma), n(N::InstanceMembers)]),
+        Rule::new(N::InstanceMembersTail, vec![]),
+        Rule::new(N::RawBytes, vec![n(N::Byte), n(N::RawBytesTail)]),
+        Rule::new(N::RawBytes, vec![]),
+        Rule::new(N::RawBytesTail, vec![t(T::Comma), n(N::RawBytes)]),
+        Rule::new(N::RawBytesTail, vec![]),
+        Rule::new(N::Byte, vec![t(T::IntLiteral)]),
+        Rule::new(N::Byte, vec![t(T::HexLiteral)])
+    ];
+
+    let unary_ops = [T::Not, T::Tilde, T::And, T::Minus];
+    let binary_ops = [
+        T::Plus, T::Minus, T::Times, T::Divide,
+        T::Equals, T::NotEquals, T::LessThan, T::GreaterThan, T::LessThanEquals, T::GreaterThanEquals,
+        T::DoubleAnd, T::PipePipe,
+        T::Assign, T::AddAssign, T::SubAssign, T::MulAssign, T::DivAssign,
+        T::Dot, T::DoubleDot
+    ];
+    let literals = [T::IntLiteral, T::FloatLiteral, T::ScientificFloat, T::StringLiteral, T::True, T::False];
+
+    for op in unary_ops.iter() {
+        rules.push(Rule::new(N::UnaryOp, vec![t(op.clone())]));
+    }
+    for op in binary_ops.iter() {
+        rules.push(Rule::new(N::BinaryOp, vec![t(op.clone())]));
+    }
+    for literal in literals.iter() {
+        rules.push(Rule::new(N::Literal, vec![t(literal.clone())]));
+    }
+
+    rules
+}
@@ -2,6 +2,9 @@
 use std::{
     fmt::{
         Debug,
+        Display,
+        Formatter,
+        Result as FmtResult,
         self
     }
 };
@@ -36,6 +39,10 @@ pub enum Token {
     #[prio = 1]
     Impl,
 
+    #[token = "const"]
+    #[prio = 1]
+    Const,
+
     #[token = "int"]
     #[prio = 1]
     Int,
@@ -91,15 +98,61 @@ pub enum Token {
     #[prio = 1]
     Continue,
 
+    #[token = "assert"]
+    #[prio = 1]
+    Assert,
+
+    #[token = "yield"]
+    #[prio = 1]
+    Yield,
+
+    #[token = "typeof"]
+    #[prio = 1]
+    TypeOf,
+
+    #[token = "sizeof"]
+    #[prio = 1]
+    SizeOf,
+
+    #[token = "comptime_assert"]
+    #[prio = 1]
+    ComptimeAssert,
+
+    #[token = "raw_bc"]
+    #[prio = 1]
+    RawBytecode,
+
+    #[token = "include_str"]
+    #[prio = 1]
+    IncludeStr,
+
+    #[token = "include_bytes"]
+    #[prio = 1]
+    IncludeBytes,
+
+    #[token = "@if"]
+    CfgIf,
+
+    #[token = "@infinite"]
+    Infinite,
+
     #[regex = "([a-zA-Z_][a-zA-Z0-9_]*)"]
     Text,
 
     #[regex = "[0-9]+"]
     IntLiteral,
 
+    #[regex = "0x[0-9a-fA-F]+"]
+    #[prio = 1]
+    HexLiteral,
+
     #[regex = "([0-9]+\\.[0-9]+)"]
     FloatLiteral,
 
+    #[regex = "([0-9]+(\\.[0-9]+)?[eE][+-]?[0-9]+)"]
+    #[prio = 1]
+    ScientificFloat,
+
     #[regex = "\"([^\"]|\\.)*\""]
     StringLiteral,
 
@@ -134,7 +187,10 @@ pub enum Token {
     DoubleColon,
 
     #[token = "||"]
-    Or,
+    PipePipe,
+
+    #[token = "|"]
+    Pipe,
 
     #[token = "&&"]
     DoubleAnd,
@@ -218,6 +274,49 @@ pub enum Token {
     #[skip]
     MultiLineComment,
 
+    /// Also a number running into a name like `1abc`, which would otherwise lex as two tokens.
+    /// Hex and scientific literals win the ties by priority.
     #[error]
+    #[regex = "[0-9]+[a-zA-Z_][a-zA-Z0-9_]*"]
     Error
-}
\ No newline at end of file
+}
+
+/// Reason the lexer produced an error token
+#[derive(PartialEq, Debug, Clone)]
+pub enum LexError {
+    /// A string literal without a closing quote, by the position of its opening quote
+    UnterminatedString(usize),
+    /// A number directly followed by letters or underscores, by its position
+    MalformedNumber(usize),
+    /// Input no token matches, by its position
+    UnknownToken(usize)
+}
+
+impl LexError {
+    /// Describes the current error token of the lexer, none if it is at another token
+    pub fn from_lexer(lexer: &PgLexer) -> Option<LexError> {
+        if lexer.token != Token::Error {
+            return None;
+        }
+
+        // A closing quote anywhere after it would have made the input a string literal
+        let start = lexer.range().start;
+        if lexer.slice().starts_with('"') {
+            Some(LexError::UnterminatedString(start))
+        } else if lexer.slice().starts_with(|c: char| c.is_ascii_digit()) {
+            Some(LexError::MalformedNumber(start))
+        } else {
+            Some(LexError::UnknownToken(start))
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            LexError::UnterminatedString(pos) => write!(f, "unterminated string literal starting at {}", pos),
+            LexError::MalformedNumber(pos) => write!(f, "malformed number literal at {}", pos),
+            LexError::UnknownToken(pos) => write!(f, "unknown token at {}", pos)
+        }
+    }
+}
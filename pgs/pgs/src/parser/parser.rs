@@ -4,6 +4,7 @@ use super::{
     },
     lexer::{
         Token,
+        LexError,
         PgLexer as Lexer
     }
 };
@@ -74,7 +75,15 @@ pub enum ParseErrorType {
     ExpectedImplType,
     ExpectedThis,
     ThisOnlyAllowedInImpls,
-    MalformedImport
+    MalformedImport,
+    ExpectedAssertMessage,
+    ExpectedPipe,
+    ExpectedNot,
+    /// A raw bytecode byte which is no int or hex literal up to 255
+    ExpectedByte,
+    ExpectedCfgFlag,
+    ExpectedIncludePath,
+    Lex(LexError)
 }
 
 #[derive(Debug)]
@@ -100,9 +109,20 @@ impl Display for ParseError {
 
 impl Error for ParseError {}
 
+/// Error for the error token the lexer is at, which is the cause of whatever was expected instead
+fn lex_error(lexer: &Lexer) -> ParseError {
+    let lex_err = LexError::from_lexer(lexer)
+        .unwrap_or(LexError::UnknownToken(lexer.range().start));
+    ParseError::new(ParseErrorType::Lex(lex_err), lexer.range())
+}
+
 macro_rules! make_parse_error {
     ($lexer:ident, $error:expr) => {
-        Err(ParseError::new($error, $lexer.range()))
+        if $lexer.token == Token::Error {
+            Err(lex_error($lexer))
+        } else {
+            Err(ParseError::new($error, $lexer.range()))
+        }
     };
 }
 
@@ -113,6 +133,28 @@ pub struct Parser {
     current_cont: RefCell<String>
 }
 
+/// An entry of the operator stack of the expression parser
+#[derive(Clone, PartialEq, Debug)]
+pub enum StackOperator {
+    Token(Token),
+    /// Unary minus in front of a non-literal operand
+    Negate
+}
+
+fn stack_op_prec(op: &StackOperator) -> i8 {
+    match op {
+        StackOperator::Token(token) => op_prec(token),
+        StackOperator::Negate => 6
+    }
+}
+
+fn is_stack_op_right_assoc(op: &StackOperator) -> bool {
+    match op {
+        StackOperator::Token(token) => is_op_right_assoc(token),
+        StackOperator::Negate => true
+    }
+}
+
 fn is_op(token: &Token) -> bool {
     match token {
         Token::Times => true,
@@ -135,36 +177,39 @@ fn is_op(token: &Token) -> bool {
         Token::SubAssign => true,
         Token::DivAssign => true,
         Token::DoubleDot => true,
-        Token::Or => true,
+        Token::PipePipe => true,
         Token::DoubleAnd => true,
         _ => false
     }
 }
 
+/// Binding strength of an operator, higher binds tighter.
+/// Assignments and ranges bind weakest, then "||", "&&", comparisons,
+/// "+" and "-", "*" and "/", unary operators and member accesses.
 fn op_prec(token: &Token) -> i8 {
     match token {
-        Token::Times => 3,
-        Token::Divide => 3,
-        Token::Plus => 2,
-        Token::Minus => 2,
-        Token::Equals => 1,
-        Token::NotEquals => 1,
-        Token::GreaterThan => 1,
-        Token::GreaterThanEquals => 1,
-        Token::LessThan => 1,
-        Token::LessThanEquals => 1,
-        Token::Not => 4,
-        Token::And => 2,
-        Token::Tilde => 2,
-        Token::Dot => 5,
+        Token::Times => 5,
+        Token::Divide => 5,
+        Token::Plus => 4,
+        Token::Minus => 4,
+        Token::Equals => 3,
+        Token::NotEquals => 3,
+        Token::GreaterThan => 3,
+        Token::GreaterThanEquals => 3,
+        Token::LessThan => 3,
+        Token::LessThanEquals => 3,
+        Token::Not => 6,
+        Token::And => 6,
+        Token::Tilde => 6,
+        Token::Dot => 7,
         Token::Assign => 0,
         Token::AddAssign => 0,
         Token::MulAssign => 0,
         Token::SubAssign => 0,
         Token::DivAssign => 0,
         Token::DoubleDot => 0,
-        Token::Or => 0,
-        Token::DoubleAnd => 0,
+        Token::PipePipe => 1,
+        Token::DoubleAnd => 2,
         _ => {
             panic!("ERROR! Not an operator");
         }
@@ -173,7 +218,7 @@ fn op_prec(token: &Token) -> i8 {
 
 fn is_op_right_assoc(token: &Token) -> bool {
     match token {
-        Token::Times => true,
+        Token::Times => false,
         Token::Divide => false,
         Token::Plus => false,
         Token::Minus => false,
@@ -193,7 +238,7 @@ fn is_op_right_assoc(token: &Token) -> bool {
         Token::SubAssign => true,
         Token::DivAssign => true,
         Token::DoubleDot => false,
-        Token::Or => false,
+        Token::PipePipe => false,
         Token::DoubleAnd => false,
         _ => {
             panic!("ERROR! Not an operator");
@@ -232,21 +277,35 @@ impl Parser {
                 Token::Impl => {
                     ret.push(self.parse_impl_decl(lexer)?);
                 },
+                Token::Const => {
+                    ret.push(self.parse_const_decl(lexer)?);
+                },
+                Token::CfgIf => {
+                    let flag = self.parse_cfg_head(lexer)?;
+                    let decl_list = self.parse_decl_list(lexer, &[Token::CloseBlock])?;
+                    self.parse_cfg_tail(lexer)?;
+                    ret.push(Declaration::Cfg(flag, decl_list));
+                },
                 _ => {
                     return Err(ParseError::new(ParseErrorType::ExpectedMod, lexer.range()));
                 }
             };
         }
 
+        if lexer.token == Token::Error {
+            return Err(lex_error(lexer));
+        }
+
         Ok(ret)
     }
 
-    pub fn parse_impl_decl(&self, lexer: &mut Lexer) -> ParseResult<Declaration> {
-        if lexer.token != Token::Impl {
-            return make_parse_error!(lexer, ParseErrorType::ExpectedImpl);
+    /// Parses a module constant, const: NAME = expr;
+    pub fn parse_const_decl(&self, lexer: &mut Lexer) -> ParseResult<Declaration> {
+        if lexer.token != Token::Const {
+            return make_parse_error!(lexer, ParseErrorType::Unknown);
         }
 
-        // Swallow "impl"
+        // Swallow "const"
         lexer.advance();
 
         if lexer.token != Token::Colon {
@@ -256,6 +315,49 @@ impl Parser {
         // Swallow ":"
         lexer.advance();
 
+        if lexer.token != Token::Text {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedVarName);
+        }
+
+        let const_name = String::from(lexer.slice());
+
+        // Swallow the name
+        lexer.advance();
+
+        if lexer.token != Token::Assign {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedAssignment);
+        }
+
+        // Swallow "="
+        lexer.advance();
+
+        let expr = self.parse_expr(lexer, &[Token::Semicolon])?;
+        if lexer.token != Token::Semicolon {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedSemicolon);
+        }
+
+        // Swallow ";"
+        lexer.advance();
+
+        Ok(
+            Declaration::Const(const_name, expr)
+        )
+    }
+
+    pub fn parse_impl_decl(&self, lexer: &mut Lexer) -> ParseResult<Declaration> {
+        if lexer.token != Token::Impl {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedImpl);
+        }
+
+        // Swallow "impl"
+        lexer.advance();
+
+        // The ":" after "impl" is optional
+        if lexer.token == Token::Colon {
+            // Swallow ":"
+            lexer.advance();
+        }
+
         if lexer.token != Token::Text {
             return make_parse_error!(lexer, ParseErrorType::ExpectedImplType);
         }
@@ -296,6 +398,36 @@ impl Parser {
         self.parse_decl_list(&mut lexer, &[])
     }
 
+    /// Parses the "@if flag {" in front of conditionally compiled code, returns the flag
+    fn parse_cfg_head(&self, lexer: &mut Lexer) -> ParseResult<String> {
+        // Swallow "@if"
+        lexer.advance();
+
+        if lexer.token != Token::Text {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedCfgFlag);
+        }
+        let flag = String::from(lexer.slice());
+        lexer.advance();
+
+        if lexer.token != Token::OpenBlock {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedOpenBlock);
+        }
+        // Swallow "{"
+        lexer.advance();
+
+        Ok(flag)
+    }
+
+    /// Parses the "}" behind conditionally compiled code
+    fn parse_cfg_tail(&self, lexer: &mut Lexer) -> ParseResult<()> {
+        if lexer.token != Token::CloseBlock {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedCloseBlock);
+        }
+        // Swallow "}"
+        lexer.advance();
+        Ok(())
+    }
+
     pub fn parse_mod_decl(&self, lexer: &mut Lexer) -> ParseResult<Declaration> {
         if lexer.token != Token::Mod {
             return Err(ParseError::new(ParseErrorType::ExpectedMod, lexer.range()));
@@ -303,13 +435,12 @@ impl Parser {
         // Swallow "mod"
         lexer.advance();
 
-        if lexer.token != Token::Colon {
-            return Err(ParseError::new(ParseErrorType::ExpectedColon, lexer.range()));
+        // The ":" after "mod" is optional
+        if lexer.token == Token::Colon {
+            // Swallow ":"
+            lexer.advance();
         }
 
-        // Swallow ":"
-        lexer.advance();
-
         if lexer.token != Token::Text {
             return Err(ParseError::new(ParseErrorType::ExpectedModName, lexer.range()));
         }
@@ -446,13 +577,12 @@ impl Parser {
         // Swallow "import"
         lexer.advance();
 
-        if lexer.token != Token::Colon {
-            return make_parse_error!(lexer, ParseErrorType::ExpectedColon);
+        // The ":" after "import" is optional
+        if lexer.token == Token::Colon {
+            // Swallow ":"
+            lexer.advance();
         }
 
-        // Swallow ":"
-        lexer.advance();
-
         let delims = &[
             Token::Semicolon,
             Token::OpenBlock,
@@ -476,6 +606,7 @@ impl Parser {
         if lexer.token != Token::Fn {
             return Err(ParseError::new(ParseErrorType::FnMissing, lexer.range()));
         }
+        let span_start = lexer.range().start;
         lexer.advance();
 
         // Parse ":"
@@ -515,6 +646,7 @@ impl Parser {
         }
 
         let code_block_opt;
+        let mut stmt_lines = Vec::new();
 
         match lexer.token {
             Token::Semicolon => {
@@ -522,8 +654,9 @@ impl Parser {
             },
             Token::OpenBlock => {
                 lexer.advance();
-                let statements = self.parse_statement_list(lexer)?;
+                let (statements, lines) = self.parse_statement_list_with_lines(lexer)?;
                 code_block_opt = Some(statements);
+                stmt_lines = lines;
             },
             _ => {
                 return Err(ParseError::new(ParseErrorType::ExpectedBlockOrSemicolon, lexer.range()));
@@ -534,6 +667,8 @@ impl Parser {
             return Err(ParseError::new(ParseErrorType::ExpectedBlockOrSemicolon, lexer.range()));
         }
 
+        let span_end = lexer.range().end;
+
         // Swallow "}"|";"
         lexer.advance();
 
@@ -541,7 +676,9 @@ impl Parser {
             name: fn_name,
             arguments: fn_args,
             returns: fn_return_type,
-            code_block: code_block_opt
+            code_block: code_block_opt,
+            stmt_lines: stmt_lines,
+            span: span_start..span_end
         };
 
         fn_decl_opt = Some(
@@ -699,6 +836,7 @@ impl Parser {
                 Type::Reference(Box::new(inner_type))
             },
             Token::OpenBracket => {
+                let start = lexer.range().start;
                 // Swallow "["
                 lexer.advance();
                 let arr_type = self.parse_type(lexer)?;
@@ -706,27 +844,27 @@ impl Parser {
                 if lexer.token == Token::Semicolon {
                     // Swallow ";"
                     lexer.advance();
-                    if lexer.token != Token::IntLiteral {
+                    if lexer.token == Token::CloseBracket {
                         return make_parse_error!(lexer, ParseErrorType::ExpectedArraySize);
                     }
-                    let arr_size_raw = String::from(lexer.slice());
-                    arr_size = Some(
-                        arr_size_raw.parse::<usize>()
-                            .map_err(|_| ParseError::new(ParseErrorType::Unknown, lexer.range()))?
-                    );
-                    // Swallow arr size
-                    lexer.advance();
+                    arr_size = Some(self.parse_expr(lexer, &[Token::CloseBracket])?);
                 }
                 if lexer.token != Token::CloseBracket {
                     return make_parse_error!(lexer, ParseErrorType::ExpectedCloseBracket);
                 }
+                let span = start..lexer.range().end;
                 lexer.advance();
-                if arr_size.is_none() {
-                    Type::AutoArray(Box::new(arr_type))
-                } else {
-                    Type::Array(Box::new(arr_type), arr_size.unwrap())
+                match arr_size {
+                    None => Type::AutoArray(Box::new(arr_type)),
+                    // Literal sizes are known without the compiler
+                    Some(Expression::IntLiteral(size)) if size >= 0 => Type::Array(Box::new(arr_type), size as usize),
+                    Some(size_expr) => Type::ConstArray(Box::new(arr_type), Box::new(size_expr), span)
                 }
             },
+            Token::Text if lexer.slice() == "void" => {
+                lexer.advance();
+                Type::Void
+            },
             Token::Text => {
                 let mut typename = String::new();
                 while lexer.token == Token::Text ||
@@ -954,11 +1092,19 @@ impl Parser {
     }
 
     pub fn parse_statement_list(&self, lexer: &mut Lexer) -> ParseResult<Vec<Statement>> {
+        let (ret, _) = self.parse_statement_list_with_lines(lexer)?;
+        Ok(ret)
+    }
+
+    /// Parses a statement list, together with the source line each statement starts at
+    pub fn parse_statement_list_with_lines(&self, lexer: &mut Lexer) -> ParseResult<(Vec<Statement>, Vec<usize>)> {
         let mut ret = Vec::new();
+        let mut lines = Vec::new();
 
         while lexer.token != Token::CloseBlock &&
             lexer.token != Token::End &&
             lexer.token != Token::Error {
+            lines.push(self.get_line(lexer.range().start));
             match lexer.token {
                 Token::Var => {
                     ret.push(self.parse_var_decl(lexer)?);
@@ -981,6 +1127,44 @@ impl Parser {
                 Token::Loop => {
                     ret.push(self.parse_loop(lexer)?);
                 },
+                Token::Assert | Token::ComptimeAssert => {
+                    ret.push(self.parse_assert(lexer)?);
+                },
+                Token::Yield => {
+                    ret.push(self.parse_yield(lexer)?);
+                },
+                Token::CfgIf => {
+                    let flag = self.parse_cfg_head(lexer)?;
+                    let stmt_list = self.parse_statement_list(lexer)?;
+                    self.parse_cfg_tail(lexer)?;
+                    ret.push(Statement::Cfg(flag, stmt_list));
+                },
+                Token::Infinite => {
+                    // Swallow "@infinite", only while loops can be annotated
+                    lexer.advance();
+                    let while_stmt = self.parse_while(lexer)?;
+                    ret.push(Statement::Infinite(Box::new(while_stmt)));
+                },
+                Token::Fn => {
+                    let fn_decl_args = match self.parse_fn_decl(lexer)? {
+                        Declaration::Function(fn_decl_args) => fn_decl_args,
+                        _ => return Err(ParseError::new(ParseErrorType::Unknown, lexer.range()))
+                    };
+                    // Local functions cannot be defined anywhere else
+                    if fn_decl_args.code_block.is_none() {
+                        return Err(ParseError::new(ParseErrorType::BlockMissing, fn_decl_args.span.clone()));
+                    }
+                    ret.push(Statement::LocalFunction(fn_decl_args));
+                },
+                Token::Import => {
+                    let line = lines.pop().unwrap_or(1);
+                    for import_decl in self.parse_import_decl(lexer)? {
+                        if let Declaration::Import(import_path, import_as) = import_decl {
+                            lines.push(line);
+                            ret.push(Statement::Import(import_path, import_as));
+                        }
+                    }
+                },
                 _ => {
                     let expr = self.parse_expr(lexer, &[Token::Semicolon])?;
                     // Swallow ";"
@@ -991,7 +1175,19 @@ impl Parser {
             
         }
 
-        Ok(ret)
+        if lexer.token == Token::Error {
+            return Err(lex_error(lexer));
+        }
+
+        Ok((ret, lines))
+    }
+
+    /// Gets the line, starting at 1, of a byte offset into the code
+    fn get_line(&self, offset: usize) -> usize {
+        let offset = offset.min(self.code.len());
+        self.code.as_bytes()[..offset].iter()
+            .filter(|byte| **byte == b'\n')
+            .count() + 1
     }
 
     pub fn try_parse_call_stmt(&self, lexer: &mut Lexer) -> ParseResult<Statement> {
@@ -1118,6 +1314,73 @@ impl Parser {
         )
     }
 
+    /// Parses an assert or comptime_assert statement
+    pub fn parse_assert(&self, lexer: &mut Lexer) -> ParseResult<Statement> {
+        let is_comptime = match lexer.token {
+            Token::Assert => false,
+            Token::ComptimeAssert => true,
+            _ => return Err(ParseError::new(ParseErrorType::UnknownStatement, lexer.range()))
+        };
+
+        // Swallow "assert" or "comptime_assert"
+        lexer.advance();
+
+        let assert_expr = self.parse_expr(lexer, &[Token::Comma, Token::Semicolon])?;
+
+        let mut message = None;
+        if lexer.token == Token::Comma {
+            // Swallow ","
+            lexer.advance();
+
+            if lexer.token != Token::StringLiteral {
+                return Err(ParseError::new(ParseErrorType::ExpectedAssertMessage, lexer.range()));
+            }
+            let string = lexer.slice();
+            message = Some(String::from(&string[1..string.len() - 1]));
+
+            // Swallow message
+            lexer.advance();
+        }
+
+        if lexer.token != Token::Semicolon {
+            return Err(ParseError::new(ParseErrorType::ExpectedSemicolon, lexer.range()));
+        }
+
+        // Swallow ";"
+        lexer.advance();
+
+        if is_comptime {
+            Ok(Statement::ComptimeAssert(Box::new(assert_expr), message))
+        } else {
+            Ok(Statement::Assert(Box::new(assert_expr), message))
+        }
+    }
+
+    pub fn parse_yield(&self, lexer: &mut Lexer) -> ParseResult<Statement> {
+        if lexer.token != Token::Yield {
+            return Err(ParseError::new(ParseErrorType::UnknownStatement, lexer.range()));
+        }
+
+        // Swallow "yield"
+        lexer.advance();
+
+        let mut yield_expr = None;
+        if lexer.token != Token::Semicolon {
+            yield_expr = Some(self.parse_expr(lexer, &[Token::Semicolon])?);
+        }
+
+        if lexer.token != Token::Semicolon {
+            return Err(ParseError::new(ParseErrorType::ExpectedSemicolon, lexer.range()));
+        }
+
+        // Swallow ";"
+        lexer.advance();
+
+        Ok(
+            Statement::Yield(yield_expr)
+        )
+    }
+
     pub fn parse_return(&self, lexer: &mut Lexer) -> ParseResult<Statement> {
         // Swallow "return"
         lexer.advance();
@@ -1250,10 +1513,17 @@ impl Parser {
         )
     }
 
-    pub fn parse_expr_push(&self, lexer: &mut Lexer, operand_stack: &mut VecDeque<Expression>, operator_stack: &mut VecDeque<Token>) -> ParseResult<Expression> {
+    pub fn parse_expr_push(&self, lexer: &mut Lexer, operand_stack: &mut VecDeque<Expression>, operator_stack: &mut VecDeque<StackOperator>) -> ParseResult<Expression> {
         //println!("parse_expr_push(): operator stack len {}", operator_stack.len());
         //println!("parse_expr_push(): operand stack len {}", operand_stack.len());
-        let op = operator_stack.pop_front().unwrap();
+        let op = match operator_stack.pop_front().unwrap() {
+            StackOperator::Token(token) => token,
+            StackOperator::Negate => {
+                let op = operand_stack.pop_front()
+                    .ok_or(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()))?;
+                return Ok(Expression::Negate(Box::new(op)));
+            }
+        };
         //println!("parse_expr_push(): operator {:?}", op);
         //println!("parse_expr_push() start");
         let expr = match op {
@@ -1354,11 +1624,16 @@ impl Parser {
                 let lhs = operand_stack.pop_front().unwrap();
                 Expression::And(Box::new(lhs), Box::new(rhs))
             },
-            Token::Or => {
+            Token::PipePipe => {
                 let rhs = operand_stack.pop_front().unwrap();
                 let lhs = operand_stack.pop_front().unwrap();
                 Expression::Or(Box::new(lhs), Box::new(rhs))
             },
+            Token::DoubleDot => {
+                let rhs = operand_stack.pop_front().unwrap();
+                let lhs = operand_stack.pop_front().unwrap();
+                Expression::Range(Box::new(lhs), Box::new(rhs))
+            },
             _ => {
                 return Err(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()));
             }
@@ -1493,6 +1768,223 @@ impl Parser {
         )
     }
 
+    pub fn parse_typeof_expr(&self, lexer: &mut Lexer) -> ParseResult<Expression> {
+        if lexer.token != Token::TypeOf {
+            return Err(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()));
+        }
+
+        // Swallow "typeof"
+        lexer.advance();
+
+        if lexer.token != Token::OpenParan {
+            return Err(ParseError::new(ParseErrorType::ExpectedOpenParan, lexer.range()));
+        }
+
+        // Swallow "("
+        lexer.advance();
+
+        let inner_expr = self.parse_expr(lexer, &[Token::CloseParan])?;
+
+        if lexer.token != Token::CloseParan {
+            return Err(ParseError::new(ParseErrorType::ExpectedCloseParan, lexer.range()));
+        }
+
+        // Swallow ")"
+        lexer.advance();
+
+        Ok(
+            Expression::TypeOf(Box::new(inner_expr))
+        )
+    }
+
+    pub fn parse_sizeof_expr(&self, lexer: &mut Lexer) -> ParseResult<Expression> {
+        if lexer.token != Token::SizeOf {
+            return Err(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()));
+        }
+
+        // Swallow "sizeof"
+        lexer.advance();
+
+        if lexer.token != Token::OpenParan {
+            return Err(ParseError::new(ParseErrorType::ExpectedOpenParan, lexer.range()));
+        }
+
+        // Swallow "("
+        lexer.advance();
+
+        let size_type = self.parse_type(lexer)?;
+
+        if lexer.token != Token::CloseParan {
+            return Err(ParseError::new(ParseErrorType::ExpectedCloseParan, lexer.range()));
+        }
+
+        // Swallow ")"
+        lexer.advance();
+
+        Ok(
+            Expression::SizeOf(size_type)
+        )
+    }
+
+    /// Parses an array of a repeated element, "[elem; count]"
+    pub fn parse_array_repeat_expr(&self, lexer: &mut Lexer) -> ParseResult<Expression> {
+        if lexer.token != Token::OpenBracket {
+            return Err(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()));
+        }
+        let start = lexer.range().start;
+
+        // Swallow "["
+        lexer.advance();
+
+        let elem_expr = self.parse_expr(lexer, &[Token::Semicolon, Token::CloseBracket])?;
+        if lexer.token != Token::Semicolon {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedSemicolon);
+        }
+
+        // Swallow ";"
+        lexer.advance();
+
+        if lexer.token == Token::CloseBracket {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedArraySize);
+        }
+        let count_expr = self.parse_expr(lexer, &[Token::CloseBracket])?;
+        if lexer.token != Token::CloseBracket {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedCloseBracket);
+        }
+        let span = start..lexer.range().end;
+
+        // Swallow "]"
+        lexer.advance();
+
+        Ok(
+            Expression::ArrayRepeat(Box::new(elem_expr), Box::new(count_expr), span)
+        )
+    }
+
+    pub fn parse_include_expr(&self, lexer: &mut Lexer) -> ParseResult<Expression> {
+        let include_token = lexer.token.clone();
+        if include_token != Token::IncludeStr && include_token != Token::IncludeBytes {
+            return Err(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()));
+        }
+        let start = lexer.range().start;
+
+        // Swallow "include_str" or "include_bytes"
+        lexer.advance();
+
+        if lexer.token != Token::OpenParan {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedOpenParan);
+        }
+
+        // Swallow "("
+        lexer.advance();
+
+        if lexer.token != Token::StringLiteral {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedIncludePath);
+        }
+        let path_literal = lexer.slice();
+        let path = String::from(&path_literal[1..path_literal.len() - 1]);
+
+        // Swallow the path
+        lexer.advance();
+
+        if lexer.token != Token::CloseParan {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedCloseParan);
+        }
+        let span = start..lexer.range().end;
+
+        // Swallow ")"
+        lexer.advance();
+
+        if include_token == Token::IncludeStr {
+            Ok(Expression::IncludeStr(path, span))
+        } else {
+            Ok(Expression::IncludeBytes(path, span))
+        }
+    }
+
+    pub fn parse_raw_bytecode_expr(&self, lexer: &mut Lexer) -> ParseResult<Expression> {
+        if lexer.token != Token::RawBytecode {
+            return Err(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()));
+        }
+
+        // Swallow "raw_bc"
+        lexer.advance();
+
+        if lexer.token != Token::Not {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedNot);
+        }
+
+        // Swallow "!"
+        lexer.advance();
+
+        if lexer.token != Token::OpenParan {
+            return make_parse_error!(lexer, ParseErrorType::ExpectedOpenParan);
+        }
+
+        // Swallow "("
+        lexer.advance();
+
+        let mut bytes = Vec::new();
+        while lexer.token != Token::CloseParan {
+            let byte = match lexer.token {
+                Token::IntLiteral => lexer.slice().parse::<u8>().ok(),
+                Token::HexLiteral => u8::from_str_radix(&lexer.slice()[2..], 16).ok(),
+                _ => None
+            };
+            match byte {
+                Some(byte) => bytes.push(byte),
+                None => return make_parse_error!(lexer, ParseErrorType::ExpectedByte)
+            };
+
+            // Swallow the byte
+            lexer.advance();
+
+            match lexer.token {
+                Token::Comma => lexer.advance(),
+                Token::CloseParan => {},
+                _ => return make_parse_error!(lexer, ParseErrorType::ExpectedCloseParan)
+            };
+        }
+
+        // Swallow ")"
+        lexer.advance();
+
+        Ok(
+            Expression::RawBytecode(bytes)
+        )
+    }
+
+    pub fn parse_lambda_expr(&self, lexer: &mut Lexer, delims: &[Token]) -> ParseResult<Expression> {
+        let params = match lexer.token {
+            // "||" is a lambda without parameters
+            Token::PipePipe => {
+                lexer.advance();
+                Vec::new()
+            },
+            Token::Pipe => {
+                // Swallow "|"
+                lexer.advance();
+
+                let params = self.parse_fn_args(lexer)?;
+
+                if lexer.token != Token::Pipe {
+                    return make_parse_error!(lexer, ParseErrorType::ExpectedPipe);
+                }
+
+                // Swallow "|"
+                lexer.advance();
+                params
+            },
+            _ => return Err(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()))
+        };
+
+        let body_expr = self.parse_expr(lexer, delims)?;
+
+        Ok(
+            Expression::Lambda(params, Box::new(body_expr))
+        )
+    }
+
     pub fn parse_expr(&self, lexer: &mut Lexer, delims: &[Token]) -> ParseResult<Expression> {
         let mut operator_stack = VecDeque::new();
         let mut operand_stack = VecDeque::new();
@@ -1501,6 +1993,9 @@ impl Parser {
         let mut open_paran_count = 0;
         let mut dec_paran_count = false;
 
+        // Whether the last pushed item was an operand, used to detect unary minus
+        let mut last_was_operand = false;
+
         while lexer.token != Token::End &&
             lexer.token != Token::Error {
 
@@ -1514,14 +2009,74 @@ impl Parser {
                 }
             }
 
+            // Index into the last operand, member accesses bind tighter so "a.b[i]" indexes "a.b"
+            if lexer.token == Token::OpenBracket && last_was_operand {
+                while operator_stack.get(0) == Some(&StackOperator::Token(Token::Dot)) {
+                    let expr = self.parse_expr_push(lexer, &mut operand_stack, &mut operator_stack)?;
+                    operand_stack.push_front(expr);
+                }
+                lexer.advance();
+                let index_expr = self.parse_expr(lexer, &[Token::CloseBracket])?;
+                if lexer.token != Token::CloseBracket {
+                    return make_parse_error!(lexer, ParseErrorType::ExpectedCloseBracket);
+                }
+                let array_expr = operand_stack.pop_front()
+                    .ok_or(ParseError::new(ParseErrorType::UnsupportedExpression, lexer.range()))?;
+                operand_stack.push_front(Expression::Index(Box::new(array_expr), Box::new(index_expr)));
+                lexer.advance();
+                continue;
+            }
+
+            // A "[" in operand position starts an array
+            if lexer.token == Token::OpenBracket && !last_was_operand {
+                let expr = self.parse_array_repeat_expr(lexer)?;
+                operand_stack.push_front(expr);
+                last_was_operand = true;
+                continue;
+            }
+
+            // A "|" or "||" in operand position starts a lambda, which takes the rest of the expression
+            if (lexer.token == Token::Pipe || lexer.token == Token::PipePipe) && !last_was_operand {
+                let expr = self.parse_lambda_expr(lexer, delims)?;
+                operand_stack.push_front(expr);
+                last_was_operand = true;
+                continue;
+            }
+
             if lexer.token == Token::True {
                 let expr = Expression::BoolLiteral(true);
                 operand_stack.push_front(expr);
+                last_was_operand = true;
             }
 
             if lexer.token == Token::False {
                 let expr = Expression::BoolLiteral(false);
                 operand_stack.push_front(expr);
+                last_was_operand = true;
+            }
+
+            if lexer.token == Token::TypeOf {
+                let expr = self.parse_typeof_expr(lexer)?;
+                operand_stack.push_front(expr);
+                last_was_operand = true;
+            }
+
+            if lexer.token == Token::SizeOf {
+                let expr = self.parse_sizeof_expr(lexer)?;
+                operand_stack.push_front(expr);
+                last_was_operand = true;
+            }
+
+            if lexer.token == Token::RawBytecode {
+                let expr = self.parse_raw_bytecode_expr(lexer)?;
+                operand_stack.push_front(expr);
+                last_was_operand = true;
+            }
+
+            if lexer.token == Token::IncludeStr || lexer.token == Token::IncludeBytes {
+                let expr = self.parse_include_expr(lexer)?;
+                operand_stack.push_front(expr);
+                last_was_operand = true;
             }
             
             if lexer.token == Token::Text {
@@ -1539,20 +2094,49 @@ impl Parser {
                     }
                 }
                 operand_stack.push_front(expr);
+                last_was_operand = true;
+            }
+
+            // Fold a "-" in operand position into a negative literal,
+            // in front of any other operand it becomes a negation
+            let mut negate_literal = false;
+            let mut negate_operand = false;
+            if lexer.token == Token::Minus && !last_was_operand {
+                let mut lexer_peek = lexer.clone();
+                lexer_peek.advance();
+                if lexer_peek.token == Token::IntLiteral ||
+                    lexer_peek.token == Token::FloatLiteral ||
+                    lexer_peek.token == Token::ScientificFloat {
+                    *lexer = lexer_peek;
+                    negate_literal = true;
+                } else {
+                    negate_operand = true;
+                }
             }
 
             if lexer.token == Token::IntLiteral {
-                let int = String::from(lexer.slice()).parse::<i64>()
+                let mut int_raw = String::from(lexer.slice());
+                if negate_literal {
+                    // Parse with the sign, so i64::MIN does not overflow
+                    int_raw.insert(0, '-');
+                }
+                let int = int_raw.parse::<i64>()
                     .map_err(|_| ParseError::new(ParseErrorType::Unknown, lexer.range()))?;
                 let expr = Expression::IntLiteral(int);
                 operand_stack.push_front(expr);
+                last_was_operand = true;
             }
 
-            if lexer.token == Token::FloatLiteral {
-                let float = String::from(lexer.slice()).parse::<f32>()
+            if lexer.token == Token::FloatLiteral ||
+                lexer.token == Token::ScientificFloat {
+                let mut float = String::from(lexer.slice()).parse::<f64>()
                     .map_err(|_| ParseError::new(ParseErrorType::Unknown, lexer.range()))?;
+                if negate_literal {
+                    float = -float;
+                }
                 let expr = Expression::FloatLiteral(float);
                 operand_stack.push_front(expr);
+                last_was_operand = true;
             }
 
             if lexer.token == Token::StringLiteral {
@@ -1560,33 +2144,40 @@ impl Parser {
                 //println!("Parsing string literal {}", string);
                 let expr = Expression::StringLiteral(string);
                 operand_stack.push_front(expr);
+                last_was_operand = true;
             }
 
-            if is_op(&lexer.token) {
+            if negate_operand {
+                // Unary operators bind to the following operand, nothing is reduced
+                operator_stack.push_front(StackOperator::Negate);
+            } else if is_op(&lexer.token) {
+                let token_op = StackOperator::Token(lexer.token.clone());
                 loop {
                     let op_opt = operator_stack.get(0);
                     if op_opt.is_none() {
                         break; // Break if operator stack is empty
                     }
                     let op = op_opt.unwrap();
-                    if *op == Token::OpenParan {
+                    if *op == StackOperator::Token(Token::OpenParan) {
                         break; // Break if operator is a "("
                     }
 
-                    if !(op_prec(&lexer.token) - op_prec(op) < 0) &&
-                        !(op_prec(&lexer.token) == op_prec(op) && !is_op_right_assoc(op)) {
+                    if !(stack_op_prec(&token_op) - stack_op_prec(op) < 0) &&
+                        !(stack_op_prec(&token_op) == stack_op_prec(op) && !is_stack_op_right_assoc(op)) {
                         break; // Break if there is no operator of greater precedence on the stack or of equal precedence and right assoc
                     }
 
                     let expr = self.parse_expr_push(lexer, &mut operand_stack, &mut operator_stack)?;
                     operand_stack.push_front(expr);
                 }
-                operator_stack.push_front(lexer.token.clone());
+                operator_stack.push_front(token_op);
+                last_was_operand = false;
             }
 
             if lexer.token == Token::OpenParan {
-                operator_stack.push_front(lexer.token.clone());
+                operator_stack.push_front(StackOperator::Token(lexer.token.clone()));
                 open_paran_count += 1;
+                last_was_operand = false;
             }
 
             if lexer.token == Token::CloseParan {
@@ -1594,7 +2185,7 @@ impl Parser {
                 while operator_stack.len() > 0 {
                     {
                         let op_ref = operator_stack.get(0).unwrap();
-                        if *op_ref == Token::OpenParan {
+                        if *op_ref == StackOperator::Token(Token::OpenParan) {
                             dec_paran_count = true;
                             pop = true;
                             break;
@@ -1607,6 +2198,7 @@ impl Parser {
                 if pop {
                     operator_stack.pop_front();
                 }
+                last_was_operand = true;
             }
 
             // If Token is delimiter
@@ -1628,6 +2220,10 @@ impl Parser {
             lexer.advance();
         }
 
+        if lexer.token == Token::Error {
+            return Err(lex_error(lexer));
+        }
+
         while operator_stack.len() > 0 {
             let expr = self.parse_expr_push(lexer, &mut operand_stack, &mut operator_stack)?;
             operand_stack.push_front(expr);
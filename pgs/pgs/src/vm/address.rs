@@ -18,7 +18,9 @@ pub enum AddressType {
     Stack,
     Heap,
     Foreign,
-    Swap
+    Swap,
+    /// A host buffer, see Core::map_buffer
+    Buffer
 }
 
 impl Address {
@@ -28,7 +30,8 @@ impl Address {
             AddressType::Stack => 1,
             AddressType::Heap => 2,
             AddressType::Swap => 3,
-            AddressType::Foreign => 4
+            AddressType::Foreign => 4,
+            AddressType::Buffer => 5
         };
         // Shift type to the 3 left most bits
         type_raw = type_raw << 61;
@@ -61,6 +64,7 @@ impl From<u64> for Address {
             2 => AddressType::Heap,
             3 => AddressType::Swap,
             4 => AddressType::Foreign,
+            5 => AddressType::Buffer,
             _ => panic!("Address is not formatted correctly!")
         };
         // Remove 2 left most bits, which are the type
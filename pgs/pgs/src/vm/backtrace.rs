@@ -0,0 +1,72 @@
+use std::fmt::{
+    Display,
+    Formatter,
+    Result as FmtResult
+};
+
+/// A single frame of a backtrace
+#[derive(PartialEq, Debug, Clone)]
+pub enum Frame {
+    /// A script function and the offset of the program counter into it
+    Script(String, usize),
+    /// A foreign function
+    Foreign(String)
+}
+
+impl Frame {
+    /// Returns the name of the function of this frame
+    pub fn name(&self) -> &String {
+        match self {
+            Frame::Script(name, _) => name,
+            Frame::Foreign(name) => name
+        }
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Frame::Script(name, offset) => write!(f, "{} (+0x{:X})", name, offset),
+            Frame::Foreign(name) => write!(f, "<foreign: {}>", name)
+        }
+    }
+}
+
+/// The call frames at the time of a runtime error, innermost first
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Backtrace {
+    pub frames: Vec<Frame>
+}
+
+impl Backtrace {
+    pub fn new() -> Backtrace {
+        Backtrace {
+            frames: Vec::new()
+        }
+    }
+
+    pub fn with_frame(mut self, frame: Frame) -> Backtrace {
+        self.frames.push(frame);
+        self
+    }
+
+    /// Returns the function names of all frames, innermost first
+    pub fn frame_names(&self) -> Vec<&String> {
+        self.frames.iter()
+            .map(|frame| frame.name())
+            .collect()
+    }
+}
+
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i == 0 {
+                write!(f, "at {}", frame)?;
+            } else {
+                write!(f, "\n  called from {}", frame)?;
+            }
+        }
+        Ok(())
+    }
+}
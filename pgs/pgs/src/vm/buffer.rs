@@ -0,0 +1,98 @@
+use std::{
+    sync::{
+        Arc,
+        Mutex
+    }
+};
+
+/// Number of address bits for the offset into a mapped buffer, the bits above hold its id
+pub const BUFFER_OFFSET_BITS: u64 = 32;
+
+/// The bytes of a mapped buffer, shared by the core and the handle. None once the buffer is unmapped.
+type SharedBytes = Arc<Mutex<Option<Vec<u8>>>>;
+
+/// A host buffer mapped into the memory of a core, see Core::map_buffer
+#[derive(Debug)]
+pub(crate) struct MappedBuffer {
+    pub name: String,
+    len: usize,
+    bytes: SharedBytes
+}
+
+impl MappedBuffer {
+    pub(crate) fn new(name: String, len: usize, bytes: SharedBytes) -> MappedBuffer {
+        MappedBuffer {
+            name,
+            len,
+            bytes
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the handle of the buffer is still alive
+    pub(crate) fn is_mapped(&self) -> bool {
+        self.bytes.lock().unwrap().is_some()
+    }
+
+    /// Runs the closure on the bytes of the buffer, None if the buffer was unmapped
+    pub(crate) fn with_bytes<R, F: FnOnce(&mut [u8]) -> R>(&self, f: F) -> Option<R> {
+        let mut bytes = self.bytes.lock().unwrap();
+        bytes.as_mut().map(|bytes| f(bytes))
+    }
+}
+
+/// Keeps a host buffer mapped into the memory of a core, the buffer is owned by the mapping.
+/// Dropping the handle unmaps and frees the buffer, unmap gives it back instead.
+/// Later accesses by scripts fail with CoreError::UnmappedBuffer.
+#[derive(Debug)]
+pub struct BufferHandle {
+    address: u64,
+    len: usize,
+    bytes: SharedBytes
+}
+
+impl BufferHandle {
+    pub(crate) fn new(address: u64, len: usize, bytes: SharedBytes) -> BufferHandle {
+        BufferHandle {
+            address,
+            len,
+            bytes
+        }
+    }
+
+    /// Gets the address of the first byte of the buffer in the memory of the core
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Gets the size of the buffer in bytes
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// Runs the closure on the bytes of the buffer, scripts cannot access them meanwhile
+    pub fn with_bytes<R, F: FnOnce(&mut [u8]) -> R>(&self, f: F) -> R {
+        let mut bytes = self.bytes.lock().unwrap();
+        // Only unmap and drop take the bytes, both consume the handle
+        f(bytes.as_mut().unwrap())
+    }
+
+    /// Unmaps the buffer and gives it back
+    pub fn unmap(self) -> Vec<u8> {
+        self.bytes.lock().unwrap()
+            .take()
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for BufferHandle {
+    fn drop(&mut self) {
+        // A poisoned lock still has to unmap the buffer
+        let mut bytes = self.bytes.lock()
+            .unwrap_or_else(|err| err.into_inner());
+        *bytes = None;
+    }
+}
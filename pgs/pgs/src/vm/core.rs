@@ -9,17 +9,41 @@ use super::{
     register::{
         Register,
         RegisterAccess
+    },
+    backtrace::{
+        Backtrace,
+        Frame
+    },
+    interrupt::VmInterrupt,
+    buffer::{
+        BufferHandle,
+        MappedBuffer,
+        BUFFER_OFFSET_BITS
+    },
+    trace::{
+        Trace,
+        TraceEntry,
+        TraceMode
+    },
+    strings::{
+        StringRepr,
+        StringTable
     }
 };
 use crate::{
     codegen::{
         program::Program,
-        uid_generator::UIDGenerator
+        uid_generator::UIDGenerator,
+        register::CALLEE_SAVED_REGISTERS
     },
+    parser::ast::Type,
     api::{
         module::Module,
         function::*,
-        adapter::Adapter
+        adapter::{
+            Adapter,
+            ForeignResult
+        }
     }
 };
 
@@ -77,19 +101,107 @@ pub type CoreResult<T> = Result<T, CoreError>;
 pub const STACK_GROW_INCREMENT: usize = 1024;
 pub const STACK_GROW_THRESHOLD: usize = 64;
 pub const SWAP_SPACE_SIZE: usize = 64;
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 4096;
+/// Number of instructions between two checks for an interrupt
+pub const INTERRUPT_CHECK_INTERVAL: u32 = 1024;
+/// HALT error code of a failed assert without message
+pub const ASSERT_ERR_CODE: u8 = 2;
+/// HALT error code of a failed assert, with the message slice in r0 (size) and r1 (address)
+pub const ASSERT_MESSAGE_ERR_CODE: u8 = 3;
+/// HALT error code of a slice with bounds out of order or outside of the sliced value
+pub const SLICE_BOUNDS_ERR_CODE: u8 = 4;
+/// Value of the canary word the memory checks place below the arguments of each call frame
+pub const FRAME_CANARY: u64 = 0x5CA7_C0DE_CA4A_41E5;
+/// TRAP code of code which the compiler considers unreachable
+pub const UNREACHABLE_TRAP_CODE: u8 = 1;
+/// Kinds of the value register of YIELD
+pub const YIELD_NONE: u8 = 0;
+pub const YIELD_INT: u8 = 1;
+pub const YIELD_FLOAT: u8 = 2;
+pub const YIELD_DOUBLE: u8 = 3;
+pub const YIELD_BOOL: u8 = 4;
+
+/// A value handed from the script to the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool)
+}
+
+/// How a run of the core ended without an error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VmExit {
+    /// The program or function ran to its end
+    Finished,
+    /// A foreign function returned a pending result, continue with Core::resume_with
+    AwaitingForeign(u64),
+    /// The script yielded, with an optional value, continue with Core::resume
+    Yielded(Option<Value>),
+    /// A VmInterrupt stopped the run before the instruction at pc, continue with Core::resume
+    Interrupted {
+        pc: usize
+    }
+}
+
+/// A call frame entered with memory checks, see Core::check_frame
+#[derive(Clone, Debug)]
+struct FrameGuard {
+    /// Depth of the call stack inside the frame
+    call_depth: usize,
+    fp: u64,
+    /// Address of the canary word below the arguments
+    canary: u64
+}
 
 pub struct Core {
     stack: Vec<u8>,
     heap: Vec<u8>,
     heap_pointers: Vec<Range<usize>>,
     foreign_pointers: HashMap<u64, u64>,
+    /// Host buffers mapped into memory, by id
+    mapped_buffers: HashMap<u64, MappedBuffer>,
+    /// Strings of the program and the ones created at runtime, see StringRepr::Handle
+    strings: StringTable,
+    next_buffer_id: u64,
     foreign_function_uids: HashSet<u64>,
+    /// Closures called instead of the ones bound in the program, by foreign function uid
+    foreign_overrides: HashMap<u64, Function>,
+    /// Host functions called by CALL_CALLBACK, by uid of the callback function
+    callbacks: HashMap<u64, ScriptCallback>,
     swap: Vec<u8>,
-    program: Option<Program>,
-    call_stack: VecDeque<usize>,
+    /// Shared with other cores until this one writes to the program memory
+    program: Option<Arc<Program>>,
+    call_stack: VecDeque<(usize, u64)>,
+    /// Depth of the call stack at which a RET ends the run, above zero while running a callback
+    base_call_depth: usize,
+    /// Values of the callee-saved registers for each frame of the call stack,
+    /// if the program saves them
+    saved_registers: Vec<[u64; 4]>,
+    max_call_depth: usize,
+    trap: Option<u8>,
+    /// Error of the running foreign function, returned once it returns
+    foreign_error: Option<CoreError>,
+    /// Token of the pending foreign call the core is suspended on
+    awaiting_foreign: Option<u64>,
+    /// Whether the core is suspended at a yield
+    yielded: bool,
+    interrupt: VmInterrupt,
+    /// Whether the core is suspended at an interrupt
+    interrupted: bool,
+    /// Whether foreign call results are recorded or replayed
+    trace_mode: Option<TraceMode>,
+    foreign_frame: Option<String>,
+    /// Execution counts indexed by opcode byte, only kept in stats mode
+    opcode_counts: Option<Box<[u64; 256]>>,
+    /// Whether memory accesses and call frames are checked, see set_memory_checks
+    memory_checks: bool,
+    /// Canaries of the script function calls entered with memory checks, innermost last
+    frame_guards: Vec<FrameGuard>,
     registers: [Register; 16],
     ip: Register,
     sp: Register,
+    fp: Register
 }
 
 #[derive(Debug)]
@@ -106,12 +218,68 @@ pub enum CoreError {
     InvalidStackPointer,
     InvalidRegister,
     NoReturnValue,
-    Halted(u8)
+    Halted(u8),
+    CallDepthExceeded(usize, String),
+    InvalidJumpTarget(u64),
+    AssertionFailed(Option<String>),
+    /// A slice with bounds out of order or outside of the sliced value
+    SliceOutOfBounds,
+    /// A TRAP with its code and optional message
+    Trapped(u8, Option<String>),
+    /// A call to a foreign function without a bound closure, or to a callback function without a registered callback
+    UnboundForeignFunction(String),
+    /// A callback, by full function name, whose arguments or return value do not match the types of its function
+    CallbackTypeMismatch(String),
+    /// Running while suspended on the pending foreign call with the token
+    AwaitingForeign(u64),
+    /// Resuming with a token the core is not suspended on
+    NotAwaitingForeign(u64),
+    /// Running while suspended at a yield
+    Yielded,
+    /// Resuming while not suspended at a yield or an interrupt
+    NotYielded,
+    /// Running while suspended at an interrupt
+    Interrupted,
+    /// A script function called by a foreign function tried to suspend the core
+    CallbackSuspended,
+    /// A foreign call, by index and full function name, which the replayed trace does not contain
+    TraceDiverged(usize, String),
+    /// A YIELD with an unknown value kind
+    InvalidYieldKind(u8),
+    /// An access outside of its memory region or a corrupted call frame, found by the memory checks
+    MemoryViolation {
+        pc: usize,
+        addr: u64,
+        region: AddressType
+    },
+    /// An access to a mapped buffer whose handle was dropped, by address
+    UnmappedBuffer(u64),
+    /// An access of n bytes past the end of a mapped buffer, by address
+    BufferOutOfBounds(u64, usize),
+    /// A string handle which is not in the string table
+    InvalidStringHandle(u64),
+    Runtime(Box<CoreError>, Backtrace)
+}
+
+impl CoreError {
+    /// Returns the error that caused a runtime error
+    pub fn root_cause(&self) -> &CoreError {
+        match self {
+            CoreError::Runtime(err, _) => err.root_cause(),
+            _ => self
+        }
+    }
 }
 
 impl Display for CoreError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{:?}", self)
+        match self {
+            CoreError::Runtime(err, backtrace) => write!(f, "{}\n{}", err, backtrace),
+            CoreError::MemoryViolation { pc, addr, region } => {
+                write!(f, "memory violation at 0x{:X}: 0x{:X} is outside of the {:?} bounds", pc, addr, region)
+            },
+            _ => write!(f, "{:?}", self)
+        }
     }
 }
 
@@ -128,6 +296,7 @@ impl Core {
         let mut sp = Register::new();
         let address = Address::new(0, AddressType::Stack);
         sp.set::<u64>(address.into());
+        let fp = sp;
         Core {
             program: None,
             swap: swap,
@@ -135,21 +304,142 @@ impl Core {
             heap: Vec::new(),
             heap_pointers: Vec::new(),
             foreign_pointers: HashMap::new(),
+            mapped_buffers: HashMap::new(),
+            strings: StringTable::new(),
+            next_buffer_id: 0,
             foreign_function_uids: HashSet::new(),
+            foreign_overrides: HashMap::new(),
+            callbacks: HashMap::new(),
             call_stack: VecDeque::new(),
+            base_call_depth: 0,
+            saved_registers: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            trap: None,
+            foreign_error: None,
+            awaiting_foreign: None,
+            yielded: false,
+            interrupt: VmInterrupt::new(),
+            interrupted: false,
+            trace_mode: None,
+            foreign_frame: None,
+            opcode_counts: None,
+            memory_checks: false,
+            frame_guards: Vec::new(),
             registers: [Register::new(); 16],
             ip: Register::new(),
-            sp: sp
+            sp: sp,
+            fp: fp
         }
     }
 
+    /// Loads a program, an Arc<Program> is shared with the other cores running it
     #[inline]
-    pub fn load_program(&mut self, program: Program) {
+    pub fn load_program<P: Into<Arc<Program>>>(&mut self, program: P) {
+        let program = program.into();
         self.foreign_function_uids.clear();
         self.foreign_function_uids = program.foreign_functions.iter().map(|(k, _)| *k).collect();
+        // The strings of the program are distinct, so they get the handles the compiler assigned
+        self.strings = StringTable::from(program.strings.as_slice());
         self.program = Some(program);
     }
 
+    /// Gets how the loaded program represents strings
+    pub fn get_string_repr(&self) -> StringRepr {
+        self.program.as_ref()
+            .map(|program| program.string_repr)
+            .unwrap_or_default()
+    }
+
+    /// Interns a string created at runtime, returns its handle
+    pub fn intern_string(&mut self, string: &str) -> u64 {
+        self.strings.intern(string)
+    }
+
+    /// Gets an interned string by its handle
+    pub fn get_string(&self, handle: u64) -> CoreResult<&str> {
+        self.strings.get(handle)
+            .ok_or(CoreError::InvalidStringHandle(handle))
+    }
+
+    /// Sets the maximum depth of nested script function calls
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// Calls the closure instead of the one the program binds to the foreign function,
+    /// for this core only. The program decides the argument layout.
+    pub fn set_foreign_override(&mut self, uid: u64, closure: Box<FunctionClosureType>) {
+        let function = Function::new(format!("{:X}", uid))
+            .with_closure(closure);
+        self.foreign_overrides.insert(uid, function);
+    }
+
+    /// Removes the override of a foreign function, returns whether there was one
+    pub fn remove_foreign_override(&mut self, uid: u64) -> bool {
+        self.foreign_overrides.remove(&uid).is_some()
+    }
+
+    /// Registers the host function scripts run when calling the callback function with the uid,
+    /// for this core only, see Function::as_callback
+    pub fn register_callback(&mut self, uid: u64, callback: ScriptCallback) {
+        self.callbacks.insert(uid, callback);
+    }
+
+    /// Enables or disables counting the executions of each opcode, disabling drops the counts
+    pub fn set_opcode_stats(&mut self, enabled: bool) {
+        self.opcode_counts = if enabled {
+            Some(Box::new([0; 256]))
+        } else {
+            None
+        };
+    }
+
+    /// Gets the execution count of every executed opcode, most executed first.
+    /// Empty if stats mode is disabled.
+    pub fn opcode_stats(&self) -> Vec<(Opcode, u64)> {
+        let counts = match self.opcode_counts.as_ref() {
+            Some(counts) => counts,
+            None => return Vec::new()
+        };
+        let mut stats: Vec<(Opcode, u64)> = counts.iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .filter_map(|(op, count)| Some((Opcode::try_from(op as u8).ok()?, *count)))
+            .collect();
+        stats.sort_by(|(_, lhs), (_, rhs)| rhs.cmp(lhs));
+        stats
+    }
+
+    /// Enables or disables the debug memory checks. With checks, every MOV*_AR and MOV*_RA
+    /// has to stay inside the used stack, the data or the heap. Every CALL places a canary word
+    /// below the arguments of the callee, which its RET has to find intact, together with the
+    /// frame pointer. Violations fail with CoreError::MemoryViolation.
+    /// Frames entered with checks keep their canaries until they return, even if checks are disabled meanwhile.
+    pub fn set_memory_checks(&mut self, enabled: bool) {
+        self.memory_checks = enabled;
+    }
+
+    /// Sets all opcode execution counts back to zero
+    pub fn reset_opcode_stats(&mut self) {
+        if let Some(counts) = self.opcode_counts.as_mut() {
+            counts.iter_mut().for_each(|count| *count = 0);
+        }
+    }
+
+    /// Gets the loaded program, e.g. to load it into other cores
+    pub fn get_program(&self) -> CoreResult<&Arc<Program>> {
+        self.program.as_ref()
+            .ok_or(CoreError::NoProgram)
+    }
+
+    /// Gets the loaded program, e.g. to replace functions in it between runs.
+    /// A program shared with other cores is copied first.
+    pub fn get_program_mut(&mut self) -> CoreResult<&mut Program> {
+        self.program.as_mut()
+            .map(Arc::make_mut)
+            .ok_or(CoreError::NoProgram)
+    }
+
     #[inline]
     pub fn program_len(&self) -> CoreResult<usize> {
         let program = self.program.as_ref()
@@ -173,6 +463,9 @@ impl Core {
         //println!("ip: {}", self.ip.get::<usize>());
         let op: u8 = self.get_op()?;
         let opcode = Opcode::try_from(op)?;
+        if let Some(counts) = self.opcode_counts.as_mut() {
+            counts[op as usize] += 1;
+        }
         //println!("opcode: {:?}", opcode);
         Ok(
             opcode
@@ -180,12 +473,14 @@ impl Core {
     }
 
     #[inline]
-    pub fn run(&mut self) -> CoreResult<()> {
+    pub fn run(&mut self) -> CoreResult<VmExit> {
         self.run_at(0)
     }
     
     #[inline]
-    pub fn run_fn(&mut self, uid: u64) -> CoreResult<()> {
+    pub fn run_fn(&mut self, uid: u64) -> CoreResult<VmExit> {
+        self.check_not_suspended()?;
+
         let fn_offset = {
             let program = self.program.as_ref()
                 .ok_or(CoreError::NoProgram)?;
@@ -194,15 +489,253 @@ impl Core {
                 .clone()
         };
 
+        // The frame of an externally called function begins at the current stack pointer
+        self.fp = self.sp;
+
         self.run_at(fn_offset)
     }
 
-    pub fn run_at(&mut self, offset: usize) -> CoreResult<()> {
+    pub fn run_at(&mut self, offset: usize) -> CoreResult<VmExit> {
+        self.check_not_suspended()?;
         self.ip.set(offset);
+        self.continue_run()
+    }
+
+    /// Continues a run suspended at a yield or an interrupt, where it stopped
+    pub fn resume(&mut self) -> CoreResult<VmExit> {
+        if !self.yielded && !self.interrupted {
+            return Err(CoreError::NotYielded);
+        }
+        self.yielded = false;
+        self.interrupted = false;
+        self.continue_run()
+    }
+
+    /// Calls a script function from a running foreign function and runs it until it returns.
+    /// Like for run_fn, the arguments are pushed with push_stack before and popped again after.
+    /// The return value is left in r0, all other registers are restored for the calling script.
+    /// Suspending the core in the callee fails with CoreError::CallbackSuspended.
+    pub fn call_fn(&mut self, uid: u64) -> CoreResult<()> {
+        let fn_offset = {
+            let program = self.program.as_ref()
+                .ok_or(CoreError::NoProgram)?;
+            *program.functions.get(&uid)
+                .ok_or(CoreError::UnknownFunctionUid)?
+        };
+        if self.call_stack.len() >= self.max_call_depth {
+            let fn_name = self.program.as_ref()
+                .and_then(|program| program.get_function_name(uid))
+                .cloned()
+                .unwrap_or_else(|| format!("{:X}", uid));
+            return Err(CoreError::CallDepthExceeded(self.call_stack.len(), fn_name));
+        }
+
+        let (ip, sp, fp) = (self.ip, self.sp, self.fp);
+        let registers = self.registers;
+        let base_call_depth = self.base_call_depth;
+        let frame_guards_len = self.frame_guards.len();
+        self.base_call_depth = self.call_stack.len();
+        self.fp = self.sp;
+        self.ip.set(fn_offset);
+
+        let run_res = self.run_loop();
+
+        // An error leaves the frames of the callee behind
+        while self.call_stack.len() > self.base_call_depth {
+            self.call_stack.pop_front();
+            self.saved_registers.pop();
+        }
+        self.frame_guards.truncate(frame_guards_len);
+        self.base_call_depth = base_call_depth;
+        let ret_value = self.registers[0];
+        self.registers = registers;
+        self.registers[0] = ret_value;
+        self.ip = ip;
+        self.sp = sp;
+        self.fp = fp;
+
+        match run_res? {
+            VmExit::Finished => Ok(()),
+            _ => {
+                self.awaiting_foreign = None;
+                self.yielded = false;
+                self.interrupted = false;
+                Err(CoreError::CallbackSuspended)
+            }
+        }
+    }
+
+    /// Abandons a suspended run, the stack is unwound to the frame of the function it started with
+    pub fn reset(&mut self) {
+        let base_fp = self.call_stack.back()
+            .map(|(_, fp)| *fp)
+            .unwrap_or(self.fp.get());
+        self.sp.set(base_fp);
+        self.fp.set(base_fp);
+        self.call_stack.clear();
+        self.saved_registers.clear();
+        self.frame_guards.clear();
+        self.base_call_depth = 0;
+        self.awaiting_foreign = None;
+        self.yielded = false;
+        self.interrupted = false;
+    }
+
+    /// Records the results of all following foreign calls, until the trace is taken
+    pub fn record_trace(&mut self) {
+        self.trace_mode = Some(TraceMode::Record(Trace::new()));
+    }
+
+    /// Feeds all following foreign calls the results of a recorded trace instead of calling them,
+    /// until the trace is taken. A call the trace does not contain fails with CoreError::TraceDiverged.
+    pub fn replay_trace(&mut self, trace: Trace) {
+        self.trace_mode = Some(TraceMode::Replay(trace, 0));
+    }
+
+    /// Stops recording or replaying, returns the trace
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        match self.trace_mode.take()? {
+            TraceMode::Record(trace) => Some(trace),
+            TraceMode::Replay(trace, _) => Some(trace)
+        }
+    }
+
+    /// Gets a handle which stops a run of this core from another thread
+    pub fn interrupt_handle(&self) -> VmInterrupt {
+        self.interrupt.clone()
+    }
+
+    fn check_not_suspended(&self) -> CoreResult<()> {
+        if let Some(token) = self.awaiting_foreign {
+            return Err(CoreError::AwaitingForeign(token));
+        }
+        if self.yielded {
+            return Err(CoreError::Yielded);
+        }
+        if self.interrupted {
+            return Err(CoreError::Interrupted);
+        }
+        Ok(())
+    }
+
+    /// Delivers the return value of the pending foreign call with the token,
+    /// as if the foreign function had returned it, and continues the run after that call
+    pub fn resume_with<T>(&mut self, token: u64, value: T) -> CoreResult<VmExit>
+        where Register: RegisterAccess<T> {
+        if self.awaiting_foreign != Some(token) {
+            return Err(CoreError::NotAwaitingForeign(token));
+        }
+        self.awaiting_foreign = None;
+        self.registers[0].set::<T>(value);
+        // The pending call was recorded without its result
+        if let Some(TraceMode::Record(trace)) = self.trace_mode.as_mut() {
+            if let Some(entry) = trace.entries.last_mut() {
+                entry.ret_value = self.registers[0].get::<u64>();
+            }
+        }
+        self.continue_run()
+    }
+
+    fn continue_run(&mut self) -> CoreResult<VmExit> {
+        self.run_loop()
+            .map_err(|err| {
+                let backtrace = self.capture_backtrace();
+                self.call_stack.clear();
+                self.saved_registers.clear();
+                self.frame_guards.clear();
+                CoreError::Runtime(Box::new(err), backtrace)
+            })
+    }
+
+    /// Sets a trap which halts the core once the current foreign function returns
+    pub fn set_trap(&mut self, err_code: u8) {
+        self.trap = Some(err_code);
+    }
+
+    /// Fails the run with the error once the current foreign function returns
+    pub fn set_foreign_error(&mut self, err: CoreError) {
+        self.foreign_error = Some(err);
+    }
+
+    /// Sets whether the current foreign function has its return value ready,
+    /// with a pending result the core suspends once the function returns
+    pub fn set_foreign_result(&mut self, result: ForeignResult) {
+        self.awaiting_foreign = match result {
+            ForeignResult::Ready => None,
+            ForeignResult::Pending(token) => Some(token)
+        };
+    }
+
+    /// Gets the token of the pending foreign call the core is suspended on
+    pub fn awaiting_foreign(&self) -> Option<u64> {
+        self.awaiting_foreign
+    }
+
+    /// Captures the call frames, mapping the program counters back to functions
+    pub fn capture_backtrace(&mut self) -> Backtrace {
+        let mut backtrace = Backtrace::new();
+        if let Some(foreign_name) = self.foreign_frame.take() {
+            backtrace = backtrace.with_frame(Frame::Foreign(foreign_name));
+        }
+        let program = match self.program.as_ref() {
+            Some(program) => program,
+            None => return backtrace
+        };
+
+        let current_ip: usize = self.ip.get();
+        let return_ips = self.call_stack.iter().map(|(ret_ip, _)| *ret_ip);
+        for ip in Some(current_ip).into_iter().chain(return_ips) {
+            // The ip already points past the instruction, which may be the last of its function
+            let pc = ip.saturating_sub(1);
+            let fn_entry = program.functions.iter()
+                .filter(|(_, fn_offset)| **fn_offset <= pc)
+                .max_by_key(|(_, fn_offset)| **fn_offset);
+            let frame = match fn_entry {
+                Some((uid, fn_offset)) => {
+                    let fn_name = program.get_function_name(*uid)
+                        .cloned()
+                        .unwrap_or_else(|| format!("{:X}", uid));
+                    Frame::Script(fn_name, ip - fn_offset)
+                },
+                None => Frame::Script(String::from("<unknown>"), ip)
+            };
+            backtrace = backtrace.with_frame(frame);
+        }
+
+        backtrace
+    }
+
+    /// Jumps to the target, which has to lie inside the code segment
+    #[inline]
+    fn jump_to(&mut self, target_ip: u64) -> CoreResult<()> {
+        let program = self.program.as_ref()
+            .ok_or(CoreError::NoProgram)?;
+        let target = target_ip as usize;
+        if target < program.code_start || target > program.code.len() {
+            return Err(CoreError::InvalidJumpTarget(target_ip));
+        }
+        self.ip.set(target_ip);
+        Ok(())
+    }
+
+    fn run_loop(&mut self) -> CoreResult<VmExit> {
         let program_len = self.program_len()?;
+        // Checking the interrupt flag only every few instructions keeps the loop fast
+        let mut interrupt_countdown = INTERRUPT_CHECK_INTERVAL;
         //println!("Program length: {}", program_len);
         while self.ip.get::<usize>() < program_len {
             //println!("ip: {}", self.ip.get::<usize>());
+            let pc: usize = self.ip.get();
+
+            interrupt_countdown -= 1;
+            if interrupt_countdown == 0 {
+                interrupt_countdown = INTERRUPT_CHECK_INTERVAL;
+                if self.interrupt.take() {
+                    // Nothing of the instruction at pc ran yet, the run continues with it on resume
+                    self.interrupted = true;
+                    return Ok(VmExit::Interrupted { pc: pc });
+                }
+            }
             let opcode = self.get_opcode()?;
             //println!("opcode: {:?}", opcode);
             //println!("Stack values: {:?}", &self.stack[0..self.sp]);
@@ -216,6 +749,20 @@ impl Core {
                         1 => {
                             return Err(CoreError::NoReturnValue);
                         },
+                        ASSERT_ERR_CODE => {
+                            return Err(CoreError::AssertionFailed(None));
+                        },
+                        ASSERT_MESSAGE_ERR_CODE => {
+                            let message_size: u64 = self.registers[0].get();
+                            let message_addr: u64 = self.registers[1].get();
+                            let message_data = self.mem_get_n((message_addr, 0), message_size as usize)?;
+                            let message = String::from_utf8(message_data)
+                                .map_err(|_| CoreError::OperatorDeserialize)?;
+                            return Err(CoreError::AssertionFailed(Some(message)));
+                        },
+                        SLICE_BOUNDS_ERR_CODE => {
+                            return Err(CoreError::SliceOutOfBounds);
+                        },
                         _ => {
                             return Err(CoreError::Halted(err_code))
                         }
@@ -326,6 +873,7 @@ impl Core {
                     let lhs_addr: u64 = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (lhs_addr, lhs_offset), size_of::<bool>())?;
                     let boolean: bool = self.mem_get((lhs_addr, lhs_offset))?;
                     self.reg(rhs_reg)?.set(boolean);
                 },
@@ -336,6 +884,7 @@ impl Core {
                     let lhs_addr: u64 = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (lhs_addr, lhs_offset), size_of::<f32>())?;
                     let float: f32 = self.mem_get((lhs_addr, lhs_offset))?;
                     self.reg(rhs_reg)?.set(float)
                 },
@@ -346,6 +895,7 @@ impl Core {
                     let lhs_addr: u64 = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (lhs_addr, lhs_offset), size_of::<i64>())?;
                     let int64: i64 = self.mem_get((lhs_addr, lhs_offset))?;
                     self.reg(rhs_reg)?.set(int64)
                 },
@@ -356,6 +906,7 @@ impl Core {
                     let lhs_addr: u64 = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (lhs_addr, lhs_offset), size_of::<u64>())?;
                     let uint64: u64 = self.mem_get((lhs_addr, lhs_offset))?;
                     self.reg(rhs_reg)?.set(uint64)
                 },
@@ -369,6 +920,7 @@ impl Core {
                     let boolean: bool = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (rhs_addr, rhs_offset), size_of::<bool>())?;
                     self.mem_set((rhs_addr, rhs_offset), boolean)?;
                 },
                 Opcode::MOVF_RA => {
@@ -381,6 +933,7 @@ impl Core {
                     let float: f32 = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (rhs_addr, rhs_offset), size_of::<f32>())?;
                     self.mem_set((rhs_addr, rhs_offset), float)?;
                 },
                 Opcode::MOVI_RA => {
@@ -393,6 +946,7 @@ impl Core {
                     let int64: i64 = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (rhs_addr, rhs_offset), size_of::<i64>())?;
                     self.mem_set((rhs_addr, rhs_offset), int64)?;
                 },
                 Opcode::MOVA_RA => {
@@ -405,6 +959,7 @@ impl Core {
                     let uint64: u64 = {
                         self.reg(lhs_reg)?.get()
                     };
+                    self.check_access(pc, (rhs_addr, rhs_offset), size_of::<u64>())?;
                     self.mem_set((rhs_addr, rhs_offset), uint64)?;
                 },
                 Opcode::LDB => {
@@ -694,7 +1249,7 @@ impl Core {
                 },
                 Opcode::JMP => {
                     let target_ip: u64 = self.get_op()?;
-                    self.ip.set(target_ip);
+                    self.jump_to(target_ip)?;
                 },
                 Opcode::JMPT => {
                     let lhs_reg: u8 = self.get_op()?;
@@ -703,7 +1258,7 @@ impl Core {
                         self.reg(lhs_reg)?.get()
                     };
                     if lhs {
-                        self.ip.set(target_ip);
+                        self.jump_to(target_ip)?;
                     }
                 },
                 Opcode::JMPF => {
@@ -713,7 +1268,7 @@ impl Core {
                         self.reg(lhs_reg)?.get()
                     };
                     if !lhs {
-                        self.ip.set(target_ip);
+                        self.jump_to(target_ip)?;
                     }
                 },
                 Opcode::DJMP => {
@@ -721,7 +1276,7 @@ impl Core {
                     let target_ip: u64 = {
                         self.reg(lhs_reg)?.get()
                     };
-                    self.ip.set(target_ip);
+                    self.jump_to(target_ip)?;
                 },
                 Opcode::DJMPT => {
                     let lhs_reg: u8 = self.get_op()?;
@@ -733,7 +1288,7 @@ impl Core {
                         self.reg(lhs_reg)?.get()
                     };
                     if lhs {
-                        self.ip.set(target_ip);
+                        self.jump_to(target_ip)?;
                     }
                 },
                 Opcode::DJMPF => {
@@ -746,17 +1301,96 @@ impl Core {
                         self.reg(lhs_reg)?.get()
                     };
                     if !lhs {
-                        self.ip.set(target_ip);
+                        self.jump_to(target_ip)?;
                     }
                 },
+                Opcode::JMP_TBL => {
+                    let index_reg: u8 = self.get_op()?;
+                    let table_addr: u64 = self.get_op()?;
+                    let index: u64 = {
+                        self.reg(index_reg)?.get()
+                    };
+                    // The table holds a code address of 8 bytes per index, the compiler checks the index beforehand
+                    let entry_addr = index.checked_mul(8)
+                        .and_then(|entry_offset| table_addr.checked_add(entry_offset))
+                        .filter(|entry_addr| entry_addr.saturating_add(8) <= program_len as u64)
+                        .ok_or(CoreError::InvalidJumpTarget(table_addr))?;
+                    let target_ip: u64 = self.mem_get((entry_addr, 0))?;
+                    self.jump_to(target_ip)?;
+                },
+                Opcode::TRAP => {
+                    let trap_code: u8 = self.get_op()?;
+                    let message_size: u64 = self.get_op()?;
+                    let message_addr: u64 = self.get_op()?;
+                    // An empty message is no message
+                    let message = match message_size {
+                        0 => None,
+                        _ => {
+                            let message_data = self.mem_get_n((message_addr, 0), message_size as usize)?;
+                            let message = String::from_utf8(message_data)
+                                .map_err(|_| CoreError::OperatorDeserialize)?;
+                            Some(message)
+                        }
+                    };
+                    return Err(CoreError::Trapped(trap_code, message));
+                },
+                Opcode::YIELD => {
+                    let kind: u8 = self.get_op()?;
+                    let value_reg: u8 = self.get_op()?;
+                    let value = match kind {
+                        YIELD_NONE => None,
+                        YIELD_INT => Some(Value::Int(self.reg(value_reg)?.get())),
+                        YIELD_FLOAT => Some(Value::Float(self.reg(value_reg)?.get::<f32>() as f64)),
+                        YIELD_DOUBLE => Some(Value::Float(self.reg(value_reg)?.get())),
+                        YIELD_BOOL => Some(Value::Bool(self.reg(value_reg)?.get())),
+                        _ => return Err(CoreError::InvalidYieldKind(kind))
+                    };
+                    // All state lives in the core, the run continues after the YIELD on resume
+                    self.yielded = true;
+                    return Ok(VmExit::Yielded(value));
+                },
+                Opcode::CALL_CALLBACK => {
+                    self.call_callback()?;
+                },
+                Opcode::SLICE_STR => {
+                    let handle_reg: u8 = self.get_op()?;
+                    let start_reg: u8 = self.get_op()?;
+                    let end_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let handle: u64 = {
+                        self.reg(handle_reg)?.get()
+                    };
+                    let start: i64 = {
+                        self.reg(start_reg)?.get()
+                    };
+                    let end: i64 = {
+                        self.reg(end_reg)?.get()
+                    };
+                    // Ranges which are out of order, outside of the string or split a character fail
+                    let slice = match (usize::try_from(start), usize::try_from(end)) {
+                        (Ok(start), Ok(end)) => self.get_string(handle)?.get(start..end),
+                        _ => None
+                    };
+                    let slice = String::from(slice.ok_or(CoreError::SliceOutOfBounds)?);
+                    let slice_handle = self.intern_string(&slice);
+                    self.reg(target_reg)?.set(slice_handle);
+                },
                 Opcode::CALL => {
                     self.call()?;
+                    // All state lives in the core, the run continues after the CALL on resume
+                    if let Some(token) = self.awaiting_foreign {
+                        return Ok(VmExit::AwaitingForeign(token));
+                    }
                 },
                 Opcode::RET => {
                     // Special case if function was called externally, the callstack is empty
-                    if self.call_stack.len() == 0 {
+                    // or back at the depth the foreign function calling it was running at
+                    if self.call_stack.len() == self.base_call_depth {
                         break;
                     }
+                    if !self.frame_guards.is_empty() {
+                        self.check_frame(pc)?;
+                    }
                     self.ret()?;
                 },
                 Opcode::NOT => {
@@ -935,12 +1569,218 @@ impl Core {
                     };
                     self.reg(target_reg)?.set(lhs >= rhs);
                 },
+                Opcode::MOVD => {
+                    let lhs: u8 = self.get_op()?;
+                    let rhs: u8 = self.get_op()?;
+                    let double: f64 = {
+                        self.reg(lhs)?.get()
+                    };
+                    self.reg(rhs)?.set(double);
+                },
+                Opcode::MOVD_A => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let lhs_offset: i16 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let rhs_offset: i16 = self.get_op()?;
+                    let lhs_addr: u64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs_addr: u64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.mem_mov_n((lhs_addr, lhs_offset), (rhs_addr, rhs_offset), 8)?;
+                },
+                Opcode::MOVD_AR => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let lhs_offset: i16 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let lhs_addr: u64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    self.check_access(pc, (lhs_addr, lhs_offset), size_of::<f64>())?;
+                    let double: f64 = self.mem_get((lhs_addr, lhs_offset))?;
+                    self.reg(rhs_reg)?.set(double)
+                },
+                Opcode::MOVD_RA => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let rhs_offset: i16 = self.get_op()?;
+                    let rhs_addr: u64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    let double: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    self.check_access(pc, (rhs_addr, rhs_offset), size_of::<f64>())?;
+                    self.mem_set((rhs_addr, rhs_offset), double)?;
+                },
+                Opcode::LDD => {
+                    let double: f64 = self.get_op()?;
+                    let lhs_reg: u8 = self.get_op()?;
+                    self.reg(lhs_reg)?.set(double);
+                },
+                Opcode::ADDD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs + rhs);
+                },
+                Opcode::SUBD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs - rhs);
+                },
+                Opcode::MULD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs * rhs);
+                },
+                Opcode::DIVD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs / rhs);
+                },
+                Opcode::ADDD_I => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs: f64 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs + rhs);
+                },
+                Opcode::SUBD_I => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs: f64 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs - rhs);
+                },
+                Opcode::MULD_I => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs: f64 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs * rhs);
+                },
+                Opcode::DIVD_I => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs: f64 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs / rhs);
+                },
+                Opcode::EQD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs == rhs);
+                },
+                Opcode::NEQD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs != rhs);
+                },
+                Opcode::LTD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs < rhs);
+                },
+                Opcode::GTD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs > rhs);
+                },
+                Opcode::LTEQD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs <= rhs);
+                },
+                Opcode::GTEQD => {
+                    let lhs_reg: u8 = self.get_op()?;
+                    let rhs_reg: u8 = self.get_op()?;
+                    let target_reg: u8 = self.get_op()?;
+                    let lhs: f64 = {
+                        self.reg(lhs_reg)?.get()
+                    };
+                    let rhs: f64 = {
+                        self.reg(rhs_reg)?.get()
+                    };
+                    self.reg(target_reg)?.set(lhs >= rhs);
+                },
                 _ => {
                     return Err(CoreError::UnimplementedOpcode(opcode));
                 }
             };
         }
-        Ok(())
+        Ok(VmExit::Finished)
     }
 
     fn mem_mov_n(&mut self, lhs: (u64, i16), rhs: (u64, i16), n: usize) -> CoreResult<()> {
@@ -950,7 +1790,9 @@ impl Core {
         let source_addr = lhs_addr.real_address as usize;
         let target_addr = rhs_addr.real_address as usize;
 
-        let bytes = {
+        let bytes = if lhs_addr.address_type == AddressType::Buffer {
+            self.buffer_access(&lhs_addr, n, |bytes| bytes.to_vec())?
+        } else {
             let source: &[u8] = match lhs_addr.address_type {
                 AddressType::Stack => {
                     &self.stack
@@ -984,6 +1826,7 @@ impl Core {
             },
             AddressType::Program => {
                 let program = self.program.as_mut()
+                    .map(Arc::make_mut)
                     .ok_or(CoreError::Unknown)?;
                 for i in 0..n {
                     program.code[target_addr + i] = bytes[i];
@@ -994,6 +1837,9 @@ impl Core {
                     self.swap[target_addr + i] = bytes[i];
                 }
             },
+            AddressType::Buffer => {
+                self.buffer_access(&rhs_addr, n, |target| target.copy_from_slice(&bytes))?;
+            },
             _ => return Err(CoreError::Unknown)
         };
 
@@ -1010,6 +1856,10 @@ impl Core {
 
         let source_addr = lhs_addr.real_address as usize;
 
+        if lhs_addr.address_type == AddressType::Buffer {
+            return self.buffer_access(&lhs_addr, n, |bytes| bytes.to_vec());
+        }
+
         let source: &[u8] = match lhs_addr.address_type {
             AddressType::Stack => {
                 &self.stack
@@ -1073,11 +1923,15 @@ impl Core {
             },
             AddressType::Program => {
                 let program = self.program.as_mut()
+                    .map(Arc::make_mut)
                     .ok_or(CoreError::Unknown)?;
                 for i in 0..n {
                     program.code[target_addr + i] = data[i];
                 }
             },
+            AddressType::Buffer => {
+                self.buffer_access(&lhs_addr, n, |target| target.copy_from_slice(&data[..n]))?;
+            },
             _ => return Err(CoreError::Unknown)
         };
 
@@ -1092,6 +1946,9 @@ impl Core {
         if reg == 17 {
             return Ok(&mut self.ip);
         }
+        if reg == 18 {
+            return Ok(&mut self.fp);
+        }
         else if reg < 16 {
             return Ok(&mut self.registers[reg as usize]);
         }
@@ -1112,14 +1969,60 @@ impl Core {
 
         let new_ip = program.functions.get(&fn_uid)
             .ok_or(CoreError::UnknownFunctionUid)?;
+        let arg_size = program.arg_sizes.get(&fn_uid)
+            .copied()
+            .unwrap_or(0);
+
+        if self.call_stack.len() >= self.max_call_depth {
+            let fn_name = program.get_function_name(fn_uid)
+                .cloned()
+                .unwrap_or_else(|| format!("{:X}", fn_uid));
+            return Err(CoreError::CallDepthExceeded(self.call_stack.len(), fn_name));
+        }
         
         let old_ip: usize = self.ip.get();
-        self.call_stack.push_front(old_ip);
+        let old_fp: u64 = self.fp.get();
+        self.call_stack.push_front((old_ip, old_fp));
+        if program.saves_callee_registers {
+            let mut saved_registers = [0; 4];
+            for (saved, reg) in saved_registers.iter_mut().zip(CALLEE_SAVED_REGISTERS.iter()) {
+                let reg_index: u8 = reg.clone().into();
+                *saved = self.registers[reg_index as usize].get();
+            }
+            self.saved_registers.push(saved_registers);
+        }
         self.ip.set(*new_ip);
+        if self.memory_checks {
+            self.push_frame_canary(arg_size)?;
+        }
+        self.fp = self.sp;
 
         Ok(())
     }
 
+    /// Places a canary word below the arguments of the function being called,
+    /// they are moved up to make room for it, see check_frame
+    fn push_frame_canary(&mut self, arg_size: usize) -> CoreResult<()> {
+        let sp: u64 = self.sp.get();
+        let sp_real = Address::from(sp).real_address as usize;
+        if sp_real < arg_size {
+            return Err(CoreError::InvalidStackPointer);
+        }
+        if sp_real + 8 > self.stack.len() {
+            return Err(CoreError::StackOverflow);
+        }
+        let canary_real = sp_real - arg_size;
+        self.stack.copy_within(canary_real..sp_real, canary_real + 8);
+        self.stack[canary_real..canary_real + 8].copy_from_slice(&FRAME_CANARY.to_le_bytes());
+        self.sp.set(sp + 8);
+        self.frame_guards.push(FrameGuard {
+            call_depth: self.call_stack.len(),
+            fp: sp + 8,
+            canary: sp - arg_size as u64
+        });
+        Ok(())
+    }
+
     /// Retrieves a foreign pointer and returns the correct
     /// Arc<Mutex<T>> if found.
     pub fn get_foreign_ptr<T>(&self, ptr: u64) -> CoreResult<Arc<Mutex<T>>> {
@@ -1157,6 +2060,48 @@ impl Core {
         Ok(ptr)
     }
 
+    /// Maps a host buffer into memory under a name, replacing a buffer mapped under the same name.
+    /// Scripts access it through its address, see BufferHandle::address and get_buffer,
+    /// without copying and only inside of its bounds. Offsets are limited to 32 bits.
+    /// The mapping owns the buffer until it is given back by BufferHandle::unmap.
+    pub fn map_buffer<T: Into<String>>(&mut self, name: T, buffer: Vec<u8>) -> BufferHandle {
+        let name = name.into();
+        // Ids are never reused, so addresses into unmapped buffers keep failing
+        self.mapped_buffers.retain(|_, mapped_buffer| mapped_buffer.is_mapped() && mapped_buffer.name != name);
+
+        let id = self.next_buffer_id;
+        self.next_buffer_id += 1;
+        let address = Address::new(id << BUFFER_OFFSET_BITS, AddressType::Buffer);
+        let len = buffer.len();
+        let bytes = Arc::new(Mutex::new(Some(buffer)));
+        self.mapped_buffers.insert(id, MappedBuffer::new(name, len, bytes.clone()));
+        BufferHandle::new(address.into(), len, bytes)
+    }
+
+    /// Gets the address and the size of the buffer mapped under the name, if its handle is alive
+    pub fn get_buffer(&self, name: &str) -> Option<(u64, usize)> {
+        self.mapped_buffers.iter()
+            .find(|(_, mapped_buffer)| mapped_buffer.name == name && mapped_buffer.is_mapped())
+            .map(|(id, mapped_buffer)| {
+                let address = Address::new(id << BUFFER_OFFSET_BITS, AddressType::Buffer);
+                (address.into(), mapped_buffer.size())
+            })
+    }
+
+    /// Runs the closure on n bytes of a mapped buffer at the address
+    fn buffer_access<R, F: FnOnce(&mut [u8]) -> R>(&self, addr: &Address, n: usize, f: F) -> CoreResult<R> {
+        let raw_addr = Address::new(addr.real_address, AddressType::Buffer).raw_address;
+        let id = addr.real_address >> BUFFER_OFFSET_BITS;
+        let offset = (addr.real_address & ((1 << BUFFER_OFFSET_BITS) - 1)) as usize;
+        let mapped_buffer = self.mapped_buffers.get(&id)
+            .ok_or(CoreError::UnmappedBuffer(raw_addr))?;
+        if offset + n > mapped_buffer.size() {
+            return Err(CoreError::BufferOutOfBounds(raw_addr, n));
+        }
+        mapped_buffer.with_bytes(|bytes| f(&mut bytes[offset..offset + n]))
+            .ok_or(CoreError::UnmappedBuffer(raw_addr))
+    }
+
     /// Removes a foreign pointer
     pub fn remove_foreign_ptr<T>(&mut self, ptr: u64) -> CoreResult<Arc<Mutex<T>>> {
         let arc_box_int = self.foreign_pointers.remove(&ptr)
@@ -1170,34 +2115,226 @@ impl Core {
     }
 
     fn call_foreign_fn(&mut self, uid: u64) -> CoreResult<()> {
+        if let Some(TraceMode::Replay(_, _)) = self.trace_mode {
+            return self.replay_foreign_call(uid);
+        }
+
         let function = {
-            self.program.as_mut()
+            self.program.as_ref()
                 .ok_or(CoreError::NoProgram)?
                 .foreign_functions
-                .remove(&uid)
+                .get(&uid)
+                .cloned()
                 .ok_or(CoreError::UnknownFunctionUid)?
         };
 
         //println!("Calling foreign function {}", function.name);
 
-        {
-            let mut adapter = Adapter::new(&function, self);
-            function.run(&mut adapter);
+        let fn_name = function.name.clone();
+        let override_function = self.foreign_overrides.get(&uid).map(|override_function| {
+            let mut function = function.clone();
+            function.rebind(override_function.clone());
+            function
+        });
+        let run_function = override_function.as_ref().unwrap_or(&function);
+        let is_bound = run_function.is_bound();
+        if is_bound {
+            // The closure is shared and only borrowed, the script functions it calls may call it again
+            let mut adapter = Adapter::new(run_function, self);
+            run_function.run(&mut adapter);
+            if let Some(err) = self.foreign_error.take() {
+                self.awaiting_foreign = None;
+                self.trap = None;
+                self.foreign_frame = Some(fn_name);
+                return Err(err);
+            }
         }
 
-        self.program.as_mut()
-            .ok_or(CoreError::NoProgram)?
-            .foreign_functions
-            .insert(uid, function);
+        if !is_bound {
+            let full_fn_name = self.program.as_ref()
+                .and_then(|program| program.get_function_name(uid))
+                .cloned()
+                .unwrap_or(fn_name);
+            return Err(CoreError::UnboundForeignFunction(full_fn_name));
+        }
+
+        let trap = self.trap.take();
+        if let Some(TraceMode::Record(trace)) = self.trace_mode.as_mut() {
+            trace.entries.push(TraceEntry {
+                fn_uid: uid,
+                ret_value: self.registers[0].get(),
+                trap
+            });
+        }
+
+        if let Some(err_code) = trap {
+            self.awaiting_foreign = None;
+            self.foreign_frame = Some(fn_name);
+            return Err(CoreError::Halted(err_code));
+        }
+
+        Ok(())
+    }
+
+    /// Runs the callback registered for the callback function, its return value is moved into r0
+    fn call_callback(&mut self) -> CoreResult<()> {
+        let uid: u64 = self.get_op()?;
+        let function = {
+            let program = self.program.as_ref()
+                .ok_or(CoreError::NoProgram)?;
+            program.foreign_functions.get(&uid)
+                .cloned()
+                .ok_or(CoreError::UnknownFunctionUid)?
+        };
+        let full_fn_name = || self.program.as_ref()
+            .and_then(|program| program.get_function_name(uid))
+            .cloned()
+            .unwrap_or_else(|| function.name.clone());
+
+        let sp = self.sp.get::<u64>();
+        let mut args = Vec::new();
+        for (i, arg_type) in function.arg_types.iter().enumerate() {
+            let addr = (sp, function.get_arg_offset(i) as i16);
+            let arg = match (arg_type, function.get_arg_size(i)) {
+                (Type::Int, _) => Value::Int(self.mem_get(addr)?),
+                (Type::Float, 4) => Value::Float(self.mem_get::<f32>(addr)? as f64),
+                (Type::Float, _) => Value::Float(self.mem_get(addr)?),
+                (Type::Bool, _) => Value::Bool(self.mem_get(addr)?),
+                _ => return Err(CoreError::CallbackTypeMismatch(full_fn_name()))
+            };
+            args.push(arg);
+        }
+
+        let callback = self.callbacks.get(&uid)
+            .ok_or_else(|| CoreError::UnboundForeignFunction(full_fn_name()))?;
+        let ret_value = callback(args);
+        match (&function.return_type, ret_value) {
+            (Type::Void, _) => {},
+            (Type::Int, Value::Int(value)) => self.registers[0].set(value),
+            (Type::Float, Value::Float(value)) if function.get_ret_size() == 4 => self.registers[0].set(value as f32),
+            (Type::Float, Value::Float(value)) => self.registers[0].set(value),
+            (Type::Bool, Value::Bool(value)) => self.registers[0].set(value),
+            _ => return Err(CoreError::CallbackTypeMismatch(full_fn_name()))
+        };
+        Ok(())
+    }
+
+    /// Returns from a foreign call with the result of the next entry of the replayed trace
+    fn replay_foreign_call(&mut self, uid: u64) -> CoreResult<()> {
+        let fn_name = {
+            let program = self.program.as_ref()
+                .ok_or(CoreError::NoProgram)?;
+            program.get_function_name(uid)
+                .or(program.foreign_functions.get(&uid).map(|function| &function.name))
+                .cloned()
+                .unwrap_or_else(|| format!("{:X}", uid))
+        };
+
+        let (trace, position) = match self.trace_mode.as_mut() {
+            Some(TraceMode::Replay(trace, position)) => (trace, position),
+            _ => return Err(CoreError::Unknown)
+        };
+        let entry = match trace.entries.get(*position) {
+            Some(entry) if entry.fn_uid == uid => *entry,
+            _ => return Err(CoreError::TraceDiverged(*position, fn_name))
+        };
+        *position += 1;
+
+        self.registers[0].set::<u64>(entry.ret_value);
+        if let Some(err_code) = entry.trap {
+            self.foreign_frame = Some(fn_name);
+            return Err(CoreError::Halted(err_code));
+        }
 
         Ok(())
     }
 
+    /// Checks that an access of n bytes stays inside its memory region,
+    /// the used part of the stack, the data of the program or the heap
+    #[inline]
+    fn check_access(&self, pc: usize, addr: (u64, i16), n: usize) -> CoreResult<()> {
+        if !self.memory_checks {
+            return Ok(());
+        }
+
+        let base_addr = Address::from(addr.0);
+        let region_size = match base_addr.address_type {
+            AddressType::Stack => Address::from(self.sp.get::<u64>()).real_address,
+            AddressType::Program => {
+                let program = self.program.as_ref()
+                    .ok_or(CoreError::NoProgram)?;
+                program.code_start as u64
+            },
+            AddressType::Heap => self.heap.len() as u64,
+            AddressType::Swap => self.swap.len() as u64,
+            AddressType::Foreign => 0,
+            // Accesses to mapped buffers are always checked
+            AddressType::Buffer => return Ok(())
+        };
+
+        let start = base_addr.real_address as i64 + addr.1 as i64;
+        if start < 0 || start as u64 + n as u64 > region_size {
+            return Err(CoreError::MemoryViolation {
+                pc: pc,
+                addr: (start.max(0) as u64) | (addr.0 & !(u64::MAX >> 3)),
+                region: base_addr.address_type
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the frame being returned from still has the canary below its arguments
+    /// and the frame pointer set by its CALL, and that its stack pointer did not run below that frame.
+    /// The canary is removed again, the arguments and a return value on the stack move down in its place.
+    fn check_frame(&mut self, pc: usize) -> CoreResult<()> {
+        let guard = match self.frame_guards.last() {
+            Some(guard) if guard.call_depth == self.call_stack.len() => guard.clone(),
+            // The frame was entered without checks
+            _ => return Ok(())
+        };
+        self.frame_guards.pop();
+
+        let canary_real = Address::from(guard.canary).real_address as usize;
+        if self.stack[canary_real..canary_real + 8] != FRAME_CANARY.to_le_bytes() {
+            return Err(CoreError::MemoryViolation {
+                pc,
+                addr: guard.canary,
+                region: AddressType::Stack
+            });
+        }
+        let fp: u64 = self.fp.get();
+        let sp: u64 = self.sp.get();
+        if fp != guard.fp || sp < guard.fp {
+            return Err(CoreError::MemoryViolation {
+                pc,
+                addr: if fp != guard.fp { fp } else { sp },
+                region: AddressType::Stack
+            });
+        }
+
+        let sp_real = Address::from(sp).real_address as usize;
+        self.stack.copy_within(canary_real + 8..sp_real, canary_real);
+        self.sp.set(sp - 8);
+        Ok(())
+    }
+
     #[inline]
     fn ret(&mut self) -> CoreResult<()> {
-        let old_ip = self.call_stack.pop_front()
+        let (old_ip, old_fp) = self.call_stack.pop_front()
             .ok_or(CoreError::EmptyCallStack)?;
         self.ip.uint64 = old_ip as u64;
+        self.fp.uint64 = old_fp;
+        let saves_callee_registers = self.program.as_ref()
+            .ok_or(CoreError::NoProgram)?
+            .saves_callee_registers;
+        if saves_callee_registers {
+            let saved_registers = self.saved_registers.pop()
+                .ok_or(CoreError::EmptyCallStack)?;
+            for (saved, reg) in saved_registers.iter().zip(CALLEE_SAVED_REGISTERS.iter()) {
+                let reg_index: u8 = reg.clone().into();
+                self.registers[reg_index as usize].set(*saved);
+            }
+        }
         Ok(())
     }
 
@@ -0,0 +1,37 @@
+use std::sync::{
+    Arc,
+    atomic::{
+        AtomicBool,
+        Ordering
+    }
+};
+
+/// Handle to stop a run of a core from another thread, see Core::interrupt_handle
+#[derive(Clone, Debug, Default)]
+pub struct VmInterrupt {
+    flag: Arc<AtomicBool>
+}
+
+impl VmInterrupt {
+    pub fn new() -> VmInterrupt {
+        VmInterrupt {
+            flag: Arc::new(AtomicBool::new(false))
+        }
+    }
+
+    /// Stops the core at its next interrupt check.
+    /// The interrupt stays pending until a run of the core checks it.
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether an interrupt is pending
+    pub fn is_interrupted(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// Clears the pending interrupt, returns whether there was one
+    pub(crate) fn take(&self) -> bool {
+        self.flag.swap(false, Ordering::Relaxed)
+    }
+}
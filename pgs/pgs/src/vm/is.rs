@@ -20,6 +20,8 @@ use std::{
 use epd::*;
 use num_traits::FromPrimitive;
 
+pub mod decode;
+
 #[derive(PartialEq, Debug, Clone, Primitive)]
 #[allow(non_camel_case_types)]
 pub enum Opcode {
@@ -92,7 +94,31 @@ pub enum Opcode {
     LTF = 67,
     GTF = 68,
     LTEQF = 69,
-    GTEQF = 70
+    GTEQF = 70,
+    MOVD = 71,
+    MOVD_A = 72,
+    MOVD_AR = 73,
+    MOVD_RA = 74,
+    LDD = 75,
+    ADDD = 76,
+    SUBD = 77,
+    MULD = 78,
+    DIVD = 79,
+    ADDD_I = 80,
+    SUBD_I = 81,
+    MULD_I = 82,
+    DIVD_I = 83,
+    EQD = 84,
+    NEQD = 85,
+    LTD = 86,
+    GTD = 87,
+    LTEQD = 88,
+    GTEQD = 89,
+    YIELD = 90,
+    JMP_TBL = 91,
+    TRAP = 92,
+    CALL_CALLBACK = 93,
+    SLICE_STR = 94
 }
 
 impl TryFrom<u8> for Opcode {
@@ -0,0 +1,248 @@
+//! Decoding of instructions from bytecode.
+//! An instruction is its opcode byte followed by its operands, without padding,
+//! see opcode_operand_layout for the operands of each opcode.
+//! The compiler checks the operands of the instructions it emits against the same layout.
+
+use super::Opcode;
+
+use std::convert::TryFrom;
+
+use bincode::deserialize;
+use serde::de::DeserializeOwned;
+
+/// Kind and encoding of an instruction operand
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OperandKind {
+    /// Register number, u8
+    Register,
+    /// Plain byte, u8
+    Byte,
+    /// Address offset, i16
+    Offset,
+    /// Byte count, u32
+    Count,
+    Int,
+    Uint,
+    Float,
+    Double,
+    Bool,
+    /// Absolute code address, u64
+    CodeAddress,
+    /// Uid of the called function, u64
+    FunctionUid
+}
+
+impl OperandKind {
+    pub fn size(&self) -> usize {
+        match self {
+            OperandKind::Register |
+            OperandKind::Byte |
+            OperandKind::Bool => 1,
+            OperandKind::Offset => 2,
+            OperandKind::Count |
+            OperandKind::Float => 4,
+            OperandKind::Int |
+            OperandKind::Uint |
+            OperandKind::Double |
+            OperandKind::CodeAddress |
+            OperandKind::FunctionUid => 8
+        }
+    }
+}
+
+/// Gets the operands the core reads after an opcode, in order
+pub fn opcode_operand_layout(opcode: &Opcode) -> &'static [OperandKind] {
+    use OperandKind::*;
+
+    match opcode {
+        Opcode::NOOP |
+        Opcode::RET => &[],
+        Opcode::HALT => &[Byte],
+        Opcode::TRAP => &[Byte, Uint, Uint],
+        Opcode::YIELD => &[Byte, Register],
+        Opcode::MOVB |
+        Opcode::MOVF |
+        Opcode::MOVI |
+        Opcode::MOVA |
+        Opcode::MOVD |
+        Opcode::NOT |
+        Opcode::DJMPT |
+        Opcode::DJMPF => &[Register, Register],
+        Opcode::MOVB_A |
+        Opcode::MOVF_A |
+        Opcode::MOVI_A |
+        Opcode::MOVA_A |
+        Opcode::MOVD_A => &[Register, Offset, Register, Offset],
+        Opcode::MOVN_A => &[Register, Offset, Register, Offset, Count],
+        Opcode::MOVB_AR |
+        Opcode::MOVF_AR |
+        Opcode::MOVI_AR |
+        Opcode::MOVA_AR |
+        Opcode::MOVD_AR => &[Register, Offset, Register],
+        Opcode::MOVB_RA |
+        Opcode::MOVF_RA |
+        Opcode::MOVI_RA |
+        Opcode::MOVA_RA |
+        Opcode::MOVD_RA => &[Register, Register, Offset],
+        Opcode::LDB => &[Bool, Register],
+        Opcode::LDF => &[Float, Register],
+        Opcode::LDD => &[Double, Register],
+        Opcode::LDI => &[Int, Register],
+        Opcode::LDA => &[Uint, Register],
+        Opcode::ADDI_I |
+        Opcode::SUBI_I |
+        Opcode::MULI_I |
+        Opcode::DIVI_I => &[Register, Int, Register],
+        Opcode::ADDU_I |
+        Opcode::SUBU_I |
+        Opcode::MULU_I |
+        Opcode::DIVU_I => &[Register, Uint, Register],
+        Opcode::ADDF_I |
+        Opcode::SUBF_I |
+        Opcode::MULF_I |
+        Opcode::DIVF_I => &[Register, Float, Register],
+        Opcode::ADDD_I |
+        Opcode::SUBD_I |
+        Opcode::MULD_I |
+        Opcode::DIVD_I => &[Register, Double, Register],
+        Opcode::JMP => &[CodeAddress],
+        Opcode::JMPT |
+        Opcode::JMPF => &[Register, CodeAddress],
+        Opcode::DJMP => &[Register],
+        Opcode::JMP_TBL => &[Register, Uint],
+        Opcode::CALL |
+        Opcode::CALL_CALLBACK => &[FunctionUid],
+        Opcode::SLICE_STR => &[Register, Register, Register, Register],
+        _ => &[Register, Register, Register]
+    }
+}
+
+/// A decoded operand value
+#[derive(Clone, PartialEq, Debug)]
+pub enum Operand {
+    Register(u8),
+    Byte(u8),
+    Offset(i16),
+    Count(u32),
+    Int(i64),
+    Uint(u64),
+    Float(f32),
+    Double(f64),
+    Bool(bool),
+    CodeAddress(u64),
+    FunctionUid(u64)
+}
+
+/// An instruction decoded from code
+#[derive(Clone, PartialEq, Debug)]
+pub struct DecodedInstruction {
+    /// Address of the opcode
+    pub addr: usize,
+    pub opcode: Opcode,
+    /// The operands with their byte offsets after the opcode
+    pub operands: Vec<(usize, Operand)>
+}
+
+impl DecodedInstruction {
+    /// Gets the size of the instruction in bytes, including the opcode
+    pub fn size(&self) -> usize {
+        1 + opcode_operand_layout(&self.opcode).iter()
+            .map(|kind| kind.size())
+            .sum::<usize>()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum DecodeError {
+    /// Address and value of a byte which is no opcode
+    InvalidOpcode(usize, u8),
+    /// Address of an instruction whose operands run past the end of the code
+    Truncated(usize, Opcode)
+}
+
+/// Iterator over the instructions of code, see decode_instructions
+pub struct Instructions<'c> {
+    code: &'c [u8],
+    addr: usize,
+    failed: bool
+}
+
+impl<'c> Iterator for Instructions<'c> {
+    type Item = Result<DecodedInstruction, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.addr >= self.code.len() {
+            return None;
+        }
+
+        let addr = self.addr;
+        let opcode = match Opcode::try_from(self.code[addr]) {
+            Ok(opcode) => opcode,
+            Err(_) => {
+                self.failed = true;
+                return Some(Err(DecodeError::InvalidOpcode(addr, self.code[addr])));
+            }
+        };
+        let size: usize = opcode_operand_layout(&opcode).iter()
+            .map(|kind| kind.size())
+            .sum();
+        let operands = self.code.get(addr + 1..addr + 1 + size)
+            .and_then(|bytes| decode_operands(&opcode, bytes));
+        match operands {
+            Some(operands) => {
+                self.addr += 1 + size;
+                Some(Ok(DecodedInstruction {
+                    addr,
+                    opcode,
+                    operands
+                }))
+            },
+            None => {
+                self.failed = true;
+                Some(Err(DecodeError::Truncated(addr, opcode)))
+            }
+        }
+    }
+}
+
+/// Decodes the instructions of the code starting at an address.
+/// Decoding stops after the first invalid opcode or truncated instruction, which is returned as error.
+pub fn decode_instructions(code: &[u8], start: usize) -> Instructions<'_> {
+    Instructions {
+        code,
+        addr: start,
+        failed: false
+    }
+}
+
+/// Decodes the operand bytes of an instruction, together with their byte offsets.
+/// None if the bytes do not match the operands of the opcode.
+pub fn decode_operands(opcode: &Opcode, bytes: &[u8]) -> Option<Vec<(usize, Operand)>> {
+    let mut ret = Vec::new();
+    let mut offset = 0;
+    for kind in opcode_operand_layout(opcode) {
+        let operand = match kind {
+            OperandKind::Register => Operand::Register(read(bytes, offset, 1)?),
+            OperandKind::Byte => Operand::Byte(read(bytes, offset, 1)?),
+            OperandKind::Offset => Operand::Offset(read(bytes, offset, 2)?),
+            OperandKind::Count => Operand::Count(read(bytes, offset, 4)?),
+            OperandKind::Int => Operand::Int(read(bytes, offset, 8)?),
+            OperandKind::Uint => Operand::Uint(read(bytes, offset, 8)?),
+            OperandKind::Float => Operand::Float(read(bytes, offset, 4)?),
+            OperandKind::Double => Operand::Double(read(bytes, offset, 8)?),
+            OperandKind::Bool => Operand::Bool(read(bytes, offset, 1)?),
+            OperandKind::CodeAddress => Operand::CodeAddress(read(bytes, offset, 8)?),
+            OperandKind::FunctionUid => Operand::FunctionUid(read(bytes, offset, 8)?)
+        };
+        ret.push((offset, operand));
+        offset += kind.size();
+    }
+    if offset != bytes.len() {
+        return None;
+    }
+    Some(ret)
+}
+
+fn read<T: DeserializeOwned>(bytes: &[u8], offset: usize, size: usize) -> Option<T> {
+    deserialize(bytes.get(offset..offset + size)?).ok()
+}
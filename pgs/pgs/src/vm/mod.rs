@@ -4,4 +4,14 @@ pub mod is;
 
 pub mod address;
 
-pub mod register;
\ No newline at end of file
+pub mod register;
+
+pub mod backtrace;
+
+pub mod interrupt;
+
+pub mod buffer;
+
+pub mod trace;
+
+pub mod strings;
\ No newline at end of file
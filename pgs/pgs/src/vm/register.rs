@@ -12,6 +12,7 @@ pub union Register {
     pub uint64: u64,
     pub int64: i64,
     pub float: f32,
+    pub double: f64,
     pub boolean: bool
 }
 
@@ -122,6 +123,27 @@ impl RegisterAccess<f32> for Register {
     }
 }
 
+impl RegisterAccess<f64> for Register {
+    fn get_val(&self) -> f64 {
+        unsafe {
+            self.double
+        }
+    }
+    fn set_val(&mut self, item: f64) {
+        self.double = item;
+    }
+    fn inc_val(&mut self, item: f64) {
+        unsafe {
+            self.double += item;
+        }
+    }
+    fn dec_val(&mut self, item: f64) {
+        unsafe {
+            self.double -= item;
+        }
+    }
+}
+
 impl RegisterAccess<bool> for Register {
     fn get_val(&self) -> bool {
         unsafe {
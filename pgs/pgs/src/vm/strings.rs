@@ -0,0 +1,74 @@
+//! Strings interned by the core, which scripts refer to by 8 byte handles
+//! instead of 16 byte fat pointers, see StringRepr.
+
+use std::collections::HashMap;
+
+/// How scripts represent string values
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StringRepr {
+    /// Size and address of the bytes, 16 bytes
+    FatPointer,
+    /// Handle into the string table of the core, 8 bytes.
+    /// Equal strings share a handle, so they compare as integers.
+    Handle
+}
+
+impl Default for StringRepr {
+    fn default() -> StringRepr {
+        StringRepr::FatPointer
+    }
+}
+
+/// Table of interned strings, the handle of a string is its index
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct StringTable {
+    strings: Vec<String>,
+    handles: HashMap<String, u64>
+}
+
+impl StringTable {
+    pub fn new() -> StringTable {
+        StringTable::default()
+    }
+
+    /// Interns a string, an equal string which is already interned keeps its handle
+    pub fn intern(&mut self, string: &str) -> u64 {
+        if let Some(handle) = self.handles.get(string) {
+            return *handle;
+        }
+        let handle = self.strings.len() as u64;
+        self.strings.push(String::from(string));
+        self.handles.insert(String::from(string), handle);
+        handle
+    }
+
+    /// Gets the string of a handle
+    pub fn get(&self, handle: u64) -> Option<&str> {
+        self.strings.get(handle as usize)
+            .map(|string| string.as_str())
+    }
+
+    /// Gets all strings, ordered by their handles
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl From<&[String]> for StringTable {
+    /// Interns the strings in order, distinct strings get their index as handle
+    fn from(strings: &[String]) -> StringTable {
+        let mut table = StringTable::new();
+        for string in strings {
+            table.intern(string);
+        }
+        table
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{
+    Serialize,
+    Serializer,
+    Deserialize,
+    Deserializer
+};
+use bincode::{
+    serialize,
+    deserialize
+};
+
+/// The outcome of a single foreign call
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct TraceEntry {
+    pub fn_uid: u64,
+    /// Raw contents of r0 once the call returned
+    pub ret_value: u64,
+    /// Error code of a trap set by the call
+    pub trap: Option<u8>
+}
+
+/// The outcomes of the foreign calls of a run, in call order.
+/// Replaying a trace feeds a run the same results without calling the foreign functions.
+/// Only the return value is recorded, writes of foreign functions to script memory are not.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace {
+            entries: Vec::new()
+        }
+    }
+
+    pub fn with_entry(mut self, entry: TraceEntry) -> Trace {
+        self.entries.push(entry);
+        self
+    }
+
+    /// Serializes the trace into its compact binary form
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialize(self).expect("Could not serialize trace!")
+    }
+
+    /// Deserializes a trace from its compact binary form, none if the bytes are no trace
+    pub fn from_bytes(bytes: &[u8]) -> Option<Trace> {
+        deserialize(bytes).ok()
+    }
+}
+
+impl Serialize for Trace {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.entries.iter()
+            .map(|entry| (entry.fn_uid, entry.ret_value, entry.trap)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Trace {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Trace, D::Error> {
+        let entries = Vec::<(u64, u64, Option<u8>)>::deserialize(deserializer)?;
+        Ok(
+            Trace {
+                entries: entries.into_iter()
+                    .map(|(fn_uid, ret_value, trap)| TraceEntry {
+                        fn_uid,
+                        ret_value,
+                        trap
+                    })
+                    .collect()
+            }
+        )
+    }
+}
+
+/// Whether the foreign calls of the core are recorded into or replayed from a trace
+#[derive(PartialEq, Debug, Clone)]
+pub enum TraceMode {
+    Record(Trace),
+    /// Replays the trace, the position is the index of the entry for the next foreign call
+    Replay(Trace, usize)
+}
@@ -2,23 +2,106 @@ extern crate pgs;
 use pgs::{
     codegen::{
         compiler::{
-            Compiler
+            Compiler,
+            CompilerError,
+            CompilerResult,
+            CompilerWarning,
+            EXPRESSION_FN_NAME
         },
         program::{
-            Program
+            Program,
+            DebugInfo,
+            FunctionInfo,
+            ContainerInfo,
+            MemberInfo,
+            LocalVariable
         },
         instruction::{
             Instruction
+        },
+        def::FunctionDef,
+        data::Data,
+        interface::{
+            FunctionSignature,
+            RequiredInterface,
+            InterfaceMismatch
+        },
+        builder::{
+            Builder,
+            BuilderError
+        },
+        register::{
+            Register,
+            RegisterAllocator
+        },
+        asm::{
+            disassemble_with_source,
+            emit_asm,
+            emit_builder_asm,
+            assemble,
+            AsmError
+        }
+    },
+    vm::{
+        core::{
+            Core,
+            CoreError,
+            UNREACHABLE_TRAP_CODE
+        },
+        is::{
+            Opcode,
+            decode::{
+                decode_instructions,
+                decode_operands,
+                Operand,
+                OperandKind
+            }
         }
     },
+    api::{
+        module::Module,
+        function::Function
+    },
     parser::{
         parser::Parser,
-        lexer::Token
+        lexer::Token,
+        ast::{
+            Type,
+            Declaration
+        }
     }
 };
 
 use pglex::prelude::Lexable;
 
+use std::{
+    sync::{
+        Arc,
+        atomic::{
+            AtomicUsize,
+            Ordering
+        }
+    },
+    thread,
+    time::{
+        Duration,
+        Instant
+    }
+};
+
+/// Parses and compiles the code with a new compiler
+fn compile_code(code: &str) -> CompilerResult<Compiler> {
+    compile_code_with(Compiler::new(), code)
+}
+
+/// Parses and compiles the code with a configured compiler
+fn compile_code_with(mut compiler: Compiler, code: &str) -> CompilerResult<Compiler> {
+    let decl_list = Parser::new(String::from(code)).parse_root_decl_list().unwrap();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    compile_res.map(|_| compiler)
+}
+
 /*
 
 #[test]
@@ -277,4 +360,2575 @@ fn test_compile_member_call() {
         println!("{}:  {:?}", pos, instr);
         pos += instr.get_size();
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_compile_void_var_decl() {
+    let code = String::from("
+        fn: nothing() {
+        }
+
+        fn: main() {
+            var x = nothing();
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+
+    let decl_list_res = parser.parse_decl_list(&mut lexer, &[]);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    match compile_res {
+        Err(CompilerError::VoidVariable(var_name)) => assert_eq!(var_name, "x"),
+        _ => panic!("Expected VoidVariable error")
+    }
+}
+
+#[test]
+fn test_compile_auto_var_decl() {
+    let code = String::from("
+        fn: main() {
+            var x = host::unknown();
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+
+    let decl_list_res = parser.parse_decl_list(&mut lexer, &[]);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    // A foreign function without a concrete return type leaves the variable type uninferred
+    let unknown_function = Function::new("unknown")
+        .with_ret_type(Type::Auto)
+        .with_closure(Box::new(|_| {}));
+
+    let mut compiler = Compiler::new()
+        .with_foreign_module(Module::new("host").with_function(unknown_function))
+        .unwrap();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    match compile_res {
+        Err(CompilerError::UnknownType(var_type)) => assert_eq!(var_type, Type::Auto),
+        _ => panic!("Expected UnknownType error")
+    }
+}
+
+#[test]
+fn test_compile_chained_foreign_modules() {
+    let code = String::from("
+        fn: main() ~ int {
+            io::print_int(math::square(4));
+            return 0;
+        }
+    ");
+
+    let square_function = Function::new("square")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(|adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            adapter.return_value(arg * arg);
+        }));
+    let print_int_function = Function::new("print_int")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Void)
+        .with_closure(Box::new(|adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            println!("{}", arg);
+        }));
+
+    let math_module = Module::new("math")
+        .with_function(square_function);
+    let io_module = Module::new("io")
+        .with_function(print_int_function);
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+
+    let decl_list_res = parser.parse_decl_list(&mut lexer, &[]);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let compiler_res = Compiler::new()
+        .with_foreign_module(math_module)
+        .and_then(|compiler| compiler.with_foreign_module(io_module));
+    assert!(compiler_res.is_ok());
+
+    let mut compiler = compiler_res.unwrap();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+
+    let program_res = compiler.get_program();
+    assert!(program_res.is_ok());
+    // math::square, io::print_int and the built-in __builtin::string::equals
+    assert_eq!(program_res.unwrap().foreign_functions.len(), 3);
+}
+
+#[test]
+fn test_compile_user_string_module() {
+    let len_function = Function::new("len")
+        .with_arg(Type::String)
+        .with_ret_type(Type::Int);
+    let compiler_res = Compiler::new()
+        .with_foreign_module(Module::new("string").with_function(len_function));
+    assert!(compiler_res.is_ok());
+
+    // String comparisons still call the built-in string module
+    let compile_res = compile_code_with(compiler_res.unwrap(), "fn: main() ~ bool { return \"a\" == \"b\"; }");
+    assert!(compile_res.is_ok());
+    let mut compiler = compile_res.unwrap();
+    let len_uid = compiler.get_function_uid(&String::from("root::string::len"));
+    let equals_uid = compiler.get_function_uid(&String::from("root::__builtin::string::equals"));
+    assert!(len_uid.is_ok() && equals_uid.is_ok());
+    assert_ne!(len_uid.unwrap(), equals_uid.unwrap());
+    assert_eq!(compiler.get_program().unwrap().foreign_functions.len(), 2);
+
+    // Only the name of the module holding the built-in modules is taken
+    match Compiler::new().with_foreign_module(Module::new("__builtin")) {
+        Err(CompilerError::DuplicateModule(name)) => assert_eq!(name, "__builtin"),
+        res => panic!("Expected a duplicate module, got {:?}", res.err())
+    }
+}
+
+#[test]
+fn test_compile_aliased_import_call() {
+    let code = String::from("
+        import a::b::deep = d;
+
+        fn: main() ~ int {
+            return d(14);
+        }
+    ");
+    let deep_function = Function::new("deep")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(|adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            adapter.return_value(arg * 3);
+        }));
+    let a_module = Module::new("a")
+        .with_module(Module::new("b").with_function(deep_function));
+
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new()
+        .with_foreign_module(a_module)
+        .unwrap();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+
+    // The alias resolves to the imported function itself
+    let deep_uid = compiler.get_function_uid(&String::from("root::a::b::deep")).unwrap();
+    let fn_def = compiler.resolve_function(&String::from("d")).unwrap();
+    assert_eq!(fn_def.name, "deep");
+    assert_eq!(fn_def.uid, deep_uid);
+    assert_eq!(fn_def.ret_type, Type::Int);
+
+    let program = compiler.get_program().unwrap();
+    let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    assert!(core.run_fn(main_uid).is_ok());
+    assert_eq!(core.reg(0).unwrap().get::<i64>(), 42);
+}
+
+#[test]
+fn test_program_builder() {
+    let code = vec![0, 0, 55];
+    let debug_info = DebugInfo::new()
+        .with_function_name(1, String::from("root::main"));
+
+    let program_res = Program::builder()
+        .code(code)
+        .add_function(1, 1)
+        .debug_info(debug_info)
+        .build();
+    assert!(program_res.is_ok());
+
+    let program = program_res.unwrap();
+    assert_eq!(program.functions.get(&1), Some(&1));
+    assert_eq!(program.get_function_name(1), Some(&String::from("root::main")));
+
+    let empty_program_res = Program::builder().build();
+    assert!(empty_program_res.is_ok());
+
+    let no_functions_res = Program::builder()
+        .code(vec![55])
+        .build();
+    assert!(no_functions_res.is_err());
+
+    let no_code_res = Program::builder()
+        .add_function(1, 0)
+        .build();
+    assert!(no_code_res.is_err());
+
+    let invalid_program_res = Program::builder()
+        .code(vec![55])
+        .add_function(1, 4)
+        .build();
+    assert!(invalid_program_res.is_err());
+}
+
+#[test]
+fn test_compile_empty_source() {
+    let code = String::from("
+        // Nothing to see here
+        /* Still nothing */
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+
+    let decl_list_res = parser.parse_decl_list(&mut lexer, &[]);
+    println!("{:?}", decl_list_res);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+    assert!(decl_list.is_empty());
+
+    // Foreign functions without arguments must not break registration
+    let nothing_function = Function::new("nothing")
+        .with_ret_type(Type::Void)
+        .with_closure(Box::new(|_| {}));
+    let module = Module::new("std")
+        .with_function(nothing_function);
+
+    let mut compiler = Compiler::new();
+    assert!(compiler.register_foreign_root_module(module).is_ok());
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+
+    let program_res = compiler.get_program();
+    println!("{:?}", program_res);
+    assert!(program_res.is_ok());
+
+    let program = program_res.unwrap();
+    assert!(program.functions.is_empty());
+    assert!(program.code.is_empty());
+
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    assert!(core.run().is_ok());
+}
+
+#[test]
+fn test_function_signature_string() {
+    let fn_def = FunctionDef::new(String::from("foo"))
+        .with_arguments(&[
+            (String::from("a"), Type::Int),
+            (String::from("b"), Type::Reference(Box::new(Type::AutoArray(Box::new(Type::Float)))))
+        ])
+        .with_ret_type(Type::Bool);
+
+    assert_eq!("foo(int, &float[]) -> bool", fn_def.signature_string());
+
+    let code = String::from("
+        fn: foo(a: int, b: float) ~ bool {
+            return true;
+        }
+
+        fn: main() {
+            foo(1);
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+
+    let decl_list_res = parser.parse_decl_list(&mut lexer, &[]);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    match compile_res {
+        Err(err @ CompilerError::ArgumentCountMismatch { .. }) => {
+            assert_eq!(err.to_string(), "foo takes 2 arguments, but was called with 1");
+        },
+        res => panic!("Expected an argument count mismatch, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_compile_index_out_of_bounds() {
+    let compile = |body: &str| {
+        let code = format!("
+            fn: main(arr: [int; 5], i: int) ~ int {{
+                var r: &[int; 5] = &arr;
+                {}
+            }}
+        ", body);
+        compile_code(&code)
+    };
+
+    match compile("return arr[10];") {
+        Err(CompilerError::IndexOutOfBounds(index, size)) => {
+            assert_eq!(index, 10);
+            assert_eq!(size, 5);
+        },
+        res => panic!("Expected an out of bounds index, got {:?}", res.err())
+    }
+    // Constant expressions are folded first
+    match compile("arr[2 + 3] = 1; return 0;") {
+        Err(err @ CompilerError::IndexOutOfBounds(5, 5)) => {
+            assert_eq!(err.to_string(), "index 5 is out of bounds of an array of size 5");
+        },
+        res => panic!("Expected an out of bounds index, got {:?}", res.err())
+    }
+    match compile("return r[0 - 1];") {
+        Err(CompilerError::IndexOutOfBounds(-1, 5)) => {},
+        res => panic!("Expected an out of bounds index, got {:?}", res.err())
+    }
+    assert!(compile("return arr[4] + r[0];").is_ok());
+    assert!(compile("return arr[i + 10];").is_ok());
+}
+
+#[test]
+fn test_type_display() {
+    let types = [
+        (Type::Void, "void"),
+        (Type::Int, "int"),
+        (Type::Float, "float"),
+        (Type::Bool, "bool"),
+        (Type::String, "string"),
+        (Type::Array(Box::new(Type::Int), 3), "int[3]"),
+        (Type::Reference(Box::new(Type::AutoArray(Box::new(Type::Bool)))), "&bool[]"),
+        (Type::Reference(Box::new(Type::Other(String::from("root::Point")))), "&root::Point")
+    ];
+    for (var_type, expected) in types.iter() {
+        assert_eq!(var_type.to_string(), *expected);
+    }
+
+    let err = CompilerError::TypeMismatch(
+        Type::Reference(Box::new(Type::AutoArray(Box::new(Type::Bool)))),
+        Type::Reference(Box::new(Type::Array(Box::new(Type::Int), 3)))
+    );
+    assert_eq!(err.to_string(), "mismatched types &bool[] and &int[3]");
+    assert_eq!(CompilerError::NotIndexable(Type::Int).to_string(), "cannot index into int");
+
+    let code = String::from("
+        fn: foo(a: int) {}
+
+        fn: main() {
+            foo(1.5);
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    match compiler.compile_root(&decl_list) {
+        Err(err @ CompilerError::TypeMismatch(_, _)) => assert_eq!(err.to_string(), "mismatched types int and float"),
+        res => panic!("Expected a type mismatch, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_check_negate_type() {
+    let check = |code: &str| {
+        let mut lexer = Token::lexer(code);
+        let expr = Parser::new(String::from(code)).parse_expr(&mut lexer, &[Token::Semicolon]).unwrap();
+        Compiler::new().check_expr_type(&expr)
+    };
+
+    assert_eq!(check("-5;").unwrap(), Type::Int);
+    assert_eq!(check("-(1.5 * 2.0);").unwrap(), Type::Float);
+    match check("-(true);") {
+        Err(CompilerError::TypeMismatch(Type::Int, Type::Bool)) => {},
+        res => panic!("Expected a type mismatch, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_compile_slice_types() {
+    assert!(compile_code("fn: main() { var s = \"hello\"[1..3]; }").is_ok());
+    match compile_code("fn: main() { var s = \"hello\"[1..true]; }") {
+        Err(CompilerError::TypeMismatch(Type::Int, Type::Bool)) => {},
+        res => panic!("Expected a type mismatch, got {:?}", res.err())
+    }
+    match compile_code("fn: main(a: [int; 4]) { var b = a[1..3]; }") {
+        Err(CompilerError::NotIndexable(Type::Array(_, 4))) => {},
+        res => panic!("Expected a non-indexable type, got {:?}", res.err())
+    }
+    match compile_code("fn: main() { var a = 1..3; }") {
+        Err(CompilerError::UnsupportedExpression(_)) => {},
+        res => panic!("Expected an unsupported expression, got {:?}", res.err())
+    }
+}
+
+#[test]
+fn test_compile_argument_count_mismatch() {
+    let code = String::from("
+        fn: foo(a: int, b: float) ~ bool {
+            return true;
+        }
+
+        fn: main() {
+            foo(1);
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    match compiler.compile_root(&decl_list) {
+        Err(CompilerError::ArgumentCountMismatch { name, expected, found }) => {
+            assert_eq!(name, "foo");
+            assert_eq!(expected, 2);
+            assert_eq!(found, 1);
+        },
+        res => panic!("Expected an argument count mismatch, got {:?}", res)
+    }
+
+    // this is not counted for member calls
+    let code = String::from("
+        cont: Point {
+            x: int;
+        }
+
+        impl: Point {
+            fn: add(&this, a: int, b: int) ~ int {
+                return this.x + a + b;
+            }
+        }
+
+        fn: main() ~ int {
+            var p = Point { x: 1 };
+            return p.add(1, 2, 3);
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    match compiler.compile_root(&decl_list) {
+        Err(CompilerError::ArgumentCountMismatch { name, expected, found }) => {
+            assert_eq!(name, "add");
+            assert_eq!(expected, 2);
+            assert_eq!(found, 3);
+        },
+        res => panic!("Expected an argument count mismatch, got {:?}", res)
+    }
+
+    // Functions without arguments take none
+    let code = String::from("
+        fn: seven() ~ int {
+            return 7;
+        }
+
+        fn: main() ~ int {
+            return seven(1, 2);
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    match compiler.compile_root(&decl_list) {
+        Err(CompilerError::ArgumentCountMismatch { name, expected, found }) => {
+            assert_eq!(name, "seven");
+            assert_eq!(expected, 0);
+            assert_eq!(found, 2);
+        },
+        res => panic!("Expected an argument count mismatch, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_compile_break_outside_loop() {
+    let code = String::from("
+        fn: main() {
+            var x: int = 1;
+            if x > 0 {
+                break;
+            }
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+
+    let decl_list_res = parser.parse_decl_list(&mut lexer, &[]);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_err());
+    assert!(compile_res.unwrap_err().to_string().contains("break outside loop"));
+
+    let code = String::from("
+        fn: main() {
+            continue;
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+
+    let decl_list_res = parser.parse_decl_list(&mut lexer, &[]);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_err());
+    assert!(compile_res.unwrap_err().to_string().contains("continue outside loop"));
+}
+
+#[test]
+fn test_find_function_anywhere() {
+    let code = String::from("
+        mod: outer {
+            mod: middle {
+                mod: inner {
+                    fn: hidden(x: int) ~ int {
+                        return x;
+                    }
+                }
+            }
+        }
+
+        fn: main() {
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let declare_res = compiler.declare_decl_list(&decl_list);
+    println!("{:?}", declare_res);
+    assert!(declare_res.is_ok());
+
+    let fn_def_res = compiler.find_function_anywhere("hidden");
+    assert!(fn_def_res.is_ok());
+
+    let fn_def = fn_def_res.unwrap();
+    assert_eq!(fn_def.name, "hidden");
+    assert_eq!(fn_def.ret_type, Type::Int);
+
+    assert!(compiler.find_function_anywhere("main").is_ok());
+    assert!(compiler.find_function_anywhere("missing").is_err());
+}
+
+#[test]
+fn test_compile_call_non_function() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+        }
+
+        fn: main() {
+            var x: int = 5;
+            x();
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    match compile_res {
+        Err(CompilerError::NotCallable(name)) => assert_eq!(name, "x"),
+        _ => panic!("Expected NotCallable error")
+    }
+
+    let code = String::from("
+        cont: Point {
+            x: int;
+        }
+
+        fn: main() {
+            Point();
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list = parser.parse_root_decl_list().unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    match compile_res {
+        Err(CompilerError::NotCallable(name)) => assert_eq!(name, "Point"),
+        _ => panic!("Expected NotCallable error")
+    }
+}
+
+#[test]
+fn test_program_symbol_at() {
+    let code = String::from("
+        fn: helper() ~ int {
+            return 2;
+        }
+
+        fn: main() ~ int {
+            return helper() + 1;
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new()
+        .with_debug_symbols(true);
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+
+    let program_res = compiler.get_program();
+    assert!(program_res.is_ok());
+    let program = program_res.unwrap();
+
+    for fn_name in ["root::helper", "root::main"].iter() {
+        let fn_uid = program.debug_info.function_names.iter()
+            .find(|(_, name)| name == fn_name)
+            .map(|(uid, _)| *uid)
+            .unwrap();
+        let fn_offset = *program.functions.get(&fn_uid).unwrap();
+
+        let symbol = program.symbol_at(fn_offset + 1).unwrap();
+        assert_eq!(&symbol.function_name, fn_name);
+        assert!(symbol.code_range.contains(&fn_offset));
+        assert!(code[symbol.source_span.clone()].starts_with("fn:"));
+        assert!(code[symbol.source_span.clone()].ends_with("}"));
+    }
+    assert!(program.symbol_at(program.code.len()).is_none());
+
+    // Without debug symbols, no symbol table is emitted
+    let mut compiler = Compiler::new();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let program = compiler.get_program().unwrap();
+    assert!(program.symbol_table.is_none());
+    assert!(program.symbol_at(0).is_none());
+}
+
+#[test]
+fn test_disassemble_with_source() {
+    let code = String::from("fn: main() ~ int {
+    var a: int = 4;
+    var b: int = a * 2;
+    a = a + b;
+    b = b - 1;
+    return a * b;
+}");
+
+    let parser = Parser::new(code.clone());
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new()
+        .with_debug_symbols(true);
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+    let program = compiler.get_program().unwrap();
+    assert_eq!(program.debug_info.line_table.values().cloned().collect::<Vec<usize>>(), vec![2, 3, 4, 5, 6]);
+
+    let listing = disassemble_with_source(&program, &code, &program.debug_info);
+    println!("{}", listing);
+    for (i, line) in code.lines().enumerate().skip(1).take(5) {
+        let comment = format!("; {}: {}", i + 1, line.trim());
+        assert_eq!(listing.matches(&comment).count(), 1, "{}", comment);
+    }
+    // Each source line comes before the instructions of its statement
+    let first_line = listing.find("; 2: var a: int = 4;").unwrap();
+    assert!(listing[first_line..].lines().nth(1).unwrap().trim().starts_with("LDI 4"));
+
+    // The comments do not change the program
+    let reassembled = assemble(&listing).unwrap();
+    assert_eq!(reassembled.code, program.code);
+
+    // Without debug symbols there are no lines to show
+    let mut compiler = Compiler::new();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let program = compiler.get_program().unwrap();
+    assert!(program.debug_info.line_table.is_empty());
+    assert_eq!(disassemble_with_source(&program, &code, &program.debug_info), emit_asm(&program));
+}
+
+#[test]
+fn test_compile_reproducible() {
+    let code = String::from("
+        cont: Vec2 {
+            x: float;
+            y: float;
+        }
+
+        fn: length_sq(v: Vec2) ~ float {
+            return v.x * v.x + v.y * v.y;
+        }
+
+        fn: greet() {
+            io::print(\"hello\");
+            io::print(\"world\");
+        }
+
+        fn: main() ~ int {
+            greet();
+            var v: Vec2 = Vec2 { x: 1.0, y: 2.0 };
+            var i: int = 0;
+            while i < 3 {
+                i = i + 1;
+            }
+            return i;
+        }
+    ");
+
+    let compile = || {
+        let print_function = Function::new("print")
+            .with_arg(Type::String)
+            .with_ret_type(Type::Void)
+            .with_closure(Box::new(|_| {}));
+        let print_int_function = Function::new("print_int")
+            .with_arg(Type::Int)
+            .with_ret_type(Type::Void)
+            .with_closure(Box::new(|_| {}));
+        let io_module = Module::new("io")
+            .with_function(print_function)
+            .with_function(print_int_function);
+
+        let compiler = Compiler::new()
+            .with_foreign_module(io_module)
+            .unwrap();
+        compile_code_with(compiler, &code).unwrap().get_program().unwrap()
+    };
+
+    let first_program = compile();
+    let second_program = compile();
+    assert_eq!(first_program.code, second_program.code);
+    assert_eq!(first_program, second_program);
+}
+
+#[test]
+fn test_builder_labels() {
+    let mut builder = Builder::new();
+    let label_end = builder.new_label();
+    let label_start = builder.new_label();
+
+    builder.place_label(label_start);
+    builder.push_instr(Instruction::new(Opcode::NOOP));
+    builder.push_instr(
+        Instruction::new(Opcode::JMPF)
+            .with_operand::<u8>(0)
+            .with_label_operand(label_end)
+    );
+    builder.push_instr(
+        Instruction::new(Opcode::JMP)
+            .with_label_operand(label_start)
+    );
+    builder.place_label(label_end);
+
+    let code_res = builder.clone().build_at(16, 0);
+    assert!(code_res.is_ok());
+
+    let code = code_res.unwrap();
+    // NOOP (1) + JMPF (1 + 1 + 8) + JMP (1 + 8)
+    assert_eq!(code.len(), 20);
+    // JMPF jumps behind the last instruction
+    assert_eq!(&code[3..11], &36u64.to_le_bytes());
+    // JMP jumps to the first instruction
+    assert_eq!(&code[12..20], &16u64.to_le_bytes());
+
+    let unplaced_label = builder.new_label();
+    builder.push_instr(
+        Instruction::new(Opcode::JMP)
+            .with_label_operand(unplaced_label)
+    );
+    match builder.build() {
+        Err(CompilerError::UnplacedLabel(_)) => {},
+        _ => panic!("Expected UnplacedLabel error")
+    }
+}
+
+#[test]
+fn test_instruction_typed_operands() {
+    let movn_instr = Instruction::movn(Register::SP, -16, Register::FP, 8, 16);
+    assert_eq!(movn_instr.next_operand_kind(), None);
+    assert_eq!(decode_operands(&Opcode::MOVN_A, &movn_instr.operands), Some(vec![
+        (0, Operand::Register(Register::SP.into())),
+        (1, Operand::Offset(-16)),
+        (3, Operand::Register(Register::FP.into())),
+        (4, Operand::Offset(8)),
+        (6, Operand::Count(16))
+    ]));
+
+    let store_instr = Instruction::mov_store(Opcode::MOVI_RA, Register::R1, Register::SP, -8);
+    assert_eq!(decode_operands(&Opcode::MOVI_RA, &store_instr.operands), Some(vec![
+        (0, Operand::Register(Register::R1.into())),
+        (1, Operand::Register(Register::SP.into())),
+        (2, Operand::Offset(-8))
+    ]));
+
+    // Operands are appended in the order of the layout of the opcode
+    let mut builder = Builder::new();
+    let label = builder.new_label();
+    let jmpf_instr = Instruction::new(Opcode::JMPF)
+        .with_register_operand(Register::R0);
+    assert_eq!(jmpf_instr.next_operand_kind(), Some(OperandKind::CodeAddress));
+    builder.place_label(label);
+    builder.push_instr(jmpf_instr.with_label_operand(label));
+    builder.push_instr(Instruction::call(7));
+    let code = builder.build().unwrap();
+    assert_eq!(code.len(), 10 + 9);
+    assert_eq!(&code[11..19], &7u64.to_le_bytes());
+}
+
+#[test]
+#[should_panic(expected = "MOVI_RA does not take an operand of 8 bytes")]
+fn test_instruction_wrong_operand_width() {
+    // The offset of MOVI_RA is an i16
+    let _ = Instruction::new(Opcode::MOVI_RA)
+        .with_operand::<u8>(Register::R0.into())
+        .with_operand::<u8>(Register::SP.into())
+        .with_operand::<u64>(8);
+}
+
+#[test]
+#[should_panic(expected = "MOVI_AR does not take a Register operand")]
+fn test_instruction_wrong_operand_kind() {
+    // MOVI_AR loads from memory and takes an offset before the target register
+    let _ = Instruction::mov_store(Opcode::MOVI_AR, Register::R0, Register::SP, -8);
+}
+
+#[test]
+#[should_panic(expected = "CALL does not take an operand of 1 bytes")]
+fn test_instruction_too_many_operands() {
+    let _ = Instruction::call(1)
+        .with_operand::<u8>(0);
+}
+
+#[test]
+fn test_builder_unique_labels() {
+    let mut builder = Builder::new();
+    assert!(builder.push_label_unique(String::from("root::main")).is_ok());
+    builder.push_instr(Instruction::new(Opcode::NOOP));
+    assert_eq!(
+        builder.push_label_unique(String::from("root::main")),
+        Err(BuilderError::DuplicateLabel(String::from("root::main")))
+    );
+    assert_eq!(builder.get_label_offset(&String::from("root::main")), Some(0));
+
+    assert!(builder.remove_label("root::main"));
+    assert!(!builder.remove_label("root::main"));
+    assert_eq!(builder.get_label_offset(&String::from("root::main")), None);
+    assert!(builder.push_label_unique(String::from("root::main")).is_ok());
+    assert_eq!(builder.get_label_offset(&String::from("root::main")), Some(1));
+}
+
+#[test]
+fn test_builder_push_raw() {
+    let mut builder = Builder::new();
+    // LDI 42 -> r1, RET
+    let mut raw = vec![0x16, 42, 0, 0, 0, 0, 0, 0, 0, 1];
+    raw.push(Opcode::RET.into());
+    assert!(builder.push_raw(&raw).is_ok());
+    assert_eq!(builder.instructions.len(), 2);
+    assert_eq!(builder.instructions[0].opcode, Opcode::LDI);
+    assert_eq!(builder.instructions[0].get_operand::<i64>(0, 8), 42);
+    assert_eq!(builder.instructions[1].opcode, Opcode::RET);
+    assert_eq!(builder.build().unwrap(), raw);
+
+    let mut builder = Builder::new();
+    // LDI without its target register
+    assert_eq!(
+        builder.push_raw(&[0x00, 0x16, 42, 0, 0, 0, 0, 0, 0, 0]),
+        Err(BuilderError::InvalidRawBytecode(1))
+    );
+    assert_eq!(builder.push_raw(&[0xFF]), Err(BuilderError::InvalidRawBytecode(0)));
+    assert!(builder.instructions.is_empty());
+}
+
+#[test]
+fn test_compile_fn_decl_twice() {
+    let code = String::from("
+        fn: main() ~ int {
+            return 4;
+        }
+    ");
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+
+    let mut compiler = Compiler::new();
+    assert!(compiler.declare_decl_list(&decl_list).is_ok());
+    assert!(compiler.compile_fn_decl(&decl_list[0]).is_ok());
+    match compiler.compile_fn_decl(&decl_list[0]) {
+        Err(CompilerError::Builder(BuilderError::DuplicateLabel(label))) => {
+            assert_eq!(label, "root::main");
+        },
+        res => panic!("Expected a duplicate label error, got {:?}", res)
+    }
+
+    // The first compilation is untouched
+    let program = compiler.get_program().unwrap();
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    let uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+    assert!(core.run_fn(uid).is_ok());
+    assert_eq!(core.reg(0).unwrap().get::<i64>(), 4);
+}
+
+#[test]
+fn test_builder_relocations() {
+    let mut builder = Builder::new();
+    let label_end = builder.new_label();
+
+    builder.push_instr(
+        Instruction::new(Opcode::LDA)
+            .with_data_operand(3)
+            .with_operand::<u8>(0)
+    );
+    builder.push_instr(
+        Instruction::new(Opcode::JMP)
+            .with_label_operand(label_end)
+    );
+    builder.push_instr(
+        Instruction::new(Opcode::LDA)
+            .with_data_operand(5)
+            .with_operand::<u8>(1)
+    );
+    builder.place_label(label_end);
+
+    let code_res = builder.build_at(32, 8);
+    assert!(code_res.is_ok());
+
+    let code = code_res.unwrap();
+    // LDA (1 + 8 + 1) + JMP (1 + 8) + LDA (1 + 8 + 1)
+    assert_eq!(code.len(), 29);
+    // Data operands are relative to the data segment
+    assert_eq!(&code[1..9], &11u64.to_le_bytes());
+    assert_eq!(&code[20..28], &13u64.to_le_bytes());
+    // Code operands are relative to the code segment
+    assert_eq!(&code[11..19], &61u64.to_le_bytes());
+}
+
+#[test]
+fn test_nested_container_size() {
+    let code = String::from("
+        cont: Inner {
+            a: int;
+            b: float;
+        }
+
+        cont: Outer {
+            x: int;
+            inner: Inner;
+            pair: [Inner; 2];
+            y: bool;
+        }
+
+        cont: Node {
+            value: int;
+            next: &Node;
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    assert!(compiler.declare_decl_list(&decl_list).is_ok());
+
+    let inner_size = compiler.get_size_of_type(&Type::Other(String::from("Inner")));
+    assert_eq!(12, inner_size.unwrap());
+    let outer_size = compiler.get_size_of_type(&Type::Other(String::from("Outer")));
+    assert_eq!(8 + 12 + 24 + 4, outer_size.unwrap());
+    // Containers may refer to themselves by reference
+    let node_size = compiler.get_size_of_type(&Type::Other(String::from("Node")));
+    assert_eq!(16, node_size.unwrap());
+}
+
+#[test]
+fn test_recursive_container_rejected() {
+    let code = String::from("
+        cont: Tree {
+            value: int;
+            branch: Branch;
+        }
+
+        cont: Branch {
+            weight: float;
+            tree: Tree;
+        }
+
+        fn: main() {
+            var t: Tree = Tree {
+                value: 0,
+                branch: Branch {
+                    weight: 0.0,
+                    tree: Tree {
+                        value: 1
+                    }
+                }
+            };
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    match compile_res {
+        Err(CompilerError::RecursiveType(_)) => {},
+        _ => panic!("Expected RecursiveType error")
+    }
+
+    let size_res = compiler.get_size_of_type(&Type::Other(String::from("Tree")));
+    match size_res {
+        Err(CompilerError::RecursiveType(_)) => {},
+        _ => panic!("Expected RecursiveType error")
+    }
+}
+
+#[test]
+fn test_compile_no_zero_stack_adjustments() {
+    let code = String::from("
+        fn: main() {
+            if true {
+            }
+            while false {
+            }
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+
+    let builder = compiler.get_builder();
+    for instr in builder.instructions.iter() {
+        println!("{:?}", instr);
+        let is_stack_adjustment = (instr.opcode == Opcode::ADDU_I || instr.opcode == Opcode::SUBU_I) &&
+            instr.get_operand::<u8>(0, 1) == 16 &&
+            instr.get_operand::<u8>(9, 1) == 16;
+        if is_stack_adjustment {
+            assert_ne!(0, instr.get_operand::<u64>(1, 8));
+        }
+    }
+
+    // if: nothing, the condition is constant; while: LDB, JMPF, JMP; return: RET; TRAP
+    assert_eq!(5, builder.instructions.len());
+
+    let program_res = compiler.get_program();
+    assert!(program_res.is_ok());
+
+    let mut core = Core::new(1024);
+    core.load_program(program_res.unwrap());
+    assert!(core.run().is_ok());
+}
+
+#[test]
+fn test_container_member_offset() {
+    let code = String::from("
+        cont: Mixed {
+            z: int;
+            a: bool;
+            m: float;
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    assert!(compiler.declare_decl_list(&decl_list).is_ok());
+
+    let cont_def = compiler.resolve_container(&String::from("Mixed")).unwrap();
+
+    // Members are laid out in declaration order
+    assert_eq!((0, Type::Int), cont_def.member_offset(&compiler, "z").unwrap());
+    assert_eq!((8, Type::Bool), cont_def.member_offset(&compiler, "a").unwrap());
+    assert_eq!((12, Type::Float), cont_def.member_offset(&compiler, "m").unwrap());
+    assert!(cont_def.member_offset(&compiler, "missing").is_err());
+}
+
+#[test]
+fn test_empty_container_size() {
+    let code = String::from("
+        cont: Empty {}
+
+        cont: Tagged {
+            tag: Empty;
+            x: int;
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+
+    let mut compiler = Compiler::new();
+    assert!(compiler.declare_decl_list(&decl_list).is_ok());
+
+    let empty_def = compiler.resolve_container(&String::from("Empty")).unwrap();
+    assert_eq!(0, empty_def.get_size(&compiler).unwrap());
+    assert_eq!(0, compiler.get_size_of_type(&Type::Other(String::from("Empty"))).unwrap());
+
+    // Empty members take no space
+    let tagged_def = compiler.resolve_container(&String::from("Tagged")).unwrap();
+    assert_eq!(8, tagged_def.get_size(&compiler).unwrap());
+    assert_eq!((0, Type::Int), tagged_def.member_offset(&compiler, "x").unwrap());
+}
+
+#[test]
+fn test_asm_round_trip() {
+    let code = String::from("
+        fn: len(s: string) ~ int {
+            return 3;
+        }
+
+        fn: fib(n: int) ~ int {
+            if n < 2 {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+
+        fn: main() ~ int {
+            var i: int = 0;
+            var sum: int = 0;
+            while i < 5 {
+                sum = sum + fib(i);
+                i = i + 1;
+            }
+            return sum * len(\"a; b\") - 1;
+        }
+    ");
+
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    assert!(compile_res.is_ok());
+    println!("{}", emit_builder_asm(&compiler.get_builder()));
+    let program = compiler.get_program().unwrap();
+
+    let asm = emit_asm(&program);
+    println!("{}", asm);
+    let assemble_res = assemble(&asm);
+    println!("{:?}", assemble_res);
+    assert!(assemble_res.is_ok());
+
+    let reassembled = assemble_res.unwrap();
+    assert_eq!(reassembled.code, program.code);
+    assert_eq!(reassembled.functions, program.functions);
+    assert_eq!(emit_asm(&reassembled), asm);
+
+    let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+    for program in vec![program, reassembled] {
+        let mut core = Core::new(1024);
+        core.load_program(program);
+        let run_res = core.run_fn(main_uid);
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        // (0 + 1 + 1 + 2 + 3) * 3 - 1
+        assert_eq!(core.reg(0).unwrap().get::<i64>(), 20);
+    }
+}
+
+#[test]
+fn test_asm_hand_written() {
+    let asm = "
+        .data
+            \"ab\\x00\" ; two bytes and a terminator
+        .code
+        .fn root::main
+            LDI 0, r1
+            LDI 1, r2
+            LDI 10, r3
+        L_loop:
+            ADDI r1, r2, r1
+            LTI r1, r3, r4
+            JMPT r4, L_loop
+            MOVI r1, r0
+            LDA data+1, r5
+            RET
+    ";
+
+    let assemble_res = assemble(asm);
+    println!("{:?}", assemble_res);
+    assert!(assemble_res.is_ok());
+
+    let program = assemble_res.unwrap();
+    assert_eq!(program.code_start, 3);
+    assert_eq!(&program.code[..3], b"ab\0");
+
+    let main_uid = *program.debug_info.function_names.iter()
+        .find(|(_, name)| name.as_str() == "root::main")
+        .unwrap().0;
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    let run_res = core.run_fn(main_uid);
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(core.reg(0).unwrap().get::<i64>(), 10);
+    assert_eq!(core.reg(5).unwrap().get::<u64>(), 1);
+
+    match assemble("    FOO r0") {
+        Err(AsmError::UnknownMnemonic(1, mnemonic)) => assert_eq!(mnemonic, "FOO"),
+        res => panic!("Expected unknown mnemonic, got {:?}", res)
+    };
+    match assemble("    JMP L_missing") {
+        Err(AsmError::UnknownLabel(1, label)) => assert_eq!(label, "L_missing"),
+        res => panic!("Expected unknown label, got {:?}", res)
+    };
+    match assemble("    ADDI r0, r1") {
+        Err(AsmError::OperandCount(1, 3)) => {},
+        res => panic!("Expected operand count error, got {:?}", res)
+    };
+}
+
+#[test]
+fn test_compile_assert() {
+    let compile = |code: &str, asserts: bool| {
+        compile_code_with(Compiler::new().with_asserts(asserts), code).unwrap().get_program().unwrap()
+    };
+
+    let code = "
+        fn: main(x: int) ~ int {
+            assert x > 0, \"bad x\";
+            assert x != 3, \"bad x\";
+            assert x < 10, \"x too large\";
+            assert x != 5;
+            return x;
+        }
+    ";
+    let code_without_asserts = "
+        fn: main(x: int) ~ int {
+            return x;
+        }
+    ";
+
+    // Equal messages are only stored once
+    let program = compile(code, true);
+    assert_eq!(&program.code[..program.code_start], b"bad xx too large");
+
+    // Disabled asserts emit neither instructions nor data
+    let disabled_program = compile(code, false);
+    let program_without_asserts = compile(code_without_asserts, true);
+    assert_eq!(disabled_program.code_start, 0);
+    assert_eq!(disabled_program.code, program_without_asserts.code);
+}
+
+#[test]
+fn test_compile_constant_if() {
+    let compile = |code: &str| compile_code(code).unwrap().get_program().unwrap();
+
+    // Neither the condition nor the block of an "if false" are emitted
+    let code = "
+        fn: main(x: int) ~ int {
+            if false {
+                var y: int = x * 2;
+                x = y + 1;
+            }
+            if 1 > 2 && true {
+                x = 0;
+            }
+            return x;
+        }
+    ";
+    let code_without_if = "
+        fn: main(x: int) ~ int {
+            return x;
+        }
+    ";
+    assert_eq!(compile(code).code, compile(code_without_if).code);
+
+    // Only the taken branch of a chain is emitted
+    let code = "
+        fn: main(x: int) ~ int {
+            if false {
+                x = 1;
+            } else if x > 4 {
+                x = 2;
+            } else if !false {
+                x = 3;
+            } else {
+                x = 4;
+            }
+            return x;
+        }
+    ";
+    let code_taken = "
+        fn: main(x: int) ~ int {
+            if x > 4 {
+                x = 2;
+            } else {
+                x = 3;
+            }
+            return x;
+        }
+    ";
+    assert_eq!(compile(code).code, compile(code_taken).code);
+
+    // Conditions of dropped branches are still checked
+    let parser = Parser::new(String::from("fn: main() { if false { } else if 1 { } }"));
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    match Compiler::new().compile_root(&decl_list) {
+        Err(CompilerError::TypeMismatch(Type::Int, Type::Bool)) => {},
+        res => panic!("Expected TypeMismatch, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_compile_fresh_registers_per_function() {
+    let code = String::from("
+        fn: first() ~ int {
+            var x: int = (1 + 2) * 3;
+            return x;
+        }
+
+        fn: second() ~ int {
+            var x: int = (1 + 2) * 3;
+            return x;
+        }
+    ");
+
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+
+    let builder = compiler.get_builder();
+    let first_pos = builder.labels[&String::from("root::first")];
+    let second_pos = builder.labels[&String::from("root::second")];
+    let first_instrs = &builder.instructions[first_pos..second_pos];
+    let second_instrs = &builder.instructions[second_pos..];
+
+    // Both functions start with the first temp register, R0 is reserved for return values
+    assert_eq!(second_instrs[0].opcode, Opcode::LDI);
+    assert_eq!(second_instrs[0].operands[8], Register::R1.into());
+    let encode = |instrs: &[Instruction]| instrs.iter()
+        .map(|instr| (instr.opcode.clone(), instr.operands.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(encode(first_instrs), encode(second_instrs));
+
+    let mut register_allocator = RegisterAllocator::new();
+    assert!(register_allocator.all_free());
+    register_allocator.block_register(Register::R4).unwrap();
+    assert!(!register_allocator.all_free());
+    register_allocator.unblock_register(Register::R4).unwrap();
+    assert!(register_allocator.all_free());
+}
+
+#[test]
+fn test_callee_saved_temps() {
+    let code = "
+        fn: leaf(a: int) ~ int {
+            return a + 1;
+        }
+
+        fn: mid(a: int) ~ int {
+            return a * 3 + leaf(a);
+        }
+
+        fn: main() ~ int {
+            var x: int = 7;
+            var y: int = x * 100 + mid(x);
+            return y * 1000 + (x * 10 + (x + mid(x)));
+        }
+    ";
+    let run = |compiler: Compiler| {
+        let decl_list = Parser::new(String::from(code)).parse_root_decl_list().unwrap();
+        let mut compiler = compiler;
+        let compile_res = compiler.compile_root(&decl_list);
+        println!("{:?}", compile_res);
+        assert!(compile_res.is_ok());
+        let program = compiler.get_program().unwrap();
+        let code_len = program.code.len();
+        // Calls only save the callee-saved registers if the program keeps values in them
+        assert_eq!(program.saves_callee_registers, compiler.has_callee_saved_temps());
+        let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+        let mut core = Core::new(1024);
+        core.load_program(program);
+        let stack_size = core.get_stack_size();
+        assert!(core.run_fn(main_uid).is_ok());
+        assert_eq!(core.get_stack_size(), stack_size);
+        (core.reg(0).unwrap().get::<i64>(), code_len)
+    };
+
+    // mid and leaf both overwrite registers main still needs
+    let expected = (700 + 21 + 8) * 1000 + 70 + 7 + 29;
+    let (spilled_result, spilled_len) = run(Compiler::new());
+    assert_eq!(spilled_result, expected);
+    let compiler = Compiler::new().with_callee_saved_temps(true);
+    assert!(compiler.has_callee_saved_temps());
+    let (saved_result, saved_len) = run(compiler);
+    assert_eq!(saved_result, expected);
+    // Moving into a register takes less code than spilling to the stack
+    assert!(saved_len < spilled_len);
+
+    assert!(!Register::R4.is_callee_saved());
+    assert!(Register::R11.is_callee_saved());
+    let mut register_allocator = RegisterAllocator::new();
+    assert_eq!(register_allocator.take_callee_saved(), None);
+    register_allocator.reserve_callee_saved().unwrap();
+    let saved_reg = register_allocator.take_callee_saved().unwrap();
+    assert_eq!(saved_reg, Register::R11);
+    assert!(!register_allocator.all_free());
+    // Reserved registers are never handed out as temporary registers
+    for _ in 0..20 {
+        assert!(!register_allocator.get_temp_register().unwrap().is_callee_saved());
+    }
+    register_allocator.release_callee_saved(saved_reg).unwrap();
+    assert!(register_allocator.all_free());
+    assert!(register_allocator.release_callee_saved(Register::R12).is_err());
+}
+
+#[test]
+fn test_compile_void_values() {
+    let void_var_codes = [
+        ("fn: noop() {} fn: main() { var x: void = noop(); }", "x"),
+        ("fn: noop() {} fn: main() { var y = noop(); }", "y"),
+        ("fn: take(v: void) {}", "v")
+    ];
+    for (code, var_name) in void_var_codes.iter() {
+        match compile_code(code) {
+            Err(CompilerError::VoidVariable(name)) => assert_eq!(name, *var_name),
+            _ => panic!("Expected VoidVariable error")
+        };
+    }
+
+    let compiler = compile_code("
+        fn: noop() {}
+        fn: take(x: int) {}
+        fn: main() {
+            noop();
+            take(1);
+        }
+    ").ok().unwrap();
+    let builder = compiler.get_builder();
+    let main_pos = builder.labels[&String::from("root::main")];
+    let main_opcodes: Vec<Opcode> = builder.instructions[main_pos..].iter()
+        .map(|instr| instr.opcode.clone())
+        .collect();
+    // Only the int argument touches the stack, the void return values never do
+    assert_eq!(main_opcodes, vec![
+        Opcode::CALL,
+        Opcode::LDI,
+        Opcode::ADDU_I,
+        Opcode::MOVI_RA,
+        Opcode::CALL,
+        Opcode::SUBU_I,
+        Opcode::RET,
+        Opcode::TRAP
+    ]);
+}
+
+#[test]
+fn test_compile_unreachable_traps() {
+    let compile = |code: &str| compile_code(code).unwrap().get_program().unwrap();
+    let trap_pos = |program: &Program| {
+        program.code.windows(2)
+            .position(|bytes| bytes == [Opcode::RET as u8, Opcode::TRAP as u8])
+    };
+
+    // Without the return, main falls through to the trap
+    let mut program = compile("fn: main() {}");
+    let ret_pos = trap_pos(&program).unwrap();
+    program.code[ret_pos] = Opcode::NOOP as u8;
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    match core.run() {
+        Err(err) => match err.root_cause() {
+            CoreError::Trapped(UNREACHABLE_TRAP_CODE, None) => {},
+            err => panic!("Expected an unreachable trap, got {:?}", err)
+        },
+        res => panic!("Expected an unreachable trap, got {:?}", res)
+    }
+
+    // Falling off the end of a function with a return value still halts
+    let program = compile("
+        fn: main() ~ int {
+            if false {
+                return 1;
+            }
+        }
+    ");
+    assert_eq!(trap_pos(&program), None);
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    match core.run() {
+        Err(err) => match err.root_cause() {
+            CoreError::NoReturnValue => {},
+            err => panic!("Expected a missing return value, got {:?}", err)
+        },
+        res => panic!("Expected a missing return value, got {:?}", res)
+    }
+    assert!(trap_pos(&compile("fn: main() ~ int { return 1; }")).is_some());
+}
+
+#[test]
+fn test_compile_f64_floats() {
+    let code = "
+        fn: add(a: float, b: float) ~ float {
+            return a + b;
+        }
+
+        fn: main() ~ int {
+            var big: float = 16777216.0;
+            var sum: float = add(big, 1.0);
+            if sum > big {
+                return 1;
+            }
+            return 0;
+        }
+    ";
+
+    let run = |f64_floats: bool| {
+        let parser = Parser::new(String::from(code));
+        let decl_list = parser.parse_root_decl_list().unwrap();
+        let mut compiler = Compiler::new()
+            .with_f64_floats(f64_floats);
+        let compile_res = compiler.compile_root(&decl_list);
+        println!("{:?}", compile_res);
+        assert!(compile_res.is_ok());
+
+        let program = compiler.get_program().unwrap();
+        let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+        let mut core = Core::new(1024);
+        core.load_program(program);
+        let run_res = core.run_fn(main_uid);
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        core.reg(0).unwrap().get::<i64>()
+    };
+
+    // 2^24 + 1 is not representable as f32, but as f64
+    assert_eq!(run(false), 0);
+    assert_eq!(run(true), 1);
+}
+
+#[test]
+fn test_compile_comptime_assert() {
+    let compile = |code: &str| compile_code(code).map(|mut compiler| compiler.get_program().unwrap());
+
+    let passing_res = compile("
+        fn: main() ~ int {
+            comptime_assert sizeof(int) == 8, \"int must be 8 bytes\";
+            comptime_assert sizeof(string) == 2 * sizeof(int) && !(1 > 2);
+            return sizeof(float);
+        }
+    ");
+    assert!(passing_res.is_ok());
+    // Passing comptime asserts emit neither instructions nor data
+    let program = passing_res.unwrap();
+    assert_eq!(program.code_start, 0);
+
+    let failing_res = compile("
+        fn: main() {
+            comptime_assert sizeof(int) == 4;
+        }
+    ");
+    match failing_res {
+        Err(CompilerError::ComptimeAssertionFailed(None)) => {},
+        res => panic!("Expected a failed comptime assertion, got {:?}", res)
+    }
+
+    let failing_res = compile("
+        fn: main() {
+            comptime_assert sizeof(int) < 8, \"int must be small\";
+        }
+    ");
+    match failing_res {
+        Err(CompilerError::ComptimeAssertionFailed(Some(message))) => assert_eq!(message, "int must be small"),
+        res => panic!("Expected a failed comptime assertion, got {:?}", res)
+    }
+
+    // Conditions which are no constants fall back to a runtime check
+    let runtime_res = compile("
+        fn: main(x: int) ~ int {
+            comptime_assert x > 0, \"bad x\";
+            return x;
+        }
+    ");
+    assert!(runtime_res.is_ok());
+    let program = runtime_res.unwrap();
+    assert_eq!(&program.code[..program.code_start], b"bad x");
+}
+
+#[test]
+fn test_compile_write_only_var_warning() {
+    let code = String::from("
+        cont: Vec2 {
+            x: float;
+            y: float;
+        }
+
+        impl: Vec2 {
+            fn: set_x(&this, x: float) {
+                this.x = x;
+            }
+        }
+
+        fn: main() ~ int {
+            var i: int = 0;
+            var last: int = 0;
+            var sum: int = 0;
+            while i < 10 {
+                last = i;
+                sum += i;
+                i = i + 1;
+            }
+            return sum;
+        }
+    ");
+
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+
+    // Only last is assigned without ever being read
+    assert_eq!(compiler.get_warnings(), &[CompilerWarning::WriteOnlyVariable(String::from("last"))]);
+    assert_eq!(compiler.get_warnings()[0].to_string(), "variable last is assigned but never read");
+}
+
+#[test]
+fn test_compile_infinite_loop_warning() {
+    let compile = |body: &str| {
+        let code = format!("
+            fn: main(x: int) ~ int {{
+                {}
+                return x;
+            }}
+        ", body);
+        compile_code(&code).unwrap().get_warnings().to_vec()
+    };
+
+    let warnings = compile("while true { x += 1; }");
+    assert_eq!(warnings, vec![CompilerWarning::InfiniteLoop(String::from("main"))]);
+    assert_eq!(warnings[0].to_string(), "loop in function main never exits, mark it with @infinite if this is intended");
+
+    // A break of a nested loop does not leave the outer one
+    let warnings = compile("while true { while x < 10 { break; } }");
+    assert_eq!(warnings, vec![CompilerWarning::InfiniteLoop(String::from("main"))]);
+
+    // Loops which can exit, have a condition or are annotated are fine
+    assert!(compile("while true { if x > 10 { break; } x += 1; }").is_empty());
+    assert!(compile("while true { while x < 10 { return x; } }").is_empty());
+    assert!(compile("while x < 10 { x += 1; }").is_empty());
+    assert!(compile("@infinite while true { yield x; }").is_empty());
+}
+
+#[test]
+fn test_program_rebind_foreign() {
+    let code = "
+        fn: main() ~ int {
+            return host::value(2);
+        }
+    ";
+    let compile = || {
+        let value_function = Function::new("value")
+            .with_arg(Type::Int)
+            .with_ret_type(Type::Int)
+            .with_closure(Box::new(|adapter| {
+                let arg: i64 = adapter.get_arg(0);
+                adapter.return_value(arg * 10);
+            }));
+        let compiler = Compiler::new()
+            .with_foreign_module(Module::new("host").with_function(value_function))
+            .unwrap();
+        let mut compiler = compile_code_with(compiler, code).unwrap();
+        let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+        let value_uid = compiler.get_function_uid(&String::from("root::host::value")).unwrap();
+        (compiler.get_program().unwrap(), main_uid, value_uid)
+    };
+    let run = |program: Program, main_uid: u64| {
+        let mut core = Core::new(1024);
+        core.load_program(program);
+        core.run_fn(main_uid)
+            .map(|_| core.reg(0).unwrap().get::<i64>())
+    };
+
+    let (mut program, main_uid, value_uid) = compile();
+    assert!(program.get_foreign_functions().contains(&(value_uid, &String::from("root::host::value"))));
+
+    // The signature has to match
+    let float_function = Function::new("value")
+        .with_arg(Type::Float)
+        .with_ret_type(Type::Int);
+    match program.rebind_foreign("host::value", float_function) {
+        Err(CompilerError::ArgumentMismatch(_)) => {},
+        res => panic!("Expected ArgumentMismatch, got {:?}", res)
+    }
+    assert!(program.rebind_foreign("host::missing", Function::new("missing")).is_err());
+
+    let rebind_res = program.rebind_foreign(value_uid, Function::new("value")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(|adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            adapter.return_value(arg * 100);
+        })));
+    assert!(rebind_res.is_ok());
+    assert_eq!(run(program, main_uid).unwrap(), 200);
+
+    // Calling an unbound function is a runtime error naming it
+    let (mut program, main_uid, _) = compile();
+    assert!(program.unbind_foreign("root::host::value").is_ok());
+    match run(program, main_uid) {
+        Err(err) => match err.root_cause() {
+            CoreError::UnboundForeignFunction(name) => assert_eq!(name, "root::host::value"),
+            _ => panic!("Expected UnboundForeignFunction, got {:?}", err)
+        },
+        Ok(_) => panic!("Expected UnboundForeignFunction")
+    }
+}
+
+#[test]
+fn test_program_shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Program>();
+
+    let code = String::from("
+        fn: sum_to(n: int) ~ int {
+            var sum: int = 0;
+            var i: int = 1;
+            while i <= n {
+                sum += host::scale(i);
+                i += 1;
+            }
+            return sum;
+        }
+
+        fn: meet() ~ int {
+            return host::wait_for_all();
+        }
+    ");
+    let scale_function = Function::new("scale")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(|adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            adapter.return_value(arg * 10);
+        }));
+    // Waits inside the call until all threads are inside it, returns how many were
+    let waiting = Arc::new(AtomicUsize::new(0));
+    let wait_function = Function::new("wait_for_all")
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(move |adapter| {
+            waiting.fetch_add(1, Ordering::SeqCst);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while waiting.load(Ordering::SeqCst) < 8 && Instant::now() < deadline {
+                thread::yield_now();
+            }
+            adapter.return_value(waiting.load(Ordering::SeqCst) as i64);
+        }));
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new()
+        .with_foreign_module(Module::new("host")
+            .with_function(scale_function)
+            .with_function(wait_function))
+        .unwrap();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let sum_to_uid = compiler.get_function_uid(&String::from("root::sum_to")).unwrap();
+    let meet_uid = compiler.get_function_uid(&String::from("root::meet")).unwrap();
+    let scale_uid = compiler.get_function_uid(&String::from("root::host::scale")).unwrap();
+    let program = Arc::new(compiler.get_program().unwrap());
+
+    let handles: Vec<_> = (1..=8i64).map(|n| {
+        let program = Arc::clone(&program);
+        thread::spawn(move || {
+            let mut core = Core::new(1024);
+            core.load_program(Arc::clone(&program));
+            // Overrides only bind to this core
+            if n == 8 {
+                core.set_foreign_override(scale_uid, Box::new(|adapter| {
+                    let arg: i64 = adapter.get_arg(0);
+                    adapter.return_value(arg);
+                }));
+            }
+            core.push_stack::<i64>(n).unwrap();
+            let run_res = core.run_fn(sum_to_uid);
+            assert!(run_res.is_ok(), "{:?}", run_res);
+            assert_eq!(core.pop_stack::<i64>().unwrap(), n);
+            // The core ran the shared program without copying it
+            assert!(Arc::ptr_eq(core.get_program().unwrap(), &program));
+            let sum = core.reg(0).unwrap().get::<i64>();
+            // All cores are inside the same foreign closure at once
+            assert!(core.run_fn(meet_uid).is_ok());
+            assert_eq!(core.reg(0).unwrap().get::<i64>(), 8);
+            (n, sum)
+        })
+    }).collect();
+
+    for handle in handles {
+        let (n, sum) = handle.join().unwrap();
+        let expected = n * (n + 1) / 2;
+        if n == 8 {
+            assert_eq!(sum, expected);
+        } else {
+            assert_eq!(sum, expected * 10);
+        }
+    }
+
+    // Changing the program of one core leaves the shared one untouched
+    let mut core = Core::new(1024);
+    core.load_program(Arc::clone(&program));
+    assert!(core.get_program_mut().unwrap().unbind_foreign("host::scale").is_ok());
+    assert!(!Arc::ptr_eq(core.get_program().unwrap(), &program));
+    assert!(program.foreign_functions[&scale_uid].is_bound());
+}
+
+#[test]
+fn test_compiler_context_out_of_bounds() {
+    let mut compiler = Compiler::new();
+    while compiler.pop_function_context().is_ok() {}
+
+    let expect_out_of_bounds = |res: Result<(), CompilerError>, expected_desc: &str, expected_index: usize| {
+        match res {
+            Err(CompilerError::InternalIndexOutOfBounds(desc, index)) => {
+                assert_eq!(desc, expected_desc);
+                assert_eq!(index, expected_index);
+            },
+            res => panic!("Expected InternalIndexOutOfBounds, got {:?}", res)
+        }
+    };
+    expect_out_of_bounds(compiler.get_current_function().map(|_| ()), "fn_context_stack", 0);
+    expect_out_of_bounds(compiler.get_current_function_mut().map(|_| ()), "fn_context_stack", 0);
+    expect_out_of_bounds(compiler.get_function(3).map(|_| ()), "fn_context_stack", 3);
+    expect_out_of_bounds(compiler.get_parent_function().map(|_| ()), "fn_context_stack", 0);
+
+    while compiler.pop_module_context().is_ok() {}
+    expect_out_of_bounds(compiler.get_current_module().map(|_| ()), "mod_context_stack", 0);
+    expect_out_of_bounds(compiler.get_root_module().map(|_| ()), "mod_context_stack", 0);
+
+    let err = compiler.get_current_function().map(|_| ()).unwrap_err();
+    assert_eq!(err.to_string(), "internal compiler error: index 0 out of bounds of fn_context_stack");
+}
+
+#[test]
+fn test_duplicate_declarations() {
+    match compile_code("mod: outer { mod: inner { } mod: inner { } }") {
+        Err(CompilerError::DuplicateModule(name)) => assert_eq!(name, "inner"),
+        res => panic!("Expected DuplicateModule, got {:?}", res.err())
+    }
+    match compile_code("mod: outer { cont: Point { x: int; } cont: Point { y: int; } }") {
+        Err(CompilerError::DuplicateContainer(name)) => assert_eq!(name, "Point"),
+        res => panic!("Expected DuplicateContainer, got {:?}", res.err())
+    }
+    match compile_code("cont: Empty {} cont: Empty {}") {
+        Err(CompilerError::DuplicateContainer(name)) => assert_eq!(name, "Empty"),
+        res => panic!("Expected DuplicateContainer, got {:?}", res.err())
+    }
+    match compile_code("mod: outer { import: std::io as io; import: std::math as io; }") {
+        Err(CompilerError::DuplicateImport(name)) => assert_eq!(name, "io"),
+        res => panic!("Expected DuplicateImport, got {:?}", res.err())
+    }
+
+    // Equal names in different parents do not clash
+    let res = compile_code("mod: a { cont: Point { x: int; } } mod: b { cont: Point { x: int; } }");
+    assert!(res.is_ok());
+    // An impl may come before its container
+    let res = compile_code("impl: Point { fn: get(&this) ~ int { return this.x; } } cont: Point { x: int; }");
+    assert!(res.is_ok());
+}
+
+#[test]
+fn test_data_intern_bytes() {
+    let mut data = Data::new();
+    let blob = [1, 2, 3, 4];
+    assert_eq!(data.intern_bytes(&blob), (4, 0));
+    assert_eq!(data.intern_bytes(&[9, 9]), (2, 4));
+    // Identical blobs are stored once
+    assert_eq!(data.intern_bytes(&blob.to_vec()), (4, 0));
+    assert_eq!(data.bytes, vec![1, 2, 3, 4, 9, 9]);
+
+    // Strings share the interned bytes
+    assert_eq!(data.get_string_slice(&String::from("hi")), (2, 6));
+    assert_eq!(data.intern_bytes(b"hi"), (2, 6));
+    assert_eq!(data.get_string_slice(&String::from("hi")), (2, 6));
+    assert_eq!(data.bytes.len(), 8);
+    assert_eq!(data.get_strings().into_iter().collect::<Vec<_>>(), vec![(6, String::from("hi"))]);
+
+    // Added bytes are never shared
+    assert_eq!(data.add_bytes(&blob), 8);
+    assert_eq!(data.intern_bytes(&blob), (4, 0));
+}
+
+#[test]
+fn test_program_introspection() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: float;
+        }
+
+        impl: Point {
+            fn: len(&this) ~ int {
+                return this.x;
+            }
+        }
+
+        mod: geo {
+            cont: Segment {
+                from: root::Point;
+                ok: bool;
+            }
+        }
+
+        fn: main(p: Point, name: string) ~ bool {
+            var a = \"hello\";
+            var b = \"world\";
+            return host::check(4);
+        }
+    ");
+    let check_function = Function::new("check")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Bool);
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new()
+        .with_foreign_module(Module::new("host").with_function(check_function))
+        .unwrap();
+    let compile_res = compiler.compile_root(&decl_list);
+    println!("{:?}", compile_res);
+    assert!(compile_res.is_ok());
+    let program = compiler.get_program().unwrap();
+
+    let functions: Vec<FunctionInfo> = program.functions()
+        .filter(|function| !function.name.starts_with("root::__builtin::"))
+        .collect();
+    let names: Vec<&str> = functions.iter()
+        .map(|function| function.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["root::Point::len", "root::host::check", "root::main"]);
+
+    let main_info = &functions[2];
+    assert_eq!(main_info.uid, compiler.get_function_uid(&String::from("root::main")).unwrap());
+    assert_eq!(main_info.offset, Some(program.functions[&main_info.uid]));
+    assert!(!main_info.foreign);
+    assert_eq!(main_info.signature_string(), "root::main(root::Point, string) -> bool");
+
+    let check_info = &functions[1];
+    assert!(check_info.foreign);
+    assert_eq!(check_info.offset, None);
+    assert_eq!(check_info.signature_string(), "root::host::check(int) -> bool");
+
+    assert_eq!(functions[0].signature_string(), "root::Point::len(&root::Point) -> int");
+
+    let containers: Vec<&ContainerInfo> = program.containers().collect();
+    assert_eq!(containers.len(), 2);
+    assert_eq!(containers[0].name, "root::Point");
+    assert_eq!(containers[0].size, 12);
+    assert_eq!(containers[0].members, vec![
+        MemberInfo { name: String::from("x"), member_type: Type::Int, offset: 0, size: 8 },
+        MemberInfo { name: String::from("y"), member_type: Type::Float, offset: 8, size: 4 }
+    ]);
+    // Members of nested containers are resolved inside their module
+    assert_eq!(containers[1].name, "root::geo::Segment");
+    assert_eq!(containers[1].size, 16);
+    assert_eq!(containers[1].members[1], MemberInfo { name: String::from("ok"), member_type: Type::Bool, offset: 12, size: 4 });
+
+    let data_strings: Vec<(usize, &str)> = program.data_strings().collect();
+    assert_eq!(data_strings, vec![(0, "hello"), (5, "world")]);
+    assert_eq!(&program.code[5..10], b"world");
+}
+
+#[test]
+fn test_compile_expression() {
+    let parser = Parser::new(String::from("2 + 3 * 4;"));
+    let mut lexer = Token::lexer("2 + 3 * 4;");
+    let expr = parser.parse_expr(&mut lexer, &[Token::Semicolon]).unwrap();
+
+    let mut compiler = Compiler::new();
+    assert_eq!(compiler.compile_expression(&expr).unwrap(), Type::Int);
+    let program = compiler.get_program().unwrap();
+    let uid = compiler.get_function_uid(&format!("root::{}", EXPRESSION_FN_NAME)).unwrap();
+
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    assert!(core.run_fn(uid).is_ok());
+    assert_eq!(core.reg(0).unwrap().get::<i64>(), 14);
+    assert_eq!(core.get_stack_size(), 0);
+
+    // Standalone expressions have no variables
+    let mut lexer = Token::lexer("x + 1;");
+    let expr = parser.parse_expr(&mut lexer, &[Token::Semicolon]).unwrap();
+    match Compiler::new().compile_expression(&expr) {
+        Err(CompilerError::UnknownVariable(name, _)) => assert_eq!(name, "x"),
+        res => panic!("Expected an unknown variable error, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_ambiguous_function() {
+    let foo = || Function::new("foo").with_ret_type(Type::Int);
+    let new_compiler = || {
+        Compiler::new()
+            .with_foreign_module(Module::new("mod_a").with_function(foo()))
+            .unwrap()
+            .with_foreign_module(Module::new("mod_b").with_function(foo()))
+            .unwrap()
+    };
+    let candidates = vec![String::from("root::mod_a::foo"), String::from("root::mod_b::foo")];
+
+    // Called unqualified from a module which defines neither
+    let code = String::from("
+        fn: main() ~ int {
+            return foo();
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = new_compiler();
+    match compiler.compile_root(&decl_list) {
+        Err(CompilerError::AmbiguousFunction(name, names)) => {
+            assert_eq!(name, "foo");
+            assert_eq!(names, candidates);
+        },
+        res => panic!("Expected an ambiguous function error, got {:?}", res)
+    }
+
+    // Qualified calls are fine
+    let code = String::from("
+        fn: main() ~ int {
+            return mod_a::foo() + mod_b::foo();
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = new_compiler();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+
+    match compiler.get_function_uid(&String::from("foo")) {
+        Err(err @ CompilerError::AmbiguousFunction(_, _)) => {
+            assert_eq!(err.to_string(), "function foo is ambiguous, it could be any of root::mod_a::foo, root::mod_b::foo");
+        },
+        res => panic!("Expected an ambiguous function error, got {:?}", res)
+    }
+    assert!(compiler.get_function_uid(&String::from("root::mod_a::foo")).is_ok());
+    // Unique unqualified names resolve to their function
+    assert_eq!(
+        compiler.get_function_uid(&String::from("main")).unwrap(),
+        compiler.get_function_uid(&String::from("root::main")).unwrap()
+    );
+    assert!(matches!(compiler.get_function_uid(&String::from("bar")), Err(CompilerError::UnknownFunction(_, _))));
+}
+
+#[test]
+fn test_program_interface() {
+    let code = String::from("
+        fn: on_init() {
+        }
+
+        fn: on_tick(delta: float, frame: int) ~ bool {
+            return true;
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let interface = compiler.get_program().unwrap().interface();
+
+    // Foreign functions are not part of the interface
+    assert_eq!(interface.functions, vec![
+        FunctionSignature::new("root::on_init"),
+        FunctionSignature::new("on_tick")
+            .with_arg(Type::Float)
+            .with_arg(Type::Int)
+            .with_ret_type(Type::Bool)
+    ]);
+    assert_eq!(interface.functions[1].to_string(), "root::on_tick(float, int) -> bool");
+
+    let satisfied = RequiredInterface::new()
+        .with_function(FunctionSignature::new("on_init"))
+        .with_function(FunctionSignature::new("on_tick")
+            .with_arg(Type::Float)
+            .with_arg(Type::Int)
+            .with_ret_type(Type::Bool));
+    assert_eq!(interface.satisfies(&satisfied), Ok(()));
+
+    let missing = RequiredInterface::new()
+        .with_function(FunctionSignature::new("on_init"))
+        .with_function(FunctionSignature::new("on_exit"));
+    let mismatches = interface.satisfies(&missing).unwrap_err();
+    assert_eq!(mismatches, vec![InterfaceMismatch::MissingFunction(String::from("root::on_exit"))]);
+    assert_eq!(mismatches[0].to_string(), "missing function root::on_exit");
+
+    let wrong_types = RequiredInterface::new()
+        .with_function(FunctionSignature::new("on_init").with_arg(Type::Int))
+        .with_function(FunctionSignature::new("on_tick")
+            .with_arg(Type::Int)
+            .with_arg(Type::Int)
+            .with_ret_type(Type::Void));
+    let mismatches = interface.satisfies(&wrong_types).unwrap_err();
+    assert_eq!(mismatches, vec![
+        InterfaceMismatch::ArgumentCount { name: String::from("root::on_init"), expected: 1, found: 0 },
+        InterfaceMismatch::ArgumentType {
+            name: String::from("root::on_tick"),
+            index: 0,
+            expected: Type::Int,
+            found: Type::Float
+        },
+        InterfaceMismatch::ReturnType { name: String::from("root::on_tick"), expected: Type::Void, found: Type::Bool }
+    ]);
+    assert_eq!(mismatches[1].to_string(), "argument 0 of root::on_tick is float, expected int");
+}
+
+#[test]
+fn test_conditional_compilation() {
+    let code = "
+        @if debug {
+            fn: checked(x: int) ~ int {
+                return x * 10;
+            }
+        }
+
+        fn: main() ~ int {
+            var x = 1;
+            @if debug {
+                x = checked(x);
+            }
+            @if release {
+                // Never type checked, the function does not exist
+                x = missing(true);
+            }
+            return x;
+        }
+    ";
+    let run = |compiler: Compiler| {
+        let decl_list = Parser::new(String::from(code)).parse_root_decl_list().unwrap();
+        let mut compiler = compiler;
+        let compile_res = compiler.compile_root(&decl_list);
+        println!("{:?}", compile_res);
+        assert!(compile_res.is_ok());
+        let program = compiler.get_program().unwrap();
+        let functions: Vec<String> = program.interface().functions.into_iter()
+            .map(|function| function.name)
+            .collect();
+        let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+        let mut core = Core::new(1024);
+        core.load_program(program);
+        assert!(core.run_fn(main_uid).is_ok());
+        (functions, core.reg(0).unwrap().get::<i64>())
+    };
+
+    let (functions, result) = run(Compiler::new());
+    assert_eq!(functions, vec!["root::main"]);
+    assert_eq!(result, 1);
+
+    let compiler = Compiler::new().with_define("debug");
+    assert!(compiler.is_defined("debug"));
+    assert!(!compiler.is_defined("release"));
+    let (functions, result) = run(compiler);
+    assert_eq!(functions, vec!["root::checked", "root::main"]);
+    assert_eq!(result, 10);
+
+    // Enabled code is checked as usual
+    let decl_list = Parser::new(String::from(code)).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new().with_define("release");
+    match compiler.compile_root(&decl_list) {
+        Err(CompilerError::UnknownFunction(name, _)) => assert_eq!(name, "missing"),
+        res => panic!("Expected an unknown function error, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_recompile_function() {
+    let code = String::from("
+        fn: value(x: int) ~ int {
+            return x + 1;
+        }
+
+        fn: main() ~ int {
+            return value(10) * 100 + value(1);
+        }
+    ");
+    let parse_fn = |code: &str| Parser::new(String::from(code)).parse_root_decl_list().unwrap().remove(0);
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+
+    let mut compiler = Compiler::new();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let value_uid = compiler.get_function_uid(&String::from("root::value")).unwrap();
+
+    assert!(compiler.invalidate_function("root::value").is_ok());
+    match compiler.validate_labels() {
+        Err(CompilerError::UnknownFunction(names, _)) => assert_eq!(names, "root::value"),
+        res => panic!("Expected a missing label, got {:?}", res)
+    }
+    match compiler.invalidate_function("root::value") {
+        Err(CompilerError::UnknownFunction(name, _)) => assert_eq!(name, "root::value"),
+        res => panic!("Expected an unknown function, got {:?}", res)
+    }
+    match compiler.invalidate_function("root::missing") {
+        Err(CompilerError::UnknownFunction(name, _)) => assert_eq!(name, "root::missing"),
+        res => panic!("Expected an unknown function, got {:?}", res)
+    }
+
+    // Callers were compiled against the old signature
+    match compiler.recompile_function(&parse_fn("fn: value(x: float) ~ int { return 0; }")) {
+        Err(CompilerError::SignatureChanged(name)) => assert_eq!(name, "root::value"),
+        res => panic!("Expected a changed signature, got {:?}", res)
+    }
+    assert!(compiler.validate_labels().is_err());
+
+    let recompile_res = compiler.recompile_function(&parse_fn("fn: value(y: int) ~ int { return y * 2; }"));
+    println!("{:?}", recompile_res);
+    assert!(recompile_res.is_ok());
+    assert_eq!(compiler.get_function_uid(&String::from("root::value")).unwrap(), value_uid);
+    assert!(compiler.validate_labels().is_ok());
+
+    let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+    let program = compiler.get_program().unwrap();
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    assert!(core.run_fn(main_uid).is_ok());
+    assert_eq!(core.reg(0).unwrap().get::<i64>(), 20 * 100 + 2);
+}
+
+#[test]
+fn test_program_locals() {
+    let code = String::from("
+        fn: main(a: int) ~ int {
+            var b: float = 1.5;
+            var c: bool = true;
+            if c {
+                var d: int = 2;
+                return d;
+            }
+            return a;
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new()
+        .with_debug_symbols(true);
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let main_uid = compiler.get_function_uid(&String::from("root::main")).unwrap();
+    let program = compiler.get_program().unwrap();
+
+    let local = |name: &str, var_type: Type, offset: i64| LocalVariable {
+        name: String::from(name),
+        var_type,
+        offset
+    };
+    let main_offset = program.functions[&main_uid];
+    // Only the arguments are in scope at the start
+    assert_eq!(program.locals_at(main_offset), &[local("a", Type::Int, -8)]);
+
+    let snapshots: Vec<&Vec<LocalVariable>> = program.debug_info.locals.values().collect();
+    // The variable of the if block is in scope inside of it
+    assert!(snapshots.contains(&&vec![
+        local("a", Type::Int, -8),
+        local("b", Type::Float, 0),
+        local("c", Type::Bool, 4),
+        local("d", Type::Int, 8)
+    ]));
+    // And out of scope again behind it
+    let last_offset = *program.debug_info.locals.keys().last().unwrap();
+    assert_eq!(program.locals_at(last_offset + 1), &[
+        local("a", Type::Int, -8),
+        local("b", Type::Float, 0),
+        local("c", Type::Bool, 4)
+    ]);
+
+    // Without debug symbols no locals are recorded
+    let decl_list = Parser::new(String::from("fn: main(a: int) {}")).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let program = compiler.get_program().unwrap();
+    assert!(program.debug_info.locals.is_empty());
+    assert_eq!(program.locals_at(program.code_start), &[]);
+}
+
+#[test]
+fn test_validate_labels() {
+    let code = String::from("
+        fn: helper() ~ int {
+            return 2;
+        }
+
+        fn: unused() {
+        }
+
+        fn: main() ~ int {
+            return 1;
+        }
+    ");
+    let decl_list = Parser::new(code.clone()).parse_root_decl_list().unwrap();
+
+    let mut compiler = Compiler::new();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    assert_eq!(compiler.validate_labels().unwrap(), vec!["root::helper", "root::main", "root::unused"]);
+
+    // Declared, but never compiled
+    let compiled: Vec<Declaration> = Parser::new(code).parse_root_decl_list().unwrap()
+        .into_iter()
+        .filter(|decl| match decl {
+            Declaration::Function(fn_decl_args) => fn_decl_args.name == "main",
+            _ => true
+        })
+        .collect();
+    let mut compiler = Compiler::new();
+    assert!(compiler.declare_decl_list(&decl_list).is_ok());
+    assert!(compiler.compile_decl_list(&compiled).is_ok());
+    match compiler.validate_labels() {
+        Err(CompilerError::UnknownFunction(names, _)) => assert_eq!(names, "root::helper, root::unused"),
+        res => panic!("Expected missing labels, got {:?}", res)
+    }
+    match compiler.get_program() {
+        Err(CompilerError::UnknownFunction(names, _)) => assert_eq!(names, "root::helper, root::unused"),
+        res => panic!("Expected missing labels, got {:?}", res.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_compile_array_repeat() {
+    let compile = |code: &str, unroll_limit: usize| {
+        compile_code_with(Compiler::new().with_array_unroll_limit(unroll_limit), code)
+            .and_then(|mut compiler| compiler.get_program())
+    };
+    let count_jumps = |code: &str, unroll_limit: usize| {
+        let program = compile(code, unroll_limit).unwrap();
+        decode_instructions(&program.code, program.code_start)
+            .filter(|instr| instr.as_ref().unwrap().opcode == Opcode::JMPT)
+            .count()
+    };
+
+    // Arrays above the limit are filled in a loop
+    let code = "const: N = 4; fn: main() { var grid = [[0.5; N]; N]; var flags = [true; 2]; }";
+    assert_eq!(count_jumps(code, 0), 3);
+    assert_eq!(count_jumps(code, 3), 2);
+    assert_eq!(count_jumps(code, 4), 0);
+
+    match compile("fn: main(n: int) { var b = [0; n]; }", 16) {
+        Err(CompilerError::NonConstantArraySize(span)) => assert_eq!(span, 27..33),
+        res => panic!("Expected a non-constant array size, got {:?}", res.err())
+    }
+    match compile("fn: main() { var b = [0; 0 - 1]; }", 16) {
+        Err(CompilerError::InvalidArraySize(-1, _)) => {},
+        res => panic!("Expected an invalid array size, got {:?}", res.err())
+    }
+    match compile("fn: main() { var b: [int; 4] = [0; 3]; }", 16) {
+        Err(CompilerError::TypeMismatch(expected, actual)) => {
+            assert_eq!(expected, Type::Array(Box::new(Type::Int), 4));
+            assert_eq!(actual, Type::Array(Box::new(Type::Int), 3));
+        },
+        res => panic!("Expected a type mismatch, got {:?}", res.err())
+    }
+}
+
+#[test]
+fn test_compile_const_array_sizes() {
+    // Constants may be declared after the types which use them
+    let compiler = compile_code("
+        fn: main(a: [[int; N]; N + 1]) ~ [bool; sizeof(int) / 2] {}
+        const: N = 2 * 3;
+    ").unwrap();
+    let fn_def = compiler.get_root_module().unwrap().get_function(&String::from("main")).unwrap();
+    assert_eq!(fn_def.arguments[0].1, Type::Array(Box::new(Type::Array(Box::new(Type::Int), 6)), 7));
+    assert_eq!(fn_def.ret_type, Type::Array(Box::new(Type::Bool), 4));
+
+    match compile_code("fn: main(a: [int; N]) {}") {
+        Err(CompilerError::NonConstantArraySize(span)) => assert_eq!(span, 12..20),
+        res => panic!("Expected a non-constant array size, got {:?}", res.err())
+    }
+    match compile_code("fn: main(n: int) { var r: &[int; n] = &n; }") {
+        Err(CompilerError::NonConstantArraySize(_)) => {},
+        res => panic!("Expected a non-constant array size, got {:?}", res.err())
+    }
+    match compile_code("const: N = 0 - 1; fn: main(a: [int; N]) {}") {
+        Err(CompilerError::InvalidArraySize(-1, span)) => assert_eq!(span, 30..38),
+        res => panic!("Expected an invalid array size, got {:?}", res.err())
+    }
+    match compile_code("cont: Huge { data: [int; 100000 * 100000]; }") {
+        Err(CompilerError::InvalidArraySize(10000000000, _)) => {},
+        res => panic!("Expected an invalid array size, got {:?}", res.err())
+    }
+    match compile_code("const: N = size(); fn: size() ~ int { return 1; }") {
+        Err(CompilerError::NonConstantExpression(name)) => assert_eq!(name, "N"),
+        res => panic!("Expected a non-constant expression, got {:?}", res.err())
+    }
+    match compile_code("const: N = 1; const: N = 2;") {
+        Err(CompilerError::DuplicateConstant(name)) => assert_eq!(name, "N"),
+        res => panic!("Expected a duplicate constant, got {:?}", res.err())
+    }
+}
+
+#[test]
+fn test_compile_dump_ir() {
+    let code = String::from("
+        fn: double(x: int) ~ int {
+            return x * 2;
+        }
+
+        fn: main(n: int) ~ int {
+            var i = 0;
+            while i < n {
+                i += 1;
+            }
+            return double(i);
+        }
+    ");
+    let decl_list = Parser::new(code).parse_root_decl_list().unwrap();
+    let mut compiler = Compiler::new();
+    assert!(compiler.compile_root(&decl_list).is_ok());
+    let ir = compiler.dump_ir();
+    println!("{}", ir);
+
+    // The pseudo-ops of each function appear in order, with symbolic jump targets and callees
+    let main_ir = &ir[ir.find(".fn root::main").unwrap()..];
+    let mut rest = main_ir;
+    for expected in ["LDI 0, r", "L0:", "LTI ", ", L1", "ADDI ", "JMP L0", "L1:", "CALL root::double", "RET"].iter() {
+        let pos = rest.find(expected)
+            .unwrap_or_else(|| panic!("Expected {} in order in {}", expected, main_ir));
+        rest = &rest[pos + expected.len()..];
+    }
+    let double_ir = &ir[ir.find(".fn root::double").unwrap()..ir.find(".fn root::main").unwrap()];
+    assert!(double_ir.contains("MULI "));
+
+    // The builder alone does not know the names of the functions
+    let builder_ir = compiler.get_builder().to_string();
+    assert!(!builder_ir.contains("CALL root::double"));
+    assert_eq!(builder_ir.lines().count(), ir.lines().count());
+}
+
+#[test]
+fn test_compile_operand_overflow() {
+
+    // Offsets up to the i16 range still compile
+    assert!(compile_code("fn: main(x: int, a: [int; 4000]) ~ int { return x + a[3999]; }").is_ok());
+
+    // The argument x lies behind the array, out of reach of a 16 bit offset to FP
+    match compile_code("fn: main(x: int, a: [int; 5000]) ~ int { return x; }") {
+        Err(CompilerError::OperandOverflow { what, value, max }) => {
+            assert_eq!(what, "variable offset");
+            assert_eq!(value, -40008);
+            assert_eq!(max, i16::MAX as i64);
+        },
+        res => panic!("Expected an operand overflow, got {:?}", res.err())
+    }
+
+    // Copying a container bigger than the offsets can span
+    let code = "
+        cont: Big {
+            data: [int; 10000];
+        }
+
+        fn: main(b: &Big) {
+            var c = ~b;
+        }
+    ";
+    match compile_code(code) {
+        Err(err @ CompilerError::OperandOverflow { .. }) => {
+            assert_eq!(err.to_string(), "dereferenced container size of 80000 exceeds the maximum of 32768 an instruction can encode");
+        },
+        res => panic!("Expected an operand overflow, got {:?}", res.err())
+    }
+}
+
+#[test]
+fn test_compile_name_suggestions() {
+    let compile = |body: &str| {
+        let code = format!("
+            cont: Point {{
+                x: int;
+                y: int;
+            }}
+
+            impl: Point {{
+                fn: length(&this) ~ int {{
+                    return this.x + this.y;
+                }}
+            }}
+
+            fn: distance(a: int, b: int) ~ int {{
+                return a - b;
+            }}
+
+            fn: main(count: int) ~ int {{
+                var point = Point {{ x: 1, y: 2 }};
+                {}
+            }}
+        ", body);
+        compile_code(&code)
+    };
+    assert!(compile("return distance(count, point.length());").is_ok());
+
+    match compile("return distnace(count, 1);") {
+        Err(CompilerError::UnknownFunction(name, suggestion)) => {
+            assert_eq!(name, "distnace");
+            assert_eq!(suggestion, Some(String::from("distance")));
+        },
+        res => panic!("Expected an unknown function error, got {:?}", res.err())
+    }
+    match compile("return total + 1;") {
+        Err(CompilerError::UnknownVariable(name, suggestion)) => {
+            assert_eq!(name, "total");
+            assert_eq!(suggestion, None);
+        },
+        res => panic!("Expected an unknown variable error, got {:?}", res.err())
+    }
+    match compile("return coutn + 1;") {
+        Err(err @ CompilerError::UnknownVariable(..)) => {
+            assert_eq!(err.to_string(), "unknown variable 'coutn'; did you mean 'count'?");
+        },
+        res => panic!("Expected an unknown variable error, got {:?}", res.err())
+    }
+    match compile("return point.z;") {
+        Err(CompilerError::UnknownMember(name, suggestion)) => {
+            assert_eq!(name, "z");
+            assert_eq!(suggestion, None);
+        },
+        res => panic!("Expected an unknown member error, got {:?}", res.err())
+    }
+    match compile("return point.lenght();") {
+        Err(err @ CompilerError::UnknownMember(..)) => {
+            assert_eq!(err.to_string(), "unknown member 'lenght'; did you mean 'length'?");
+        },
+        res => panic!("Expected an unknown member error, got {:?}", res.err())
+    }
+
+    // Unrelated names are not suggested
+    match compile("return unrelated(count);") {
+        Err(err @ CompilerError::UnknownFunction(..)) => {
+            assert_eq!(err.to_string(), "unknown function 'unrelated'");
+        },
+        res => panic!("Expected an unknown function error, got {:?}", res.err())
+    }
+    let err = CompilerError::UnknownFunction(String::from("distnace"), Some(String::from("distance")));
+    assert_eq!(err.to_string(), "unknown function 'distnace'; did you mean 'distance'?");
+}
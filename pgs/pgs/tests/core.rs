@@ -2,8 +2,24 @@ extern crate pgs;
 use pgs::{
     vm::{
         core::*,
-        is::Opcode
+        is::{
+            Opcode,
+            decode::*
+        },
+        address::{
+            Address,
+            AddressType
+        },
+        strings::{
+            StringTable,
+            StringRepr
+        }
+    },
+    api::{
+        function::Function,
+        adapter::Adapter
     },
+    parser::ast::Type,
     codegen::{
         program::Program,
         builder::Builder,
@@ -12,6 +28,8 @@ use pgs::{
 };
 
 use bincode::serialize;
+
+use std::collections::HashMap;
 #[test]
 fn test_core_addi() {
     let mut builder = Builder::new();
@@ -45,7 +63,7 @@ fn test_core_addi() {
     builder.push_instr(add_sp_instr);
     builder.push_instr(mov_instr);
 
-    let program = Program::new().with_code(builder.build());
+    let program = Program::new().with_code(builder.build().unwrap());
 
     let mut core = Core::new(1024);
     core.load_program(program);
@@ -126,4 +144,350 @@ fn test_core_foreign_ptr() {
         let int = int_arc.lock().unwrap();
         assert_eq!(int.0, 10);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_core_jump_into_data() {
+    let mut builder = Builder::new();
+
+    let jmp_instr = Instruction::new(Opcode::JMP) // JMP 0
+        .with_operand(0u64);
+    builder.push_instr(jmp_instr);
+
+    // 8 bytes of data in front of the code
+    let mut code = serialize(&42u64).unwrap();
+    code.append(&mut builder.build().unwrap());
+    let program = Program::new()
+        .with_code(code)
+        .with_code_start(8);
+
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    let run_res = core.run_at(8);
+    match run_res {
+        Err(err) => match err.root_cause() {
+            CoreError::InvalidJumpTarget(0) => {},
+            _ => panic!("Expected InvalidJumpTarget, got {:?}", err)
+        },
+        Ok(_) => panic!("Expected InvalidJumpTarget")
+    }
+}
+
+#[test]
+fn test_core_memory_checks() {
+    let mut builder = Builder::new();
+
+    let lda_instr = Instruction::new(Opcode::LDA) // LDA 8, r2
+        .with_operand(8u64)
+        .with_operand(2u8);
+    let add_sp_instr = Instruction::new(Opcode::ADDU) // ADDU sp, r2, sp
+        .with_operand(16u8)
+        .with_operand(2u8)
+        .with_operand(16u8);
+    let ldi_instr = Instruction::new(Opcode::LDI) // LDI 7, r0
+        .with_operand(7i64)
+        .with_operand(0u8);
+    let mov_instr = Instruction::new(Opcode::MOVI_RA) // MOVI r0, [sp-4], half above the stack
+        .with_operand(0u8)
+        .with_operand(16u8)
+        .with_operand::<i16>(-4);
+    builder.push_instr(lda_instr);
+    builder.push_instr(add_sp_instr);
+    builder.push_instr(ldi_instr);
+    builder.push_instr(mov_instr);
+    // LDA (10 bytes) + ADDU (4 bytes) + LDI (10 bytes)
+    let mov_offset = 24;
+    let code = builder.build().unwrap();
+
+    // The bad store goes unnoticed without checks
+    let mut core = Core::new(1024);
+    core.load_program(Program::new().with_code(code.clone()));
+    assert!(core.run().is_ok());
+
+    let mut core = Core::new(1024);
+    core.set_memory_checks(true);
+    core.load_program(Program::new().with_code(code));
+    let run_res = core.run();
+    match run_res {
+        Err(err) => match err.root_cause() {
+            CoreError::MemoryViolation { pc, region: AddressType::Stack, .. } => assert_eq!(*pc, mov_offset),
+            _ => panic!("Expected MemoryViolation, got {:?}", err)
+        },
+        Ok(_) => panic!("Expected MemoryViolation")
+    }
+}
+
+#[test]
+fn test_core_memory_checks_frame() {
+    // main: pushes 16 bytes and calls f
+    let mut main_builder = Builder::new();
+    main_builder.push_instr(Instruction::new(Opcode::LDA) // LDA 16, r2
+        .with_operand(16u64)
+        .with_operand(2u8));
+    main_builder.push_instr(Instruction::new(Opcode::ADDU) // ADDU sp, r2, sp
+        .with_operand(16u8)
+        .with_operand(2u8)
+        .with_operand(16u8));
+    main_builder.push_instr(Instruction::new(Opcode::CALL) // CALL f
+        .with_operand(1u64));
+    main_builder.push_instr(Instruction::new(Opcode::RET));
+
+    // f: pops 8 bytes it never pushed, eating into the frame of main
+    let mut f_builder = Builder::new();
+    f_builder.push_instr(Instruction::new(Opcode::LDA) // LDA 8, r2
+        .with_operand(8u64)
+        .with_operand(2u8));
+    f_builder.push_instr(Instruction::new(Opcode::SUBU) // SUBU sp, r2, sp
+        .with_operand(16u8)
+        .with_operand(2u8)
+        .with_operand(16u8));
+    f_builder.push_instr(Instruction::new(Opcode::RET));
+
+    let mut code = main_builder.build().unwrap();
+    let f_offset = code.len();
+    code.append(&mut f_builder.build().unwrap());
+    let mut functions = HashMap::new();
+    functions.insert(0u64, 0usize);
+    functions.insert(1u64, f_offset);
+
+    let mut core = Core::new(1024);
+    core.set_memory_checks(true);
+    core.load_program(Program::new().with_code(code).with_functions(functions));
+    let run_res = core.run_fn(0);
+    match run_res {
+        Err(err) => match err.root_cause() {
+            CoreError::MemoryViolation { region: AddressType::Stack, .. } => {},
+            _ => panic!("Expected MemoryViolation, got {:?}", err)
+        },
+        Ok(_) => panic!("Expected MemoryViolation")
+    }
+}
+
+#[test]
+fn test_core_memory_checks_canary() {
+    // main: pushes a local and the argument 5 of f, returns the result of f
+    let mut main_builder = Builder::new();
+    main_builder.push_instr(Instruction::new(Opcode::LDI) // LDI 5, r0
+        .with_operand(5i64)
+        .with_operand(0u8));
+    main_builder.push_instr(Instruction::new(Opcode::LDA) // LDA 16, r2
+        .with_operand(16u64)
+        .with_operand(2u8));
+    main_builder.push_instr(Instruction::new(Opcode::ADDU) // ADDU sp, r2, sp
+        .with_operand(16u8)
+        .with_operand(2u8)
+        .with_operand(16u8));
+    main_builder.push_instr(Instruction::new(Opcode::MOVI_RA) // MOVI r0, [sp-8]
+        .with_operand(0u8)
+        .with_operand(16u8)
+        .with_operand::<i16>(-8));
+    main_builder.push_instr(Instruction::call(1));
+    main_builder.push_instr(Instruction::new(Opcode::SUBU) // SUBU sp, r2, sp
+        .with_operand(16u8)
+        .with_operand(2u8)
+        .with_operand(16u8));
+    main_builder.push_instr(Instruction::new(Opcode::RET));
+    let main_code = main_builder.build().unwrap();
+
+    // f: returns its argument plus one, optionally after storing 0 right below the argument
+    let build_program = |store_below_arg: bool| {
+        let mut f_builder = Builder::new();
+        if store_below_arg {
+            f_builder.push_instr(Instruction::new(Opcode::LDI) // LDI 0, r1
+                .with_operand(0i64)
+                .with_operand(1u8));
+            f_builder.push_instr(Instruction::new(Opcode::MOVI_RA) // MOVI r1, [fp-16]
+                .with_operand(1u8)
+                .with_operand(18u8)
+                .with_operand::<i16>(-16));
+        }
+        f_builder.push_instr(Instruction::new(Opcode::MOVI_AR) // MOVI [fp-8], r0
+            .with_operand(18u8)
+            .with_operand::<i16>(-8)
+            .with_operand(0u8));
+        f_builder.push_instr(Instruction::new(Opcode::LDI) // LDI 1, r1
+            .with_operand(1i64)
+            .with_operand(1u8));
+        f_builder.push_instr(Instruction::new(Opcode::ADDI) // ADDI r0, r1, r0
+            .with_operand(0u8)
+            .with_operand(1u8)
+            .with_operand(0u8));
+        f_builder.push_instr(Instruction::new(Opcode::RET));
+
+        let mut code = main_code.clone();
+        let f_offset = code.len();
+        code.append(&mut f_builder.build().unwrap());
+        let mut functions = HashMap::new();
+        functions.insert(0u64, 0usize);
+        functions.insert(1u64, f_offset);
+        let mut arg_sizes = HashMap::new();
+        arg_sizes.insert(1u64, 8usize);
+        Program::new()
+            .with_code(code)
+            .with_functions(functions)
+            .with_arg_sizes(arg_sizes)
+    };
+
+    // The argument is moved above the canary and back, f still finds it and main pops it
+    let mut core = Core::new(1024);
+    core.set_memory_checks(true);
+    core.load_program(build_program(false));
+    assert!(core.run_fn(0).is_ok());
+    assert_eq!(core.reg(0).unwrap().get::<i64>(), 6);
+    assert_eq!(core.get_stack_size(), 0);
+
+    // The store below the argument overwrites the canary instead of the local of main,
+    // which is only noticed with checks
+    let mut core = Core::new(1024);
+    core.load_program(build_program(true));
+    assert!(core.run_fn(0).is_ok());
+
+    let mut core = Core::new(1024);
+    core.set_memory_checks(true);
+    core.load_program(build_program(true));
+    let canary_address: u64 = Address::new(8, AddressType::Stack).into();
+    match core.run_fn(0) {
+        Err(err) => match err.root_cause() {
+            CoreError::MemoryViolation { addr, region: AddressType::Stack, .. } => assert_eq!(*addr, canary_address),
+            _ => panic!("Expected MemoryViolation, got {:?}", err)
+        },
+        Ok(_) => panic!("Expected MemoryViolation")
+    }
+}
+
+#[test]
+fn test_decode_instructions() {
+    assert_eq!(opcode_operand_layout(&Opcode::RET), &[]);
+    assert_eq!(opcode_operand_layout(&Opcode::LDI), &[OperandKind::Int, OperandKind::Register]);
+    assert_eq!(opcode_operand_layout(&Opcode::MOVN_A), &[
+        OperandKind::Register,
+        OperandKind::Offset,
+        OperandKind::Register,
+        OperandKind::Offset,
+        OperandKind::Count
+    ]);
+
+    let mut code = vec![0xFF; 4];
+    // LDI -3, r1
+    code.push(Opcode::LDI.into());
+    code.extend(serialize(&-3i64).unwrap());
+    code.push(1);
+    // MOVI_RA r1, sp, -8
+    code.push(Opcode::MOVI_RA.into());
+    code.push(1);
+    code.push(16);
+    code.extend(serialize(&-8i16).unwrap());
+    // JMPT r1, 4
+    code.push(Opcode::JMPT.into());
+    code.push(1);
+    code.extend(serialize(&4u64).unwrap());
+    // CALL 0xAB
+    code.push(Opcode::CALL.into());
+    code.extend(serialize(&0xABu64).unwrap());
+    code.push(Opcode::RET.into());
+
+    // Decoding starts behind the leading data
+    let instructions: Vec<DecodedInstruction> = decode_instructions(&code, 4)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(instructions, vec![
+        DecodedInstruction {
+            addr: 4,
+            opcode: Opcode::LDI,
+            operands: vec![(0, Operand::Int(-3)), (8, Operand::Register(1))]
+        },
+        DecodedInstruction {
+            addr: 14,
+            opcode: Opcode::MOVI_RA,
+            operands: vec![(0, Operand::Register(1)), (1, Operand::Register(16)), (2, Operand::Offset(-8))]
+        },
+        DecodedInstruction {
+            addr: 19,
+            opcode: Opcode::JMPT,
+            operands: vec![(0, Operand::Register(1)), (1, Operand::CodeAddress(4))]
+        },
+        DecodedInstruction {
+            addr: 29,
+            opcode: Opcode::CALL,
+            operands: vec![(0, Operand::FunctionUid(0xAB))]
+        },
+        DecodedInstruction {
+            addr: 38,
+            opcode: Opcode::RET,
+            operands: Vec::new()
+        }
+    ]);
+    let sizes: Vec<usize> = instructions.iter().map(|instruction| instruction.size()).collect();
+    assert_eq!(sizes, vec![10, 5, 10, 9, 1]);
+
+    // Decoding stops at the first invalid or truncated instruction
+    let invalid: Vec<_> = decode_instructions(&code, 0).collect();
+    assert_eq!(invalid, vec![Err(DecodeError::InvalidOpcode(0, 0xFF))]);
+    let truncated: Vec<_> = decode_instructions(&code[..code.len() - 2], 4).collect();
+    assert_eq!(truncated.len(), 4);
+    assert_eq!(truncated[3], Err(DecodeError::Truncated(29, Opcode::CALL)));
+}
+
+#[test]
+fn test_core_string_table() {
+    let strings = vec![String::from("a"), String::from("b")];
+    let mut table = StringTable::from(strings.as_slice());
+    assert_eq!(table.len(), 2);
+    assert_eq!(table.intern("b"), 1);
+    assert_eq!(table.intern("c"), 2);
+    assert_eq!(table.intern("c"), 2);
+    assert_eq!(table.get(0), Some("a"));
+    assert_eq!(table.get(2), Some("c"));
+    assert_eq!(table.get(3), None);
+    assert_eq!(table.strings(), &[String::from("a"), String::from("b"), String::from("c")]);
+}
+
+#[test]
+fn test_core_invalid_string_handle_arg() {
+    let mut builder = Builder::new();
+    builder.push_instr(Instruction::new(Opcode::LDI) // LDI 999, r0
+        .with_operand(999i64)
+        .with_operand(0u8));
+    builder.push_instr(Instruction::new(Opcode::LDA) // LDA 8, r2
+        .with_operand(8u64)
+        .with_operand(2u8));
+    builder.push_instr(Instruction::new(Opcode::ADDU) // ADDU sp, r2, sp
+        .with_operand(16u8)
+        .with_operand(2u8)
+        .with_operand(16u8));
+    builder.push_instr(Instruction::new(Opcode::MOVI_RA) // MOVI r0, [sp-8]
+        .with_operand(0u8)
+        .with_operand(16u8)
+        .with_operand::<i16>(-8));
+    builder.push_instr(Instruction::call(1));
+    builder.push_instr(Instruction::new(Opcode::RET));
+
+    let mut inspect_function = Function::new("inspect")
+        .with_arg(Type::String)
+        .with_closure(Box::new(|adapter: &mut Adapter| {
+            let string: String = adapter.get_arg(0);
+            assert!(string.is_empty());
+        }));
+    inspect_function.set_arg_offsets(vec![-8]);
+    let mut functions = HashMap::new();
+    functions.insert(0u64, 0usize);
+    let mut foreign_functions = HashMap::new();
+    foreign_functions.insert(1u64, inspect_function);
+
+    let mut program = Program::new()
+        .with_code(builder.build().unwrap())
+        .with_functions(functions)
+        .with_foreign_functions(foreign_functions);
+    program.string_repr = StringRepr::Handle;
+
+    // The handle is not in the string table, the run fails once the foreign function returns
+    let mut core = Core::new(1024);
+    core.load_program(program);
+    match core.run_fn(0) {
+        Err(err) => match err.root_cause() {
+            CoreError::InvalidStringHandle(999) => {},
+            _ => panic!("Expected InvalidStringHandle, got {:?}", err)
+        },
+        Ok(_) => panic!("Expected InvalidStringHandle")
+    }
+}
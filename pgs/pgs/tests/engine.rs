@@ -1,20 +1,50 @@
 extern crate pgs;
 use pgs::{
     codegen::{
-        compiler::Compiler,
+        compiler::{
+            Compiler,
+            CompilerError
+        },
         register::Register
     },
     parser::{
         parser::Parser,
         ast::Type
     },
-    engine::Engine,
+    engine::{
+        Engine,
+        EngineError
+    },
+    vm::{
+        core::{
+            CoreError,
+            VmExit,
+            Value
+        },
+        backtrace::Frame,
+        is::Opcode,
+        trace::Trace,
+        strings::StringRepr
+    },
     api::{
         module::Module,
         function::Function,
-        adapter::Adapter
+        adapter::{
+            Adapter,
+            ForeignResult
+        }
     }
 };
+use std::{
+    sync::{
+        Arc,
+        Mutex,
+        mpsc
+    },
+    thread,
+    time::Duration,
+    convert::TryInto
+};
 /*
 #[test]
 fn test_engine_simple_function() {
@@ -429,4 +459,2615 @@ fn test_engine_member_call() {
     assert_eq!(engine.get_stack_size(), 0);
     println!("{:?}", run_res);
     assert!(run_res.is_ok());
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_engine_nested_call_args() {
+    let code = String::from("
+        fn: f(a: int, b: int) ~ int {
+            return a - b;
+        }
+
+        fn: g(x: int) ~ int {
+            return x * 2;
+        }
+
+        fn: h(y: int) ~ int {
+            return y + 1;
+        }
+
+        fn: main() ~ int {
+            var x: int = 5;
+            var y: int = 2;
+            return f(g(x), h(y));
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(7, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_call_in_arithmetic_arg() {
+    let code = String::from("
+        fn: f(a: int, b: int) ~ int {
+            return a - b;
+        }
+
+        fn: g(x: int) ~ int {
+            var z: int = x * 3;
+            return z + 1;
+        }
+
+        fn: main() ~ int {
+            return f(1 + g(2), 3);
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(5, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_call_in_arithmetic() {
+    let code = String::from("
+        fn: g(x: int) ~ int {
+            return x * 2;
+        }
+
+        fn: main() ~ int {
+            var x: int = g(3) + g(4) * 10;
+            return x;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(86, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_nested_early_return() {
+    let code = String::from("
+        fn: pick(x: int) ~ int {
+            var a: int = x * 2;
+            if x > 0 {
+                var b: int = a + 1;
+                if b > 4 {
+                    var c: int = b * 10;
+                    return c;
+                }
+                return b;
+            }
+            var d: int = 0 - a;
+            return d;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    for (x, expected) in [(3, 70), (1, 3), (-2, 4)].iter() {
+        assert!(engine.push_stack::<i64>(*x).is_ok());
+        let run_res = engine.run_fn("root::pick");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), *expected);
+        assert_eq!(engine.get_stack_size(), 8);
+        assert!(engine.pop_stack::<i64>().is_ok());
+    }
+}
+
+#[test]
+fn test_engine_call_depth_exceeded() {
+    let code = String::from("
+        fn: recurse() {
+            recurse();
+        }
+
+        fn: main() {
+            recurse();
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    engine.set_max_call_depth(64);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    match run_res.map_err(|e| *e) {
+        Err(EngineError::CoreError(core_err)) => {
+            match core_err.root_cause() {
+                CoreError::CallDepthExceeded(depth, fn_name) => {
+                    assert_eq!(*depth, 64);
+                    assert_eq!(fn_name, "root::recurse");
+                },
+                _ => panic!("Expected CallDepthExceeded error")
+            }
+        },
+        _ => panic!("Expected CallDepthExceeded error")
+    }
+}
+
+#[test]
+fn test_engine_call_depth_below_limit() {
+    let code = String::from("
+        fn: count(n: int) ~ int {
+            if n == 0 {
+                return 0;
+            }
+            return count(n - 1) + 1;
+        }
+
+        fn: main() ~ int {
+            return count(99);
+        }
+    ");
+
+    let mut engine = Engine::new(1024 * 64);
+    engine.set_max_call_depth(100);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(99, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_discarded_call_result() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: int;
+            z: int;
+        }
+
+        fn: make_point() ~ Point {
+            return Point {
+                x: 1,
+                y: 2,
+                z: 3
+            };
+        }
+
+        fn: get_value() ~ int {
+            return 7;
+        }
+
+        fn: main() ~ int {
+            var before: int = 42;
+            var i: int = 0;
+            while i < 1000 {
+                make_point();
+                get_value();
+                i += 1;
+            }
+            return before;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(42, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_discarded_call_result_straight() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: int;
+            z: int;
+        }
+
+        fn: make_point() ~ Point {
+            return Point {
+                x: 1,
+                y: 2,
+                z: 3
+            };
+        }
+
+        fn: main() ~ int {
+            var before: int = 42;
+            make_point();
+            make_point();
+            make_point();
+            make_point();
+            make_point();
+            make_point();
+            return before;
+        }
+    ");
+
+    let mut engine = Engine::new(128);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(42, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_interleaved_locals() {
+    let code = String::from("
+        fn: add(a: int, b: int) ~ int {
+            var sum: int = a + b;
+            return sum;
+        }
+
+        fn: main() ~ int {
+            var x: int = 1;
+            var y: int = add(x, 2) + x * 3;
+            var i: int = 0;
+            while i < 3 {
+                var inner: int = add(i, y);
+                if inner > 5 {
+                    var deep: int = add(inner, x);
+                    y = y + deep - inner;
+                }
+                i = i + 1;
+            }
+            var z: int = add(x, y) + add(y, x);
+            return z - x;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    // y = 3 + 3 = 6, incremented by x in each of the 3 iterations -> 9
+    // z = 10 + 10 = 20
+    assert_eq!(19, result_res.unwrap());
+    assert_eq!(0, engine.get_stack_size());
+}
+
+#[test]
+fn test_engine_interleaved_cont_locals() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: int;
+        }
+
+        fn: make_point(x: int, y: int) ~ Point {
+            return Point {
+                x: x,
+                y: y
+            };
+        }
+
+        fn: double(n: int) ~ int {
+            return n * 2;
+        }
+
+        fn: main() ~ int {
+            var a: int = double(2);
+            var p: Point = make_point(a, double(a));
+            var b: int = double(p.x) + p.y;
+            if b > 0 {
+                var q: Point = make_point(b, a);
+                p.y = q.x + double(q.y);
+            }
+            return p.x + p.y;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    // a = 4, p = (4, 8), b = 16, p.y = 16 + 8 = 24
+    assert_eq!(28, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_runtime_backtrace() {
+    let code = String::from("
+        fn: parse(n: int) ~ int {
+            if n > 100 {
+                return n;
+            }
+        }
+
+        fn: helper(n: int) ~ int {
+            return parse(n);
+        }
+
+        fn: main() ~ int {
+            return helper(1);
+        }
+
+        fn: main_ok() ~ int {
+            return helper(200);
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    match run_res.map_err(|e| *e) {
+        Err(EngineError::CoreError(CoreError::Runtime(err, backtrace))) => {
+            println!("{}", backtrace);
+            match *err {
+                CoreError::NoReturnValue => {},
+                _ => panic!("Expected NoReturnValue error")
+            }
+            assert_eq!(
+                backtrace.frame_names(),
+                vec!["root::parse", "root::helper", "root::main"]
+            );
+            let rendered = backtrace.to_string();
+            assert!(rendered.starts_with("at root::parse (+0x"));
+            assert!(rendered.contains("\n  called from root::helper (+0x"));
+            assert!(rendered.contains("\n  called from root::main (+0x"));
+        },
+        _ => panic!("Expected a runtime error")
+    }
+
+    // The core is usable again after a runtime error
+    let run_res = engine.run_fn("root::main_ok");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 200);
+}
+
+#[test]
+fn test_engine_foreign_backtrace() {
+    let code = String::from("
+        fn: helper() {
+            std::fail();
+        }
+
+        fn: main() {
+            helper();
+        }
+    ");
+
+    let fail_function = Function::new("fail")
+        .with_ret_type(Type::Void)
+        .with_closure(Box::new(|adapter| {
+            adapter.trap(7);
+        }));
+    let module = Module::new("std")
+        .with_function(fail_function);
+
+    let mut engine = Engine::new(1024);
+    let reg_res = engine.register_module(module);
+    assert!(reg_res.is_ok());
+
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    match run_res.map_err(|e| *e) {
+        Err(EngineError::CoreError(CoreError::Runtime(err, backtrace))) => {
+            println!("{}", backtrace);
+            match *err {
+                CoreError::Halted(7) => {},
+                _ => panic!("Expected Halted(7) error")
+            }
+            assert_eq!(backtrace.frames[0], Frame::Foreign(String::from("fail")));
+            assert_eq!(
+                backtrace.frame_names(),
+                vec!["fail", "root::helper", "root::main"]
+            );
+            assert!(backtrace.to_string().starts_with("at <foreign: fail>\n  called from root::helper"));
+        },
+        _ => panic!("Expected a runtime error")
+    }
+}
+
+#[test]
+fn test_engine_nested_control_flow() {
+    let code = String::from("
+        fn: classify(n: int) ~ int {
+            if n < 2 {
+                return 0;
+            } else if n < 4 {
+                if n == 2 {
+                    return 1;
+                } else {
+                    return 2;
+                }
+            } else if n < 6 {
+                return 3;
+            }
+            return 4;
+        }
+
+        fn: main() ~ int {
+            var sum: int = 0;
+            var i: int = 0;
+            while i < 10 {
+                i = i + 1;
+                if i == 3 {
+                    continue;
+                }
+                var j: int = 0;
+                while true {
+                    j = j + 1;
+                    if j > i {
+                        break;
+                    } else if j == 2 {
+                        continue;
+                    } else {
+                        var k: int = 0;
+                        while k < 3 {
+                            k = k + 1;
+                            if k == 2 {
+                                break;
+                            }
+                            sum = sum + classify(j);
+                        }
+                    }
+                }
+                if i == 8 {
+                    break;
+                }
+            }
+            return sum;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    let classify = |n: i64| -> i64 {
+        if n < 2 { 0 } else if n < 4 { if n == 2 { 1 } else { 2 } } else if n < 6 { 3 } else { 4 }
+    };
+    let mut expected = 0;
+    for i in 1..=8 {
+        if i == 3 {
+            continue;
+        }
+        for j in 1..=i {
+            if j != 2 {
+                expected += classify(j);
+            }
+        }
+    }
+    assert_eq!(expected, result_res.unwrap());
+    assert_eq!(0, engine.get_stack_size());
+}
+
+#[test]
+fn test_engine_call_results_in_expr() {
+    let code = String::from("
+        fn: three() ~ int {
+            return 3;
+        }
+
+        fn: four() ~ int {
+            return 4;
+        }
+
+        fn: half() ~ float {
+            return 0.5;
+        }
+
+        fn: main() ~ int {
+            var f: float = half() + half() * 3.0;
+            if f == 2.0 {
+                return three() + four() * three() - four();
+            }
+            return 0;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(11, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_cont_member_layout() {
+    let code = String::from("
+        cont: Mixed {
+            z: int;
+            flag: bool;
+            a: float;
+            count: int;
+        }
+
+        fn: main() ~ int {
+            var m: Mixed = Mixed {
+                z: 5,
+                flag: true,
+                a: 2.5,
+                count: 7
+            };
+            m.count += m.z;
+            if m.flag && m.a == 2.5 {
+                return m.count;
+            }
+            return 0;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(12, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_string_equality() {
+    let code = String::from("
+        fn: main() ~ int {
+            var result: int = 0;
+            if \"hello\" == \"hello\" {
+                result = result + 1;
+            }
+            if \"hello\" == \"world\" {
+                result = result + 10;
+            }
+            if \"hello\" != \"world\" {
+                result = result + 100;
+            }
+            if \"hello\" != \"hello\" {
+                result = result + 1000;
+            }
+            return result;
+        }
+    ");
+
+    for string_repr in [StringRepr::FatPointer, StringRepr::Handle].iter() {
+    let mut engine = Engine::new(1024);
+        assert!(engine.set_string_repr(*string_repr).is_ok());
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+
+        let result_res = engine.get_register_value::<i64>(Register::R0);
+        assert!(result_res.is_ok());
+
+        assert_eq!(101, result_res.unwrap());
+    }
+}
+
+#[test]
+fn test_engine_strings_around_jumps() {
+    let code = String::from("
+        fn: main() {
+            log::push(\"before\");
+            var i: int = 0;
+            while i < 2 {
+                if i == 0 {
+                    log::push(\"first\");
+                } else {
+                    log::push(\"second\");
+                }
+                i = i + 1;
+            }
+            log::push(\"after\");
+        }
+    ");
+
+    for string_repr in [StringRepr::FatPointer, StringRepr::Handle].iter() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+        let fn_log = log.clone();
+        let push_function = Function::new("push")
+            .with_arg(Type::String)
+            .with_ret_type(Type::Void)
+            .with_closure(Box::new(move |adapter: &mut Adapter| {
+                let arg: String = adapter.get_arg(0);
+                fn_log.lock().unwrap().push(arg);
+            }));
+        let module = Module::new("log")
+            .with_function(push_function);
+
+        let mut engine = Engine::new(1024);
+        assert!(engine.register_module(module).is_ok());
+        assert!(engine.set_string_repr(*string_repr).is_ok());
+
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+
+        assert_eq!(*log.lock().unwrap(), vec!["before", "first", "second", "after"]);
+    }
+}
+
+#[test]
+fn test_engine_leading_while_loop() {
+    let code = String::from("
+        fn: countdown(n: int) ~ int {
+            while n > 0 {
+                n = n - 1;
+            }
+            return n + 5;
+        }
+
+        fn: main() ~ int {
+            return countdown(3);
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(5, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_negate() {
+    let code = String::from("
+        fn: negate(x: int) ~ int {
+            return -x;
+        }
+
+        fn: main() ~ int {
+            var f: float = -(1.5 * 2.0);
+            if f == -3.0 {
+                return negate(4) * -2 + -(1 + 2);
+            }
+            return 0;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let result_res = engine.get_register_value::<i64>(Register::R0);
+    assert!(result_res.is_ok());
+
+    assert_eq!(5, result_res.unwrap());
+}
+
+#[test]
+fn test_engine_assert() {
+    let code = String::from("
+        fn: check(x: int) ~ int {
+            assert x > 0;
+            assert x < 10, \"x out of range\";
+            return x * 2;
+        }
+    ");
+
+    for string_repr in [StringRepr::FatPointer, StringRepr::Handle].iter() {
+    let mut engine = Engine::new(1024);
+        assert!(engine.set_string_repr(*string_repr).is_ok());
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+
+        engine.push_stack::<i64>(4).unwrap();
+        let run_res = engine.run_fn("root::check");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 8);
+
+        let expect_assert = |engine: &mut Engine, arg: i64, expected: Option<&str>| {
+            engine.push_stack::<i64>(arg).unwrap();
+            let run_res = engine.run_fn("root::check");
+            println!("{:?}", run_res);
+            match run_res.map_err(|e| *e) {
+                Err(EngineError::CoreError(core_err)) => {
+                    match core_err.root_cause() {
+                        CoreError::AssertionFailed(message) => assert_eq!(message.as_deref(), expected),
+                        _ => panic!("Expected AssertionFailed error")
+                    }
+                },
+                _ => panic!("Expected AssertionFailed error")
+            };
+        };
+        expect_assert(&mut engine, 12, Some("x out of range"));
+        expect_assert(&mut engine, -1, None);
+    }
+}
+
+#[test]
+fn test_engine_typeof() {
+    let code = String::from("
+        fn: side_effect() ~ int {
+            log::push(\"called\");
+            return 1;
+        }
+
+        fn: main() {
+            var x: int = 1;
+            log::push(typeof(42));
+            log::push(typeof(side_effect() > 0));
+            log::push(typeof(&x));
+            log::push(typeof(-1.5));
+        }
+    ");
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let fn_log = log.clone();
+    let push_function = Function::new("push")
+        .with_arg(Type::String)
+        .with_ret_type(Type::Void)
+        .with_closure(Box::new(move |adapter: &mut Adapter| {
+            let arg: String = adapter.get_arg(0);
+            fn_log.lock().unwrap().push(arg);
+        }));
+    let module = Module::new("log")
+        .with_function(push_function);
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(module).is_ok());
+
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    // The operand of typeof is never evaluated
+    assert_eq!(*log.lock().unwrap(), vec!["int", "bool", "&int", "float"]);
+}
+
+#[test]
+fn test_engine_opcode_stats() {
+    let code = String::from("
+        fn: main() ~ int {
+            var i: int = 0;
+            while i < 10 {
+                i = i + 1;
+            }
+            return i;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    // Nothing is counted unless stats are enabled
+    assert!(engine.run_fn("root::main").is_ok());
+    assert!(engine.opcode_stats().is_empty());
+
+    engine.set_opcode_stats(true);
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    let stats = engine.opcode_stats();
+    println!("{:?}", stats);
+    assert!(stats.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    let count_of = |opcode: Opcode| stats.iter()
+        .find(|(stat_opcode, _)| *stat_opcode == opcode)
+        .map(|(_, count)| *count);
+    // One addition per iteration
+    assert_eq!(count_of(Opcode::ADDI), Some(10));
+    // The condition is checked once more than the loop runs
+    assert_eq!(count_of(Opcode::LTI), Some(11));
+    assert_eq!(count_of(Opcode::RET), Some(1));
+
+    engine.reset_opcode_stats();
+    assert!(engine.opcode_stats().is_empty());
+}
+
+#[test]
+fn test_engine_foreign_override() {
+    let code = String::from("
+        fn: main() ~ int {
+            return host::value(2);
+        }
+    ");
+
+    let value_function = Function::new("value")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(|adapter: &mut Adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            adapter.return_value(arg * 10);
+        }));
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(Module::new("host").with_function(value_function)).is_ok());
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 20);
+
+    // Swapped without recompiling
+    let override_res = engine.set_foreign_override("root::host::value", Box::new(|adapter: &mut Adapter| {
+        let arg: i64 = adapter.get_arg(0);
+        adapter.return_value(arg * 100);
+    }));
+    assert!(override_res.is_ok());
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 200);
+
+    assert_eq!(engine.remove_foreign_override("root::host::value").unwrap(), true);
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 20);
+
+    assert!(engine.set_foreign_override("root::host::missing", Box::new(|_: &mut Adapter| {})).is_err());
+}
+
+#[test]
+fn test_engine_compound_lvalues() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: int;
+        }
+
+        fn: main(arr: [int; 4]) ~ int {
+            var x: int = 1;
+            var p: &int = &x;
+            ~p = 5;
+
+            var pt: Point = Point { x: 0, y: 0 };
+            pt.y = 7;
+
+            arr[2] = x + pt.y;
+            arr[3] += arr[0];
+
+            var r: &[int; 4] = &arr;
+            r[1] = 9;
+
+            return arr[2] * 100 + arr[3] * 10 + arr[1];
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    for i in 1..=4 {
+        engine.push_stack::<i64>(i).unwrap();
+    }
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 1259);
+}
+
+#[test]
+fn test_engine_foreign_suspend_resume() {
+    let code = String::from("
+        fn: main() ~ int {
+            var a: int = host::fetch(2);
+            var b: int = a + 1;
+            return b * host::fetch(3);
+        }
+    ");
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let fetch_requests = requests.clone();
+    let fetch_function = Function::new("fetch")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(move |adapter: &mut Adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            fetch_requests.lock().unwrap().push(arg);
+            adapter.set_result(ForeignResult::Pending(arg as u64));
+        }));
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(Module::new("host").with_function(fetch_function)).is_ok());
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    assert_eq!(engine.run_fn("root::main").unwrap(), VmExit::AwaitingForeign(2));
+    // Nothing else may run while suspended, and only the awaited call can be resumed
+    assert!(engine.run_fn("root::main").is_err());
+    assert!(engine.resume_with::<i64>(3, 0).is_err());
+
+    // Work done on the Rust side before the value is delivered
+    let value = requests.lock().unwrap().iter().sum::<i64>() * 20;
+    assert_eq!(engine.resume_with::<i64>(2, value).unwrap(), VmExit::AwaitingForeign(3));
+    assert_eq!(engine.resume_with::<i64>(3, 5).unwrap(), VmExit::Finished);
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 205);
+    assert_eq!(*requests.lock().unwrap(), vec![2, 3]);
+}
+
+#[test]
+fn test_engine_yield() {
+    let code = String::from("
+        fn: step(n: int) {
+            yield n;
+        }
+
+        fn: main() ~ int {
+            var i: int = 0;
+            yield;
+            while i < 5 {
+                i += 1;
+                step(i);
+            }
+            return i * 10;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let stack_size = engine.get_stack_size();
+    assert_eq!(engine.run_fn("root::main").unwrap(), VmExit::Yielded(None));
+    assert!(engine.run_fn("root::main").is_err());
+
+    for i in 1..=5 {
+        assert_eq!(engine.resume().unwrap(), VmExit::Yielded(Some(Value::Int(i))));
+    }
+    assert_eq!(engine.resume().unwrap(), VmExit::Finished);
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 50);
+    assert_eq!(engine.get_stack_size(), stack_size);
+    assert!(engine.resume().is_err());
+
+    // The core can run again once the coroutine finished
+    assert_eq!(engine.run_fn("root::main").unwrap(), VmExit::Yielded(None));
+}
+
+#[test]
+fn test_engine_reload_fn() {
+    let code = String::from("
+        fn: one() ~ int {
+            return 1;
+        }
+
+        fn: main() ~ int {
+            yield;
+            return one();
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    assert_eq!(engine.run_fn("root::main").unwrap(), VmExit::Yielded(None));
+
+    // The suspended main keeps running its code, but calls the new one()
+    let reload_res = engine.reload_code("fn: one() ~ int { return 2; }");
+    println!("{:?}", reload_res);
+    assert!(reload_res.is_ok());
+    assert_eq!(engine.resume().unwrap(), VmExit::Finished);
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 2);
+
+    // Reloaded functions get their own data
+    let reload_res = engine.reload_code("
+        fn: main() ~ int {
+            if \"hot\" == \"hot\" {
+                if \"hot\" != \"cold\" {
+                    return one() * 10;
+                }
+            }
+            return 0;
+        }
+    ");
+    println!("{:?}", reload_res);
+    assert!(reload_res.is_ok());
+    assert_eq!(engine.run_fn("root::main").unwrap(), VmExit::Finished);
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 20);
+
+    let changed_signatures = [
+        "fn: one(x: int) ~ int { return x; }",
+        "fn: one() ~ float { return 2.0; }"
+    ];
+    for changed in changed_signatures.iter() {
+        match engine.reload_code(changed).map_err(|err| *err) {
+            Err(EngineError::CompileError(CompilerError::SignatureChanged(name))) => {
+                assert_eq!(name, "root::one");
+            },
+            res => panic!("Expected a changed signature error, got {:?}", res)
+        }
+    }
+
+    // Rejected reloads leave the program as it was
+    assert_eq!(engine.run_fn("root::main").unwrap(), VmExit::Finished);
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 20);
+}
+
+#[test]
+fn test_engine_while_call_condition() {
+    let code = String::from("
+        fn: below(i: int, limit: int) ~ bool {
+            return i < limit;
+        }
+
+        fn: main() ~ int {
+            var sum: int = 0;
+            var i: int = 0;
+            var step: int = 3;
+            while below(i, 5) {
+                var doubled: int = step * 2;
+                sum = sum + doubled + i;
+                i = i + 1;
+                if below(i, 3) {
+                    continue;
+                }
+                step = step + 1;
+            }
+            return sum * 100 + i * 10 + step;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+
+    // step is 3 for i in 0..3, then 4 and 5, doubled: 6 + 6 + 6 + 8 + 10 = 36, plus i: 10
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 46 * 100 + 5 * 10 + 6);
+    assert_eq!(engine.get_stack_size(), stack_size);
+
+    // The condition itself advances the loop variable
+    let code = String::from("
+        fn: advance(counter: &int) ~ bool {
+            ~counter = (~counter) + 1;
+            return ~counter <= 3;
+        }
+
+        fn: main() ~ int {
+            var i: int = 0;
+            var total: int = 0;
+            while advance(&i) {
+                var weight: int = 10;
+                total = total + i * weight;
+            }
+            return total + i;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 60 + 4);
+    assert_eq!(engine.get_stack_size(), stack_size);
+}
+
+#[test]
+fn test_engine_interrupt() {
+    let code = String::from("
+        fn: spin() {
+            while true {}
+        }
+
+        fn: answer() ~ int {
+            return 42;
+        }
+    ");
+
+    let (handle_tx, handle_rx) = mpsc::channel();
+    let (exit_tx, exit_rx) = mpsc::channel();
+    let runner = thread::spawn(move || {
+        let mut engine = Engine::new(1024);
+        engine.load_code(&code).unwrap();
+        handle_tx.send(engine.interrupt_handle()).unwrap();
+
+        let stack_size = engine.get_stack_size();
+        let exit = engine.run_fn("root::spin").map_err(|err| err.to_string());
+        exit_tx.send(exit).unwrap();
+        assert!(engine.run_fn("root::answer").is_err());
+
+        // The interrupted run can be continued, until it is interrupted again
+        let exit = engine.resume().map_err(|err| err.to_string());
+        exit_tx.send(exit).unwrap();
+
+        engine.reset();
+        assert_eq!(engine.get_stack_size(), stack_size);
+        let exit = engine.run_fn("root::answer").map_err(|err| err.to_string());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 42);
+        exit_tx.send(exit).unwrap();
+    });
+
+    let interrupt = handle_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    for _ in 0..2 {
+        thread::sleep(Duration::from_millis(10));
+        interrupt.interrupt();
+        match exit_rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Ok(VmExit::Interrupted { .. }) => {},
+            exit => panic!("Expected an interrupted run, got {:?}", exit)
+        }
+        assert!(!interrupt.is_interrupted());
+    }
+    assert_eq!(exit_rx.recv_timeout(Duration::from_secs(5)).unwrap(), Ok(VmExit::Finished));
+    runner.join().unwrap();
+}
+
+#[test]
+fn test_engine_operator_precedence() {
+    let code = String::from("
+        fn: main() ~ int {
+            var x: int = 2;
+            var p: &int = &x;
+            var either: bool = 1 < 2 || 2 < 1 && false;
+            if either {
+                return 5 * 3 / 2 * 10 + ~p + 1;
+            }
+            return 0;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    // (5 * 3) / 2 truncates to 7
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 73);
+}
+
+#[test]
+fn test_engine_record_replay() {
+    let code = String::from("
+        fn: roll() ~ int {
+            return random::next();
+        }
+
+        fn: main() ~ int {
+            var a: int = random::next();
+            var b: int = roll();
+            return a * 1000 + b;
+        }
+    ");
+
+    let next_function = Function::new("next")
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(|adapter: &mut Adapter| {
+            adapter.return_value((rand::random::<u32>() % 1000) as i64);
+        }));
+    let random_module = Module::new("random")
+        .with_function(next_function);
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(random_module).is_ok());
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    engine.record_trace();
+    assert!(engine.run_fn("root::main").is_ok());
+    let recorded = engine.get_register_value::<i64>(Register::R0).unwrap();
+    let trace = engine.take_trace().unwrap();
+    assert_eq!(trace.entries.len(), 2);
+    assert_eq!(recorded, trace.entries[0].ret_value as i64 * 1000 + trace.entries[1].ret_value as i64);
+
+    let bytes = trace.to_bytes();
+    let replayed_trace = Trace::from_bytes(&bytes).unwrap();
+    assert_eq!(replayed_trace, trace);
+
+    // Replays never call the foreign function
+    let overridden = engine.set_foreign_override("root::random::next", Box::new(|_: &mut Adapter| {
+        panic!("Replays must not call foreign functions");
+    }));
+    assert!(overridden.is_ok());
+
+    let stack_size = engine.get_stack_size();
+    engine.replay_trace(replayed_trace);
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), recorded);
+    assert_eq!(engine.get_stack_size(), stack_size);
+    assert_eq!(engine.take_trace(), Some(trace.clone()));
+
+    // A run with more foreign calls than the trace diverges from it
+    let short_trace = Trace::new()
+        .with_entry(trace.entries[0]);
+    engine.replay_trace(short_trace);
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    match run_res.map_err(|e| *e) {
+        Err(EngineError::CoreError(core_err)) => {
+            match core_err.root_cause() {
+                CoreError::TraceDiverged(index, fn_name) => {
+                    assert_eq!(*index, 1);
+                    assert_eq!(fn_name, "root::random::next");
+                },
+                err => panic!("Expected TraceDiverged error, got {:?}", err)
+            }
+        },
+        res => panic!("Expected TraceDiverged error, got {:?}", res)
+    }
+
+    assert!(Trace::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+}
+
+#[test]
+fn test_engine_raw_bytecode() {
+    let code = String::from("
+        fn: answer() {
+            raw_bc!(0x16, 42, 0, 0, 0, 0, 0, 0, 0, 0x00);
+        }
+
+        fn: main() ~ int {
+            raw_bc!(0x01, 7);
+            return 0;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    // LDI 42 -> r0
+    let stack_size = engine.get_stack_size();
+    assert!(engine.run_fn("root::answer").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 42);
+    assert_eq!(engine.get_stack_size(), stack_size);
+
+    // HALT 7
+    let run_res = engine.run_fn("root::main");
+    match run_res.map_err(|e| *e) {
+        Err(EngineError::CoreError(core_err)) => {
+            match core_err.root_cause() {
+                CoreError::Halted(7) => {},
+                err => panic!("Expected Halted(7) error, got {:?}", err)
+            }
+        },
+        res => panic!("Expected Halted(7) error, got {:?}", res)
+    }
+
+    // An incomplete instruction is rejected when compiling
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code("fn: main() { raw_bc!(0x16, 42); }");
+    println!("{:?}", load_res);
+    assert!(load_res.is_err());
+}
+
+#[test]
+fn test_engine_empty_container() {
+    let code = String::from("
+        cont: Empty {}
+
+        cont: Tagged {
+            tag: Empty;
+            x: int;
+        }
+
+        fn: take(e: Empty, t: Tagged) ~ int {
+            return t.x + 1;
+        }
+
+        fn: main() ~ int {
+            var e: Empty = Empty {};
+            var t: Tagged = Tagged { tag: Empty {}, x: 41 };
+            return take(e, t) + sizeof(Empty) + sizeof(Tagged) - sizeof(int);
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 42);
+    assert_eq!(engine.get_stack_size(), stack_size);
+}
+
+#[test]
+fn test_engine_constant_if() {
+    let code = String::from("
+        fn: main() ~ int {
+            var x: int = 1;
+            if 2 > 1 {
+                var a: int = 40;
+                x += a;
+            } else {
+                var b: int = 1000;
+                x += b;
+            }
+            if false {
+                x = 0;
+            } else if x == 41 {
+                var c: int = 1;
+                x += c;
+            }
+            var d: int = 0;
+            return x + d;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 42);
+    assert_eq!(engine.get_stack_size(), stack_size);
+}
+
+#[test]
+fn test_engine_local_import() {
+    let code = String::from("
+        fn: main() ~ int {
+            import math::double = twice;
+            var x = twice(5);
+            if x == 10 {
+                import: math::{double};
+                x = double(x);
+            }
+            return x;
+        }
+
+        fn: other() ~ int {
+            return twice(1);
+        }
+    ");
+    let double_function = || Function::new("double")
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(|adapter: &mut Adapter| {
+            let arg: i64 = adapter.get_arg(0);
+            adapter.return_value(arg * 2);
+        }));
+
+    // Local imports are not visible outside of their function
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(Module::new("math").with_function(double_function())).is_ok());
+    match engine.load_code(&code).map_err(|err| *err) {
+        Err(EngineError::CompileError(CompilerError::UnknownFunction(name, _))) => assert_eq!(name, "twice"),
+        res => panic!("Expected an unknown function error, got {:?}", res)
+    }
+
+    let code = code.replace("return twice(1);", "import math::double = twice; return twice(1);");
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(Module::new("math").with_function(double_function())).is_ok());
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 20);
+    assert!(engine.run_fn("root::other").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 2);
+}
+
+#[test]
+fn test_engine_assign_call_index() {
+    let code = String::from("
+        fn: compute_index(n: int) ~ int {
+            var doubled: int = n * 2;
+            var shifted: int = doubled - 1;
+            return shifted;
+        }
+
+        fn: compute_value(base: int) ~ int {
+            var scaled: int = base * 10;
+            return scaled + compute_index(base);
+        }
+
+        fn: main(arr: [int; 4]) ~ int {
+            var marker: int = 7;
+            arr[compute_index(2)] = compute_value(5);
+            arr[compute_index(1)] += compute_value(1);
+            return arr[0] * 1000000 + arr[1] * 10000 + arr[2] * 1000 + arr[3] * 10 + marker;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    for i in 1..=4 {
+        engine.push_stack::<i64>(i).unwrap();
+    }
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    // arr[3] = 5 * 10 + 9, arr[1] = 2 + 1 * 10 + 1, the others and the local stay untouched
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 1_000_000 + 13 * 10_000 + 3 * 1000 + 59 * 10 + 7);
+    assert_eq!(engine.get_stack_size(), stack_size);
+}
+
+#[test]
+fn test_engine_container_copies() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: int;
+        }
+
+        cont: Line {
+            p: Point;
+            q: Point;
+            w: int;
+        }
+
+        fn: make_line(w: int) ~ Line {
+            return Line { p: Point { x: 1, y: 2 }, q: Point { x: w, y: w }, w: w };
+        }
+
+        fn: main() ~ int {
+            var a: Point = Point { x: 1, y: 2 };
+            var b: Point = Point { x: 0, y: 0 };
+            b = a;
+            a.x = 9;
+
+            var l: Line = Line { p: Point { x: 3, y: 4 }, q: Point { x: 5, y: 6 }, w: 7 };
+            l.p = l.q;
+            l.q.x = 9;
+
+            var m: Line = make_line(0);
+            m = l;
+            l.p.y = 9;
+            l.w = 9;
+
+            var c: Point = l.q;
+            l.q.y = 0;
+
+            var r: &Point = &a;
+            ~r = b;
+            b.y = 9;
+
+            return b.x * 100000000 + l.p.x * 10000000 + m.p.y * 1000000 + m.w * 100000
+                + c.x * 10000 + c.y * 1000 + a.y * 100 + make_line(3).q.y * 10 + m.q.x;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+    let stack_size = engine.get_stack_size();
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_stack_size(), stack_size);
+    // Each copy keeps the values at the time of the assignment
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 156_796_239);
+}
+
+#[test]
+fn test_engine_string_slices() {
+    let code = String::from("
+        fn: main() {
+            host::inspect(\"hello world\"[2..5]);
+            host::inspect(\"hello world\"[6..11][1..3]);
+            host::inspect(\"hello world\"[4..4]);
+            if \"hello world\"[6..11] == \"world\" {
+                host::inspect(\"equal\");
+            }
+        }
+
+        fn: slice(start: int, end: int) {
+            host::inspect(\"hello\"[start..end]);
+        }
+    ");
+
+    // Slices of handles are interned, so they compare equal to literals
+    for string_repr in [StringRepr::FatPointer, StringRepr::Handle].iter() {
+        let inspected = Arc::new(Mutex::new(Vec::new()));
+        let inspect_strings = inspected.clone();
+        let inspect_function = Function::new("inspect")
+            .with_arg(Type::String)
+            .with_closure(Box::new(move |adapter: &mut Adapter| {
+                let string: String = adapter.get_arg(0);
+                inspect_strings.lock().unwrap().push(string);
+            }));
+
+        let mut engine = Engine::new(1024);
+        assert!(engine.register_module(Module::new("host").with_function(inspect_function)).is_ok());
+        assert!(engine.set_string_repr(*string_repr).is_ok());
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+        let stack_size = engine.get_stack_size();
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        assert_eq!(engine.get_stack_size(), stack_size);
+        assert_eq!(*inspected.lock().unwrap(), vec!["llo", "or", "", "equal"]);
+
+        // Bounds are checked at runtime
+        for (start, end, is_ok) in [(0, 5, true), (-1, 2, false), (3, 2, false), (2, 6, false)] {
+            engine.reset();
+            engine.push_stack::<i64>(start).unwrap();
+            engine.push_stack::<i64>(end).unwrap();
+            let run_res = engine.run_fn("root::slice");
+            if is_ok {
+                assert!(run_res.is_ok());
+                continue;
+            }
+            match run_res.map_err(|e| *e) {
+                Err(EngineError::CoreError(core_err)) => {
+                    assert!(matches!(core_err.root_cause(), CoreError::SliceOutOfBounds));
+                },
+                res => panic!("Expected a slice out of bounds, got {:?}", res)
+            }
+        }
+        assert_eq!(inspected.lock().unwrap().last(), Some(&String::from("hello")));
+    }
+}
+
+#[test]
+fn test_engine_include_files() {
+    let fixtures_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    let code = String::from("
+        fn: main() ~ int {
+            var table = include_bytes(\"table.bin\");
+            var banner_len: int = host::inspect(include_str(\"banner.txt\"));
+            host::inspect(typeof(table));
+            return table[0] * 1000000 + table[1] * 100000 + table[7] * 100 + banner_len;
+        }
+    ");
+
+    let inspected = Arc::new(Mutex::new(Vec::new()));
+    let inspect_strings = inspected.clone();
+    let inspect_function = Function::new("inspect")
+        .with_arg(Type::String)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(move |adapter: &mut Adapter| {
+            let string: String = adapter.get_arg(0);
+            adapter.return_value(string.len() as i64);
+            inspect_strings.lock().unwrap().push(string);
+        }));
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(Module::new("host").with_function(inspect_function)).is_ok());
+    engine.set_include_root(fixtures_dir);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 3_000_000 + 100_000 + 25500 + 11);
+    assert_eq!(*inspected.lock().unwrap(), vec![String::from("Hello, pgs!"), String::from("&int[8]")]);
+
+    let load_include = |path: &str, include_root: Option<&str>| {
+        let code = format!("fn: main() {{ var s = include_str(\"{}\"); }}", path);
+        let mut engine = Engine::new(1024);
+        if let Some(include_root) = include_root {
+            engine.set_include_root(include_root);
+        }
+        engine.load_code(&code).map_err(|err| *err)
+    };
+    // The span covers the include
+    match load_include("missing.txt", Some(fixtures_dir)) {
+        Err(EngineError::CompileError(CompilerError::IncludeFailed { path, span, .. })) => {
+            assert_eq!(path, "missing.txt");
+            assert_eq!(span, 21..47);
+        },
+        res => panic!("Expected a failed include, got {:?}", res)
+    }
+    match load_include("../engine.rs", Some(fixtures_dir)) {
+        Err(EngineError::CompileError(err @ CompilerError::IncludeFailed { .. })) => {
+            assert!(err.to_string().contains("outside of the include root"), "{}", err);
+        },
+        res => panic!("Expected a failed include, got {:?}", res)
+    }
+    match load_include("banner.txt", None) {
+        Err(EngineError::CompileError(CompilerError::IncludeFailed { reason, .. })) => {
+            assert_eq!(reason, "no include root is set");
+        },
+        res => panic!("Expected a failed include, got {:?}", res)
+    }
+    assert!(load_include("table.bin", Some(fixtures_dir)).is_err());
+    assert!(load_include("./banner.txt", Some(fixtures_dir)).is_ok());
+}
+
+#[test]
+fn test_engine_nested_calls() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: int;
+        }
+
+        impl: Point {
+            fn: sum(&this) ~ int {
+                return this.x * 10 + this.y;
+            }
+        }
+
+        fn: inner(a: int) ~ int {
+            var local: int = a * 3;
+            return local + 1;
+        }
+
+        fn: outer(a: int, b: int) ~ int {
+            return a * 100 + b;
+        }
+
+        fn: make_point(x: int, y: int) ~ Point {
+            return Point { x: x, y: y };
+        }
+
+        fn: main() ~ int {
+            var before: int = 5;
+            var nested: int = outer(inner(1), inner(2));
+            var deep: int = outer(outer(inner(0), 2), inner(outer(0, 1)));
+            var chained: int = make_point(inner(1), 2).sum();
+            return nested * 1000000 + deep * 100 + chained + before * 0;
+        }
+    ");
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    // nested = 4 * 100 + 7, deep = (1 * 100 + 2) * 100 + 4, chained = 4 * 10 + 2
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 407 * 1000000 + 10204 * 100 + 42);
+    assert_eq!(engine.get_stack_size(), stack_size);
+}
+
+#[test]
+fn test_engine_memory_checks_clean_run() {
+    let code = String::from("
+        cont: Point {
+            x: int;
+            y: int;
+        }
+
+        impl: Point {
+            fn: sum(&this) ~ int {
+                return this.x * 10 + this.y;
+            }
+        }
+
+        fn: make_point(x: int, y: int) ~ Point {
+            return Point { x: x, y: y };
+        }
+
+        fn: shift(p: Point, d: int) ~ Point {
+            return Point { x: p.x + d, y: p.y + d };
+        }
+
+        fn: total(arr: &[int; 4], scale: int) ~ int {
+            var sum = 0;
+            var i = 0;
+            while i < 4 {
+                if arr[i] < 0 {
+                    return 0 - 1;
+                }
+                sum += arr[i] * scale;
+                i += 1;
+            }
+            return sum;
+        }
+
+        fn: fib(n: int) ~ int {
+            if n < 2 {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+
+        fn: main() ~ int {
+            log::push(\"start\");
+            var arr: [int; 4] = [1; 4];
+            arr[1] = 2;
+            arr[2] = 3;
+            arr[3] = 4;
+            var p: Point = shift(make_point(1, 2), 3);
+            var summed = total(&arr, 2) + p.sum();
+            if \"a\" != \"b\" {
+                log::push(\"end\");
+            }
+            return summed * 100 + fib(10);
+        }
+    ");
+
+    // A regular program runs the same with memory checks, without violations
+    for memory_checks in [false, true] {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let fn_log = log.clone();
+        let push_function = Function::new("push")
+            .with_arg(Type::String)
+            .with_closure(Box::new(move |adapter: &mut Adapter| {
+                let arg: String = adapter.get_arg(0);
+                fn_log.lock().unwrap().push(arg);
+            }));
+
+        let mut engine = Engine::new(4096);
+        assert!(engine.register_module(Module::new("log").with_function(push_function)).is_ok());
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+        engine.set_memory_checks(memory_checks);
+
+        let stack_size = engine.get_stack_size();
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        // total = 20, p = (4, 5)
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), (20 + 45) * 100 + 55);
+        assert_eq!(engine.get_stack_size(), stack_size);
+        assert_eq!(*log.lock().unwrap(), vec!["start", "end"]);
+    }
+}
+
+#[test]
+fn test_engine_jump_table() {
+    let code = String::from("
+        fn: classify(v: int) ~ int {
+            if v == 1 {
+                return 10;
+            } else if v == 2 {
+                return 20;
+            } else if 3 == v {
+                return 30;
+            } else if v == 5 {
+                return 50;
+            } else if v == 6 {
+                return 60;
+            } else if v == 7 {
+                return 70;
+            } else {
+                return 1;
+            }
+            return 0;
+        }
+
+        fn: classify_no_else(v: int) ~ int {
+            var r: int = 2;
+            if v == 4 {
+                r = 40;
+            } else if v == 5 {
+                r = 50;
+            } else if v == 6 {
+                r = 60;
+            } else if v == 7 {
+                r = 70;
+            }
+            return r;
+        }
+
+        fn: classify_sparse(v: int) ~ int {
+            var r: int = 3;
+            if v == 1 {
+                r = 10;
+            } else if v == 100 {
+                r = 20;
+            } else if v == 1000 {
+                r = 30;
+            } else if v == 10000 {
+                r = 40;
+            }
+            return r;
+        }
+
+        fn: main() ~ int {
+            var total: int = 0;
+            var i: int = 0 - 2;
+            while i < 10 {
+                total = total + classify(i) * (i + 3) + classify_no_else(i) * 1000 * (i + 3);
+                i = i + 1;
+            }
+            return total;
+        }
+
+        fn: main_sparse() ~ int {
+            return classify_sparse(100) * 100 + classify_sparse(2);
+        }
+    ");
+
+    let classify = |v: i64| match v {
+        1 => 10, 2 => 20, 3 => 30, 5 => 50, 6 => 60, 7 => 70,
+        _ => 1
+    };
+    let classify_no_else = |v: i64| match v {
+        4 => 40, 5 => 50, 6 => 60, 7 => 70,
+        _ => 2
+    };
+    let expected: i64 = (-2..10)
+        .map(|i| classify(i) * (i + 3) + classify_no_else(i) * 1000 * (i + 3))
+        .sum();
+
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+    let stack_size = engine.get_stack_size();
+    engine.set_opcode_stats(true);
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), expected);
+    assert_eq!(engine.get_stack_size(), stack_size);
+
+    let count_of = |stats: &[(Opcode, u64)], opcode: Opcode| stats.iter()
+        .find(|(stat_opcode, _)| *stat_opcode == opcode)
+        .map(|(_, count)| *count);
+    // Values outside of the tables never reach the jump
+    let in_tables = (-2..10).filter(|i| (1..=7).contains(i)).count() + (-2..10).filter(|i| (4..=7).contains(i)).count();
+    assert_eq!(count_of(&engine.opcode_stats(), Opcode::JMP_TBL), Some(in_tables as u64));
+
+    // Sparse values are compared one after another
+    engine.reset_opcode_stats();
+    assert!(engine.run_fn("root::main_sparse").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 2003);
+    assert_eq!(count_of(&engine.opcode_stats(), Opcode::JMP_TBL), None);
+
+    // Extreme values take the default path without overflowing
+    for (fn_name, default) in [("root::classify", 1), ("root::classify_no_else", 2)] {
+        for v in [i64::MIN, i64::MAX] {
+            engine.push_stack::<i64>(v).unwrap();
+            let run_res = engine.run_fn(fn_name);
+            println!("{:?}", run_res);
+            assert!(run_res.is_ok());
+            assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), default);
+            assert!(engine.pop_stack::<i64>().is_ok());
+        }
+    }
+}
+
+#[test]
+fn test_engine_mapped_buffers() {
+    let code = String::from("
+        fn: fill() {
+            var pixels = host::pixels();
+            var i = 0;
+            while i < 4 {
+                pixels[i] = i * 3 + 1;
+                i = i + 1;
+            }
+        }
+
+        fn: sum() ~ int {
+            var pixels = host::pixels();
+            return pixels[0] + pixels[1] + pixels[2] + pixels[3];
+        }
+
+        fn: overrun() {
+            var pixels = host::pixels();
+            var i = 4;
+            pixels[i] = 1;
+        }
+    ");
+
+    // The script sees the bytes of the buffer as 4 ints
+    let pixels_function = Function::new("pixels")
+        .with_ret_type(Type::Reference(Box::new(Type::Array(Box::new(Type::Int), 4))))
+        .with_closure(Box::new(|adapter: &mut Adapter| {
+            let (address, _) = adapter.get_buffer("pixels").unwrap_or((0, 0));
+            adapter.return_value(address);
+        }));
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(Module::new("host").with_function(pixels_function)).is_ok());
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let handle = engine.map_buffer("pixels", vec![0u8; 32]);
+    assert_eq!(handle.size(), 32);
+    {
+        let run_res = engine.run_fn("root::fill");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+
+        // Accesses stay inside of the buffer, with or without memory checks
+        for memory_checks in [false, true] {
+            engine.reset();
+            engine.set_memory_checks(memory_checks);
+            match engine.run_fn("root::overrun").map_err(|e| *e) {
+                Err(EngineError::CoreError(core_err)) => {
+                    assert!(matches!(core_err.root_cause(), CoreError::BufferOutOfBounds(_, 8)));
+                },
+                res => panic!("Expected a buffer out of bounds, got {:?}", res)
+            }
+        }
+        engine.set_memory_checks(false);
+    }
+    // The host writes to the buffer while it is mapped
+    handle.with_bytes(|bytes| bytes[8] = 5);
+    engine.reset();
+    assert!(engine.run_fn("root::sum").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 23);
+
+    let mut buffer = handle.unmap();
+    let ints: Vec<i64> = buffer.chunks(8)
+        .map(|chunk| i64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    assert_eq!(ints, vec![1, 5, 7, 10]);
+
+    // A buffer mapped again under the name replaces the old one
+    buffer[0] = 5;
+    {
+        let _handle = engine.map_buffer("pixels", vec![0u8; 32]);
+        let _handle = engine.map_buffer("pixels", buffer);
+        engine.reset();
+        assert!(engine.run_fn("root::sum").is_ok());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 27);
+    }
+
+    // Scripts holding the address of a dropped mapping trap instead of reading freed memory
+    let handle = engine.map_buffer("other", vec![0u8; 32]);
+    let stale_address = handle.address();
+    drop(handle);
+    let override_res = engine.set_foreign_override("root::host::pixels", Box::new(move |adapter: &mut Adapter| {
+        adapter.return_value(stale_address);
+    }));
+    assert!(override_res.is_ok());
+    engine.reset();
+    match engine.run_fn("root::sum").map_err(|e| *e) {
+        Err(EngineError::CoreError(core_err)) => {
+            assert!(matches!(core_err.root_cause(), CoreError::UnmappedBuffer(address) if *address == stale_address));
+        },
+        res => panic!("Expected an unmapped buffer, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_engine_script_callbacks() {
+    let code = String::from("
+        fn: square(x: int) ~ int {
+            return x * x;
+        }
+
+        fn: main() ~ int {
+            var a = 3;
+            var b = host::apply(0, 4);
+            return a * 100 + b + host::apply(0, a);
+        }
+
+        fn: reenter(x: int) ~ int {
+            if x > 0 {
+                return host::apply(1, x - 1);
+            }
+            return 0;
+        }
+
+        fn: suspend(x: int) ~ int {
+            yield;
+            return x;
+        }
+
+        fn: apply_suspend() ~ int {
+            return host::apply(2, 1);
+        }
+    ");
+
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let apply_errors = errors.clone();
+    let callbacks: Arc<Mutex<Vec<u64>>> = Arc::new(Mutex::new(Vec::new()));
+    let apply_callbacks = callbacks.clone();
+    // Calls the script function with the index, returns its result plus one
+    let apply_function = Function::new("apply")
+        .with_arg(Type::Int)
+        .with_arg(Type::Int)
+        .with_ret_type(Type::Int)
+        .with_closure(Box::new(move |adapter: &mut Adapter| {
+            let index: i64 = adapter.get_arg(0);
+            let uid = apply_callbacks.lock().unwrap()[index as usize];
+            let x: i64 = adapter.get_arg(1);
+            adapter.core.push_stack(x).unwrap();
+            let call_res = adapter.call_fn(uid);
+            adapter.core.pop_stack::<i64>().unwrap();
+            match call_res {
+                Ok(_) => {
+                    let ret: i64 = adapter.core.reg(Register::R0.into()).unwrap().get();
+                    adapter.return_value(ret + 1);
+                },
+                Err(err) => {
+                    apply_errors.lock().unwrap().push(err);
+                    adapter.trap(7);
+                }
+            };
+        }));
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(Module::new("host").with_function(apply_function)).is_ok());
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+    for name in ["root::square", "root::reenter", "root::suspend"] {
+        let uid = engine.compiler.get_function_uid(&String::from(name)).unwrap();
+        callbacks.lock().unwrap().push(uid);
+    }
+
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 300 + 17 + 10);
+    assert_eq!(engine.get_stack_size(), stack_size);
+
+    // The script function called by a foreign function may call it again
+    engine.reset();
+    engine.push_stack::<i64>(3).unwrap();
+    let run_res = engine.run_fn("root::reenter");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 3);
+    assert!(engine.pop_stack::<i64>().is_ok());
+    assert_eq!(engine.get_stack_size(), stack_size);
+    assert!(errors.lock().unwrap().is_empty());
+
+    // Nor can it suspend the core
+    engine.reset();
+    match engine.run_fn("root::apply_suspend").map_err(|e| *e) {
+        Err(EngineError::CoreError(core_err)) => assert!(matches!(core_err.root_cause(), CoreError::Halted(7))),
+        res => panic!("Expected a trap, got {:?}", res)
+    }
+    assert!(matches!(errors.lock().unwrap().pop(), Some(CoreError::CallbackSuspended)));
+
+    engine.reset();
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 327);
+}
+
+#[test]
+fn test_engine_registered_callbacks() {
+    let code = String::from("
+        fn: main() ~ int {
+            var scaled = host::scale(4, 2.5);
+            if host::is_even(scaled) {
+                return scaled + 1;
+            }
+            return scaled;
+        }
+
+        fn: notify_twice() {
+            host::notify(3);
+            host::notify(4);
+        }
+    ");
+
+    // Callback functions are declared like foreign functions, but without a closure
+    let host_module = Module::new("host")
+        .with_function(Function::new("scale")
+            .with_arg(Type::Int)
+            .with_arg(Type::Float)
+            .with_ret_type(Type::Int)
+            .as_callback())
+        .with_function(Function::new("is_even")
+            .with_arg(Type::Int)
+            .with_ret_type(Type::Bool)
+            .as_callback())
+        .with_function(Function::new("notify")
+            .with_arg(Type::Int)
+            .as_callback());
+    assert!(host_module.functions.values().all(|function| function.is_callback()));
+
+    let mut engine = Engine::new(1024);
+    assert!(engine.register_module(host_module).is_ok());
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let scale_res = engine.register_callback("root::host::scale", Box::new(|args: Vec<Value>| {
+        match args[..] {
+            [Value::Int(x), Value::Float(factor)] => Value::Int((x as f64 * factor) as i64),
+            _ => panic!("Unexpected arguments {:?}", args)
+        }
+    }));
+    assert!(scale_res.is_ok());
+
+    // Calling a callback function fails until a callback is registered for it
+    match engine.run_fn("root::main").map_err(|e| *e) {
+        Err(EngineError::CoreError(core_err)) => {
+            assert!(matches!(core_err.root_cause(), CoreError::UnboundForeignFunction(name) if name == "root::host::is_even"));
+        },
+        res => panic!("Expected an unbound callback, got {:?}", res)
+    }
+
+    let is_even_res = engine.register_callback("root::host::is_even", Box::new(|args: Vec<Value>| {
+        Value::Bool(args[0] == Value::Int(10))
+    }));
+    assert!(is_even_res.is_ok());
+    engine.reset();
+    engine.set_opcode_stats(true);
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 11);
+    let stats = engine.opcode_stats();
+    assert!(stats.contains(&(Opcode::CALL_CALLBACK, 2)));
+
+    // Void callbacks get their arguments, their return value is ignored
+    let notified = Arc::new(Mutex::new(Vec::new()));
+    let callback_notified = notified.clone();
+    let notify_res = engine.register_callback("root::host::notify", Box::new(move |args: Vec<Value>| {
+        callback_notified.lock().unwrap().extend(args);
+        Value::Bool(false)
+    }));
+    assert!(notify_res.is_ok());
+    assert!(engine.run_fn("root::notify_twice").is_ok());
+    assert_eq!(*notified.lock().unwrap(), vec![Value::Int(3), Value::Int(4)]);
+
+    // The returned value has to match the return type of the callback function
+    let is_even_res = engine.register_callback("root::host::is_even", Box::new(|_| Value::Int(1)));
+    assert!(is_even_res.is_ok());
+    match engine.run_fn("root::main").map_err(|e| *e) {
+        Err(EngineError::CoreError(core_err)) => {
+            assert!(matches!(core_err.root_cause(), CoreError::CallbackTypeMismatch(name) if name == "root::host::is_even"));
+        },
+        res => panic!("Expected a callback type mismatch, got {:?}", res)
+    }
+
+    // Only ints, floats and bools are passed to callbacks
+    let mut engine = Engine::new(1024);
+    let register_res = engine.register_module(Module::new("host")
+        .with_function(Function::new("log")
+            .with_arg(Type::String)
+            .as_callback()));
+    match register_res.map_err(|e| *e) {
+        Err(EngineError::CompileError(CompilerError::InvalidCallbackType(name, Type::String))) => {
+            assert_eq!(name, "root::host::log");
+        },
+        res => panic!("Expected an invalid callback type, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_engine_local_functions() {
+    let code = String::from("
+        fn: triple(x: int) ~ int {
+            return x * 3;
+        }
+
+        fn: main() ~ int {
+            var x = 4;
+            fn: helper(a: int) ~ int {
+                return triple(a) + 1;
+            }
+            if x > 0 {
+                fn: fact(n: int) ~ int {
+                    if n <= 1 {
+                        return 1;
+                    }
+                    return n * fact(n - 1);
+                }
+                x = helper(x) + fact(x);
+            }
+            return x;
+        }
+
+        fn: other() ~ int {
+            fn: helper() ~ int {
+                return 2;
+            }
+            return helper();
+        }
+    ");
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 13 + 24);
+    // Each function has its own local functions
+    assert!(engine.run_fn("root::other").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 2);
+
+    // Local functions are not visible outside of their function
+    let code = String::from("
+        fn: main() ~ int {
+            fn: helper() ~ int {
+                return 1;
+            }
+            return helper();
+        }
+
+        fn: other() ~ int {
+            return helper();
+        }
+    ");
+    match Engine::new(1024).load_code(&code).map_err(|err| *err) {
+        Err(EngineError::CompileError(CompilerError::UnknownFunction(name, _))) => assert_eq!(name, "helper"),
+        res => panic!("Expected an unknown function error, got {:?}", res)
+    }
+
+    // Nor can they capture the variables of their function
+    let code = String::from("
+        fn: main() ~ int {
+            var x = 1;
+            fn: helper() ~ int {
+                return x;
+            }
+            return helper();
+        }
+    ");
+    match Engine::new(1024).load_code(&code).map_err(|err| *err) {
+        Err(EngineError::CompileError(CompilerError::CapturedVariable(name))) => assert_eq!(name, "x"),
+        res => panic!("Expected a captured variable error, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_engine_comparison_chains() {
+    let code = String::from("
+        fn: main(n: int) ~ int {
+            var i = 0;
+            var done = false;
+            var count = 0;
+            while i < n && !done {
+                count += 1;
+                i += 1;
+                if i == 5 {
+                    done = true;
+                }
+            }
+            var ok = 1 < 2 && 3 == 3;
+            var nok = 1 < 2 && 3 != 3;
+            if ok && !nok || count > 100 {
+                count += 100;
+            }
+            return count;
+        }
+    ");
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    // The loop stops at n or after five iterations, whichever comes first
+    for (n, expected) in [(3, 103), (10, 105), (0, 100)].iter() {
+        let stack_size = engine.get_stack_size();
+        engine.push_stack::<i64>(*n).unwrap();
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), *expected);
+        assert_eq!(engine.get_stack_size(), stack_size + 8);
+    }
+}
+
+#[test]
+fn test_engine_const_array_sizes() {
+    let code = String::from("
+        const: MAX = 2;
+        const: LEN = MAX * 2;
+
+        cont: Buffer {
+            data: [int; LEN];
+            len: int;
+        }
+
+        fn: main(arr: [int; MAX * 2]) ~ int {
+            var r: &[int; LEN] = &arr;
+            r[LEN - 1] = 9;
+            return arr[3] * 1000 + sizeof([int; MAX + 1]) * 10 + sizeof(Buffer) - LEN * 8;
+        }
+    ");
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    for i in 1..=4 {
+        engine.push_stack::<i64>(i).unwrap();
+    }
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 9000 + 240 + 8);
+}
+
+#[test]
+fn test_engine_zero_arg_call() {
+    let code = String::from("
+        fn: seven() ~ int {
+            var x = 3;
+            return x + 4;
+        }
+
+        fn: main() ~ int {
+            var a = 10;
+            var b = seven() * a;
+            return b + seven() + a;
+        }
+    ");
+    let mut engine = Engine::new(1024);
+    let load_res = engine.load_code(&code);
+    println!("{:?}", load_res);
+    assert!(load_res.is_ok());
+
+    let stack_size = engine.get_stack_size();
+    let run_res = engine.run_fn("root::main");
+    println!("{:?}", run_res);
+    assert!(run_res.is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 70 + 7 + 10);
+    assert_eq!(engine.get_stack_size(), stack_size);
+
+    assert!(engine.run_fn("root::seven").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 7);
+    assert_eq!(engine.get_stack_size(), stack_size);
+}
+
+#[test]
+fn test_engine_string_reprs() {
+    let code = String::from("
+        fn: main() ~ int {
+            var result: int = 0;
+            log::push(\"hello\");
+            if \"hello\" == \"hello\" {
+                result = result + 1;
+            }
+            if \"hello\" == \"world\" {
+                result = result + 10;
+            }
+            if \"hello\" != \"world\" {
+                result = result + 100;
+            }
+            var i: int = 0;
+            while i < 2 {
+                log::push(\"loop\");
+                i = i + 1;
+            }
+            log::push(\"world\");
+            return result;
+        }
+    ");
+
+    for string_repr in [StringRepr::FatPointer, StringRepr::Handle].iter() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let fn_log = log.clone();
+        let push_function = Function::new("push")
+            .with_arg(Type::String)
+            .with_ret_type(Type::Void)
+            .with_closure(Box::new(move |adapter: &mut Adapter| {
+                let arg: String = adapter.get_arg(0);
+                fn_log.lock().unwrap().push(arg);
+            }));
+        let module = Module::new("log")
+            .with_function(push_function);
+
+        let mut engine = Engine::new(1024);
+        assert!(engine.register_module(module).is_ok());
+        assert!(engine.set_string_repr(*string_repr).is_ok());
+
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+
+        let stack_size = engine.get_stack_size();
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 101);
+        assert_eq!(engine.get_stack_size(), stack_size);
+        assert_eq!(*log.lock().unwrap(), vec!["hello", "loop", "loop", "world"]);
+    }
+}
+
+#[test]
+fn test_engine_string_handle_limits() {
+    let mut engine = Engine::new(1024);
+    assert!(engine.set_string_repr(StringRepr::Handle).is_ok());
+    assert!(engine.load_code("fn: main() ~ int { if \"a\" == \"a\" { return 1; } return 0; }").is_ok());
+
+    // Known strings keep their handles, new ones would not be in the table of the core
+    let reload_res = engine.reload_code("fn: main() ~ int { if \"a\" != \"a\" { return 1; } return 2; }");
+    println!("{:?}", reload_res);
+    assert!(reload_res.is_ok());
+    assert!(engine.run_fn("root::main").is_ok());
+    assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 2);
+
+    match engine.reload_code("fn: main() ~ int { if \"a\" == \"b\" { return 1; } return 3; }").map_err(|err| *err) {
+        Err(EngineError::CompileError(CompilerError::Unimplemented(_))) => {},
+        res => panic!("Expected reloading new strings to be unimplemented, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_engine_array_repeat() {
+    let code = String::from("
+        const: LEN = 256;
+
+        fn: main() ~ int {
+            var buffer = [7; LEN];
+            var flags: [bool; 3] = [true; 3];
+            var sum = 0;
+            var i = 0;
+            while i < LEN {
+                sum += buffer[i];
+                i += 1;
+            }
+            buffer[LEN - 1] = 0;
+            if flags[2] {
+                sum += buffer[LEN - 1];
+                sum += buffer[LEN - 2];
+            }
+            return sum;
+        }
+    ");
+
+    // Filled in a loop by default, and by a store per element with a high enough limit
+    for unroll_limit in [None, Some(256)].iter() {
+        let mut engine = Engine::new(4096);
+        if let Some(unroll_limit) = unroll_limit {
+            engine.compiler = Compiler::new().with_array_unroll_limit(*unroll_limit);
+        }
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+
+        let stack_size = engine.get_stack_size();
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), 256 * 7 + 7);
+        assert_eq!(engine.get_stack_size(), stack_size);
+    }
+}
+
+#[test]
+fn test_engine_nested_array_repeat() {
+    let code = String::from("
+        fn: main() ~ int {
+            var grid = [[1; 4]; 4];
+            var big = [[2; 3]; 20];
+            grid[2][3] = 5;
+            var sum = 0;
+            var i = 0;
+            while i < 4 {
+                var j = 0;
+                while j < 4 {
+                    sum += grid[i][j];
+                    j += 1;
+                }
+                i += 1;
+            }
+            return sum * 100 + big[19][2] + big[0][0];
+        }
+    ");
+
+    for unroll_limit in [0, 16].iter() {
+        let mut engine = Engine::new(4096);
+        engine.compiler = Compiler::new().with_array_unroll_limit(*unroll_limit);
+        let load_res = engine.load_code(&code);
+        println!("{:?}", load_res);
+        assert!(load_res.is_ok());
+
+        let run_res = engine.run_fn("root::main");
+        println!("{:?}", run_res);
+        assert!(run_res.is_ok());
+        assert_eq!(engine.get_register_value::<i64>(Register::R0).unwrap(), (15 + 5) * 100 + 4);
+    }
+}
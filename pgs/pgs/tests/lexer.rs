@@ -2,7 +2,10 @@ extern crate pgs;
 extern crate pglex;
 use pgs::{
     parser::{
-        lexer::Token
+        lexer::{
+            Token,
+            LexError
+        }
     }
 };
 use pglex::prelude::Lexable;
@@ -30,6 +33,54 @@ fn test_lex_string_literal() {
     assert_eq!(lexer.slice(), "\"This is a string literal.\"");
 }
 
+#[test]
+fn test_lex_unterminated_string_literal() {
+    let lexer = Token::lexer("\"abc");
+
+    assert_eq!(lexer.token, Token::Error);
+    let lex_err = LexError::from_lexer(&lexer);
+    assert_eq!(lex_err, Some(LexError::UnterminatedString(0)));
+    assert_eq!(lex_err.unwrap().to_string(), "unterminated string literal starting at 0");
+
+    let mut lexer = Token::lexer("print(\"abc");
+    assert_eq!(LexError::from_lexer(&lexer), None);
+    lexer.advance();
+    lexer.advance();
+    assert_eq!(LexError::from_lexer(&lexer), Some(LexError::UnterminatedString(6)));
+}
+
+#[test]
+fn test_lex_underscore_identifiers() {
+    let mut lexer = Token::lexer("my_var _tmp __x9 while_ while");
+
+    for name in ["my_var", "_tmp", "__x9", "while_"].iter() {
+        assert_eq!(lexer.token, Token::Text);
+        assert_eq!(lexer.slice(), *name);
+        lexer.advance();
+    }
+    assert_eq!(lexer.token, Token::While);
+}
+
+#[test]
+fn test_lex_malformed_number() {
+    let mut lexer = Token::lexer("1abc 0x1f 1e5 2_0");
+
+    assert_eq!(lexer.token, Token::Error);
+    assert_eq!(lexer.slice(), "1abc");
+    let lex_err = LexError::from_lexer(&lexer);
+    assert_eq!(lex_err, Some(LexError::MalformedNumber(0)));
+    assert_eq!(lex_err.unwrap().to_string(), "malformed number literal at 0");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::HexLiteral);
+    assert_eq!(lexer.slice(), "0x1f");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::ScientificFloat);
+    assert_eq!(lexer.slice(), "1e5");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Error);
+    assert_eq!(lexer.slice(), "2_0");
+}
+
 #[test]
 fn test_lex_while() {
     let mut lexer = Token::lexer("while nextT <= t2 { }");
@@ -48,6 +99,90 @@ fn test_lex_while() {
     lexer.advance();
 }
 
+#[test]
+fn test_lex_loop_keywords() {
+    let mut lexer = Token::lexer("for loop while break continue format loops");
+
+    assert_eq!(lexer.token, Token::For);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Loop);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::While);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Break);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Continue);
+    lexer.advance();
+    // Keywords only match whole words
+    assert_eq!(lexer.token, Token::Text);
+    assert_eq!(lexer.slice(), "format");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Text);
+    assert_eq!(lexer.slice(), "loops");
+}
+
+#[test]
+fn test_lex_pipes() {
+    let mut lexer = Token::lexer("a || b");
+
+    assert_eq!(lexer.token, Token::Text);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::PipePipe);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Text);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::End);
+
+    let mut lexer = Token::lexer("|x: int| x + 1");
+    let expected = [
+        Token::Pipe, Token::Text, Token::Colon, Token::Int, Token::Pipe,
+        Token::Text, Token::Plus, Token::IntLiteral
+    ];
+    for token in expected.iter() {
+        assert_eq!(lexer.token, *token);
+        lexer.advance();
+    }
+    assert_eq!(lexer.token, Token::End);
+}
+
+#[test]
+fn test_lex_raw_bytecode() {
+    let mut lexer = Token::lexer("raw_bc!(0x16, 42, 0xfF)");
+    let expected = [
+        Token::RawBytecode, Token::Not, Token::OpenParan, Token::HexLiteral, Token::Comma,
+        Token::IntLiteral, Token::Comma, Token::HexLiteral, Token::CloseParan
+    ];
+    for token in expected.iter() {
+        assert_eq!(lexer.token, *token);
+        lexer.advance();
+    }
+    assert_eq!(lexer.token, Token::End);
+}
+
+#[test]
+fn test_lex_include() {
+    let mut lexer = Token::lexer("include_str include_bytes include_string");
+    let expected = [Token::IncludeStr, Token::IncludeBytes, Token::Text];
+    for token in expected.iter() {
+        assert_eq!(lexer.token, *token);
+        lexer.advance();
+    }
+    assert_eq!(lexer.token, Token::End);
+}
+
+#[test]
+fn test_lex_cfg_if() {
+    let mut lexer = Token::lexer("@if debug { if }");
+    let expected = [
+        Token::CfgIf, Token::Text, Token::OpenBlock, Token::If, Token::CloseBlock
+    ];
+    for token in expected.iter() {
+        assert_eq!(lexer.token, *token);
+        lexer.advance();
+    }
+    assert_eq!(lexer.token, Token::End);
+}
+
 #[test]
 fn test_lex_function_decl() {
     let mut lexer = Token::lexer("fn main() {}");
@@ -100,4 +235,40 @@ fn test_lex_weird_mod_name() {
     lexer.advance();
     assert_eq!(lexer.token, Token::Text);
     lexer.advance();
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_lex_float_literal() {
+    let mut lexer = Token::lexer("3.14 -3.14 1.2+");
+
+    assert_eq!(lexer.token, Token::FloatLiteral);
+    assert_eq!(lexer.slice(), "3.14");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Minus);
+    lexer.advance();
+    assert_eq!(lexer.token, Token::FloatLiteral);
+    assert_eq!(lexer.slice(), "3.14");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::FloatLiteral);
+    assert_eq!(lexer.slice(), "1.2");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Plus);
+}
+
+#[test]
+fn test_lex_scientific_float() {
+    let mut lexer = Token::lexer("6.02e23 1E-3 3.1+");
+
+    assert_eq!(lexer.token, Token::ScientificFloat);
+    assert_eq!(lexer.slice(), "6.02e23");
+    assert_eq!(lexer.slice().parse::<f32>(), Ok(6.02e23));
+    lexer.advance();
+    assert_eq!(lexer.token, Token::ScientificFloat);
+    assert_eq!(lexer.slice(), "1E-3");
+    assert_eq!(lexer.slice().parse::<f32>(), Ok(1e-3));
+    lexer.advance();
+    assert_eq!(lexer.token, Token::FloatLiteral);
+    assert_eq!(lexer.slice(), "3.1");
+    lexer.advance();
+    assert_eq!(lexer.token, Token::Plus);
+}
@@ -4,10 +4,20 @@ use pgs::{
     parser::{
         parser::*,
         ast::*,
-        lexer::*
+        lexer::*,
+        grammar::{
+            self,
+            Grammar,
+            GrammarError,
+            NonTerminal,
+            Rule,
+            Symbol
+        }
     }
 };
 
+use std::fs;
+
 use pglex::prelude::Lexable;
 
 #[test]
@@ -246,6 +256,143 @@ fn test_parse_float_expr() {
     expr.print(0);
 }
 
+#[test]
+fn test_parse_negative_float_expr() {
+    let code = String::from("
+        -1.5 * (1.0 - -2.5) - 0.5;
+    ");
+    let mut lexer = Token::lexer(code.as_str());
+    let parser = Parser::new(code.clone());
+
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    assert!(expr_res.is_ok());
+    let expr = expr_res.unwrap();
+    expr.print(0);
+
+    let expected = Expression::Subtraction(
+        Box::new(Expression::Multiplication(
+            Box::new(Expression::FloatLiteral(-1.5)),
+            Box::new(Expression::Subtraction(
+                Box::new(Expression::FloatLiteral(1.0)),
+                Box::new(Expression::FloatLiteral(-2.5))
+            ))
+        )),
+        Box::new(Expression::FloatLiteral(0.5))
+    );
+    assert_eq!(expr, expected);
+}
+
+#[test]
+fn test_parse_array_repeat_expr() {
+    let parse = |code: &str| {
+        let mut lexer = Token::lexer(code);
+        let parser = Parser::new(String::from(code));
+        parser.parse_expr(&mut lexer, &[Token::Semicolon])
+    };
+
+    assert_eq!(
+        parse("[0; 256] ;").unwrap(),
+        Expression::ArrayRepeat(Box::new(Expression::IntLiteral(0)), Box::new(Expression::IntLiteral(256)), 0..8)
+    );
+    let inner = Expression::ArrayRepeat(
+        Box::new(Expression::Negate(Box::new(Expression::Variable(String::from("x"))))),
+        Box::new(Expression::Variable(String::from("N"))),
+        1..8
+    );
+    assert_eq!(
+        parse("[[-x; N]; N + 1];").unwrap(),
+        Expression::ArrayRepeat(
+            Box::new(inner),
+            Box::new(Expression::Addition(
+                Box::new(Expression::Variable(String::from("N"))),
+                Box::new(Expression::IntLiteral(1))
+            )),
+            0..16
+        )
+    );
+
+    for code in ["[0];", "[0; ];", "[0; 4;"].iter() {
+        assert!(parse(code).is_err(), "{}", code);
+    }
+}
+
+#[test]
+fn test_parse_negative_int_and_negate_expr() {
+    let parse = |code: &str| {
+        let mut lexer = Token::lexer(code);
+        let parser = Parser::new(String::from(code));
+        let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+        assert!(expr_res.is_ok());
+        expr_res.unwrap()
+    };
+
+    assert_eq!(parse("-5;"), Expression::IntLiteral(-5));
+    assert_eq!(parse("-9223372036854775808;"), Expression::IntLiteral(i64::MIN));
+    assert_eq!(
+        parse("-x;"),
+        Expression::Negate(Box::new(Expression::Variable(String::from("x"))))
+    );
+    assert_eq!(
+        parse("3 - -x * 2;"),
+        Expression::Subtraction(
+            Box::new(Expression::IntLiteral(3)),
+            Box::new(Expression::Multiplication(
+                Box::new(Expression::Negate(Box::new(Expression::Variable(String::from("x"))))),
+                Box::new(Expression::IntLiteral(2))
+            ))
+        )
+    );
+    assert_eq!(
+        parse("-(1 + 2);"),
+        Expression::Negate(Box::new(Expression::Addition(
+            Box::new(Expression::IntLiteral(1)),
+            Box::new(Expression::IntLiteral(2))
+        )))
+    );
+}
+
+#[test]
+fn test_parse_index_expr() {
+    let parse = |code: &str| {
+        let mut lexer = Token::lexer(code);
+        let parser = Parser::new(String::from(code));
+        let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+        println!("{:?}", expr_res);
+        assert!(expr_res.is_ok());
+        expr_res.unwrap()
+    };
+    let var = |name: &str| Box::new(Expression::Variable(String::from(name)));
+
+    assert_eq!(
+        parse("arr[i + 1] = ~p;"),
+        Expression::Assign(
+            Box::new(Expression::Index(
+                var("arr"),
+                Box::new(Expression::Addition(var("i"), Box::new(Expression::IntLiteral(1))))
+            )),
+            Box::new(Expression::Deref(var("p")))
+        )
+    );
+    assert_eq!(
+        parse("~a.b[m[0]];"),
+        Expression::Deref(Box::new(Expression::Index(
+            Box::new(Expression::MemberAccess(var("a"), var("b"))),
+            Box::new(Expression::Index(var("m"), Box::new(Expression::IntLiteral(0))))
+        )))
+    );
+    // Ranges bind weakest
+    assert_eq!(
+        parse("s[i + 1..n];"),
+        Expression::Index(
+            var("s"),
+            Box::new(Expression::Range(
+                Box::new(Expression::Addition(var("i"), Box::new(Expression::IntLiteral(1)))),
+                var("n")
+            ))
+        )
+    );
+}
+
 #[test]
 fn test_parse_raw_expr() {
     let code = String::from("
@@ -260,6 +407,113 @@ fn test_parse_raw_expr() {
     expr.print(0);
 }
 
+#[test]
+fn test_parse_grouped_expr() {
+    let parser = Parser::new(String::new());
+    let int = |i: i64| Box::new(Expression::IntLiteral(i));
+
+    let mut lexer = Token::lexer("(1 + 2) * 3;");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    println!("{:?}", expr_res);
+    assert_eq!(expr_res.unwrap(), Expression::Multiplication(
+        Box::new(Expression::Addition(int(1), int(2))),
+        int(3)
+    ));
+    assert_eq!(lexer.token, Token::Semicolon);
+
+    let mut lexer = Token::lexer("1 + 2 * 3;");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    assert_eq!(expr_res.unwrap(), Expression::Addition(
+        int(1),
+        Box::new(Expression::Multiplication(int(2), int(3)))
+    ));
+
+    // Redundant and nested parentheses only group
+    let mut lexer = Token::lexer("((1)) * ((2 - 3) / 4);");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    assert_eq!(expr_res.unwrap(), Expression::Multiplication(
+        int(1),
+        Box::new(Expression::Division(
+            Box::new(Expression::Subtraction(int(2), int(3))),
+            int(4)
+        ))
+    ));
+
+    // Inside call arguments, where ")" is also the delimiter
+    let mut lexer = Token::lexer("f((a + b) * c)");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    assert_eq!(expr_res.unwrap(), Expression::Call(String::from("f"), vec![
+        Expression::Multiplication(
+            Box::new(Expression::Addition(
+                Box::new(Expression::Variable(String::from("a"))),
+                Box::new(Expression::Variable(String::from("b")))
+            )),
+            Box::new(Expression::Variable(String::from("c")))
+        )
+    ]));
+}
+
+#[test]
+fn test_parse_mixed_operator_precedence() {
+    let parser = Parser::new(String::new());
+    let var = |name: &str| Box::new(Expression::Variable(String::from(name)));
+    let parse = |code: &str| {
+        let mut lexer = Token::lexer(code);
+        let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+        println!("{}: {:?}", code, expr_res);
+        expr_res.unwrap()
+    };
+
+    assert_eq!(parse("a + b < c * d;"), Expression::LessThan(
+        Box::new(Expression::Addition(var("a"), var("b"))),
+        Box::new(Expression::Multiplication(var("c"), var("d")))
+    ));
+    assert_eq!(parse("a < b && c >= d || e;"), Expression::Or(
+        Box::new(Expression::And(
+            Box::new(Expression::LessThan(var("a"), var("b"))),
+            Box::new(Expression::GreaterThanEquals(var("c"), var("d")))
+        )),
+        var("e")
+    ));
+    // "&&" binds tighter than "||"
+    assert_eq!(parse("a || b && c;"), Expression::Or(
+        var("a"),
+        Box::new(Expression::And(var("b"), var("c")))
+    ));
+    assert_eq!(parse("!a && b == c;"), Expression::And(
+        Box::new(Expression::Not(var("a"))),
+        Box::new(Expression::Equals(var("b"), var("c")))
+    ));
+    // Unary operators bind tighter than "*" and "+"
+    assert_eq!(parse("-a * b;"), Expression::Multiplication(
+        Box::new(Expression::Negate(var("a"))),
+        var("b")
+    ));
+    assert_eq!(parse("~p + 1;"), Expression::Addition(
+        Box::new(Expression::Deref(var("p"))),
+        Box::new(Expression::IntLiteral(1))
+    ));
+    // Operators of the same level group from the left
+    assert_eq!(parse("a * b / c;"), Expression::Division(
+        Box::new(Expression::Multiplication(var("a"), var("b"))),
+        var("c")
+    ));
+    assert_eq!(parse("a - b - c;"), Expression::Subtraction(
+        Box::new(Expression::Subtraction(var("a"), var("b"))),
+        var("c")
+    ));
+    assert_eq!(parse("x = a + b * c > d;"), Expression::Assign(
+        var("x"),
+        Box::new(Expression::GreaterThan(
+            Box::new(Expression::Addition(
+                var("a"),
+                Box::new(Expression::Multiplication(var("b"), var("c")))
+            )),
+            var("d")
+        ))
+    ));
+}
+
 #[test]
 fn test_parse_raw_var_expr() {
     let code = String::from("
@@ -454,6 +708,91 @@ fn test_parse_mod_decl() {
     assert!(decl_list_res.is_ok());
 }
 
+#[test]
+fn test_parse_mod_decl_without_colon() {
+    let code = String::from("
+        mod outer {
+            fn: one() ~ int {
+                return 1;
+            }
+            mod inner {
+                fn: two() ~ int {
+                    return 2;
+                }
+            }
+        }
+    ");
+
+    let parser = Parser::new(code);
+    let decl_list_res = parser.parse_root_decl_list();
+    println!("{:?}", decl_list_res);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+    assert_eq!(decl_list.len(), 1);
+    let outer_decls = match &decl_list[0] {
+        Declaration::Module(name, decls) if name == "outer" => decls,
+        decl => panic!("Expected module outer, got {:?}", decl)
+    };
+    assert_eq!(outer_decls.len(), 2);
+    match &outer_decls[0] {
+        Declaration::Function(fn_decl_args) => assert_eq!(fn_decl_args.name, "one"),
+        decl => panic!("Expected function one, got {:?}", decl)
+    }
+    match &outer_decls[1] {
+        Declaration::Module(name, decls) => {
+            assert_eq!(name, "inner");
+            match decls.as_slice() {
+                [Declaration::Function(fn_decl_args)] => assert_eq!(fn_decl_args.name, "two"),
+                decls => panic!("Expected function two, got {:?}", decls)
+            }
+        },
+        decl => panic!("Expected module inner, got {:?}", decl)
+    }
+}
+
+#[test]
+fn test_parse_import_and_impl_decls() {
+    let code = String::from("
+        import a::b::c;
+        import: std::println;
+
+        impl Printable for Point {
+            fn: print(&this);
+        }
+
+        impl: Point {
+            fn: len(&this) ~ int;
+        }
+    ");
+
+    let parser = Parser::new(code);
+    let decl_list_res = parser.parse_root_decl_list();
+    println!("{:?}", decl_list_res);
+    assert!(decl_list_res.is_ok());
+
+    let decl_list = decl_list_res.unwrap();
+    assert_eq!(decl_list.len(), 4);
+    assert_eq!(decl_list[0], Declaration::Import(String::from("a::b::c"), String::from("c")));
+    assert_eq!(decl_list[1], Declaration::Import(String::from("std::println"), String::from("println")));
+    match &decl_list[2] {
+        Declaration::Impl(impl_type, impl_for, decls) => {
+            assert_eq!(impl_type, "Printable");
+            assert_eq!(impl_for, "Point");
+            assert_eq!(decls.len(), 1);
+        },
+        decl => panic!("Expected impl of Printable for Point, got {:?}", decl)
+    }
+    match &decl_list[3] {
+        Declaration::Impl(impl_type, impl_for, decls) => {
+            assert_eq!(impl_type, "Point");
+            assert_eq!(impl_for, "Point");
+            assert_eq!(decls.len(), 1);
+        },
+        decl => panic!("Expected impl of Point, got {:?}", decl)
+    }
+}
+
 #[test]
 fn test_parse_while() {
     let code = String::from("
@@ -473,6 +812,162 @@ fn test_parse_while() {
     }
 }
 
+#[test]
+fn test_parse_break_continue() {
+    let code = String::from("
+        while i < 10 {
+            if i == 5 {
+                break;
+            }
+            continue;
+        }
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+    let stmt_res = parser.parse_while(&mut lexer);
+    println!("{:?}", stmt_res);
+    assert!(stmt_res.is_ok());
+
+    match stmt_res.unwrap() {
+        Statement::While(_, stmt_list) => {
+            assert_eq!(stmt_list.len(), 2);
+            match &stmt_list[0] {
+                Statement::If(if_args) => assert_eq!(if_args.if_block.as_slice(), &[Statement::Break]),
+                stmt => panic!("Expected if, got {:?}", stmt)
+            }
+            assert_eq!(stmt_list[1], Statement::Continue);
+        },
+        stmt => panic!("Expected while, got {:?}", stmt)
+    }
+
+    let mut lexer = Token::lexer("break");
+    assert!(parser.parse_break(&mut lexer).is_err());
+    let mut lexer = Token::lexer("continue 1;");
+    assert!(parser.parse_continue(&mut lexer).is_err());
+}
+
+#[test]
+fn test_parse_yield() {
+    let code = String::from("
+        yield;
+        yield x + 1;
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+    let stmts_res = parser.parse_statement_list(&mut lexer);
+    println!("{:?}", stmts_res);
+    assert!(stmts_res.is_ok());
+    assert_eq!(stmts_res.unwrap(), vec![
+        Statement::Yield(None),
+        Statement::Yield(Some(Expression::Addition(
+            Box::new(Expression::Variable(String::from("x"))),
+            Box::new(Expression::IntLiteral(1))
+        )))
+    ]);
+
+    let mut lexer = Token::lexer("yield 1");
+    assert!(parser.parse_yield(&mut lexer).is_err());
+}
+
+#[test]
+fn test_parse_unterminated_string() {
+    let code = String::from("fn: main() {\n    var s = \"abc;\n}");
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    println!("{:?}", decl_list_res);
+    match decl_list_res {
+        Err(ParseError { error_type: ParseErrorType::Lex(LexError::UnterminatedString(pos)), .. }) => {
+            assert_eq!(pos, code.find('"').unwrap());
+        },
+        res => panic!("Expected an unterminated string error, got {:?}", res)
+    }
+}
+
+#[test]
+fn test_parse_malformed_number() {
+    let code = String::from("fn: main() {\n    var x = 1abc;\n}");
+    let parser = Parser::new(code.clone());
+    let decl_list_res = parser.parse_root_decl_list();
+    println!("{:?}", decl_list_res);
+    match decl_list_res {
+        Err(ParseError { error_type: ParseErrorType::Lex(LexError::MalformedNumber(pos)), .. }) => {
+            assert_eq!(pos, code.find('1').unwrap());
+        },
+        res => panic!("Expected a malformed number error, got {:?}", res)
+    }
+
+    let parser = Parser::new(String::from("fn: main() { var my_var = 1; var _tmp = my_var; }"));
+    assert!(parser.parse_root_decl_list().is_ok());
+}
+
+#[test]
+fn test_parse_lambda_expr() {
+    let parser = Parser::new(String::new());
+
+    let mut lexer = Token::lexer("|x: int, y: int| x + y;");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    println!("{:?}", expr_res);
+    assert_eq!(expr_res.unwrap(), Expression::Lambda(
+        vec![(String::from("x"), Type::Int), (String::from("y"), Type::Int)],
+        Box::new(Expression::Addition(
+            Box::new(Expression::Variable(String::from("x"))),
+            Box::new(Expression::Variable(String::from("y")))
+        ))
+    ));
+    assert_eq!(lexer.token, Token::Semicolon);
+
+    // Without parameters, "||" after an operand stays a logical or
+    let mut lexer = Token::lexer("|| a || b;");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    assert_eq!(expr_res.unwrap(), Expression::Lambda(
+        Vec::new(),
+        Box::new(Expression::Or(
+            Box::new(Expression::Variable(String::from("a"))),
+            Box::new(Expression::Variable(String::from("b")))
+        ))
+    ));
+
+    let mut lexer = Token::lexer("|x: int x;");
+    assert!(parser.parse_expr(&mut lexer, &[Token::Semicolon]).is_err());
+}
+
+#[test]
+fn test_parse_raw_bytecode_expr() {
+    let parser = Parser::new(String::new());
+
+    let mut lexer = Token::lexer("raw_bc!(0x01, 7, 0xFF,);");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    println!("{:?}", expr_res);
+    assert_eq!(expr_res.unwrap(), Expression::RawBytecode(vec![0x01, 7, 0xFF]));
+    assert_eq!(lexer.token, Token::Semicolon);
+
+    let mut lexer = Token::lexer("raw_bc!();");
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    assert_eq!(expr_res.unwrap(), Expression::RawBytecode(Vec::new()));
+
+    for code in ["raw_bc!(256);", "raw_bc!(0x100);", "raw_bc!(x);"].iter() {
+        let mut lexer = Token::lexer(*code);
+        match parser.parse_expr(&mut lexer, &[Token::Semicolon]) {
+            Err(ParseError { error_type: ParseErrorType::ExpectedByte, .. }) => {},
+            res => panic!("Expected ExpectedByte error for {}, got {:?}", code, res)
+        }
+    }
+
+    let mut lexer = Token::lexer("raw_bc!(1 2);");
+    match parser.parse_expr(&mut lexer, &[Token::Semicolon]) {
+        Err(ParseError { error_type: ParseErrorType::ExpectedCloseParan, .. }) => {},
+        res => panic!("Expected ExpectedCloseParan error, got {:?}", res)
+    }
+
+    let mut lexer = Token::lexer("raw_bc(1);");
+    match parser.parse_expr(&mut lexer, &[Token::Semicolon]) {
+        Err(ParseError { error_type: ParseErrorType::ExpectedNot, .. }) => {},
+        res => panic!("Expected ExpectedNot error, got {:?}", res)
+    }
+}
+
 #[test]
 fn test_parse_loop() {
     let code = String::from("
@@ -571,6 +1066,45 @@ fn test_parse_if_else_if_else() {
     println!("{:?}", stmt_res.unwrap());
 }
 
+#[test]
+fn test_parse_if_chain_args() {
+    let code = String::from("
+        if x == 1 {
+            return 1;
+        } else if x == 2 {
+            return 2;
+        } else if x == 3 {
+        } else {
+            return 4;
+        }
+        return 5;
+    ");
+
+    let parser = Parser::new(code.clone());
+    let mut lexer = Token::lexer(code.as_str());
+    let stmt_res = parser.parse_if(&mut lexer);
+    println!("{:?}", stmt_res);
+    assert!(stmt_res.is_ok());
+
+    let var = |name: &str| Box::new(Expression::Variable(String::from(name)));
+    let x_equals = |n: i64| Expression::Equals(var("x"), Box::new(Expression::IntLiteral(n)));
+    let ret = |n: i64| Statement::Return(Some(Expression::IntLiteral(n)));
+    assert_eq!(stmt_res.unwrap(), Statement::If(IfStatementArgs {
+        if_expr: x_equals(1),
+        if_block: vec![ret(1)],
+        else_if_list: Some(vec![
+            (x_equals(2), vec![ret(2)]),
+            (x_equals(3), vec![])
+        ]),
+        else_block: Some(vec![ret(4)])
+    }));
+    // The chain ends after the else block
+    assert_eq!(lexer.token, Token::Return);
+
+    let mut lexer = Token::lexer("if x { } else return 1;");
+    assert!(parser.parse_if(&mut lexer).is_err());
+}
+
 #[test]
 fn test_parse_member() {
     let code = String::from("
@@ -669,4 +1203,377 @@ fn test_parse_cont_instance() {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_type_classification() {
+    let slice_type = Type::Reference(Box::new(Type::AutoArray(Box::new(Type::Int))));
+    let int_ref_type = Type::Reference(Box::new(Type::Int));
+    let cont_ref_type = Type::Reference(Box::new(Type::Other(String::from("Vector"))));
+
+    let register_types = [Type::Int, Type::Float, Type::Bool, int_ref_type.clone(), cont_ref_type.clone()];
+    for reg_type in register_types.iter() {
+        assert!(reg_type.fits_in_register());
+        assert!(reg_type.is_primitive());
+        assert!(!reg_type.is_fat_pointer());
+    }
+
+    let stack_types = [
+        Type::Void,
+        Type::String,
+        Type::Auto,
+        Type::Array(Box::new(Type::Int), 4),
+        Type::AutoArray(Box::new(Type::Int)),
+        Type::Other(String::from("Vector")),
+        Type::Tuple(vec![Type::Int, Type::Float])
+    ];
+    for stack_type in stack_types.iter() {
+        assert!(!stack_type.fits_in_register());
+        assert!(!stack_type.is_primitive());
+        assert!(!stack_type.is_fat_pointer());
+    }
+
+    assert!(slice_type.is_fat_pointer());
+    assert!(!slice_type.fits_in_register());
+    assert!(!slice_type.is_primitive());
+}
+
+#[test]
+fn test_grammar_is_ll1() {
+    let grammar_res = Grammar::new(grammar::pgs_rules(), NonTerminal::Program);
+    assert!(grammar_res.is_ok());
+
+    // S -> "a" | "a" "b" needs two tokens of lookahead
+    let ambiguous_rules = vec![
+        Rule::new(NonTerminal::Program, vec![Symbol::Terminal(Token::Text)]),
+        Rule::new(NonTerminal::Program, vec![Symbol::Terminal(Token::Text), Symbol::Terminal(Token::Semicolon)])
+    ];
+    match Grammar::new(ambiguous_rules, NonTerminal::Program) {
+        Err(GrammarError::Conflict(NonTerminal::Program, Token::Text)) => {},
+        _ => panic!("Expected a conflict")
+    }
+}
+
+#[test]
+fn test_verify_grammar_test_scripts() {
+    let scripts_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_scripts");
+    let mut script_count = 0;
+    let mut valid_script_count = 0;
+    for entry in fs::read_dir(scripts_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let code = fs::read_to_string(&path).unwrap();
+        let parser = Parser::new(code.clone());
+        let parses = parser.parse_root_decl_list().is_ok();
+        // The verifier has to agree with the parser
+        assert_eq!(grammar::verify_grammar(&grammar::tokenize(&code)), parses, "{:?}", path);
+        script_count += 1;
+        if parses {
+            valid_script_count += 1;
+        }
+    }
+    assert!(script_count > 0);
+    assert!(valid_script_count > 0);
+
+    let valid_programs = [
+        "",
+        "fn: main() ~ int { return 4; }",
+        "fn: foreign(a: int, b: [float; 4], c: &Vec2);",
+        "mod: math { fn: square(x: float) ~ float { return x * x; } }",
+        "mod math { mod trig { fn: sin(x: float) ~ float; } }",
+        "import a::b::c; impl Printable for Point { fn: print(&this); }",
+        "fn: main() { yield; yield x * 2; }",
+        "fn: main() { import std::io::print; if a { import: std::{io::*, math::sqrt = root_of}; } }",
+        "@if debug { fn: log(); mod: dbg { } } fn: main() { @if debug { log(); } @if trace { } }",
+        "fn: main() { var t = include_bytes(\"t.bin\"); if include_str(\"a\") { print(include_str(\"b\")); } }",
+        "fn: main() { var f = |x: int, y: int| x + y; var g = || a || b; }",
+        "fn: main() { raw_bc!(0x01, 7); raw_bc!(); raw_bc!(0,); }",
+        "import: std::{print, io::*, math::sqrt = root_of};",
+        "cont: Vec2 { x: float; y: float; } impl: Vec2 { fn: len(&this) ~ float { return this.x + this.y; } }",
+        "fn: main() { var v = Vec2 { x: 1.0, y: -2.0 }; if v.x == 1.0 && !flag { v.y += 1.0; } else if (v) { } else { loop { break; } } }",
+        "fn: main() { var i: int = 0; while i < 10 { i = i + 1; continue; } while false; std::println(\"done\"); }",
+        "fn: main(a: [int; 4]) { a[0] = 1; ~p = a[a[1]][2]; if a[3] > 0 { } }",
+        "fn: main() { @infinite while true { yield; } @infinite while true; }",
+        "fn: main() { var b = [0; 256]; var g = [[1.0; N]; N + 1]; g[0][1] = b[2]; }"
+    ];
+    for code in valid_programs.iter() {
+        assert!(grammar::verify_grammar(&grammar::tokenize(code)), "{}", code);
+    }
+}
+
+#[test]
+fn test_verify_grammar_invalid() {
+    let invalid_programs = [
+        "fn main() {}",
+        "fn: main() { return 4 }",
+        "fn: main() { var = 4; }",
+        "fn: main() { if x { }",
+        "fn: main() { x + ; }",
+        "cont: Vec2 { x: float }",
+        "import: std::;",
+        "fn: main() { (1 + 2; }",
+        "fn: main() { include_str(path); }",
+        "fn: main() ~ { }",
+        "fn: main() { a[1 = 2; }",
+        "fn: main() { var f = |x: int x; }",
+        "fn: main() { raw_bc!(1 2); }",
+        "fn: main() { raw_bc(1); }",
+        "fn: main() { @infinite loop { } }",
+        "fn: main() { var b = [0]; }",
+        "fn: main() { var b = [0; ]; }",
+        "var x = 4;"
+    ];
+    for code in invalid_programs.iter() {
+        assert!(!grammar::verify_grammar(&grammar::tokenize(code)), "{}", code);
+    }
+}
+
+#[test]
+fn test_parse_assert() {
+    let code = String::from("
+        assert x > 0;
+        assert y, \"y must hold\";
+    ");
+
+    let mut lexer = Token::lexer(code.as_str());
+    let parser = Parser::new(code.clone());
+    let stmt_list_res = parser.parse_statement_list(&mut lexer);
+    println!("{:?}", stmt_list_res);
+    assert!(stmt_list_res.is_ok());
+
+    let stmt_list = stmt_list_res.unwrap();
+    assert_eq!(stmt_list, vec![
+        Statement::Assert(
+            Box::new(Expression::GreaterThan(
+                Box::new(Expression::Variable(String::from("x"))),
+                Box::new(Expression::IntLiteral(0))
+            )),
+            None
+        ),
+        Statement::Assert(
+            Box::new(Expression::Variable(String::from("y"))),
+            Some(String::from("y must hold"))
+        )
+    ]);
+
+    let mut lexer = Token::lexer("assert x, 5;");
+    let parser = Parser::new(String::from("assert x, 5;"));
+    assert!(parser.parse_statement_list(&mut lexer).is_err());
+}
+
+#[test]
+fn test_parse_typeof() {
+    let code = "typeof(x + 1) == typeof(f(2));";
+    let mut lexer = Token::lexer(code);
+    let parser = Parser::new(String::from(code));
+    let expr_res = parser.parse_expr(&mut lexer, &[Token::Semicolon]);
+    println!("{:?}", expr_res);
+    assert!(expr_res.is_ok());
+    assert_eq!(expr_res.unwrap(), Expression::Equals(
+        Box::new(Expression::TypeOf(Box::new(Expression::Addition(
+            Box::new(Expression::Variable(String::from("x"))),
+            Box::new(Expression::IntLiteral(1))
+        )))),
+        Box::new(Expression::TypeOf(Box::new(Expression::Call(
+            String::from("f"),
+            vec![Expression::IntLiteral(2)]
+        ))))
+    ));
+}
+
+#[test]
+fn test_parse_comptime_assert() {
+    let code = String::from("
+        comptime_assert sizeof(int) == 8, \"int must be 8 bytes\";
+        comptime_assert sizeof(&[float]) > 0;
+    ");
+
+    let mut lexer = Token::lexer(code.as_str());
+    let parser = Parser::new(code.clone());
+    let stmt_list_res = parser.parse_statement_list(&mut lexer);
+    println!("{:?}", stmt_list_res);
+    assert!(stmt_list_res.is_ok());
+
+    let stmt_list = stmt_list_res.unwrap();
+    assert_eq!(stmt_list, vec![
+        Statement::ComptimeAssert(
+            Box::new(Expression::Equals(
+                Box::new(Expression::SizeOf(Type::Int)),
+                Box::new(Expression::IntLiteral(8))
+            )),
+            Some(String::from("int must be 8 bytes"))
+        ),
+        Statement::ComptimeAssert(
+            Box::new(Expression::GreaterThan(
+                Box::new(Expression::SizeOf(Type::Reference(Box::new(Type::AutoArray(Box::new(Type::Float)))))),
+                Box::new(Expression::IntLiteral(0))
+            )),
+            None
+        )
+    ]);
+}
+
+#[test]
+fn test_parse_import_stmt() {
+    let code = String::from("fn: main() {
+        import: math::{double, half};
+        if true {
+            import math::double = twice;
+        }
+    }");
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let fn_decl_args = match &decl_list[0] {
+        Declaration::Function(fn_decl_args) => fn_decl_args,
+        decl => panic!("Expected a function, got {:?}", decl)
+    };
+    let stmt_list = fn_decl_args.code_block.as_ref().unwrap();
+    assert_eq!(stmt_list[0], Statement::Import(String::from("math::double"), String::from("double")));
+    assert_eq!(stmt_list[1], Statement::Import(String::from("math::half"), String::from("half")));
+    // Every import of a multi import is a statement on the same line
+    assert_eq!(fn_decl_args.stmt_lines, vec![2, 2, 3]);
+    match &stmt_list[2] {
+        Statement::If(if_stmt_args) => assert_eq!(if_stmt_args.if_block, vec![
+            Statement::Import(String::from("math::double"), String::from("twice"))
+        ]),
+        stmt => panic!("Expected an if statement, got {:?}", stmt)
+    }
+}
+
+#[test]
+fn test_parse_cfg() {
+    let code = String::from("
+        @if debug {
+            fn: log(x: int);
+        }
+
+        fn: main() {
+            @if debug {
+                log(1);
+            }
+        }
+    ");
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    match &decl_list[0] {
+        Declaration::Cfg(flag, cfg_decl_list) => {
+            assert_eq!(flag, "debug");
+            assert_eq!(cfg_decl_list.len(), 1);
+        },
+        decl => panic!("Expected a cfg declaration, got {:?}", decl)
+    }
+    let fn_decl_args = match &decl_list[1] {
+        Declaration::Function(fn_decl_args) => fn_decl_args,
+        decl => panic!("Expected a function, got {:?}", decl)
+    };
+    match &fn_decl_args.code_block.as_ref().unwrap()[0] {
+        Statement::Cfg(flag, stmt_list) => {
+            assert_eq!(flag, "debug");
+            assert_eq!(stmt_list.len(), 1);
+        },
+        stmt => panic!("Expected a cfg statement, got {:?}", stmt)
+    }
+
+    for code in ["@if { }", "@if debug fn: main();", "fn: main() { @if debug { log(1); }"].iter() {
+        assert!(Parser::new(String::from(*code)).parse_root_decl_list().is_err(), "{}", code);
+    }
+}
+
+#[test]
+fn test_parse_infinite_loop() {
+    let code = String::from("
+        @infinite while true {
+            yield;
+        }
+    ");
+    let mut lexer = Token::lexer(code.as_str());
+    let parser = Parser::new(code.clone());
+    let stmt_list = parser.parse_statement_list(&mut lexer).unwrap();
+    assert_eq!(stmt_list, vec![
+        Statement::Infinite(Box::new(Statement::While(
+            Box::new(Expression::BoolLiteral(true)),
+            vec![Statement::Yield(None)]
+        )))
+    ]);
+
+    // Only while loops can be annotated
+    let code = "fn: main() { @infinite loop { } }";
+    assert!(Parser::new(String::from(code)).parse_root_decl_list().is_err());
+}
+
+#[test]
+fn test_parse_include_expr() {
+    let code = String::from("include_bytes(\"assets/table.bin\") + include_str(\"banner.txt\");");
+    let mut lexer = Token::lexer(code.as_str());
+    let parser = Parser::new(code.clone());
+    let expr = parser.parse_expr(&mut lexer, &[Token::Semicolon]).unwrap();
+    assert_eq!(expr, Expression::Addition(
+        Box::new(Expression::IncludeBytes(String::from("assets/table.bin"), 0..33)),
+        Box::new(Expression::IncludeStr(String::from("banner.txt"), 36..61))
+    ));
+
+    for code in ["include_str(banner);", "include_bytes \"a\";", "include_str(\"a\";"].iter() {
+        let mut lexer = Token::lexer(*code);
+        let parser = Parser::new(String::from(*code));
+        assert!(parser.parse_expr(&mut lexer, &[Token::Semicolon]).is_err(), "{}", code);
+    }
+}
+
+#[test]
+fn test_parse_local_fn() {
+    let code = String::from("
+        fn: main() ~ int {
+            var x = 1;
+            fn: helper(a: int) ~ int {
+                return a + 1;
+            }
+            return helper(x);
+        }
+    ");
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    let fn_decl_args = match &decl_list[0] {
+        Declaration::Function(fn_decl_args) => fn_decl_args,
+        decl => panic!("Expected a function, got {:?}", decl)
+    };
+    let stmt_list = fn_decl_args.code_block.as_ref().unwrap();
+    assert_eq!(stmt_list.len(), 3);
+    match &stmt_list[1] {
+        Statement::LocalFunction(local_fn_decl_args) => {
+            assert_eq!(local_fn_decl_args.name, "helper");
+            assert_eq!(local_fn_decl_args.arguments.len(), 1);
+            assert!(local_fn_decl_args.code_block.is_some());
+        },
+        stmt => panic!("Expected a local function, got {:?}", stmt)
+    }
+
+    // Local functions need a body
+    let code = String::from("fn: main() { fn: helper(); }");
+    assert!(Parser::new(code).parse_root_decl_list().is_err());
+}
+
+#[test]
+fn test_parse_const_decl() {
+    let code = String::from("
+        const: MAX = 4;
+        fn: main(a: [int; MAX * 2], b: [int; 3]) {}
+    ");
+    let parser = Parser::new(code);
+    let decl_list = parser.parse_root_decl_list().unwrap();
+    assert_eq!(decl_list[0], Declaration::Const(String::from("MAX"), Expression::IntLiteral(4)));
+    let fn_decl_args = match &decl_list[1] {
+        Declaration::Function(fn_decl_args) => fn_decl_args,
+        decl => panic!("Expected a function, got {:?}", decl)
+    };
+    match &fn_decl_args.arguments[0].1 {
+        Type::ConstArray(inner_type, size_expr, _) => {
+            assert_eq!(**inner_type, Type::Int);
+            assert!(matches!(**size_expr, Expression::Multiplication(_, _)));
+        },
+        arg_type => panic!("Expected a constant sized array, got {:?}", arg_type)
+    }
+    assert_eq!(fn_decl_args.arguments[1].1, Type::Array(Box::new(Type::Int), 3));
+
+    for code in ["const MAX = 4;", "const: MAX;", "const: MAX = 4", "fn: main(a: [int; ]) {}"].iter() {
+        assert!(Parser::new(String::from(*code)).parse_root_decl_list().is_err(), "{}", code);
+    }
+}
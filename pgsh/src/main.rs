@@ -66,6 +66,11 @@ fn build_app<'a>() -> App<'a, 'a> {
                 .takes_value(true)
                 .help("Filename of the script to execute")
         )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .help("Prints how often each opcode was executed")
+        )
         .arg(
             Arg::with_name("arguments")
                 .required(false)
@@ -87,6 +92,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     let filename = filename_opt.unwrap();
 
     let mut engine = Engine::new(1024);
+    let print_stats = app_matches.is_present("stats");
+    engine.set_opcode_stats(print_stats);
 
     let arguments_opt = app_matches.values_of("arguments");
     if arguments_opt.is_some() {
@@ -111,6 +118,12 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     engine.run_file(Path::new(filename))?;
 
+    if print_stats {
+        for (opcode, count) in engine.opcode_stats() {
+            eprintln!("{:>12} {:?}", count, opcode);
+        }
+    }
+
     //println!("Script run. stack size: {}", engine.get_stack_size());
 
     let exit_code = engine.get_register_value::<i64>(Register::R0)?;